@@ -0,0 +1,193 @@
+/* SeaStreamer File C API */
+
+#ifndef SEA_STREAMER_FILE_H
+#define SEA_STREAMER_FILE_H
+
+/* This file is generated by cbindgen. Do not edit manually. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Success
+#define SS_OK 0
+
+// The end of file (or stream) is reached. Seeking past the end of file also returns this.
+#define SS_END 1
+
+// Error, see `ss_last_error`
+#define SS_ERR -1
+
+// A reader of a SeaStreamer file.
+typedef struct SsReader SsReader;
+
+// A writer of a SeaStreamer file.
+typedef struct SsWriter SsWriter;
+
+// The file header.
+typedef struct SsHeader {
+  // NUL terminated. Borrowed from the reader.
+  const char *file_name;
+  // Unix timestamp in milliseconds
+  int64_t created_at;
+  uint32_t beacon_interval;
+} SsHeader;
+
+// A message. The stream key and payload are borrowed from the reader.
+typedef struct SsMessage {
+  // Not NUL terminated
+  const char *stream_key;
+  size_t stream_key_len;
+  uint64_t shard_id;
+  uint64_t sequence;
+  // Unix timestamp in milliseconds
+  int64_t timestamp;
+  const uint8_t *payload;
+  size_t payload_len;
+  uint16_t checksum;
+} SsMessage;
+
+// A marker in a beacon, i.e. the last message of a stream before the beacon.
+// The stream key is borrowed from the reader.
+typedef struct SsMarker {
+  // Not NUL terminated
+  const char *stream_key;
+  size_t stream_key_len;
+  uint64_t shard_id;
+  uint64_t sequence;
+  // Unix timestamp in milliseconds
+  int64_t timestamp;
+  uint16_t running_checksum;
+} SsMarker;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Get the description of the last error that occurred on this thread. Returns null if there was
+// none. The string is valid until the next failing call on this thread.
+const char *ss_last_error(void);
+
+// Open a file for reading, from the beginning. Returns null on error.
+//
+// # Safety
+//
+// `path` must be a NUL terminated string.
+struct SsReader *ss_reader_open(const char *path);
+
+// Close the reader. `reader` can be null.
+//
+// # Safety
+//
+// `reader` must be returned by `ss_reader_open` and not already closed.
+void ss_reader_close(struct SsReader *reader);
+
+// Get the file header.
+//
+// # Safety
+//
+// `reader` must be a valid reader, and `header` must point to a writable `SsHeader`.
+int32_t ss_reader_header(const struct SsReader *reader, struct SsHeader *header);
+
+// Read the next message. Returns `SS_END` at the end of file, or on an End-of-Stream message.
+//
+// # Safety
+//
+// `reader` must be a valid reader, and `message` must point to a writable `SsMessage`.
+int32_t ss_reader_next(struct SsReader *reader, struct SsMessage *message);
+
+// Seek to the first message after `timestamp` (in milliseconds), using the beacons of all streams
+// in the file to narrow down the search. Returns `SS_END` if there is no such message.
+//
+// # Safety
+//
+// `reader` must be a valid reader.
+int32_t ss_reader_seek_timestamp(struct SsReader *reader, int64_t timestamp);
+
+// Like `ss_reader_seek_timestamp`, but only use the markers of the given stream to narrow down
+// the search.
+//
+// # Safety
+//
+// `reader` must be a valid reader, and `stream_key` must be a NUL terminated string.
+int32_t ss_reader_seek_stream_timestamp(struct SsReader *reader,
+                                        const char *stream_key,
+                                        uint64_t shard_id,
+                                        int64_t timestamp);
+
+// Rewind to the N-th beacon. 0 means the beginning of file. Returns the beacon index actually
+// arrived at, or -1 on error.
+//
+// # Safety
+//
+// `reader` must be a valid reader.
+int64_t ss_reader_rewind(struct SsReader *reader, uint32_t nth);
+
+// Current position in the file, in bytes.
+//
+// # Safety
+//
+// `reader` must be a valid reader.
+uint64_t ss_reader_offset(const struct SsReader *reader);
+
+// Get the most recent beacon's index, and its number of markers. The beacon is cleared on each
+// beacon point; an index of 0 means no beacon has been read since.
+//
+// # Safety
+//
+// `reader` must be a valid reader. `num_markers` can be null.
+uint32_t ss_reader_beacon(const struct SsReader *reader, size_t *num_markers);
+
+// Get the i-th marker of the most recent beacon.
+//
+// # Safety
+//
+// `reader` must be a valid reader, and `marker` must point to a writable `SsMarker`.
+int32_t ss_reader_beacon_marker(const struct SsReader *reader, size_t i, struct SsMarker *marker);
+
+// Open a file for writing. If `append` is true and the file exists, messages are appended to
+// the file (an End-of-Stream message at the end is removed), following its beacon interval.
+// Otherwise the file is overwritten. `beacon_interval` should be a multiple of 1024, or 0 for
+// the default. Returns null on error.
+//
+// # Safety
+//
+// `path` must be a NUL terminated string.
+struct SsWriter *ss_writer_open(const char *path, uint32_t beacon_interval, bool append);
+
+// Append a message. This does not block; use `ss_writer_flush` to make sure it has been written.
+//
+// # Safety
+//
+// `writer` must be a valid writer, `stream_key` must be a NUL terminated string, and `payload`
+// must point to `payload_len` bytes (it can be null if `payload_len` is 0).
+int32_t ss_writer_append(struct SsWriter *writer,
+                         const char *stream_key,
+                         uint64_t shard_id,
+                         uint64_t sequence,
+                         int64_t timestamp,
+                         const uint8_t *payload,
+                         size_t payload_len);
+
+// Flush buffered messages to disk.
+//
+// # Safety
+//
+// `writer` must be a valid writer.
+int32_t ss_writer_flush(struct SsWriter *writer);
+
+// Flush and close the writer, optionally ending the file with an End-of-Stream message.
+// The writer is freed even on error. `writer` can be null.
+//
+// # Safety
+//
+// `writer` must be returned by `ss_writer_open` and not already closed.
+int32_t ss_writer_close(struct SsWriter *writer, bool eos);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* SEA_STREAMER_FILE_H */
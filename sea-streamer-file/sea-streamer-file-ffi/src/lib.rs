@@ -0,0 +1,505 @@
+//! ### `sea-streamer-file-ffi`: C API for SeaStreamer files
+//!
+//! This crate exposes a stable C ABI for reading and writing `.ss` files, such that other languages
+//! can work with the format without reimplementing it. The header file is at
+//! `include/sea_streamer_file.h`, generated by `cbindgen` (see `cbindgen.toml`).
+//!
+//! A reader wraps [`MessageSource`] in `Replay` mode, and a writer wraps [`MessageSink`]. Each handle
+//! owns a single threaded runtime, so the calls are blocking. Handles are not thread safe, but
+//! different handles can be used in different threads.
+//!
+//! ```c
+//! SsReader *reader = ss_reader_open("capture.ss");
+//! SsMessage message;
+//! while (ss_reader_next(reader, &message) == SS_OK) {
+//!     printf("%.*s %" PRIu64 "\n", (int)message.stream_key_len, message.stream_key, message.sequence);
+//! }
+//! ss_reader_close(reader);
+//! ```
+//!
+//! Functions returning `int32_t` return `SS_OK` on success and `SS_ERR` on error. On error, a
+//! description can be retrieved with `ss_last_error`. Pointers handed out by a reader (stream keys
+//! and payloads) are borrowed, and remain valid until the next call on the same reader.
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString},
+    os::raw::c_char,
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr,
+};
+
+use sea_streamer_file::{
+    format::{Marker, Message},
+    is_end_of_stream, FileErr, FileId, MessageSink, MessageSource, SeekErr, SeekTarget,
+    DEFAULT_BEACON_INTERVAL, DEFAULT_FILE_SIZE_LIMIT,
+};
+use sea_streamer_types::{
+    Buffer, Message as MessageTrait, MessageHeader, OwnedMessage, SeqPos, ShardId, StreamKey,
+    Timestamp,
+};
+use tokio::runtime::{Builder, Runtime};
+
+/// Success
+pub const SS_OK: i32 = 0;
+/// The end of file (or stream) is reached. Seeking past the end of file also returns this.
+pub const SS_END: i32 = 1;
+/// Error, see `ss_last_error`
+pub const SS_ERR: i32 = -1;
+
+/// A reader of a SeaStreamer file.
+pub struct SsReader {
+    runtime: Runtime,
+    source: MessageSource,
+    file_name: CString,
+    current: Option<Message>,
+}
+
+/// A writer of a SeaStreamer file.
+pub struct SsWriter {
+    runtime: Runtime,
+    sink: MessageSink,
+}
+
+/// The file header.
+#[repr(C)]
+pub struct SsHeader {
+    /// NUL terminated. Borrowed from the reader.
+    pub file_name: *const c_char,
+    /// Unix timestamp in milliseconds
+    pub created_at: i64,
+    pub beacon_interval: u32,
+}
+
+/// A message. The stream key and payload are borrowed from the reader.
+#[repr(C)]
+pub struct SsMessage {
+    /// Not NUL terminated
+    pub stream_key: *const c_char,
+    pub stream_key_len: usize,
+    pub shard_id: u64,
+    pub sequence: u64,
+    /// Unix timestamp in milliseconds
+    pub timestamp: i64,
+    pub payload: *const u8,
+    pub payload_len: usize,
+    pub checksum: u16,
+}
+
+/// A marker in a beacon, i.e. the last message of a stream before the beacon.
+/// The stream key is borrowed from the reader.
+#[repr(C)]
+pub struct SsMarker {
+    /// Not NUL terminated
+    pub stream_key: *const c_char,
+    pub stream_key_len: usize,
+    pub shard_id: u64,
+    pub sequence: u64,
+    /// Unix timestamp in milliseconds
+    pub timestamp: i64,
+    pub running_checksum: u16,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error<E: ToString>(err: E) {
+    let message = err.to_string().replace('\0', " ");
+    LAST_ERROR.with(|e| *e.borrow_mut() = CString::new(message).ok());
+}
+
+/// Run `f`, turning errors and panics into `err`.
+fn guard<T, E: ToString, F: FnOnce() -> Result<T, E>>(err: T, f: F) -> T {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(e)) => {
+            set_last_error(e);
+            err
+        }
+        Err(_) => {
+            set_last_error("Panicked");
+            err
+        }
+    }
+}
+
+fn runtime() -> Result<Runtime, FileErr> {
+    Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(FileErr::IoError)
+}
+
+unsafe fn to_str<'a>(s: *const c_char, name: &str) -> Result<&'a str, String> {
+    if s.is_null() {
+        return Err(format!("{name} is null"));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| format!("{name} is not valid UTF-8"))
+}
+
+fn to_millis(ts: &Timestamp) -> i64 {
+    (ts.unix_timestamp_nanos() / 1_000_000) as i64
+}
+
+fn from_millis(ms: i64) -> Result<Timestamp, String> {
+    Timestamp::from_unix_timestamp_nanos(ms as i128 * 1_000_000).map_err(|e| e.to_string())
+}
+
+/// Get the description of the last error that occurred on this thread. Returns null if there was
+/// none. The string is valid until the next failing call on this thread.
+#[no_mangle]
+pub extern "C" fn ss_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| match e.borrow().as_ref() {
+        Some(e) => e.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// Open a file for reading, from the beginning. Returns null on error.
+///
+/// # Safety
+///
+/// `path` must be a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ss_reader_open(path: *const c_char) -> *mut SsReader {
+    guard(ptr::null_mut(), || {
+        let path = to_str(path, "path")?;
+        let runtime = runtime().map_err(|e| e.to_string())?;
+        let source = runtime
+            .block_on(MessageSource::new(
+                FileId::new(path),
+                sea_streamer_file::StreamMode::Replay,
+            ))
+            .map_err(|e| e.to_string())?;
+        let file_name = CString::new(source.file_header().file_name.replace('\0', ""))
+            .map_err(|e| e.to_string())?;
+        Ok::<_, String>(Box::into_raw(Box::new(SsReader {
+            runtime,
+            source,
+            file_name,
+            current: None,
+        })))
+    })
+}
+
+/// Close the reader. `reader` can be null.
+///
+/// # Safety
+///
+/// `reader` must be returned by `ss_reader_open` and not already closed.
+#[no_mangle]
+pub unsafe extern "C" fn ss_reader_close(reader: *mut SsReader) {
+    if !reader.is_null() {
+        drop(Box::from_raw(reader));
+    }
+}
+
+/// Get the file header.
+///
+/// # Safety
+///
+/// `reader` must be a valid reader, and `header` must point to a writable `SsHeader`.
+#[no_mangle]
+pub unsafe extern "C" fn ss_reader_header(reader: *const SsReader, header: *mut SsHeader) -> i32 {
+    guard(SS_ERR, || {
+        let reader = reader.as_ref().ok_or("reader is null")?;
+        let header = header.as_mut().ok_or("header is null")?;
+        let file_header = reader.source.file_header();
+        *header = SsHeader {
+            file_name: reader.file_name.as_ptr(),
+            created_at: to_millis(&file_header.created_at),
+            beacon_interval: file_header.beacon_interval,
+        };
+        Ok::<_, &str>(SS_OK)
+    })
+}
+
+/// Read the next message. Returns `SS_END` at the end of file, or on an End-of-Stream message.
+///
+/// # Safety
+///
+/// `reader` must be a valid reader, and `message` must point to a writable `SsMessage`.
+#[no_mangle]
+pub unsafe extern "C" fn ss_reader_next(reader: *mut SsReader, message: *mut SsMessage) -> i32 {
+    guard(SS_ERR, || {
+        let reader = reader.as_mut().ok_or("reader is null")?;
+        let out = message.as_mut().ok_or("message is null")?;
+        reader.current = None;
+        let message = match reader.runtime.block_on(reader.source.next()) {
+            Ok(message) => message,
+            Err(FileErr::NotEnoughBytes) => return Ok(SS_END),
+            Err(e) => return Err(e.to_string()),
+        };
+        if is_end_of_stream(&message.message) {
+            return Ok(SS_END);
+        }
+        let current = reader.current.insert(message);
+        let header = current.message.header();
+        let payload = current.message.message();
+        *out = SsMessage {
+            stream_key: header.stream_key().name().as_ptr() as *const c_char,
+            stream_key_len: header.stream_key().name().len(),
+            shard_id: header.shard_id().id(),
+            sequence: *header.sequence(),
+            timestamp: to_millis(header.timestamp()),
+            payload: payload.as_bytes().as_ptr(),
+            payload_len: payload.size(),
+            checksum: current.checksum,
+        };
+        Ok(SS_OK)
+    })
+}
+
+/// Seek to the first message after `timestamp` (in milliseconds), using the beacons of all streams
+/// in the file to narrow down the search. Returns `SS_END` if there is no such message.
+///
+/// # Safety
+///
+/// `reader` must be a valid reader.
+#[no_mangle]
+pub unsafe extern "C" fn ss_reader_seek_timestamp(reader: *mut SsReader, timestamp: i64) -> i32 {
+    guard(SS_ERR, || {
+        let reader = reader.as_mut().ok_or("reader is null")?;
+        let target = SeekTarget::Timestamp(from_millis(timestamp)?);
+        reader.current = None;
+        seek_result(reader.runtime.block_on(reader.source.seek_all(target)))
+    })
+}
+
+/// Like `ss_reader_seek_timestamp`, but only use the markers of the given stream to narrow down
+/// the search.
+///
+/// # Safety
+///
+/// `reader` must be a valid reader, and `stream_key` must be a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ss_reader_seek_stream_timestamp(
+    reader: *mut SsReader,
+    stream_key: *const c_char,
+    shard_id: u64,
+    timestamp: i64,
+) -> i32 {
+    guard(SS_ERR, || {
+        let reader = reader.as_mut().ok_or("reader is null")?;
+        let stream_key =
+            StreamKey::new(to_str(stream_key, "stream_key")?).map_err(|e| e.to_string())?;
+        let target = SeekTarget::Timestamp(from_millis(timestamp)?);
+        reader.current = None;
+        seek_result(
+            reader.runtime.block_on(reader.source.seek(
+                &stream_key,
+                &ShardId::new(shard_id),
+                target,
+            )),
+        )
+    })
+}
+
+fn seek_result(res: Result<(), FileErr>) -> Result<i32, String> {
+    match res {
+        Ok(()) => Ok(SS_OK),
+        Err(FileErr::SeekErr(SeekErr::OutOfBound)) => Ok(SS_END),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Rewind to the N-th beacon. 0 means the beginning of file. Returns the beacon index actually
+/// arrived at, or -1 on error.
+///
+/// # Safety
+///
+/// `reader` must be a valid reader.
+#[no_mangle]
+pub unsafe extern "C" fn ss_reader_rewind(reader: *mut SsReader, nth: u32) -> i64 {
+    guard(-1, || {
+        let reader = reader.as_mut().ok_or("reader is null")?;
+        reader.current = None;
+        reader
+            .runtime
+            .block_on(reader.source.rewind(SeqPos::At(nth as u64)))
+            .map(|nth| nth as i64)
+            .map_err(|e| e.to_string())
+    })
+}
+
+/// Current position in the file, in bytes.
+///
+/// # Safety
+///
+/// `reader` must be a valid reader.
+#[no_mangle]
+pub unsafe extern "C" fn ss_reader_offset(reader: *const SsReader) -> u64 {
+    match reader.as_ref() {
+        Some(reader) => reader.source.offset(),
+        None => 0,
+    }
+}
+
+/// Get the most recent beacon's index, and its number of markers. The beacon is cleared on each
+/// beacon point; an index of 0 means no beacon has been read since.
+///
+/// # Safety
+///
+/// `reader` must be a valid reader. `num_markers` can be null.
+#[no_mangle]
+pub unsafe extern "C" fn ss_reader_beacon(reader: *const SsReader, num_markers: *mut usize) -> u32 {
+    match reader.as_ref() {
+        Some(reader) => {
+            let (nth, markers) = reader.source.beacon();
+            if let Some(num_markers) = num_markers.as_mut() {
+                *num_markers = markers.len();
+            }
+            nth
+        }
+        None => 0,
+    }
+}
+
+/// Get the i-th marker of the most recent beacon.
+///
+/// # Safety
+///
+/// `reader` must be a valid reader, and `marker` must point to a writable `SsMarker`.
+#[no_mangle]
+pub unsafe extern "C" fn ss_reader_beacon_marker(
+    reader: *const SsReader,
+    i: usize,
+    marker: *mut SsMarker,
+) -> i32 {
+    guard(SS_ERR, || {
+        let reader = reader.as_ref().ok_or("reader is null")?;
+        let out = marker.as_mut().ok_or("marker is null")?;
+        let Marker {
+            header,
+            running_checksum,
+        } = reader
+            .source
+            .beacon()
+            .1
+            .get(i)
+            .ok_or("index out of range")?;
+        *out = SsMarker {
+            stream_key: header.stream_key().name().as_ptr() as *const c_char,
+            stream_key_len: header.stream_key().name().len(),
+            shard_id: header.shard_id().id(),
+            sequence: *header.sequence(),
+            timestamp: to_millis(header.timestamp()),
+            running_checksum: running_checksum.0,
+        };
+        Ok::<_, &str>(SS_OK)
+    })
+}
+
+/// Open a file for writing. If `append` is true and the file exists, messages are appended to
+/// the file (an End-of-Stream message at the end is removed), following its beacon interval.
+/// Otherwise the file is overwritten. `beacon_interval` should be a multiple of 1024, or 0 for
+/// the default. Returns null on error.
+///
+/// # Safety
+///
+/// `path` must be a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ss_writer_open(
+    path: *const c_char,
+    beacon_interval: u32,
+    append: bool,
+) -> *mut SsWriter {
+    guard(ptr::null_mut(), || {
+        let path = to_str(path, "path")?;
+        let beacon_interval = match beacon_interval {
+            0 => DEFAULT_BEACON_INTERVAL,
+            v if v % 1024 == 0 => v,
+            _ => return Err("beacon_interval should be a multiple of 1024".to_owned()),
+        };
+        let runtime = runtime().map_err(|e| e.to_string())?;
+        let file_id = FileId::new(path);
+        let sink = runtime
+            .block_on(async {
+                if append {
+                    MessageSink::append(file_id, beacon_interval, DEFAULT_FILE_SIZE_LIMIT).await
+                } else {
+                    MessageSink::new(file_id, beacon_interval, DEFAULT_FILE_SIZE_LIMIT).await
+                }
+            })
+            .map_err(|e| e.to_string())?;
+        Ok(Box::into_raw(Box::new(SsWriter { runtime, sink })))
+    })
+}
+
+/// Append a message. This does not block; use `ss_writer_flush` to make sure it has been written.
+///
+/// # Safety
+///
+/// `writer` must be a valid writer, `stream_key` must be a NUL terminated string, and `payload`
+/// must point to `payload_len` bytes (it can be null if `payload_len` is 0).
+#[no_mangle]
+pub unsafe extern "C" fn ss_writer_append(
+    writer: *mut SsWriter,
+    stream_key: *const c_char,
+    shard_id: u64,
+    sequence: u64,
+    timestamp: i64,
+    payload: *const u8,
+    payload_len: usize,
+) -> i32 {
+    guard(SS_ERR, || {
+        let writer = writer.as_mut().ok_or("writer is null")?;
+        let stream_key =
+            StreamKey::new(to_str(stream_key, "stream_key")?).map_err(|e| e.to_string())?;
+        let payload = if payload_len == 0 {
+            Vec::new()
+        } else if payload.is_null() {
+            return Err("payload is null".to_owned());
+        } else {
+            std::slice::from_raw_parts(payload, payload_len).to_vec()
+        };
+        let header = MessageHeader::new(
+            stream_key,
+            ShardId::new(shard_id),
+            sequence,
+            from_millis(timestamp)?,
+        );
+        writer
+            .sink
+            .write(OwnedMessage::new(header, payload))
+            .map_err(|e| e.to_string())?;
+        Ok(SS_OK)
+    })
+}
+
+/// Flush buffered messages to disk.
+///
+/// # Safety
+///
+/// `writer` must be a valid writer.
+#[no_mangle]
+pub unsafe extern "C" fn ss_writer_flush(writer: *mut SsWriter) -> i32 {
+    guard(SS_ERR, || {
+        let writer = writer.as_mut().ok_or("writer is null")?;
+        writer
+            .runtime
+            .block_on(writer.sink.flush())
+            .map_err(|e| e.to_string())?;
+        Ok::<_, String>(SS_OK)
+    })
+}
+
+/// Flush and close the writer, optionally ending the file with an End-of-Stream message.
+/// The writer is freed even on error. `writer` can be null.
+///
+/// # Safety
+///
+/// `writer` must be returned by `ss_writer_open` and not already closed.
+#[no_mangle]
+pub unsafe extern "C" fn ss_writer_close(writer: *mut SsWriter, eos: bool) -> i32 {
+    if writer.is_null() {
+        return SS_OK;
+    }
+    let SsWriter { runtime, sink } = *Box::from_raw(writer);
+    guard(SS_ERR, || {
+        runtime.block_on(sink.end(eos)).map_err(|e| e.to_string())?;
+        Ok::<_, String>(SS_OK)
+    })
+}
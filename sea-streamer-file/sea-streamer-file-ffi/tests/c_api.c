@@ -0,0 +1,116 @@
+/* Exercise the C API: write a file, then read it back, seek and rewind. */
+#include <assert.h>
+#include <inttypes.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "sea_streamer_file.h"
+
+#define CHECK(expr)                                                            \
+    do {                                                                       \
+        if (!(expr)) {                                                         \
+            fprintf(stderr, "%s:%d: %s failed: %s\n", __FILE__, __LINE__,      \
+                    #expr, ss_last_error() ? ss_last_error() : "");            \
+            return 1;                                                          \
+        }                                                                      \
+    } while (0)
+
+static const int64_t EPOCH = 1690000000000;
+
+static int write_messages(const char *path, uint64_t from, uint64_t to, bool append) {
+    SsWriter *writer = ss_writer_open(path, 1024, append);
+    CHECK(writer != NULL);
+    char payload[64];
+    for (uint64_t i = from; i < to; i++) {
+        const char *stream_key = i % 2 ? "odd" : "even";
+        int len = snprintf(payload, sizeof(payload), "hello-%" PRIu64, i);
+        CHECK(ss_writer_append(writer, stream_key, 0, i, EPOCH + (int64_t)i * 1000,
+                               (const uint8_t *)payload, (size_t)len) == SS_OK);
+    }
+    CHECK(ss_writer_flush(writer) == SS_OK);
+    CHECK(ss_writer_close(writer, true) == SS_OK);
+    return 0;
+}
+
+int main(int argc, char **argv) {
+    if (argc != 2) {
+        fprintf(stderr, "usage: %s <file>\n", argv[0]);
+        return 2;
+    }
+    const char *path = argv[1];
+    const uint64_t N = 200;
+
+    CHECK(ss_writer_open(path, 1000, false) == NULL);
+    CHECK(ss_last_error() != NULL);
+
+    CHECK(write_messages(path, 0, N / 2, false) == 0);
+    CHECK(write_messages(path, N / 2, N, true) == 0);
+
+    SsReader *reader = ss_reader_open(path);
+    CHECK(reader != NULL);
+
+    SsHeader header;
+    CHECK(ss_reader_header(reader, &header) == SS_OK);
+    CHECK(header.beacon_interval == 1024);
+    CHECK(strstr(path, header.file_name) != NULL);
+
+    SsMessage message;
+    uint64_t count = 0;
+    uint32_t beacons = 0;
+    int res;
+    while ((res = ss_reader_next(reader, &message)) == SS_OK) {
+        const char *stream_key = count % 2 ? "odd" : "even";
+        char payload[64];
+        int len = snprintf(payload, sizeof(payload), "hello-%" PRIu64, count);
+        CHECK(message.stream_key_len == strlen(stream_key));
+        CHECK(memcmp(message.stream_key, stream_key, message.stream_key_len) == 0);
+        CHECK(message.shard_id == 0);
+        CHECK(message.sequence == count);
+        CHECK(message.timestamp == EPOCH + (int64_t)count * 1000);
+        CHECK(message.payload_len == (size_t)len);
+        CHECK(memcmp(message.payload, payload, message.payload_len) == 0);
+
+        size_t num_markers = 0;
+        if (ss_reader_beacon(reader, &num_markers) != 0) {
+            beacons++;
+            for (size_t i = 0; i < num_markers; i++) {
+                SsMarker marker;
+                CHECK(ss_reader_beacon_marker(reader, i, &marker) == SS_OK);
+                CHECK(marker.sequence <= count);
+            }
+            SsMarker marker;
+            CHECK(ss_reader_beacon_marker(reader, num_markers, &marker) == SS_ERR);
+        }
+        count++;
+    }
+    CHECK(res == SS_END);
+    CHECK(count == N);
+    CHECK(beacons > 0);
+
+    /* seek into the appended part, then back */
+    CHECK(ss_reader_seek_timestamp(reader, EPOCH + 150 * 1000) == SS_OK);
+    CHECK(ss_reader_next(reader, &message) == SS_OK);
+    CHECK(message.sequence == 151);
+    CHECK(message.timestamp == EPOCH + 151 * 1000);
+
+    CHECK(ss_reader_seek_stream_timestamp(reader, "odd", 0, EPOCH + 51 * 1000) == SS_OK);
+    CHECK(ss_reader_next(reader, &message) == SS_OK);
+    CHECK(message.sequence == 52);
+
+    /* there is nothing after, except the End-of-Stream message */
+    CHECK(ss_reader_seek_timestamp(reader, EPOCH + 1000 * 1000) != SS_ERR);
+    CHECK(ss_reader_next(reader, &message) == SS_END);
+
+    CHECK(ss_reader_rewind(reader, 0) == 0);
+    CHECK(ss_reader_next(reader, &message) == SS_OK);
+    CHECK(message.sequence == 0);
+    CHECK(ss_reader_offset(reader) > 0);
+
+    ss_reader_close(reader);
+
+    CHECK(ss_reader_open("/nonexistent/file.ss") == NULL);
+    CHECK(ss_last_error() != NULL);
+
+    printf("ok\n");
+    return 0;
+}
@@ -0,0 +1,47 @@
+//! Compile and run `tests/c_api.c` against the shared library.
+#[cfg(target_os = "linux")]
+#[test]
+fn c_api() {
+    use std::{path::PathBuf, process::Command};
+
+    let manifest = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    // the cdylib is built alongside this test, in target/<profile>/deps
+    let exe = std::env::current_exe().unwrap();
+    let lib_dir = exe.parent().unwrap().to_owned();
+    assert!(
+        lib_dir.join("libsea_streamer_file_ffi.so").exists(),
+        "shared library not found in {}",
+        lib_dir.display()
+    );
+    let out_dir =
+        std::env::temp_dir().join(format!("sea-streamer-file-ffi-{}", std::process::id()));
+    std::fs::create_dir_all(&out_dir).unwrap();
+    let binary = out_dir.join("c_api");
+
+    let status = Command::new(std::env::var("CC").unwrap_or_else(|_| "cc".to_owned()))
+        .arg("-std=c99")
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg("-I")
+        .arg(manifest.join("include"))
+        .arg(manifest.join("tests/c_api.c"))
+        .arg("-L")
+        .arg(&lib_dir)
+        .arg("-lsea_streamer_file_ffi")
+        .arg("-o")
+        .arg(&binary)
+        .status()
+        .expect("Failed to run the C compiler");
+    assert!(status.success());
+
+    let output = Command::new(&binary)
+        .arg(out_dir.join("c_api.ss"))
+        .env("LD_LIBRARY_PATH", &lib_dir)
+        .output()
+        .unwrap();
+    println!("{}", String::from_utf8_lossy(&output.stdout));
+    eprintln!("{}", String::from_utf8_lossy(&output.stderr));
+    assert!(output.status.success());
+
+    std::fs::remove_dir_all(&out_dir).ok();
+}
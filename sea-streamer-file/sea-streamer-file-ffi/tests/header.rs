@@ -0,0 +1,29 @@
+//! Regenerate the C header with cbindgen, and check that `include/sea_streamer_file.h` is up to date.
+#[test]
+fn header() {
+    use std::path::PathBuf;
+
+    let manifest = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let config = cbindgen::Config::from_file(manifest.join("cbindgen.toml")).unwrap();
+    let bindings = cbindgen::Builder::new()
+        .with_crate(&manifest)
+        .with_config(config)
+        .generate()
+        .expect("Failed to generate the header");
+    let mut generated = Vec::new();
+    bindings.write(&mut generated);
+    let generated = String::from_utf8(generated).unwrap();
+
+    let path = manifest.join("include/sea_streamer_file.h");
+    let committed = std::fs::read_to_string(&path).unwrap();
+    if generated != committed {
+        if std::env::var("UPDATE_HEADER").is_ok() {
+            std::fs::write(&path, generated).unwrap();
+        } else {
+            panic!(
+                "{} is out of date; run `UPDATE_HEADER=1 cargo test --test header`",
+                path.display()
+            );
+        }
+    }
+}
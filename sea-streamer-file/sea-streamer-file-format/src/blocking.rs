@@ -0,0 +1,286 @@
+//! Blocking reader and writer of `.ss` files, on top of `std::io::Read` and `std::io::Write`.
+//!
+//! They share the same codec (see [`crate::format`]) with the async `MessageSource` and `MessageSink`
+//! of `sea-streamer-file`, but do not depend on any async runtime. There is no live streaming,
+//! seeking or file watching here; reading ends with [`CodecErr::NotEnoughBytes`] at the end of input.
+use std::{
+    collections::BTreeMap,
+    io::{ErrorKind, Read, Write},
+};
+
+use sea_streamer_types::{
+    Message as MessageTrait, MessageHeader, OwnedMessage, SeqNo, ShardId, StreamKey, Timestamp,
+};
+
+use crate::{
+    format::{decode_with, Beacon, Checksum, FormatErr, Header, Marker, Message, RunningChecksum},
+    ByteBuffer, ByteSink, Bytes, CodecErr,
+};
+
+/// A blocking file reader that demux messages and beacon
+pub struct MessageReader<R: Read> {
+    header: Header,
+    reader: R,
+    offset: u64,
+    beacon: (u32, Vec<Marker>),
+}
+
+/// A `ByteSink` over `std::io::Write`. Use a `BufWriter` to avoid blocking on every write.
+pub struct WriteSink<W: Write> {
+    writer: W,
+}
+
+/// A blocking file writer that mux messages and beacon
+pub struct MessageWriter<W: Write> {
+    sink: WriteSink<W>,
+    mux: BeaconMux,
+}
+
+/// Mux messages and beacons into a byte stream. This does no IO by itself.
+pub struct BeaconMux {
+    pub offset: u64,
+    beacon_interval: u32,
+    beacon: BTreeMap<(StreamKey, ShardId), BeaconState>,
+    beacon_count: u32,
+}
+
+/// The latest state of a stream, to be written in the next beacon.
+pub struct BeaconState {
+    pub seq_no: SeqNo,
+    pub ts: Timestamp,
+    pub running_checksum: RunningChecksum,
+}
+
+impl<R: Read> MessageReader<R> {
+    /// Creates a new message reader. First, the stream Header is read from the reader,
+    /// which is expected to be at the file's beginning.
+    pub fn new(mut reader: R) -> Result<Self, CodecErr> {
+        let header: Header = decode_with(|size| read_bytes(&mut reader, size))?;
+        if (header.beacon_interval as usize) < Header::size() {
+            return Err(CodecErr::FormatErr(FormatErr::BeaconInterval));
+        }
+        Ok(Self {
+            header,
+            reader,
+            offset: Header::size() as u64,
+            beacon: (0, Vec::new()),
+        })
+    }
+
+    pub fn file_header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Read the next message. The checksum of the payload is verified.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Message, CodecErr> {
+        let message: Message = decode_with(|size| self.demux_bytes(size))?;
+        let computed = message.compute_checksum();
+        if message.checksum != computed {
+            Err(CodecErr::FormatErr(FormatErr::ChecksumErr {
+                received: message.checksum,
+                computed,
+            }))
+        } else {
+            Ok(message)
+        }
+    }
+
+    /// Get the most recent Beacon and it's index. Unlike `MessageSource::beacon`, it is not cleared.
+    pub fn beacon(&self) -> (u32, &[Marker]) {
+        (self.beacon.0, &self.beacon.1)
+    }
+
+    #[inline]
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Read the next `size` bytes of messages, skipping over the beacons in between.
+    fn demux_bytes(&mut self, size: usize) -> Result<Bytes, CodecErr> {
+        let beacon_interval = self.header.beacon_interval as u64;
+        let mut bytes = Vec::with_capacity(size);
+        loop {
+            if self.offset > 0 && self.offset % beacon_interval == 0 {
+                let i = (self.offset / beacon_interval) as u32;
+                let reader = &mut self.reader;
+                let beacon: Beacon = decode_with(|size| read_bytes(reader, size))?;
+                self.offset += beacon.size() as u64;
+                self.beacon = (i, beacon.items);
+            }
+
+            let chunk = std::cmp::min(
+                size - bytes.len(),                                         // remaining size
+                (beacon_interval - self.offset % beacon_interval) as usize, // should not read past the next beacon
+            );
+            bytes.extend_from_slice(&read_bytes(&mut self.reader, chunk)?.bytes());
+            self.offset += chunk as u64;
+
+            if bytes.len() == size {
+                return Ok(Bytes::from_bytes(bytes));
+            }
+        }
+    }
+}
+
+impl<W: Write> WriteSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn flush(&mut self) -> Result<(), CodecErr> {
+        self.writer.flush().map_err(CodecErr::IoError)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> ByteSink for WriteSink<W> {
+    type Error = CodecErr;
+
+    fn write(&mut self, bytes: Bytes) -> Result<(), CodecErr> {
+        let res = match bytes {
+            Bytes::Empty => Ok(()),
+            Bytes::Byte(b) => self.writer.write_all(&[b]),
+            Bytes::Word(w) => self.writer.write_all(&w),
+            Bytes::Bytes(v) => self.writer.write_all(&v),
+        };
+        res.map_err(CodecErr::IoError)
+    }
+}
+
+impl<W: Write> MessageWriter<W> {
+    /// Creates a new message writer, writing the header first.
+    /// The writer is expected to be at the file's beginning.
+    ///
+    /// If the header has a key id, the payloads are expected to be encrypted already.
+    pub fn new(writer: W, header: Header) -> Result<Self, CodecErr> {
+        let beacon_interval = header.beacon_interval;
+        if (beacon_interval as usize) < Header::size() {
+            return Err(CodecErr::FormatErr(FormatErr::BeaconInterval));
+        }
+        let mut sink = WriteSink::new(writer);
+        let mut offset = header.write_to(&mut sink)?;
+        if offset == beacon_interval as usize {
+            // a very special case
+            offset += Beacon {
+                remaining_messages_bytes: 0,
+                items: Default::default(),
+            }
+            .write_to(&mut sink)?;
+        }
+        Ok(Self {
+            sink,
+            mux: BeaconMux::new(offset as u64, beacon_interval),
+        })
+    }
+
+    pub fn write(&mut self, message: OwnedMessage) -> Result<Checksum, CodecErr> {
+        self.mux.write(&mut self.sink, message)
+    }
+
+    #[inline]
+    pub fn offset(&self) -> u64 {
+        self.mux.offset
+    }
+
+    pub fn flush(&mut self) -> Result<(), CodecErr> {
+        self.sink.flush()
+    }
+
+    /// End this stream. Returns the inner writer.
+    pub fn end(mut self) -> Result<W, CodecErr> {
+        self.flush()?;
+        Ok(self.sink.into_inner())
+    }
+}
+
+impl BeaconMux {
+    pub fn new(offset: u64, beacon_interval: u32) -> Self {
+        Self {
+            offset,
+            beacon_interval,
+            beacon: Default::default(),
+            beacon_count: 0,
+        }
+    }
+
+    /// Set the state of a stream, e.g. when appending to an existing file.
+    pub fn update_stream_state(&mut self, key: (StreamKey, ShardId), state: BeaconState) {
+        self.beacon.insert(key, state);
+    }
+
+    /// Write a message, and a beacon whenever we reach a beacon interval.
+    pub fn write<S>(&mut self, sink: &mut S, message: OwnedMessage) -> Result<Checksum, S::Error>
+    where
+        S: ByteSink,
+        S::Error: From<FormatErr> + From<CodecErr>,
+    {
+        let key = (message.stream_key(), message.shard_id());
+        let (seq_no, ts) = (message.sequence(), message.timestamp());
+        let message = Message {
+            message,
+            checksum: 0,
+        };
+        let mut buffer = ByteBuffer::new();
+        let (_, checksum) = message.write_to(&mut buffer)?;
+        let entry = self.beacon.entry(key).or_insert(BeaconState {
+            seq_no,
+            ts,
+            running_checksum: RunningChecksum::new(),
+        });
+        entry.seq_no = std::cmp::max(seq_no, entry.seq_no);
+        entry.ts = std::cmp::max(ts, entry.ts);
+        entry.running_checksum.update(checksum);
+
+        while !buffer.is_empty() {
+            let chunk = self.beacon_interval as usize
+                - (self.offset % self.beacon_interval as u64) as usize;
+            let chunk: ByteBuffer = buffer.consume(std::cmp::min(chunk, buffer.size()));
+            self.offset += chunk.write_to(sink)? as u64;
+
+            if self.offset > 0 && self.offset % self.beacon_interval as u64 == 0 {
+                let num_markers = Beacon::num_markers(self.beacon_interval as usize);
+                let mut items = Vec::new();
+                // We may not have enough space to fit in all beacon for every stream.
+                // In which case, we'll round-robin among them.
+                for ((key, sid), beacon) in self
+                    .beacon
+                    .iter()
+                    .skip(self.beacon_count as usize % self.beacon.len())
+                    .chain(self.beacon.iter())
+                    .take(std::cmp::min(self.beacon.len(), num_markers))
+                {
+                    items.push(Marker {
+                        header: MessageHeader::new(key.to_owned(), *sid, beacon.seq_no, beacon.ts),
+                        running_checksum: beacon.running_checksum.crc(),
+                    });
+                }
+                let beacon_count = items.len();
+                let beacon = Beacon {
+                    remaining_messages_bytes: buffer.size() as u32,
+                    items,
+                };
+                self.offset += beacon.write_to(sink)? as u64;
+                self.beacon_count += beacon_count as u32;
+            }
+        }
+
+        Ok(checksum)
+    }
+}
+
+fn read_bytes(reader: &mut impl Read, size: usize) -> Result<Bytes, CodecErr> {
+    let mut bytes = vec![0; size];
+    match reader.read_exact(&mut bytes) {
+        Ok(()) => Ok(Bytes::from_bytes(bytes)),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Err(CodecErr::NotEnoughBytes),
+        Err(e) => Err(CodecErr::IoError(e)),
+    }
+}
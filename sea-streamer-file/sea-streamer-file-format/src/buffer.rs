@@ -1,10 +1,27 @@
-use crate::{ByteSink, ByteSource, FileErr};
+use crate::CodecErr;
 use std::{
     cmp::Ordering,
     collections::VecDeque,
-    future::{ready, Ready},
+    future::{ready, Future, Ready},
 };
 
+pub trait ByteSource {
+    type Error;
+    type Future<'a>: Future<Output = Result<Bytes, Self::Error>>
+    where
+        Self: 'a;
+
+    #[allow(clippy::needless_lifetimes)]
+    fn request_bytes<'a>(&'a mut self, size: usize) -> Self::Future<'a>;
+}
+
+pub trait ByteSink {
+    type Error;
+
+    /// This should never block.
+    fn write(&mut self, bytes: Bytes) -> Result<(), Self::Error>;
+}
+
 pub trait Appendable: Default {
     fn append(&mut self, bytes: Bytes);
 }
@@ -131,7 +148,7 @@ impl ByteBuffer {
 
 /// IO methods
 impl ByteBuffer {
-    pub fn write_to(self, sink: &mut impl ByteSink) -> Result<usize, FileErr> {
+    pub fn write_to<S: ByteSink>(self, sink: &mut S) -> Result<usize, S::Error> {
         let mut sum = 0;
         for bytes in self.buf {
             sum += bytes.len();
@@ -142,19 +159,22 @@ impl ByteBuffer {
 }
 
 impl ByteSource for ByteBuffer {
-    type Future<'a> = Ready<Result<Bytes, FileErr>>;
+    type Error = CodecErr;
+    type Future<'a> = Ready<Result<Bytes, CodecErr>>;
 
     fn request_bytes(&mut self, size: usize) -> Self::Future<'_> {
         if size <= self.size() {
             ready(Ok(self.consume(size)))
         } else {
-            ready(Err(FileErr::NotEnoughBytes))
+            ready(Err(CodecErr::NotEnoughBytes))
         }
     }
 }
 
 impl ByteSink for ByteBuffer {
-    fn write(&mut self, bytes: Bytes) -> Result<(), FileErr> {
+    type Error = CodecErr;
+
+    fn write(&mut self, bytes: Bytes) -> Result<(), CodecErr> {
         self.append(bytes);
         Ok(())
     }
@@ -317,12 +337,12 @@ impl Bytes {
 /// IO methods
 impl Bytes {
     #[inline]
-    pub async fn read_from(file: &mut impl ByteSource, size: usize) -> Result<Self, FileErr> {
+    pub async fn read_from<S: ByteSource>(file: &mut S, size: usize) -> Result<Self, S::Error> {
         file.request_bytes(size).await
     }
 
     #[inline]
-    pub fn write_to(self, sink: &mut impl ByteSink) -> Result<usize, FileErr> {
+    pub fn write_to<S: ByteSink>(self, sink: &mut S) -> Result<usize, S::Error> {
         let size = self.len();
         sink.write(self)?;
         Ok(size)
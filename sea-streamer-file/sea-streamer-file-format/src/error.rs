@@ -0,0 +1,69 @@
+use crate::format::{FormatErr, ShortStringErr, UnixTimestampErr};
+use sea_streamer_types::StreamKeyErr;
+use std::str::Utf8Error;
+use thiserror::Error;
+
+/// Error of decoding from a buffer of bytes.
+#[derive(Error, Debug, Clone, Copy)]
+pub enum DecodeErr {
+    /// The buffer is too short. At least this many bytes are needed in total.
+    #[error("Incomplete: need {0} bytes")]
+    Incomplete(usize),
+    #[error("FormatErr: {0}")]
+    FormatErr(#[source] FormatErr),
+    #[error("Utf8Error: {0}")]
+    Utf8Error(#[source] Utf8Error),
+}
+
+/// Error of reading and writing with std::io.
+#[derive(Error, Debug)]
+pub enum CodecErr {
+    #[error("IO Error: {0}")]
+    IoError(#[source] std::io::Error),
+    #[error("FormatErr: {0}")]
+    FormatErr(#[source] FormatErr),
+    #[error("Utf8Error: {0}")]
+    Utf8Error(#[source] Utf8Error),
+    #[error("Not Enough Bytes: the file might be truncated.")]
+    NotEnoughBytes,
+}
+
+impl From<FormatErr> for DecodeErr {
+    fn from(e: FormatErr) -> Self {
+        DecodeErr::FormatErr(e)
+    }
+}
+
+impl From<ShortStringErr> for DecodeErr {
+    fn from(e: ShortStringErr) -> Self {
+        DecodeErr::FormatErr(e.into())
+    }
+}
+
+impl From<UnixTimestampErr> for DecodeErr {
+    fn from(e: UnixTimestampErr) -> Self {
+        DecodeErr::FormatErr(e.into())
+    }
+}
+
+impl From<StreamKeyErr> for DecodeErr {
+    fn from(e: StreamKeyErr) -> Self {
+        DecodeErr::FormatErr(e.into())
+    }
+}
+
+impl From<FormatErr> for CodecErr {
+    fn from(e: FormatErr) -> Self {
+        CodecErr::FormatErr(e)
+    }
+}
+
+impl From<DecodeErr> for CodecErr {
+    fn from(e: DecodeErr) -> Self {
+        match e {
+            DecodeErr::Incomplete(_) => CodecErr::NotEnoughBytes,
+            DecodeErr::FormatErr(e) => CodecErr::FormatErr(e),
+            DecodeErr::Utf8Error(e) => CodecErr::Utf8Error(e),
+        }
+    }
+}
@@ -0,0 +1,878 @@
+//! The SeaStreamer file format is a container format designed to be seekable.
+//! It does not concerns what format the payload is encoded in.
+//! It has internal checksum to ensure integrity.
+//! It is a binary file format, but is readable with a plain text editor (if the payload is UTF-8).
+//!
+//! There is a header. Every N bytes there will be a Beacon summarizing the streams so far.
+//! A message can be spliced by one or more beacons.
+//!
+//! ```ignore
+//! +-----------------~-----------------+
+//! |               Header              |
+//! +-----------------~-----------------+
+//! |               Message             |
+//! +-----------------~-----------------+
+//! |               Message ...         |
+//! +-----------------~-----------------+
+//! |               Beacon              |
+//! +-----------------~-----------------+
+//! |               Message ...         |
+//! +-----------------~-----------------+
+//!
+//! Header is:
+//! +--------+--------+---------+---~---+----~----+------+
+//! |  0x53  |  0x73  | version | meta  | padding | 0x0D |
+//! +--------+--------+---------+---~---+----~----+------+
+//!
+//! Header meta v1 is always 128 - 3 bytes long. Padding is stuffed with 0, ending with a \n.
+//!
+//! Header meta v1 is:
+//! +----~----+----+----+----+----+----+----+----+----+----+----+----+----+----~---+
+//! |file name|        created at (ms)        |  beacon interval  |  key id (opt)  |
+//! +----~----+----+----+----+----+----+----+----+----+----+----+----+----+----~---+
+//!
+//! The file name and key id are short strings, i.e. prefixed by a byte of length. The key id is
+//! only present if the message payloads are encrypted; otherwise it falls into the padding,
+//! which reads as an empty string.
+//!
+//! Message is:
+//! +---~----+---+----+---+----+----~----+-----+----+------+
+//! | header | size of payload | payload | checksum | 0x0D |
+//! +---~----+---+----+---+----+----~----+-----+----+------+
+//!
+//! Message spliced:
+//! +----~----+----~---+--------~-------+
+//! | message | beacon | message cont'd |
+//! +----~----+----~---+--------~-------+
+//!
+//! Beacon is:
+//! +------+-----+------+-----+------+--------------+----~----+-----+------+
+//! | 0x0D | remaining message bytes | num of items |  item   | ... | 0x0D |
+//! +------+-----+------+-----+------+--------------+----~----+-----+------+
+//!
+//! Message header is same as beacon item:
+//! +-------------------+--------~---------+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//! | len of stream key | stream key chars |   shard id    |     seq no    |   timestamp   |
+//! +-------------------+--------~---------+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//!
+//! Except that beacon item has an extra tail:
+//! +---------+--------+
+//! | running checksum |
+//! +---------+--------+
+//! ```
+//!
+//! All numbers are encoded in big endian. There are 0x0D in places so that it will not blow up
+//! plain text editors. And it's semi-human-readable.
+//!
+//! A SeaStreamer file can be terminated by a End-of-Stream Message,
+//! with the stream key `SEA_STREAMER_INTERNAL` and payload `EOS`.
+
+use crate::{
+    crc::{crc16_cdma2000, crc_update},
+    ByteSink, ByteSource, Bytes, DecodeErr,
+};
+use sea_streamer_types::{
+    Buffer, Message as MessageTrait, OwnedMessage, ShardId, StreamKey, StreamKeyErr, Timestamp,
+};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct HeaderV1 {
+    pub file_name: String,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_timestamp"))]
+    pub created_at: Timestamp,
+    pub beacon_interval: u32,
+    /// The id of the key, if the message payloads are encrypted. See `sea_streamer_file::crypto`.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub key_id: Option<String>,
+}
+
+pub type Header = HeaderV1;
+
+pub const HEADER_SIZE: usize = 128;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct MessageHeader(pub sea_streamer_types::MessageHeader);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub message: OwnedMessage,
+    pub checksum: u16,
+}
+
+#[cfg(feature = "serde_json")]
+#[derive(Serialize)]
+pub struct MessageJson<'a> {
+    pub header: &'a sea_streamer_types::MessageHeader,
+    pub payload: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Beacon {
+    pub remaining_messages_bytes: u32,
+    pub items: Vec<Marker>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Marker {
+    pub header: sea_streamer_types::MessageHeader,
+    pub running_checksum: Checksum,
+}
+
+pub struct RunningChecksum {
+    crc: u16,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[repr(transparent)]
+pub struct Checksum(pub u16);
+
+// `ShortString` definition inside
+pub use short_string::*;
+
+/// Timestamp in seconds
+#[repr(transparent)]
+pub struct UnixTimestamp(pub Timestamp);
+
+#[repr(transparent)]
+pub struct U64(pub u64);
+
+#[repr(transparent)]
+pub struct U32(pub u32);
+
+#[repr(transparent)]
+pub struct U16(pub u16);
+
+#[derive(Error, Debug, Clone, Copy)]
+pub enum FormatErr {
+    #[error("Byte mark mismatch")]
+    ByteMark,
+    #[error("Version mismatch")]
+    Version,
+    #[error("ShortStringErr: {0}")]
+    ShortStringErr(#[from] ShortStringErr),
+    #[error("UnixTimestampErr: {0}")]
+    UnixTimestampErr(#[from] UnixTimestampErr),
+    #[error("StreamKeyErr: {0}")]
+    StreamKeyErr(#[from] StreamKeyErr),
+    #[error("TooManyBeacon")]
+    TooManyBeacon,
+    #[error("Header too long")]
+    HeaderTooLong,
+    #[error("Checksum error: received {received}, computed {computed}")]
+    ChecksumErr { received: u16, computed: u16 },
+    #[error("Beacon interval must be at least the header size")]
+    BeaconInterval,
+}
+
+/// A cursor over a buffer of bytes, from which values are decoded.
+pub struct Decoder<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+/// A value that can be decoded from a buffer of bytes, without any IO.
+pub trait Decode: Sized {
+    /// Decode a value from the cursor. If the buffer is too short,
+    /// returns [`DecodeErr::Incomplete`] with the total number of bytes needed so far.
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeErr>;
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    /// Number of bytes decoded so far.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Take the next `size` bytes.
+    pub fn take(&mut self, size: usize) -> Result<&'a [u8], DecodeErr> {
+        let end = self.pos + size;
+        if end > self.buf.len() {
+            return Err(DecodeErr::Incomplete(end));
+        }
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub fn byte(&mut self) -> Result<u8, DecodeErr> {
+        Ok(self.take(1)?[0])
+    }
+}
+
+/// Decode a value, fetching exactly the missing bytes with `fetch` until it is complete.
+/// This never reads past the end of the value.
+pub fn decode_with<T, E, F>(mut fetch: F) -> Result<T, E>
+where
+    T: Decode,
+    E: From<DecodeErr>,
+    F: FnMut(usize) -> Result<Bytes, E>,
+{
+    let mut buf = Vec::new();
+    loop {
+        match T::decode(&mut Decoder::new(&buf)) {
+            Ok(value) => return Ok(value),
+            Err(DecodeErr::Incomplete(size)) => {
+                debug_assert!(size > buf.len());
+                buf.extend_from_slice(&fetch(size - buf.len())?.bytes());
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Same as [`decode_with`], but fetch bytes from a [`ByteSource`].
+pub async fn decode_from<T, S>(file: &mut S) -> Result<T, S::Error>
+where
+    T: Decode,
+    S: ByteSource,
+    S::Error: From<DecodeErr>,
+{
+    let mut buf = Vec::new();
+    loop {
+        match T::decode(&mut Decoder::new(&buf)) {
+            Ok(value) => return Ok(value),
+            Err(DecodeErr::Incomplete(size)) => {
+                debug_assert!(size > buf.len());
+                let bytes = file.request_bytes(size - buf.len()).await?;
+                buf.extend_from_slice(&bytes.bytes());
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+impl Decode for HeaderV1 {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeErr> {
+        let bytes = decoder.take(3)?;
+        if bytes[0] != 0x53 {
+            return Err(FormatErr::ByteMark.into());
+        }
+        if bytes[1] != 0x73 {
+            return Err(FormatErr::ByteMark.into());
+        }
+        if bytes[2] != 0x01 {
+            return Err(FormatErr::Version.into());
+        }
+        let file_name = ShortString::decode(decoder)?.string();
+        let created_at = UnixTimestamp::decode(decoder)?.0;
+        let beacon_interval = U32::decode(decoder)?.0;
+        let mut ret = Self {
+            file_name,
+            created_at,
+            beacon_interval,
+            key_id: None,
+        };
+        let mut padding_size = ret.padding_size();
+        if padding_size > 1 {
+            // there is room for a key id; an empty string is just padding
+            let key_id = ShortString::decode(decoder)?.string();
+            if key_id.is_empty() {
+                padding_size -= 1;
+            } else {
+                ret.key_id = Some(key_id);
+                padding_size = ret.padding_size();
+            }
+        }
+        let _padding = decoder.take(padding_size)?;
+        Ok(ret)
+    }
+}
+
+impl HeaderV1 {
+    pub async fn read_from<S: ByteSource>(file: &mut S) -> Result<Self, S::Error>
+    where
+        S::Error: From<DecodeErr>,
+    {
+        decode_from(file).await
+    }
+
+    pub fn write_to<S: ByteSink>(self, sink: &mut S) -> Result<usize, S::Error>
+    where
+        S::Error: From<FormatErr>,
+    {
+        let mut sum = 0;
+        sum += Bytes::Byte(0x53).write_to(sink)?;
+        sum += Bytes::Byte(0x73).write_to(sink)?;
+        sum += Bytes::Byte(0x01).write_to(sink)?;
+        if self.meta_size() >= HEADER_SIZE - 3 {
+            return Err(FormatErr::HeaderTooLong.into());
+        }
+        let padding_size = self.padding_size();
+        sum += ShortString::new(self.file_name)
+            .map_err(FormatErr::from)?
+            .write_to(sink)?;
+        sum += UnixTimestamp(self.created_at).write_to(sink)?;
+        sum += U32(self.beacon_interval).write_to(sink)?;
+        if let Some(key_id) = self.key_id {
+            sum += ShortString::new(key_id)
+                .map_err(FormatErr::from)?
+                .write_to(sink)?;
+        }
+        sum += Bytes::Bytes(vec![0; padding_size - 1]).write_to(sink)?;
+        sum += Bytes::Byte(0x0D).write_to(sink)?;
+        Ok(sum)
+    }
+
+    #[inline]
+    pub const fn size() -> usize {
+        HEADER_SIZE
+    }
+
+    pub fn padding_size(&self) -> usize {
+        HEADER_SIZE - 3 - self.meta_size()
+    }
+
+    fn meta_size(&self) -> usize {
+        ShortString::size_of(&self.file_name)
+            + UnixTimestamp::size()
+            + U32::size()
+            + self
+                .key_id
+                .as_deref()
+                .map(ShortString::size_of)
+                .unwrap_or(0)
+    }
+}
+
+impl Decode for Message {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeErr> {
+        let header = MessageHeader::decode(decoder)?.0;
+        let size = U32::decode(decoder)?.0;
+        let payload = decoder.take(size as usize)?.to_vec();
+        let checksum = U16::decode(decoder)?.0;
+        let message = OwnedMessage::new(header, payload);
+        _ = decoder.byte()?;
+        Ok(Self { message, checksum })
+    }
+}
+
+impl Message {
+    pub async fn read_from<S: ByteSource>(file: &mut S) -> Result<Self, S::Error>
+    where
+        S::Error: From<DecodeErr>,
+    {
+        decode_from(file).await
+    }
+
+    pub fn write_to<S: ByteSink>(self, sink: &mut S) -> Result<(usize, Checksum), S::Error>
+    where
+        S::Error: From<FormatErr>,
+    {
+        let mut sum = 0;
+        let (header, payload) = self.message.take();
+        sum += MessageHeader(header).write_to(sink)?;
+        let size = payload.len().try_into().expect("Message too big");
+        sum += U32(size).write_to(sink)?;
+        let checksum = crc16_cdma2000(&payload);
+        sum += Bytes::Bytes(payload).write_to(sink)?;
+        sum += U16(checksum).write_to(sink)?;
+        sum += Bytes::Byte(0x0D).write_to(sink)?;
+        Ok((sum, Checksum(checksum)))
+    }
+
+    pub fn size(&self) -> usize {
+        MessageHeader::size_of(self.message.header())
+            + U32::size()
+            + self.message.message().size()
+            + U16::size()
+            + 1
+    }
+
+    pub fn compute_checksum(&self) -> u16 {
+        crc16_cdma2000(self.message.message().as_bytes())
+    }
+}
+
+impl Beacon {
+    pub fn empty() -> Self {
+        Self {
+            remaining_messages_bytes: 0,
+            items: Vec::new(),
+        }
+    }
+
+    pub async fn read_from<S: ByteSource>(file: &mut S) -> Result<Self, S::Error>
+    where
+        S::Error: From<DecodeErr>,
+    {
+        decode_from(file).await
+    }
+
+    pub fn write_to<S: ByteSink>(self, sink: &mut S) -> Result<usize, S::Error>
+    where
+        S::Error: From<FormatErr>,
+    {
+        let mut sum = 0;
+        sum += Bytes::Byte(0x0D).write_to(sink)?;
+        if self.items.len() > u8::MAX as usize {
+            return Err(FormatErr::TooManyBeacon.into());
+        }
+        sum += U32(self.remaining_messages_bytes).write_to(sink)?;
+        sum += Bytes::Byte(self.items.len().try_into().unwrap()).write_to(sink)?;
+        for item in self.items {
+            sum += item.write_to(sink)?;
+        }
+        sum += Bytes::Byte(0x0D).write_to(sink)?;
+        Ok(sum)
+    }
+
+    pub fn size(&self) -> usize {
+        let mut size = 1 + U32::size() + 1;
+        for item in self.items.iter() {
+            size += item.size();
+        }
+        size + 1
+    }
+
+    /// Calculate the maximum number of markers that can be fitted in the given space
+    pub fn max_markers(space: usize) -> usize {
+        if space < 7 {
+            return 0;
+        }
+        std::cmp::min(u8::MAX as usize, (space - 7) / Marker::max_size())
+    }
+
+    /// The reasonable number of markers to use, given the beacon_interval
+    pub fn num_markers(beacon_interval: usize) -> usize {
+        Self::max_markers(beacon_interval) / 2
+    }
+}
+
+impl Decode for Beacon {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeErr> {
+        _ = decoder.byte()?;
+        let remaining_messages_bytes = U32::decode(decoder)?.0;
+        let mut items = Vec::new();
+        let num = decoder.byte()?;
+        for _ in 0..num {
+            items.push(Marker::decode(decoder)?);
+        }
+        _ = decoder.byte()?;
+        Ok(Self {
+            remaining_messages_bytes,
+            items,
+        })
+    }
+}
+
+impl Decode for Marker {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeErr> {
+        let header = MessageHeader::decode(decoder)?.0;
+        let running_checksum = Checksum(U16::decode(decoder)?.0);
+        Ok(Self {
+            header,
+            running_checksum,
+        })
+    }
+}
+
+impl Marker {
+    pub async fn read_from<S: ByteSource>(file: &mut S) -> Result<Self, S::Error>
+    where
+        S::Error: From<DecodeErr>,
+    {
+        decode_from(file).await
+    }
+
+    pub fn write_to<S: ByteSink>(self, sink: &mut S) -> Result<usize, S::Error>
+    where
+        S::Error: From<FormatErr>,
+    {
+        let mut sum = 0;
+        sum += MessageHeader(self.header).write_to(sink)?;
+        sum += U16(self.running_checksum.0).write_to(sink)?;
+        Ok(sum)
+    }
+
+    pub fn size(&self) -> usize {
+        MessageHeader::size_of(&self.header) + U16::size()
+    }
+
+    pub fn max_size() -> usize {
+        MessageHeader::max_size() + 2
+    }
+}
+
+impl Decode for MessageHeader {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeErr> {
+        use sea_streamer_types::MessageHeader as Header;
+        let stream_key = StreamKey::new(ShortString::decode(decoder)?.string())?;
+        let shard_id = ShardId::new(U64::decode(decoder)?.0);
+        let sequence = U64::decode(decoder)?.0;
+        let timestamp = UnixTimestamp::decode(decoder)?.0;
+        Ok(Self(Header::new(stream_key, shard_id, sequence, timestamp)))
+    }
+}
+
+impl MessageHeader {
+    pub async fn read_from<S: ByteSource>(file: &mut S) -> Result<Self, S::Error>
+    where
+        S::Error: From<DecodeErr>,
+    {
+        decode_from(file).await
+    }
+
+    pub fn write_to<S: ByteSink>(self, sink: &mut S) -> Result<usize, S::Error>
+    where
+        S::Error: From<FormatErr>,
+    {
+        let mut sum = 0;
+        let h = self.0;
+        sum += ShortString::new(h.stream_key().name().to_owned())
+            .map_err(FormatErr::from)?
+            .write_to(sink)?;
+        sum += U64(h.shard_id().id()).write_to(sink)?;
+        sum += U64(*h.sequence()).write_to(sink)?;
+        sum += UnixTimestamp(*h.timestamp()).write_to(sink)?;
+        Ok(sum)
+    }
+
+    pub fn size(&self) -> usize {
+        Self::size_of(&self.0)
+    }
+
+    pub fn size_of(header: &sea_streamer_types::MessageHeader) -> usize {
+        ShortString::size_of(header.stream_key().name())
+            + U64::size()
+            + U64::size()
+            + UnixTimestamp::size()
+    }
+
+    pub fn max_size() -> usize {
+        ShortString::max_size() + U64::size() + U64::size() + UnixTimestamp::size()
+    }
+}
+
+mod short_string {
+    use super::*;
+
+    #[derive(Error, Debug, Clone, Copy)]
+    pub enum ShortStringErr {
+        #[error("String too long")]
+        StringTooLong,
+    }
+
+    #[repr(transparent)]
+    pub struct ShortString(String); // I want to hide the inner String
+
+    impl ShortString {
+        pub fn new(string: String) -> Result<Self, ShortStringErr> {
+            if string.len() <= u8::MAX as usize {
+                Ok(Self(string))
+            } else {
+                Err(ShortStringErr::StringTooLong)
+            }
+        }
+
+        pub fn string(self) -> String {
+            self.0
+        }
+
+        pub async fn read_from<S: ByteSource>(file: &mut S) -> Result<Self, S::Error>
+        where
+            S::Error: From<DecodeErr>,
+        {
+            decode_from(file).await
+        }
+
+        pub fn write_to<S: ByteSink>(self, sink: &mut S) -> Result<usize, S::Error> {
+            let mut sum = 0;
+            sum += Bytes::Byte(self.0.len() as u8).write_to(sink)?;
+            sum += Bytes::Bytes(self.0.into_bytes()).write_to(sink)?;
+            Ok(sum)
+        }
+
+        pub fn size(&self) -> usize {
+            1 + self.0.len()
+        }
+
+        pub fn size_of(string: &str) -> usize {
+            1 + string.len()
+        }
+
+        pub fn max_size() -> usize {
+            1 + u8::MAX as usize
+        }
+    }
+
+    impl Decode for ShortString {
+        fn decode(decoder: &mut Decoder) -> Result<Self, DecodeErr> {
+            let len = decoder.byte()?;
+            let bytes = decoder.take(len as usize)?;
+            Ok(Self(
+                std::str::from_utf8(bytes)
+                    .map_err(DecodeErr::Utf8Error)?
+                    .to_owned(),
+            ))
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, Copy)]
+pub enum UnixTimestampErr {
+    #[error("Out of range")]
+    OutOfRange,
+}
+
+impl Decode for UnixTimestamp {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeErr> {
+        let ts = U64::decode(decoder)?.0;
+        Ok(Self(
+            Timestamp::from_unix_timestamp_nanos(ts as i128 * 1_000_000)
+                .map_err(|_| UnixTimestampErr::OutOfRange)?,
+        ))
+    }
+}
+
+impl UnixTimestamp {
+    pub async fn read_from<S: ByteSource>(file: &mut S) -> Result<Self, S::Error>
+    where
+        S::Error: From<DecodeErr>,
+    {
+        decode_from(file).await
+    }
+
+    pub fn write_to<S: ByteSink>(self, sink: &mut S) -> Result<usize, S::Error> {
+        U64((self.0.unix_timestamp_nanos() / 1_000_000)
+            .try_into()
+            .expect("Should not be negative"))
+        .write_to(sink)
+    }
+
+    pub fn size() -> usize {
+        U64::size()
+    }
+}
+
+/// CRC16/CDMA2000
+impl RunningChecksum {
+    pub fn new() -> Self {
+        Self { crc: Self::init() }
+    }
+
+    #[inline]
+    pub fn init() -> u16 {
+        0xFFFF
+    }
+
+    pub fn resume(crc: Checksum) -> Self {
+        Self { crc: crc.0 }
+    }
+
+    pub fn update(&mut self, checksum: Checksum) {
+        let bytes = checksum.0.to_be_bytes();
+        self.byte(bytes[0]);
+        self.byte(bytes[1]);
+    }
+
+    fn byte(&mut self, byte: u8) {
+        self.crc = crc_update(self.crc, &[byte]);
+    }
+
+    pub fn crc(&self) -> Checksum {
+        Checksum(self.crc)
+    }
+}
+
+impl Default for RunningChecksum {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Error, Debug, Clone, Copy)]
+pub enum U64Err {}
+
+impl Decode for U64 {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeErr> {
+        let b = decoder.take(8)?;
+        Ok(Self(u64::from_be_bytes([
+            b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7],
+        ])))
+    }
+}
+
+impl U64 {
+    pub async fn read_from<S: ByteSource>(file: &mut S) -> Result<Self, S::Error>
+    where
+        S::Error: From<DecodeErr>,
+    {
+        decode_from(file).await
+    }
+
+    pub fn write_to<S: ByteSink>(self, sink: &mut S) -> Result<usize, S::Error> {
+        let bytes = self.0.to_be_bytes().to_vec();
+        assert_eq!(bytes.len(), 8);
+        Bytes::from_bytes(bytes).write_to(sink)
+    }
+
+    pub fn size() -> usize {
+        8
+    }
+}
+
+#[derive(Error, Debug, Clone, Copy)]
+pub enum U32Err {}
+
+impl Decode for U32 {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeErr> {
+        let b = decoder.take(4)?;
+        Ok(Self(u32::from_be_bytes([b[0], b[1], b[2], b[3]])))
+    }
+}
+
+impl U32 {
+    pub async fn read_from<S: ByteSource>(file: &mut S) -> Result<Self, S::Error>
+    where
+        S::Error: From<DecodeErr>,
+    {
+        decode_from(file).await
+    }
+
+    pub fn write_to<S: ByteSink>(self, sink: &mut S) -> Result<usize, S::Error> {
+        let bytes = self.0.to_be_bytes().to_vec();
+        assert_eq!(bytes.len(), 4);
+        Bytes::from_bytes(bytes).write_to(sink)
+    }
+
+    pub fn size() -> usize {
+        4
+    }
+}
+
+#[derive(Error, Debug, Clone, Copy)]
+pub enum U16Err {}
+
+impl Decode for U16 {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeErr> {
+        let b = decoder.take(2)?;
+        Ok(Self(u16::from_be_bytes([b[0], b[1]])))
+    }
+}
+
+impl U16 {
+    pub async fn read_from<S: ByteSource>(file: &mut S) -> Result<Self, S::Error>
+    where
+        S::Error: From<DecodeErr>,
+    {
+        decode_from(file).await
+    }
+
+    pub fn write_to<S: ByteSink>(self, sink: &mut S) -> Result<usize, S::Error> {
+        let bytes = self.0.to_be_bytes().to_vec();
+        assert_eq!(bytes.len(), 2);
+        Bytes::from_bytes(bytes).write_to(sink)
+    }
+
+    pub fn size() -> usize {
+        2
+    }
+}
+
+#[cfg(feature = "serde")]
+pub fn serialize_timestamp<S>(ts: &Timestamp, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_str(
+        ts.format(sea_streamer_types::TIMESTAMP_FORMAT)
+            .unwrap()
+            .as_str(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use sea_streamer_types::Buffer;
+
+    use super::*;
+
+    #[test]
+    fn test_running_checksum() {
+        let mut checksum = RunningChecksum::new();
+        checksum.byte(b'1');
+        checksum.byte(b'2');
+        checksum.byte(b'3');
+        checksum.byte(b'4');
+        checksum.byte(b'5');
+        checksum.byte(b'6');
+        checksum.byte(b'7');
+        checksum.byte(b'8');
+        checksum.byte(b'9');
+        assert_eq!(checksum.crc(), Checksum(0x4C06));
+        checksum.byte(b'a');
+        checksum.byte(b'b');
+        checksum.byte(b'c');
+        checksum.byte(b'd');
+        assert_eq!(checksum.crc(), Checksum(0xA106));
+        assert_eq!(
+            checksum.crc().0,
+            crc16_cdma2000(&"123456789abcd".into_bytes())
+        );
+    }
+
+    #[test]
+    fn test_decode_incomplete() {
+        let header = MessageHeader(sea_streamer_types::MessageHeader::new(
+            StreamKey::new("hello").unwrap(),
+            ShardId::new(1),
+            2,
+            Timestamp::from_unix_timestamp(1_700_000_000).unwrap(),
+        ));
+        let message = Message {
+            message: OwnedMessage::new(header.0.clone(), b"world".to_vec()),
+            checksum: 0,
+        };
+        let mut buffer = crate::ByteBuffer::new();
+        let (size, checksum) = message.clone().write_to(&mut buffer).unwrap();
+        let bytes = buffer.consume::<Bytes>(size).bytes();
+
+        // the length byte of the stream key, the stream key, shard id, sequence, timestamp,
+        // payload size, payload, checksum and finally the 0x0D
+        let mut steps = Vec::new();
+        let mut len = 0;
+        while let Err(DecodeErr::Incomplete(need)) =
+            Message::decode(&mut Decoder::new(&bytes[..len]))
+        {
+            steps.push(need);
+            len = need;
+        }
+        assert_eq!(steps, vec![1, 6, 14, 22, 30, 34, 39, 41, 42]);
+        assert_eq!(size, 42);
+
+        let mut decoder = Decoder::new(&bytes);
+        let decoded = Message::decode(&mut decoder).unwrap();
+        assert_eq!(decoder.position(), size);
+        assert_eq!(decoded.message, message.message);
+        assert_eq!(decoded.checksum, checksum.0);
+
+        let mut requests = Vec::new();
+        let mut offset = 0;
+        let decoded: Message = decode_with(|n| {
+            requests.push(n);
+            offset += n;
+            Ok::<_, DecodeErr>(Bytes::from_slice(&bytes[offset - n..offset]))
+        })
+        .unwrap();
+        assert_eq!(decoded.message, message.message);
+        assert_eq!(requests.iter().sum::<usize>(), size);
+    }
+
+    #[test]
+    fn test_num_markers() {
+        assert_eq!(Beacon::num_markers(640), 1);
+        // we can only fit 1 marker in 1kb
+        assert_eq!(Beacon::num_markers(1024), 1);
+    }
+}
@@ -0,0 +1,34 @@
+//! ### `sea-streamer-file-format`: SeaStreamer File Format
+//!
+//! The codec of `.ss` (sea-stream) files, without any async runtime. To learn more about the file format, read
+//! [`src/format.rs`](https://github.com/SeaQL/sea-streamer/blob/main/sea-streamer-file/sea-streamer-file-format/src/format.rs).
+//!
+//! Decoding is sans-IO: a [`format::Decode`] type is decoded from a buffer of bytes, and if the buffer is too short,
+//! [`DecodeErr::Incomplete`] tells how many bytes are needed. The async `MessageSource` of `sea-streamer-file`
+//! and the [`blocking::MessageReader`] here are both built on the same decoders.
+//!
+//! The `blocking` module provides `MessageReader` and `MessageWriter` over `std::io::Read` and `std::io::Write`,
+//! for simple tools and non-async programs:
+//!
+//! ```ignore
+//! let file = std::io::BufReader::new(std::fs::File::open("capture.ss")?);
+//! let mut reader = MessageReader::new(file)?;
+//! loop {
+//!     match reader.next() {
+//!         Ok(message) => println!("{:?}", message.message.header()),
+//!         Err(CodecErr::NotEnoughBytes) => break,
+//!         Err(e) => return Err(e.into()),
+//!     }
+//! }
+//! ```
+//!
+//! Encrypted payloads are read and written as they are, decryption and encryption are done by `sea-streamer-file`,
+//! whose `blocking` module wraps the reader and writer here.
+pub mod blocking;
+mod buffer;
+mod crc;
+mod error;
+pub mod format;
+
+pub use buffer::*;
+pub use error::*;
@@ -0,0 +1,121 @@
+// cargo test --test blocking -- --nocapture
+#[test]
+fn blocking_sample() -> Result<(), Box<dyn std::error::Error>> {
+    use sea_streamer_file_format::{blocking::MessageReader, CodecErr};
+    use sea_streamer_types::Message;
+    use std::{fs::File, io::BufReader};
+
+    let file = BufReader::new(File::open("../tests/sample-1.ss")?);
+    let mut reader = MessageReader::new(file)?;
+    let mut seq = Vec::new();
+    loop {
+        match reader.next() {
+            Ok(m) => {
+                if m.message.stream_key().name() == "event" {
+                    seq.push(m.message.sequence());
+                }
+            }
+            Err(CodecErr::NotEnoughBytes) => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    assert_eq!(seq, (1..=22).collect::<Vec<_>>());
+
+    // a truncated file ends early, without panicking
+    let bytes = std::fs::read("../tests/sample-1.ss")?;
+    let mut reader = MessageReader::new(&bytes[..bytes.len() / 2])?;
+    let mut count = 0;
+    loop {
+        match reader.next() {
+            Ok(_) => count += 1,
+            Err(CodecErr::NotEnoughBytes) => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    assert!(count > 0);
+    assert!(matches!(
+        MessageReader::new(&bytes[..10]),
+        Err(CodecErr::NotEnoughBytes)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn blocking_loopback() -> Result<(), Box<dyn std::error::Error>> {
+    use sea_streamer_file_format::{
+        blocking::{MessageReader, MessageWriter},
+        format::Header,
+        CodecErr,
+    };
+    use sea_streamer_types::{
+        Buffer, Message, MessageHeader, OwnedMessage, ShardId, StreamKey, Timestamp,
+    };
+    use std::{fs::File, io::BufReader};
+
+    let now = Timestamp::now_utc();
+    let now = now.replace_millisecond(now.millisecond())?;
+    let header = Header {
+        file_name: "blocking".to_owned(),
+        created_at: now,
+        beacon_interval: 1024,
+        key_id: None,
+    };
+    let message = |i: u64| {
+        let key = StreamKey::new(if i % 2 == 0 { "hello" } else { "world" }).unwrap();
+        let header = MessageHeader::new(key, ShardId::new(0), i, now);
+        OwnedMessage::new(
+            header,
+            format!("message-{i}-{}", "x".repeat(i as usize)).into_bytes(),
+        )
+    };
+
+    let mut writer = MessageWriter::new(Vec::new(), header.clone())?;
+    for i in 0..100 {
+        writer.write(message(i))?;
+    }
+    let offset = writer.offset();
+    let bytes = writer.end()?;
+    assert_eq!(bytes.len() as u64, offset);
+    assert!(bytes.len() > 5 * 1024);
+
+    let mut reader = MessageReader::new(bytes.as_slice())?;
+    assert_eq!(reader.file_header(), &header);
+    for i in 0..100 {
+        let m = reader.next()?;
+        assert_eq!(m.message.header(), message(i).header());
+        assert_eq!(
+            m.message.message().as_bytes(),
+            message(i).message().as_bytes()
+        );
+    }
+    assert!(reader.beacon().0 > 0);
+    assert!(!reader.beacon().1.is_empty());
+    assert!(matches!(reader.next(), Err(CodecErr::NotEnoughBytes)));
+
+    // re-writing a file gives the same bytes, beacons included
+    let bytes = std::fs::read("../tests/sample-1.ss")?;
+    let mut reader = MessageReader::new(BufReader::new(File::open("../tests/sample-1.ss")?))?;
+    let mut writer = MessageWriter::new(Vec::new(), reader.file_header().clone())?;
+    loop {
+        match reader.next() {
+            Ok(m) => writer.write(m.message)?,
+            Err(CodecErr::NotEnoughBytes) => break,
+            Err(e) => return Err(e.into()),
+        };
+    }
+    assert_eq!(writer.end()?, bytes);
+
+    assert!(matches!(
+        MessageWriter::new(
+            Vec::new(),
+            Header {
+                beacon_interval: 16,
+                ..header
+            }
+        ),
+        Err(CodecErr::FormatErr(_))
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,115 @@
+//! This program converts a binary SeaStreamer .ss file into Apache Arrow IPC or Parquet.
+//!
+//! ```ignore
+//! converter --file capture.ss --output capture.parquet --payload json --chunk-size 1000000
+//! ```
+use anyhow::{anyhow, Result};
+use sea_streamer_file::{
+    converter::{convert, ConverterOptions, OutputFormat, PayloadFormat, DEFAULT_BATCH_SIZE},
+    FileId,
+};
+use sea_streamer_types::StreamKey;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+struct Args {
+    #[structopt(long, help = "Convert this file")]
+    file: FileId,
+    #[structopt(
+        long,
+        help = "Output path. With --chunk-size, a chunk index is appended to the file stem"
+    )]
+    output: PathBuf,
+    #[structopt(
+        long,
+        help = "The output format: `arrow` or `parquet`. If unset, guessed from the output extension"
+    )]
+    format: Option<Format>,
+    #[structopt(
+        long,
+        help = "How to store the payload: `binary`, `utf8` or `json`",
+        default_value = "binary"
+    )]
+    payload: Payload,
+    #[structopt(long, help = "Number of rows per record batch", default_value = "1024")]
+    batch_size: usize,
+    #[structopt(long, help = "Start a new output file every N rows")]
+    chunk_size: Option<usize>,
+    #[structopt(
+        long,
+        help = "Only convert these streams, comma separated",
+        use_delimiter = true
+    )]
+    stream_key: Vec<StreamKey>,
+}
+
+struct Format(OutputFormat);
+struct Payload(PayloadFormat);
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+
+    let Args {
+        file,
+        output,
+        format,
+        payload,
+        batch_size,
+        chunk_size,
+        stream_key,
+    } = Args::from_args();
+
+    let format = match format {
+        Some(Format(format)) => format,
+        None => match output.extension().and_then(|e| e.to_str()) {
+            Some("parquet") => OutputFormat::Parquet,
+            Some("arrow" | "feather" | "ipc") => OutputFormat::ArrowIpc,
+            _ => return Err(anyhow!("Please specify --format")),
+        },
+    };
+
+    let mut options = ConverterOptions::default();
+    options
+        .set_output_format(format)
+        .set_payload_format(payload.0)
+        .set_batch_size(if batch_size > 0 {
+            batch_size
+        } else {
+            DEFAULT_BATCH_SIZE
+        })
+        .set_chunk_size(chunk_size)
+        .set_stream_keys(stream_key);
+
+    for path in convert(file, output, &options).await? {
+        log::info!("Written {}", path.display());
+    }
+
+    Ok(())
+}
+
+impl std::str::FromStr for Format {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "arrow" | "ipc" => Ok(Self(OutputFormat::ArrowIpc)),
+            "parquet" => Ok(Self(OutputFormat::Parquet)),
+            _ => Err("Invalid Format"),
+        }
+    }
+}
+
+impl std::str::FromStr for Payload {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "binary" => Ok(Self(PayloadFormat::Binary)),
+            "utf8" => Ok(Self(PayloadFormat::Utf8)),
+            "json" => Ok(Self(PayloadFormat::Json)),
+            _ => Err("Invalid Payload Format"),
+        }
+    }
+}
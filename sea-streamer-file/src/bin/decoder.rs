@@ -8,6 +8,9 @@
 //! # beacon
 //! ```
 //!
+//! Encrypted files can be decoded with `--key-file`, which requires the `encryption` feature.
+//! See [`sea_streamer_file::crypto::KeyRing`] for the key file format.
+//!
 //! Example `ndjson` format:
 //!
 //! ```ignore
@@ -17,10 +20,11 @@
 //! ```
 use anyhow::Result;
 use sea_streamer_file::{
-    format::MessageJson, is_end_of_stream, FileErr, FileId, MessageSource, StreamMode,
+    crypto::KeyRing, format::MessageJson, is_end_of_stream, FileErr, FileId, MessageSource,
+    StreamMode,
 };
 use sea_streamer_types::{Buffer, Message, TIMESTAMP_FORMAT};
-use std::str::FromStr;
+use std::{path::PathBuf, str::FromStr};
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
@@ -31,6 +35,8 @@ struct Args {
     header_only: bool,
     #[structopt(long, help = "The output format", default_value = "log")]
     format: Format,
+    #[structopt(long, help = "Decrypt the file with keys from this key file")]
+    key_file: Option<PathBuf>,
 }
 
 enum Format {
@@ -46,9 +52,13 @@ async fn main() -> Result<()> {
         file,
         header_only,
         format,
+        key_file,
     } = Args::from_args();
 
     let mut source = MessageSource::new(file, StreamMode::Replay).await?;
+    if let Some(key_file) = key_file {
+        source.set_key_provider(&KeyRing::read_from_file(key_file)?)?;
+    }
 
     // print the header
     match format {
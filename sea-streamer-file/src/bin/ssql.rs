@@ -0,0 +1,137 @@
+//! This program runs SQL queries over SeaStreamer .ss files.
+//!
+//! Files are referenced in the `FROM` (or `JOIN`) clause as single-quoted paths ending in `.ss`:
+//!
+//! ```ignore
+//! ssql "SELECT stream_key, count(*) FROM 'capture.ss' WHERE timestamp > '2023-06-05T13:55:53' GROUP BY 1"
+//! ```
+use anyhow::Result;
+use datafusion::{common::TableReference, prelude::SessionContext};
+use sea_streamer_file::{
+    converter::PayloadFormat,
+    sql::{FileTable, FileTableOptions, DEFAULT_SCHEMA_SAMPLE},
+    FileId,
+};
+use std::sync::Arc;
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+struct Args {
+    #[structopt(
+        long,
+        help = "How to expose the payload: `binary`, `utf8` or `json`",
+        default_value = "json"
+    )]
+    payload: Payload,
+    #[structopt(
+        long,
+        help = "Number of messages used to infer the schema of JSON payloads",
+        default_value = "1024"
+    )]
+    schema_sample: usize,
+    #[structopt(help = "The SQL query")]
+    query: String,
+}
+
+struct Payload(PayloadFormat);
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+
+    let Args {
+        payload,
+        schema_sample,
+        query,
+    } = Args::from_args();
+
+    let mut options = FileTableOptions::default();
+    options
+        .set_payload_format(payload.0)
+        .set_schema_sample(if schema_sample > 0 {
+            schema_sample
+        } else {
+            DEFAULT_SCHEMA_SAMPLE
+        });
+
+    let (query, paths) = rewrite_query(&query);
+    let ctx = SessionContext::new();
+    for path in paths {
+        let table = FileTable::new(FileId::new(path.clone()), options.clone()).await?;
+        ctx.register_table(TableReference::bare(path), Arc::new(table))?;
+    }
+    log::debug!("{query}");
+
+    ctx.sql(&query).await?.show().await?;
+
+    Ok(())
+}
+
+/// Replace `FROM 'file.ss'` with `FROM "file.ss"`, so that it would be parsed as a table name.
+/// Returns the rewritten query and the paths of the files.
+fn rewrite_query(query: &str) -> (String, Vec<String>) {
+    let mut output = String::with_capacity(query.len());
+    let mut paths = Vec::new();
+    let mut word = String::new();
+    let mut prev_word = String::new();
+    let mut chars = query.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c.is_alphanumeric() || c == '_' {
+            word.push(c);
+            output.push(c);
+            continue;
+        }
+        if !word.is_empty() {
+            prev_word = std::mem::take(&mut word).to_uppercase();
+        }
+        match c {
+            '\'' => {
+                let mut literal = String::new();
+                while let Some(c) = chars.next() {
+                    if c == '\'' {
+                        if chars.peek() == Some(&'\'') {
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    literal.push(c);
+                }
+                if (prev_word == "FROM" || prev_word == "JOIN") && literal.ends_with(".ss") {
+                    output.push('"');
+                    output.push_str(&literal.replace('"', "\"\""));
+                    output.push('"');
+                    if !paths.contains(&literal) {
+                        paths.push(literal);
+                    }
+                } else {
+                    output.push('\'');
+                    output.push_str(&literal.replace('\'', "''"));
+                    output.push('\'');
+                }
+                prev_word.clear();
+            }
+            c if c.is_whitespace() => output.push(c),
+            c => {
+                output.push(c);
+                prev_word.clear();
+            }
+        }
+    }
+
+    (output, paths)
+}
+
+impl std::str::FromStr for Payload {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "binary" => Ok(Self(PayloadFormat::Binary)),
+            "utf8" => Ok(Self(PayloadFormat::Utf8)),
+            "json" => Ok(Self(PayloadFormat::Json)),
+            _ => Err("Invalid Payload Format"),
+        }
+    }
+}
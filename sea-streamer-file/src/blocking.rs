@@ -0,0 +1,145 @@
+//! Blocking reader and writer of `.ss` files, on top of `std::io::Read` and `std::io::Write`.
+//!
+//! The reader and writer wrap those of [`sea_streamer_file_format::blocking`], which share the same codec
+//! with [`crate::MessageSource`] and [`crate::MessageSink`] and do not depend on any async runtime;
+//! here payloads are decrypted and encrypted as well. This is intended for simple tools
+//! and non-async programs. There is no live streaming, seeking or file watching here; reading
+//! ends with [`FileErr::NotEnoughBytes`] at the end of input.
+//!
+//! ```ignore
+//! let file = std::io::BufReader::new(std::fs::File::open("capture.ss")?);
+//! let mut reader = MessageReader::new(file)?;
+//! loop {
+//!     match reader.next() {
+//!         Ok(message) => println!("{:?}", message.message.header()),
+//!         Err(FileErr::NotEnoughBytes) => break,
+//!         Err(e) => return Err(e.into()),
+//!     }
+//! }
+//! ```
+use std::io::{Read, Write};
+
+use sea_streamer_file_format::blocking::{MessageReader as Reader, MessageWriter as Writer};
+use sea_streamer_types::OwnedMessage;
+
+use crate::{
+    crypto::{Cipher, CryptoErr, KeyProvider},
+    end_of_stream,
+    format::{Checksum, Header, Marker, Message},
+    messages::{decrypt_message, encrypt_message},
+    ConfigErr, FileErr,
+};
+
+pub use sea_streamer_file_format::blocking::WriteSink;
+
+/// A blocking file reader that demux messages and beacon, and decrypt the payloads
+pub struct MessageReader<R: Read> {
+    reader: Reader<R>,
+    cipher: Option<Cipher>,
+}
+
+/// A blocking file writer that mux messages and beacon, and encrypt the payloads
+pub struct MessageWriter<W: Write> {
+    writer: Writer<W>,
+    cipher: Option<Cipher>,
+}
+
+impl<R: Read> MessageReader<R> {
+    /// Creates a new message reader. First, the stream Header is read from the reader,
+    /// which is expected to be at the file's beginning.
+    pub fn new(reader: R) -> Result<Self, FileErr> {
+        Ok(Self {
+            reader: Reader::new(reader)?,
+            cipher: None,
+        })
+    }
+
+    pub fn file_header(&self) -> &Header {
+        self.reader.file_header()
+    }
+
+    /// Same as [`crate::MessageSource::set_key_provider`].
+    pub fn set_key_provider(&mut self, keys: &dyn KeyProvider) -> Result<(), FileErr> {
+        if let Some(key_id) = &self.reader.file_header().key_id {
+            self.cipher = Some(Cipher::for_file(keys, key_id)?);
+        }
+        Ok(())
+    }
+
+    /// Read the next message. The payload is decrypted if the file is encrypted.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Message, FileErr> {
+        let message = self.reader.next()?;
+        decrypt_message(self.reader.file_header(), self.cipher.as_ref(), message)
+    }
+
+    /// Get the most recent Beacon and it's index. Same as [`crate::MessageSource::beacon`],
+    /// except that it is not cleared.
+    pub fn beacon(&self) -> (u32, &[Marker]) {
+        self.reader.beacon()
+    }
+
+    #[inline]
+    pub fn offset(&self) -> u64 {
+        self.reader.offset()
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader.into_inner()
+    }
+}
+
+impl<W: Write> MessageWriter<W> {
+    /// Creates a new message writer, writing the header first.
+    /// The writer is expected to be at the file's beginning.
+    pub fn new(writer: W, header: Header) -> Result<Self, FileErr> {
+        if let Some(key_id) = header.key_id {
+            return Err(CryptoErr::KeyNotFound(key_id).into());
+        }
+        Self::new_with(writer, header, None)
+    }
+
+    /// Like [`MessageWriter::new`], but encrypt message payloads with the current key of the
+    /// provider. The key id of the header is overwritten.
+    pub fn new_with_keys(
+        writer: W,
+        mut header: Header,
+        keys: &dyn KeyProvider,
+    ) -> Result<Self, FileErr> {
+        let cipher = Cipher::current(keys)?;
+        header.key_id = cipher.as_ref().map(|c| c.key_id().to_owned());
+        Self::new_with(writer, header, cipher)
+    }
+
+    fn new_with(writer: W, header: Header, cipher: Option<Cipher>) -> Result<Self, FileErr> {
+        if (header.beacon_interval as usize) < Header::size() {
+            return Err(FileErr::ConfigErr(ConfigErr::InvalidBeaconInterval));
+        }
+        Ok(Self {
+            writer: Writer::new(writer, header)?,
+            cipher,
+        })
+    }
+
+    pub fn write(&mut self, message: OwnedMessage) -> Result<Checksum, FileErr> {
+        let message = encrypt_message(self.cipher.as_ref(), message)?;
+        Ok(self.writer.write(message)?)
+    }
+
+    #[inline]
+    pub fn offset(&self) -> u64 {
+        self.writer.offset()
+    }
+
+    pub fn flush(&mut self) -> Result<(), FileErr> {
+        Ok(self.writer.flush()?)
+    }
+
+    /// End this stream, with an optional EOS message. Returns the inner writer.
+    pub fn end(mut self, eos: bool) -> Result<W, FileErr> {
+        if eos {
+            self.write(end_of_stream())?;
+        }
+        Ok(self.writer.end()?)
+    }
+}
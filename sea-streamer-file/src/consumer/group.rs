@@ -8,12 +8,12 @@ use std::{
 
 use super::{CtrlMsg, FileConsumer};
 use crate::{
-    is_end_of_stream, is_pulse, pulse_message, ConfigErr, FileErr, FileId, MessageSource,
-    StreamMode,
+    crypto::KeyProvider, is_end_of_stream, is_pulse, pulse_message, ConfigErr, FileErr, FileId,
+    MessageSource, StreamMode,
 };
 use sea_streamer_types::{
     export::futures::{select, FutureExt},
-    ConsumerGroup, Message, ShardId, SharedMessage, StreamKey,
+    ConsumerGroup, GroupBalancer, LoadBalance, Message, ShardId, SharedMessage, StreamKey,
 };
 
 lazy_static::lazy_static! {
@@ -50,6 +50,7 @@ struct Streamer {
     subscribers: Subscribers,
     ctrl: Sender<CtrlMsg>,
     tick: Sender<()>,
+    key_provider: Option<Arc<dyn KeyProvider>>,
 }
 
 pub struct StreamerInfo {
@@ -74,6 +75,7 @@ struct SubscriberMap {
     senders: HashMap<Sid, Sender<Result<SharedMessage, FileErr>>>,
     groups: Vec<((ConsumerGroup, StreamKey), Vec<Sid>)>,
     ungrouped: Vec<(StreamKey, Sid)>,
+    balancers: HashMap<ConsumerGroup, GroupBalancer<Sid>>,
 }
 
 impl Streamers {
@@ -95,13 +97,16 @@ impl Streamers {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn add(
         &mut self,
         file_id: FileId,
         mode: StreamMode,
         group: Option<ConsumerGroup>,
+        load_balance: LoadBalance,
         keys: Vec<StreamKey>,
         prefetch_message: usize,
+        key_provider: Option<Arc<dyn KeyProvider>>,
     ) -> Result<FileConsumer, FileErr> {
         let (sender, receiver) = unbounded();
         self.max_sid += 1;
@@ -116,12 +121,17 @@ impl Streamers {
                 .iter_mut()
                 .find(|(_, h)| h.subscribers.has_group(group))
             {
-                if *m == mode {
+                if *m != mode {
                     // consumers in the same group must use the same mode
-                    Some(h)
-                } else {
-                    // you are wrong
                     return Err(FileErr::ConfigErr(ConfigErr::SameGroupSameMode));
+                } else if !same_key_provider(&h.key_provider, &key_provider) {
+                    // and decrypt with the same keys
+                    return Err(FileErr::ConfigErr(ConfigErr::SameGroupSameKeyProvider));
+                } else if !h.subscribers.same_load_balance(group, &load_balance) {
+                    // and be balanced in the same way
+                    return Err(FileErr::ConfigErr(ConfigErr::SameGroupSameLoadBalance));
+                } else {
+                    Some(h)
                 }
             } else {
                 // no existing members yet, so whatever is asked for
@@ -131,8 +141,13 @@ impl Streamers {
             // no group
             match mode {
                 StreamMode::Live => {
-                    // live stream can be shared among consumers
-                    handles.iter_mut().find(|(p, _)| p == &mode).map(|(_, h)| h)
+                    // live stream can be shared among consumers with the same keys
+                    handles
+                        .iter_mut()
+                        .find(|(p, h)| {
+                            p == &mode && same_key_provider(&h.key_provider, &key_provider)
+                        })
+                        .map(|(_, h)| h)
                 }
                 StreamMode::LiveReplay | StreamMode::Replay => {
                     // otherwise each consumer 'owns' a streamer
@@ -144,14 +159,17 @@ impl Streamers {
             handles.push((
                 mode,
                 Streamer::create(
-                    MessageSource::new(file_id.clone(), mode).await?,
+                    open_source(file_id.clone(), mode, key_provider.as_deref()).await?,
                     prefetch_message,
+                    key_provider,
                 ),
             ));
             handle = Some(&mut handles.last_mut().unwrap().1);
         }
         let handle = handle.unwrap();
-        handle.subscribers.add(sid, sender, group, keys);
+        handle
+            .subscribers
+            .add(sid, sender, group, load_balance, keys);
         Ok(FileConsumer::new(
             file_id,
             sid,
@@ -200,16 +218,21 @@ impl Streamers {
                             handle.subscribers.remove(sid).expect("Checked by has_sid");
                         // create a new source
                         let prefetch_message = handle.subscribers.prefetch_message;
+                        let key_provider = handle.key_provider.clone();
                         handles.push((
                             new_mode,
                             Streamer::create(
-                                MessageSource::new(file_id.clone(), new_mode).await?,
+                                open_source(file_id.clone(), new_mode, key_provider.as_deref())
+                                    .await?,
                                 prefetch_message,
+                                key_provider,
                             ),
                         ));
                         let handle = &mut handles.last_mut().unwrap().1;
                         // subscribe to the source
-                        handle.subscribers.add(sid, sender, None, keys);
+                        handle
+                            .subscribers
+                            .add(sid, sender, None, Default::default(), keys);
                         break;
                     }
                 }
@@ -235,15 +258,47 @@ pub(crate) async fn new_consumer(
     file_id: FileId,
     mode: StreamMode,
     group: Option<ConsumerGroup>,
+    load_balance: LoadBalance,
     keys: Vec<StreamKey>,
     prefetch_message: usize,
+    key_provider: Option<Arc<dyn KeyProvider>>,
 ) -> Result<FileConsumer, FileErr> {
     let mut streamers = STREAMERS.lock().await;
     streamers
-        .add(file_id, mode, group, keys, prefetch_message)
+        .add(
+            file_id,
+            mode,
+            group,
+            load_balance,
+            keys,
+            prefetch_message,
+            key_provider,
+        )
         .await
 }
 
+async fn open_source(
+    file_id: FileId,
+    mode: StreamMode,
+    key_provider: Option<&dyn KeyProvider>,
+) -> Result<MessageSource, FileErr> {
+    let mut source = MessageSource::new(file_id, mode).await?;
+    if let Some(keys) = key_provider {
+        source.set_key_provider(keys)?;
+    }
+    Ok(source)
+}
+
+/// Whether both are the same `KeyProvider` instance, or both are absent.
+fn same_key_provider(a: &Option<Arc<dyn KeyProvider>>, b: &Option<Arc<dyn KeyProvider>>) -> bool {
+    match (a, b) {
+        // only compare the data pointers, as vtables of the same type are not necessarily unique
+        (Some(a), Some(b)) => Arc::as_ptr(a) as *const () == Arc::as_ptr(b) as *const (),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
 pub(crate) fn remove_consumer(sid: Sid) {
     CONTROL.0.send(BgTask::Drop(sid)).expect("Should never die");
 }
@@ -265,7 +320,11 @@ pub async fn query_streamer(file_id: &FileId) -> Option<Vec<StreamerInfo>> {
 }
 
 impl Streamer {
-    fn create(mut source: MessageSource, prefetch_message: usize) -> Self {
+    fn create(
+        mut source: MessageSource,
+        prefetch_message: usize,
+        key_provider: Option<Arc<dyn KeyProvider>>,
+    ) -> Self {
         let subscribers = Subscribers::new(prefetch_message);
         let (ctrler, ctrl) = bounded(0);
         let (ticker, tick) = bounded(1);
@@ -343,6 +402,7 @@ impl Streamer {
             subscribers: ret,
             ctrl: ctrler,
             tick: ticker,
+            key_provider,
         }
     }
 }
@@ -397,6 +457,14 @@ impl Subscribers {
         map.groups.iter().any(|((g, _), _)| g == group)
     }
 
+    /// Whether the group is balanced with this strategy, or has no strategy yet
+    fn same_load_balance(&self, group: &ConsumerGroup, load_balance: &LoadBalance) -> bool {
+        let map = self.subscribers.lock().unwrap();
+        map.balancers
+            .get(group)
+            .map_or(true, |balancer| balancer.strategy() == load_balance)
+    }
+
     fn info(&self) -> Vec<SubscriberInfo> {
         let map = self.subscribers.lock().unwrap();
         let mut subs = Vec::new();
@@ -424,10 +492,16 @@ impl Subscribers {
         sid: Sid,
         sender: Sender<Result<SharedMessage, FileErr>>,
         my_group: Option<ConsumerGroup>,
+        load_balance: LoadBalance,
         my_keys: Vec<StreamKey>,
     ) {
         let mut map = self.subscribers.lock().unwrap();
         if map.senders.insert(sid, sender).is_none() {
+            if let Some(my_group) = &my_group {
+                map.balancers
+                    .entry(my_group.clone())
+                    .or_insert_with(|| GroupBalancer::new(load_balance));
+            }
             for my_key in my_keys {
                 match my_group.clone() {
                     Some(my_group) => {
@@ -476,6 +550,11 @@ impl Subscribers {
                 }
             }
             map.groups.retain(|(_, sids)| !sids.is_empty());
+            if let Some(group) = &group {
+                if !map.groups.iter().any(|((g, _), _)| g == group) {
+                    map.balancers.remove(group);
+                }
+            }
             Some((sender, group, keys))
         } else {
             None
@@ -483,16 +562,22 @@ impl Subscribers {
     }
 
     fn dispatch(&self, message: Result<SharedMessage, FileErr>) {
-        let map = self.subscribers.lock().unwrap();
+        let mut map = self.subscribers.lock().unwrap();
+        let map = &mut *map;
         match message {
             Ok(message) => {
                 // send to relevant subscribers
-                for ((_, stream_key), sids) in map.groups.iter() {
-                    if is_pulse(&message) || stream_key == message.header().stream_key() {
+                for ((group, stream_key), sids) in map.groups.iter() {
+                    if is_pulse(&message) {
                         // This round-robin is deterministic
                         let sid = sids[message.sequence() as usize % sids.len()];
                         let sender = map.senders.get(&sid).unwrap();
                         sender.send(Ok(message.clone())).ok();
+                    } else if stream_key == message.header().stream_key() {
+                        let balancer = map.balancers.get_mut(group).expect("Added with group");
+                        let sid = balancer.select(sids, &message);
+                        let sender = map.senders.get(&sid).unwrap();
+                        sender.send(Ok(message.clone())).ok();
                     }
                 }
 
@@ -113,9 +113,14 @@ impl ConsumerTrait for FileConsumer {
     /// Otherwise it will await the next message.
     fn next(&self) -> Self::NextFuture<'_> {
         if let Err(TrySendError::Disconnected(_)) = self.ctrl.try_send(CtrlMsg::Read) {
-            // race: there is a possibility that *after* we enter the receiver future
-            // ctrl disconnect immediately. it will manifest in the StreamEnded below.
-            NextFuture::Error(Some(StreamErr::Backend(FileErr::StreamEnded)))
+            if !self.receiver.is_empty() {
+                // the streamer has ended, but the error it ended with is still in the buffer
+                NextFuture::Future(self.receiver.recv_async())
+            } else {
+                // race: there is a possibility that *after* we enter the receiver future
+                // ctrl disconnect immediately. it will manifest in the StreamEnded below.
+                NextFuture::Error(Some(StreamErr::Backend(FileErr::StreamEnded)))
+            }
         } else {
             NextFuture::Future(self.receiver.recv_async())
         }
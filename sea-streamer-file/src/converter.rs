@@ -0,0 +1,457 @@
+//! Convert `.ss` files into Apache Arrow IPC or Parquet, so that recorded streams can be loaded
+//! into DataFrames.
+//!
+//! Every message becomes one row, with the following columns:
+//!
+//! | column       | type                    |
+//! |--------------|-------------------------|
+//! | `stream_key` | `Utf8`                  |
+//! | `shard_id`   | `UInt64`                |
+//! | `sequence`   | `UInt64`                |
+//! | `timestamp`  | `Timestamp(ms, "UTC")`  |
+//!
+//! followed by the payload, according to [`PayloadFormat`].
+//!
+//! For [`PayloadFormat::Json`], objects are flattened into one column per leaf, e.g. the payload
+//! `{"id":1,"user":{"name":"a"}}` results in the columns `payload.id` and `payload.user.name`.
+//! Arrays are kept as list columns. Payloads which are not objects are placed in the `payload`
+//! column, and payloads which are not valid JSON are placed in the same column as strings.
+//! The schema of an output file is inferred from its first batch; fields that only appear
+//! afterwards are dropped.
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use arrow::{
+    array::{ArrayRef, BinaryBuilder, StringBuilder, TimestampMillisecondBuilder, UInt64Builder},
+    datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit},
+    error::ArrowError,
+    ipc::writer::FileWriter,
+    json::reader::{infer_json_schema_from_iterator, ReaderBuilder},
+    record_batch::RecordBatch,
+};
+use parquet::{arrow::ArrowWriter, errors::ParquetError};
+use sea_streamer_types::{
+    Buffer, Message as MessageTrait, MessageHeader, StreamKey, SEA_STREAMER_INTERNAL,
+};
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use crate::{is_end_of_stream, FileErr, FileId, MessageSource, StreamMode};
+
+pub const DEFAULT_BATCH_SIZE: usize = 1024;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Arrow IPC file format, a.k.a. Feather V2
+    ArrowIpc,
+    Parquet,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PayloadFormat {
+    /// A `Binary` column named `payload`
+    Binary,
+    /// A `Utf8` column named `payload`. Payloads which are not valid UTF-8 become nulls.
+    Utf8,
+    /// Flatten JSON objects into `payload.*` columns
+    Json,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConverterOptions {
+    output_format: OutputFormat,
+    payload_format: PayloadFormat,
+    batch_size: usize,
+    chunk_size: Option<usize>,
+    stream_keys: Vec<StreamKey>,
+}
+
+#[derive(Error, Debug)]
+pub enum ConvertErr {
+    #[error("FileErr: {0}")]
+    FileErr(#[source] FileErr),
+    #[error("ArrowError: {0}")]
+    ArrowError(#[source] ArrowError),
+    #[error("ParquetError: {0}")]
+    ParquetError(#[source] ParquetError),
+    #[error("IO Error: {0}")]
+    IoError(#[source] std::io::Error),
+}
+
+/// Accumulates messages and builds Arrow `RecordBatch`es.
+pub struct RecordBatchBuilder {
+    payload_format: PayloadFormat,
+    json_schema: Option<SchemaRef>,
+    stream_key: StringBuilder,
+    shard_id: UInt64Builder,
+    sequence: UInt64Builder,
+    timestamp: TimestampMillisecondBuilder,
+    binary: BinaryBuilder,
+    utf8: StringBuilder,
+    json: Vec<Value>,
+    len: usize,
+}
+
+enum BatchWriter {
+    ArrowIpc(FileWriter<File>),
+    Parquet(ArrowWriter<File>),
+}
+
+impl Default for ConverterOptions {
+    fn default() -> Self {
+        Self {
+            output_format: OutputFormat::Parquet,
+            payload_format: PayloadFormat::Binary,
+            batch_size: DEFAULT_BATCH_SIZE,
+            chunk_size: None,
+            stream_keys: Vec::new(),
+        }
+    }
+}
+
+impl ConverterOptions {
+    pub fn output_format(&self) -> OutputFormat {
+        self.output_format
+    }
+    /// Default is `Parquet`.
+    pub fn set_output_format(&mut self, v: OutputFormat) -> &mut Self {
+        self.output_format = v;
+        self
+    }
+
+    pub fn payload_format(&self) -> PayloadFormat {
+        self.payload_format
+    }
+    /// Default is `Binary`.
+    pub fn set_payload_format(&mut self, v: PayloadFormat) -> &mut Self {
+        self.payload_format = v;
+        self
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+    /// Number of rows per RecordBatch (or Parquet row group). Must be non-zero.
+    ///
+    /// Default is [`DEFAULT_BATCH_SIZE`].
+    pub fn set_batch_size(&mut self, v: usize) -> &mut Self {
+        self.batch_size = v.max(1);
+        self
+    }
+
+    pub fn chunk_size(&self) -> Option<usize> {
+        self.chunk_size
+    }
+    /// If set, start a new output file every N rows. The files will be named like
+    /// `<stem>-<index>.<extension>`, with index starting from 0.
+    ///
+    /// Default is `None`, i.e. everything goes into a single file.
+    pub fn set_chunk_size(&mut self, v: Option<usize>) -> &mut Self {
+        self.chunk_size = v.map(|v| v.max(1));
+        self
+    }
+
+    pub fn stream_keys(&self) -> &[StreamKey] {
+        &self.stream_keys
+    }
+    /// Only convert messages of these streams. Empty means all streams.
+    ///
+    /// Default is empty.
+    pub fn set_stream_keys(&mut self, v: Vec<StreamKey>) -> &mut Self {
+        self.stream_keys = v;
+        self
+    }
+}
+
+/// Convert a `.ss` file. Returns the paths of the output files.
+///
+/// If the file ends with an End-of-Stream message, or is truncated, the conversion stops there.
+/// Internal messages are skipped.
+pub async fn convert(
+    file_id: FileId,
+    output: PathBuf,
+    options: &ConverterOptions,
+) -> Result<Vec<PathBuf>, ConvertErr> {
+    let mut source = MessageSource::new(file_id, StreamMode::Replay)
+        .await
+        .map_err(ConvertErr::FileErr)?;
+    let mut builder = RecordBatchBuilder::new(options.payload_format);
+    let mut writer: Option<BatchWriter> = None;
+    let mut outputs = Vec::new();
+    let mut rows = 0;
+
+    loop {
+        let message = match source.next().await {
+            Ok(message) => message.message,
+            Err(FileErr::NotEnoughBytes) => break,
+            Err(e) => return Err(ConvertErr::FileErr(e)),
+        };
+        if is_end_of_stream(&message) {
+            break;
+        }
+        let header = message.header();
+        if header.stream_key().name() == SEA_STREAMER_INTERNAL
+            || (!options.stream_keys.is_empty()
+                && !options.stream_keys.contains(header.stream_key()))
+        {
+            continue;
+        }
+        builder.append(header, message.message().as_bytes());
+        rows += 1;
+
+        let chunk_full = matches!(options.chunk_size, Some(size) if rows == size);
+        if builder.len() >= options.batch_size || chunk_full {
+            write_batch(&mut builder, &mut writer, &mut outputs, &output, options)?;
+        }
+        if chunk_full {
+            writer.take().unwrap().close()?;
+            builder.reset_schema();
+            rows = 0;
+        }
+    }
+
+    if !builder.is_empty() {
+        write_batch(&mut builder, &mut writer, &mut outputs, &output, options)?;
+    }
+    if let Some(writer) = writer {
+        writer.close()?;
+    }
+
+    Ok(outputs)
+}
+
+/// Write the pending rows, opening a new output file if there is none.
+fn write_batch(
+    builder: &mut RecordBatchBuilder,
+    writer: &mut Option<BatchWriter>,
+    outputs: &mut Vec<PathBuf>,
+    output: &Path,
+    options: &ConverterOptions,
+) -> Result<(), ConvertErr> {
+    let batch = builder.finish().map_err(ConvertErr::ArrowError)?;
+    if writer.is_none() {
+        let path = chunk_path(output, options.chunk_size, outputs.len());
+        *writer = Some(BatchWriter::new(
+            &path,
+            options.output_format,
+            batch.schema(),
+        )?);
+        outputs.push(path);
+    }
+    writer.as_mut().unwrap().write(&batch)
+}
+
+fn chunk_path(output: &Path, chunk_size: Option<usize>, index: usize) -> PathBuf {
+    if chunk_size.is_none() {
+        return output.to_owned();
+    }
+    let stem = output
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match output.extension() {
+        Some(ext) => format!("{stem}-{index}.{}", ext.to_string_lossy()),
+        None => format!("{stem}-{index}"),
+    };
+    output.with_file_name(name)
+}
+
+impl BatchWriter {
+    fn new(path: &Path, format: OutputFormat, schema: SchemaRef) -> Result<Self, ConvertErr> {
+        let file = File::create(path).map_err(ConvertErr::IoError)?;
+        Ok(match format {
+            OutputFormat::ArrowIpc => {
+                Self::ArrowIpc(FileWriter::try_new(file, &schema).map_err(ConvertErr::ArrowError)?)
+            }
+            OutputFormat::Parquet => Self::Parquet(
+                ArrowWriter::try_new(file, schema, None).map_err(ConvertErr::ParquetError)?,
+            ),
+        })
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> Result<(), ConvertErr> {
+        match self {
+            Self::ArrowIpc(w) => w.write(batch).map_err(ConvertErr::ArrowError),
+            Self::Parquet(w) => w.write(batch).map_err(ConvertErr::ParquetError),
+        }
+    }
+
+    fn close(self) -> Result<(), ConvertErr> {
+        match self {
+            Self::ArrowIpc(mut w) => w.finish().map_err(ConvertErr::ArrowError),
+            Self::Parquet(w) => w.close().map(|_| ()).map_err(ConvertErr::ParquetError),
+        }
+    }
+}
+
+impl RecordBatchBuilder {
+    pub fn new(payload_format: PayloadFormat) -> Self {
+        Self {
+            payload_format,
+            json_schema: None,
+            stream_key: StringBuilder::new(),
+            shard_id: UInt64Builder::new(),
+            sequence: UInt64Builder::new(),
+            timestamp: TimestampMillisecondBuilder::new().with_timezone("UTC"),
+            binary: BinaryBuilder::new(),
+            utf8: StringBuilder::new(),
+            json: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// The columns describing the message header, which every batch begins with.
+    pub fn header_fields() -> Vec<Field> {
+        vec![
+            Field::new("stream_key", DataType::Utf8, false),
+            Field::new("shard_id", DataType::UInt64, false),
+            Field::new("sequence", DataType::UInt64, false),
+            Field::new(
+                "timestamp",
+                DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+                false,
+            ),
+        ]
+    }
+
+    pub fn append(&mut self, header: &MessageHeader, payload: &[u8]) {
+        self.stream_key.append_value(header.stream_key().name());
+        self.shard_id.append_value(header.shard_id().id());
+        self.sequence.append_value(*header.sequence());
+        self.timestamp
+            .append_value((header.timestamp().unix_timestamp_nanos() / 1_000_000) as i64);
+        match self.payload_format {
+            PayloadFormat::Binary => self.binary.append_value(payload),
+            PayloadFormat::Utf8 => self.utf8.append_option(std::str::from_utf8(payload).ok()),
+            PayloadFormat::Json => self.json.push(flatten_payload(payload)),
+        }
+        self.len += 1;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The schema of the JSON payload columns, if already inferred.
+    pub fn json_schema(&self) -> Option<&SchemaRef> {
+        self.json_schema.as_ref()
+    }
+
+    /// Use this schema for the JSON payload columns, instead of inferring from the first batch.
+    pub fn set_json_schema(&mut self, schema: SchemaRef) {
+        self.json_schema = Some(schema);
+    }
+
+    /// Infer the schema of the JSON payload columns again on the next batch.
+    pub fn reset_schema(&mut self) {
+        self.json_schema = None;
+    }
+
+    /// Build a RecordBatch from the appended messages, and reset the builder.
+    pub fn finish(&mut self) -> Result<RecordBatch, ArrowError> {
+        let mut fields = Self::header_fields();
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(self.stream_key.finish()),
+            Arc::new(self.shard_id.finish()),
+            Arc::new(self.sequence.finish()),
+            Arc::new(self.timestamp.finish()),
+        ];
+        match self.payload_format {
+            PayloadFormat::Binary => {
+                fields.push(Field::new("payload", DataType::Binary, false));
+                columns.push(Arc::new(self.binary.finish()));
+            }
+            PayloadFormat::Utf8 => {
+                fields.push(Field::new("payload", DataType::Utf8, true));
+                columns.push(Arc::new(self.utf8.finish()));
+            }
+            PayloadFormat::Json => {
+                let values = std::mem::take(&mut self.json);
+                let schema = match &self.json_schema {
+                    Some(schema) => schema.clone(),
+                    None => {
+                        let schema =
+                            Arc::new(infer_json_schema_from_iterator(values.iter().map(Ok))?);
+                        self.json_schema = Some(schema.clone());
+                        schema
+                    }
+                };
+                if !schema.fields().is_empty() {
+                    let mut decoder = ReaderBuilder::new(schema)
+                        .with_batch_size(values.len().max(1))
+                        .build_decoder()?;
+                    decoder.serialize(&values)?;
+                    if let Some(batch) = decoder.flush()? {
+                        fields.extend(batch.schema().fields().iter().map(|f| f.as_ref().clone()));
+                        columns.extend(batch.columns().iter().cloned());
+                    }
+                }
+            }
+        }
+        self.len = 0;
+
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
+    }
+}
+
+fn flatten_payload(payload: &[u8]) -> Value {
+    let mut map = Map::new();
+    match serde_json::from_slice(payload) {
+        Ok(Value::Object(object)) => flatten_object("payload", object, &mut map),
+        Ok(Value::Null) => (),
+        Ok(value) => {
+            map.insert("payload".to_owned(), value);
+        }
+        Err(_) => {
+            map.insert(
+                "payload".to_owned(),
+                Value::String(String::from_utf8_lossy(payload).into_owned()),
+            );
+        }
+    }
+    Value::Object(map)
+}
+
+fn flatten_object(prefix: &str, object: Map<String, Value>, map: &mut Map<String, Value>) {
+    for (key, value) in object {
+        let key = format!("{prefix}.{key}");
+        match value {
+            Value::Object(object) => flatten_object(&key, object, map),
+            value => {
+                map.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_flatten_payload() {
+        assert_eq!(
+            flatten_payload(br#"{"id":1,"user":{"name":"a","tags":["x"]}}"#),
+            serde_json::json!({
+                "payload.id": 1,
+                "payload.user.name": "a",
+                "payload.user.tags": ["x"],
+            })
+        );
+        assert_eq!(
+            flatten_payload(b"[1,2]"),
+            serde_json::json!({ "payload": [1, 2] })
+        );
+        assert_eq!(
+            flatten_payload(b"hello"),
+            serde_json::json!({ "payload": "hello" })
+        );
+    }
+}
@@ -0,0 +1,294 @@
+//! Encryption at rest for `.ss` files.
+//!
+//! Message payloads are encrypted with AES-256-GCM, each with a random 96-bit nonce. The message
+//! header (stream key, shard id, sequence and timestamp) is authenticated as associated data, so
+//! a payload cannot be moved to another message unnoticed. The encrypted payload is stored as:
+//!
+//! ```ignore
+//! +-----~-----+--------~--------+-----~-----+
+//! | nonce: 12 |    ciphertext   |  tag: 16  |
+//! +-----~-----+--------~--------+-----~-----+
+//! ```
+//!
+//! The file header, message headers and beacons are left in plaintext, such that a file can still
+//! be surveyed and seeked without the key. Internal messages (e.g. End-of-Stream) are not encrypted
+//! either. The id of the key is recorded in the file header; a file is encrypted with a single key,
+//! and keys can be rotated by starting new files with a new key.
+//!
+//! Keys are supplied by a [`KeyProvider`], which is usually a [`KeyRing`] loaded from a key file.
+//! This requires the `encryption` feature.
+use std::{collections::HashMap, fmt::Debug, path::Path, str::FromStr};
+
+#[cfg(feature = "encryption")]
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Nonce,
+};
+use sea_streamer_types::MessageHeader;
+use thiserror::Error;
+
+use crate::FileErr;
+
+pub const KEY_SIZE: usize = 32;
+pub const NONCE_SIZE: usize = 12;
+pub const TAG_SIZE: usize = 16;
+/// Key ids are recorded in the file header, so they are kept short.
+pub const MAX_KEY_ID_LEN: usize = 32;
+
+pub type Key = [u8; KEY_SIZE];
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CryptoErr {
+    #[error("Encryption is not enabled: please enable the `encryption` feature")]
+    Disabled,
+    #[error("Key not found: `{0}`")]
+    KeyNotFound(String),
+    #[error("Invalid key id: should be 1 to {MAX_KEY_ID_LEN} ASCII characters without whitespace")]
+    InvalidKeyId,
+    #[error("Invalid key: should be {KEY_SIZE} bytes in hex")]
+    InvalidKey,
+    #[error("The file is not encrypted, but a key is provided")]
+    Unencrypted,
+    #[error("Failed to encrypt")]
+    EncryptionFailed,
+    #[error("Failed to decrypt: wrong key or corrupted payload")]
+    DecryptionFailed,
+}
+
+/// Provides keys for encrypting and decrypting files.
+pub trait KeyProvider: Debug + Send + Sync {
+    /// The key (and its id) to encrypt new files with.
+    /// If None, new files will not be encrypted.
+    fn current_key(&self) -> Option<(String, Key)>;
+
+    /// Look up a key by id, to read or append to existing files.
+    fn key(&self, key_id: &str) -> Option<Key>;
+}
+
+/// A set of keys, one of which is the current key.
+///
+/// The key file format is one key per line, with the key id followed by the key in hex.
+/// Empty lines and lines starting with `#` are ignored. The first key is the current key.
+///
+/// ```ignore
+/// # generated with `openssl rand -hex 32`
+/// 2023-10 5a2f1b0e6c7d48a39e01f2d3c4b5a6978877665544332211ffeeddccbbaa9988
+/// 2023-09 00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff
+/// ```
+#[derive(Default, Clone)]
+pub struct KeyRing {
+    current: Option<String>,
+    keys: HashMap<String, Key>,
+}
+
+/// Encrypts and decrypts message payloads with a single key.
+pub(crate) struct Cipher {
+    key_id: String,
+    #[cfg(feature = "encryption")]
+    aead: Aes256Gcm,
+}
+
+impl KeyRing {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Add a key. The first key added becomes the current key.
+    pub fn add_key(&mut self, key_id: &str, key: Key) -> Result<&mut Self, CryptoErr> {
+        if !is_valid_key_id(key_id) {
+            return Err(CryptoErr::InvalidKeyId);
+        }
+        if self.current.is_none() {
+            self.current = Some(key_id.to_owned());
+        }
+        self.keys.insert(key_id.to_owned(), key);
+        Ok(self)
+    }
+
+    pub fn current_key_id(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    /// Use this key to encrypt new files. The key must have been added.
+    pub fn set_current_key(&mut self, key_id: &str) -> Result<&mut Self, CryptoErr> {
+        if !self.keys.contains_key(key_id) {
+            return Err(CryptoErr::KeyNotFound(key_id.to_owned()));
+        }
+        self.current = Some(key_id.to_owned());
+        Ok(self)
+    }
+
+    /// Load a key file.
+    pub fn read_from_file<P: AsRef<Path>>(path: P) -> Result<Self, FileErr> {
+        let string = std::fs::read_to_string(path).map_err(FileErr::IoError)?;
+        string.parse().map_err(FileErr::CryptoErr)
+    }
+}
+
+impl FromStr for KeyRing {
+    type Err = CryptoErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut ring = Self::new();
+        for line in s.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let (key_id, key) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(key_id), Some(key), None) => (key_id, key),
+                _ => return Err(CryptoErr::InvalidKey),
+            };
+            ring.add_key(key_id, parse_hex_key(key)?)?;
+        }
+        Ok(ring)
+    }
+}
+
+impl KeyProvider for KeyRing {
+    fn current_key(&self) -> Option<(String, Key)> {
+        let key_id = self.current.as_ref()?;
+        Some((key_id.clone(), *self.keys.get(key_id)?))
+    }
+
+    fn key(&self, key_id: &str) -> Option<Key> {
+        self.keys.get(key_id).copied()
+    }
+}
+
+impl Debug for KeyRing {
+    /// Only key ids are printed.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut key_ids: Vec<_> = self.keys.keys().collect();
+        key_ids.sort();
+        f.debug_struct("KeyRing")
+            .field("current", &self.current)
+            .field("keys", &key_ids)
+            .finish()
+    }
+}
+
+fn is_valid_key_id(key_id: &str) -> bool {
+    !key_id.is_empty()
+        && key_id.len() <= MAX_KEY_ID_LEN
+        && key_id.chars().all(|c| c.is_ascii_graphic())
+}
+
+fn parse_hex_key(hex: &str) -> Result<Key, CryptoErr> {
+    if hex.len() != KEY_SIZE * 2 || !hex.is_ascii() {
+        return Err(CryptoErr::InvalidKey);
+    }
+    let mut key = [0; KEY_SIZE];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte =
+            u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| CryptoErr::InvalidKey)?;
+    }
+    Ok(key)
+}
+
+impl Cipher {
+    /// Returns the cipher for the current key, or None if there is no current key.
+    pub(crate) fn current(keys: &dyn KeyProvider) -> Result<Option<Self>, CryptoErr> {
+        match keys.current_key() {
+            Some((key_id, key)) => Ok(Some(Self::new(key_id, &key)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the cipher for the key of a file.
+    pub(crate) fn for_file(keys: &dyn KeyProvider, key_id: &str) -> Result<Self, CryptoErr> {
+        match keys.key(key_id) {
+            Some(key) => Self::new(key_id.to_owned(), &key),
+            None => Err(CryptoErr::KeyNotFound(key_id.to_owned())),
+        }
+    }
+
+    #[cfg(feature = "encryption")]
+    fn new(key_id: String, key: &Key) -> Result<Self, CryptoErr> {
+        if !is_valid_key_id(&key_id) {
+            return Err(CryptoErr::InvalidKeyId);
+        }
+        Ok(Self {
+            key_id,
+            aead: Aes256Gcm::new(key.into()),
+        })
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    fn new(_: String, _: &Key) -> Result<Self, CryptoErr> {
+        Err(CryptoErr::Disabled)
+    }
+
+    pub(crate) fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    #[cfg(feature = "encryption")]
+    pub(crate) fn encrypt(
+        &self,
+        header: &MessageHeader,
+        payload: &[u8],
+    ) -> Result<Vec<u8>, CryptoErr> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .aead
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: payload,
+                    aad: &associated_data(header),
+                },
+            )
+            .map_err(|_| CryptoErr::EncryptionFailed)?;
+        let mut bytes = Vec::with_capacity(NONCE_SIZE + ciphertext.len());
+        bytes.extend_from_slice(&nonce);
+        bytes.extend_from_slice(&ciphertext);
+        Ok(bytes)
+    }
+
+    #[cfg(feature = "encryption")]
+    pub(crate) fn decrypt(
+        &self,
+        header: &MessageHeader,
+        payload: &[u8],
+    ) -> Result<Vec<u8>, CryptoErr> {
+        if payload.len() < NONCE_SIZE + TAG_SIZE {
+            return Err(CryptoErr::DecryptionFailed);
+        }
+        let (nonce, ciphertext) = payload.split_at(NONCE_SIZE);
+        self.aead
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: &associated_data(header),
+                },
+            )
+            .map_err(|_| CryptoErr::DecryptionFailed)
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    pub(crate) fn encrypt(&self, _: &MessageHeader, _: &[u8]) -> Result<Vec<u8>, CryptoErr> {
+        Err(CryptoErr::Disabled)
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    pub(crate) fn decrypt(&self, _: &MessageHeader, _: &[u8]) -> Result<Vec<u8>, CryptoErr> {
+        Err(CryptoErr::Disabled)
+    }
+}
+
+/// The message header, as stored in the file. Timestamps are stored in milliseconds.
+#[cfg(feature = "encryption")]
+fn associated_data(header: &MessageHeader) -> Vec<u8> {
+    let stream_key = header.stream_key().name().as_bytes();
+    let mut bytes = Vec::with_capacity(1 + stream_key.len() + 24);
+    bytes.push(stream_key.len() as u8);
+    bytes.extend_from_slice(stream_key);
+    bytes.extend_from_slice(&header.shard_id().id().to_be_bytes());
+    bytes.extend_from_slice(&header.sequence().to_be_bytes());
+    let millis = (header.timestamp().unix_timestamp_nanos() / 1_000_000) as u64;
+    bytes.extend_from_slice(&millis.to_be_bytes());
+    bytes
+}
@@ -122,6 +122,7 @@ impl DynFileSource {
 }
 
 impl ByteSource for DynFileSource {
+    type Error = FileErr;
     type Future<'a> = DynReadFuture<'a>;
 
     fn request_bytes(&mut self, size: usize) -> Self::Future<'_> {
@@ -1,5 +1,10 @@
-use crate::{format::FormatErr, ConfigErr};
-use sea_streamer_types::{StreamErr, StreamResult};
+use crate::{
+    crypto::CryptoErr,
+    format::{FormatErr, ShortStringErr, UnixTimestampErr},
+    ConfigErr,
+};
+use sea_streamer_file_format::{CodecErr, DecodeErr};
+use sea_streamer_types::{StreamErr, StreamKeyErr, StreamResult};
 use std::str::Utf8Error;
 use thiserror::Error;
 
@@ -19,6 +24,8 @@ pub enum FileErr {
     FormatErr(#[source] FormatErr),
     #[error("SeekErr: {0}")]
     SeekErr(#[source] SeekErr),
+    #[error("CryptoErr: {0}")]
+    CryptoErr(#[source] CryptoErr),
     #[error("File Removed")]
     FileRemoved,
     #[error("File Limit Exceeded")]
@@ -41,6 +48,57 @@ pub enum SeekErr {
     Exhausted,
 }
 
+impl From<CryptoErr> for FileErr {
+    fn from(e: CryptoErr) -> Self {
+        FileErr::CryptoErr(e)
+    }
+}
+
+impl From<FormatErr> for FileErr {
+    fn from(e: FormatErr) -> Self {
+        FileErr::FormatErr(e)
+    }
+}
+
+impl From<ShortStringErr> for FileErr {
+    fn from(e: ShortStringErr) -> Self {
+        FileErr::FormatErr(e.into())
+    }
+}
+
+impl From<UnixTimestampErr> for FileErr {
+    fn from(e: UnixTimestampErr) -> Self {
+        FileErr::FormatErr(e.into())
+    }
+}
+
+impl From<StreamKeyErr> for FileErr {
+    fn from(e: StreamKeyErr) -> Self {
+        FileErr::FormatErr(e.into())
+    }
+}
+
+impl From<DecodeErr> for FileErr {
+    fn from(e: DecodeErr) -> Self {
+        match e {
+            DecodeErr::Incomplete(_) => FileErr::NotEnoughBytes,
+            DecodeErr::FormatErr(e) => FileErr::FormatErr(e),
+            DecodeErr::Utf8Error(e) => FileErr::Utf8Error(e),
+        }
+    }
+}
+
+impl From<CodecErr> for FileErr {
+    fn from(e: CodecErr) -> Self {
+        match e {
+            CodecErr::IoError(e) => FileErr::IoError(e),
+            CodecErr::FormatErr(e) => FileErr::FormatErr(e),
+            CodecErr::Utf8Error(e) => FileErr::Utf8Error(e),
+            CodecErr::NotEnoughBytes => FileErr::NotEnoughBytes,
+        }
+    }
+}
+
 pub type FileResult<T> = StreamResult<T, FileErr>;
 
 impl FileErr {
@@ -54,6 +112,7 @@ impl FileErr {
             FileErr::WatchError(e) => FileErr::WatchError(e.clone()),
             FileErr::FormatErr(e) => FileErr::FormatErr(*e),
             FileErr::SeekErr(e) => FileErr::SeekErr(*e),
+            FileErr::CryptoErr(e) => FileErr::CryptoErr(e.clone()),
             FileErr::FileRemoved => FileErr::FileRemoved,
             FileErr::FileLimitExceeded => FileErr::FileLimitExceeded,
             FileErr::TaskDead(e) => FileErr::TaskDead(e),
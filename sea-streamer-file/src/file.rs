@@ -94,6 +94,7 @@ impl FileReader {
 }
 
 impl ByteSource for FileReader {
+    type Error = FileErr;
     type Future<'a> = FileReaderFuture<'a>;
 
     /// Read N bytes from file. If there is not enough bytes, it will return `NotEnoughBytes` error.
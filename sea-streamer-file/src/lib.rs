@@ -15,11 +15,11 @@
 //! The SeaStreamerFile format is designed for efficient fast-forward and seeking. This is enabled by placing an array
 //! of Beacons at fixed interval in the file. A Beacon contains a summary of the streams, so it acts like an inplace
 //! index. It also allows readers to align with the message boundaries. To learn more about the file format, read
-//! [`src/format.rs`](https://github.com/SeaQL/sea-streamer/blob/main/sea-streamer-file/src/format.rs).
+//! [`src/format.rs`](https://github.com/SeaQL/sea-streamer/blob/main/sea-streamer-file/sea-streamer-file-format/src/format.rs).
 //!
 //! On top of that, are the high-level SeaStreamer multi-producer, multi-consumer stream semantics, resembling
 //! the behaviour of other SeaStreamer backends. In particular, the load-balancing behaviour is same as Stdio,
-//! i.e. round-robin.
+//! i.e. round-robin by default, and can be changed with `FileConsumerOptions::set_load_balance`.
 //!
 //! ### Decoder
 //!
@@ -50,14 +50,64 @@
 //!
 //! There is also a Typescript implementation under [`sea-streamer-file-reader`](https://github.com/SeaQL/sea-streamer/tree/main/sea-streamer-file/sea-streamer-file-reader).
 //!
+//! ### Blocking IO
+//!
+//! The `blocking` module provides `MessageReader` and `MessageWriter` over `std::io::Read` and `std::io::Write`,
+//! sharing the same codec as `MessageSource` and `MessageSink`. They do not need an async runtime,
+//! so simple tools and non-async programs can read and write `.ss` files with `sea-streamer-file` without any runtime features.
+//! The codec and a plain blocking reader and writer live in [`sea-streamer-file-format`](https://github.com/SeaQL/sea-streamer/tree/main/sea-streamer-file/sea-streamer-file-format),
+//! which does not depend on any async runtime at all.
+//!
+//! ### Encryption
+//!
+//! With the `encryption` feature, message payloads can be encrypted at rest with AES-256-GCM.
+//! Set a `KeyProvider` (e.g. a `KeyRing` loaded from a key file) with `FileConnectOptions::set_key_provider`.
+//! New files are encrypted with the current key, and the key id is recorded in the file header,
+//! so older files can still be read and appended to after the key is rotated.
+//! Stream keys, message headers and beacons stay in plaintext, so files can still be seeked without the key.
+//! The decoder takes a `--key-file` argument.
+//!
+//! ### C API
+//!
+//! [`sea-streamer-file-ffi`](https://github.com/SeaQL/sea-streamer/tree/main/sea-streamer-file/sea-streamer-file-ffi) is a `cdylib` exposing a C API
+//! to read, seek and append to `.ss` files, with the header file under `include/`.
+//! It wraps `MessageSource` and `MessageSink`, so beacons can be inspected and are maintained on write.
+//!
+//! ### Converter
+//!
+//! Recorded streams can be converted into Apache Arrow IPC or Parquet, to be loaded into DataFrames.
+//! This is also available as a library under the `converter` feature:
+//!
+//! ```sh
+//! alias converter='cargo run --package sea-streamer-file --features=executables,converter --bin converter'
+//! converter -- --file <file> --output <file>.parquet --payload json --chunk-size 1000000
+//! ```
+//!
+//! There will be one row per message, with columns `stream_key`, `shard_id`, `sequence` and `timestamp`.
+//! The payload is stored as `binary`, `utf8`, or flattened into `payload.*` columns as `json`.
+//! Use `--stream-key` to select streams.
+//!
+//! ### SQL
+//!
+//! With the `datafusion` feature, `.ss` files can be queried with SQL through a DataFusion `TableProvider`.
+//! JSON payloads are exposed as `payload.*` columns, and predicates on `timestamp` are pushed down to seek the file.
+//! There is also a small CLI, where files are referenced by path:
+//!
+//! ```sh
+//! alias ssql='cargo run --package sea-streamer-file --features=executables,datafusion --bin ssql'
+//! ssql -- "SELECT stream_key, count(*) FROM 'capture.ss' WHERE timestamp > '2023-06-05T13:55:53' GROUP BY 1"
+//! ```
+//!
 //! ### TODO
 //!
 //! 1. Resumable: currently unimplemented. A potential implementation might be to commit into a local SQLite database.
 //! 2. Sharding: currently it only streams to Shard ZERO.
 //! 3. Verify: a utility program to verify and repair SeaStreamer binary file.
-mod buffer;
+pub mod blocking;
 mod consumer;
-mod crc;
+#[cfg(feature = "converter")]
+pub mod converter;
+pub mod crypto;
 mod dyn_file;
 mod error;
 mod file;
@@ -66,17 +116,19 @@ mod messages;
 mod producer;
 mod sink;
 mod source;
+#[cfg(feature = "datafusion")]
+pub mod sql;
 mod streamer;
 mod surveyor;
 mod watcher;
 
-pub use buffer::*;
 pub use consumer::*;
 pub use dyn_file::*;
 pub use error::*;
 pub use file::*;
 pub use messages::*;
 pub use producer::*;
+pub use sea_streamer_file_format::{Appendable, ByteBuffer, ByteSink, ByteSource, Bytes};
 pub use sink::*;
 pub use source::*;
 pub use streamer::*;
@@ -1,4 +1,4 @@
-use std::{cmp::Ordering, collections::BTreeMap, num::NonZeroU32, path::Path};
+use std::{cmp::Ordering, num::NonZeroU32, path::Path};
 
 use sea_streamer_types::{
     export::futures::{future::BoxFuture, FutureExt},
@@ -6,8 +6,11 @@ use sea_streamer_types::{
     SharedMessage, StreamKey, Timestamp, SEA_STREAMER_INTERNAL,
 };
 
+pub(crate) use sea_streamer_file_format::blocking::{BeaconMux, BeaconState};
+
 use crate::{
-    format::{Beacon, Checksum, FormatErr, Header, Marker, Message, RunningChecksum},
+    crypto::{Cipher, CryptoErr, KeyProvider},
+    format::{Beacon, Checksum, FormatErr, Header, Marker, Message},
     AsyncFile, BeaconReader, ByteBuffer, ByteSource, Bytes, DynFileSource, FileErr, FileId,
     FileReader, FileSink, FileSourceType, SeekErr, StreamMode, SurveyResult, Surveyor,
 };
@@ -23,17 +26,16 @@ pub struct MessageSource {
     offset: u64,
     beacon: (u32, Vec<Marker>),
     pending: Option<Message>,
+    cipher: Option<Cipher>,
 }
 
 /// A high level file writer that mux messages and beacon
 pub struct MessageSink {
     sink: FileSinkState,
-    offset: u64,
-    beacon_interval: u32,
-    beacon: BTreeMap<(StreamKey, ShardId), BeaconState>,
-    beacon_count: u32,
+    mux: BeaconMux,
     message_count: u32,
     started_from: u64,
+    cipher: Option<Cipher>,
 }
 
 enum FileSinkState {
@@ -54,12 +56,6 @@ pub enum SeekTarget {
     End,
 }
 
-pub(crate) struct BeaconState {
-    pub(crate) seq_no: SeqNo,
-    pub(crate) ts: Timestamp,
-    pub(crate) running_checksum: RunningChecksum,
-}
-
 impl MessageSource {
     /// Creates a new message source. First, the stream Header is read
     /// from the file's beginning.
@@ -90,6 +86,7 @@ impl MessageSource {
             offset: Header::size() as u64,
             beacon: (0, Vec::new()),
             pending: None,
+            cipher: None,
         };
         if mode == StreamMode::Live {
             stream.rewind(SeqPos::End).await?;
@@ -101,6 +98,15 @@ impl MessageSource {
         &self.header
     }
 
+    /// Provide the key to decrypt message payloads, if the file is encrypted.
+    /// Without the key, [`MessageSource::next`] would fail on an encrypted file.
+    pub fn set_key_provider(&mut self, keys: &dyn KeyProvider) -> Result<(), FileErr> {
+        if let Some(key_id) = &self.header.key_id {
+            self.cipher = Some(Cipher::for_file(keys, key_id)?);
+        }
+        Ok(())
+    }
+
     /// Rewind the message stream to a coarse position.
     /// SeqNo is regarded as the N-th beacon.
     /// Returns the current location in terms of N-th beacon.
@@ -186,6 +192,22 @@ impl MessageSource {
         stream_key: &StreamKey,
         shard_id: &ShardId,
         to: SeekTarget,
+    ) -> Result<(), FileErr> {
+        self.seek_by(Some((stream_key, shard_id)), to).await
+    }
+
+    /// Like `seek`, but regard the file as a whole, i.e. consider the markers of all streams.
+    /// A beacon is only to the left of the target if all of its markers are.
+    ///
+    /// Warning: This future must not be canceled.
+    pub async fn seek_all(&mut self, to: SeekTarget) -> Result<(), FileErr> {
+        self.seek_by(None, to).await
+    }
+
+    async fn seek_by(
+        &mut self,
+        stream: Option<(&StreamKey, &ShardId)>,
+        to: SeekTarget,
     ) -> Result<(), FileErr> {
         // a short cut
         match to {
@@ -202,13 +224,26 @@ impl MessageSource {
         let res = 'outer: loop {
             // survey the beacons to narrow down the scope of search
             let surveyor = match Surveyor::new(self, |b: &Beacon| {
-                for item in b.items.iter() {
-                    if (stream_key, shard_id) == (item.header.stream_key(), item.header.shard_id())
-                    {
-                        return compare(&to, &item.header);
+                if let Some((stream_key, shard_id)) = stream {
+                    for item in b.items.iter() {
+                        if (stream_key, shard_id)
+                            == (item.header.stream_key(), item.header.shard_id())
+                        {
+                            return compare(&to, &item.header);
+                        }
                     }
+                    SurveyResult::Undecided
+                } else if b.items.is_empty() {
+                    SurveyResult::Undecided
+                } else if b
+                    .items
+                    .iter()
+                    .all(|item| compare(&to, &item.header) == SurveyResult::Left)
+                {
+                    SurveyResult::Left
+                } else {
+                    SurveyResult::Right
                 }
-                SurveyResult::Undecided
             })
             .await
             {
@@ -235,7 +270,7 @@ impl MessageSource {
             };
             // read until we found what we want
             loop {
-                let mess = match self.next().await {
+                let mess = match self.next_raw().await {
                     Ok(m) => m,
                     Err(e) => {
                         break 'outer match e {
@@ -330,8 +365,14 @@ impl MessageSource {
         Ok(())
     }
 
-    /// Read the next message.
+    /// Read the next message. The payload is decrypted if the file is encrypted.
     pub async fn next(&mut self) -> Result<Message, FileErr> {
+        let message = self.next_raw().await?;
+        decrypt_message(&self.header, self.cipher.as_ref(), message)
+    }
+
+    /// Read the next message, without decrypting the payload.
+    pub(crate) async fn next_raw(&mut self) -> Result<Message, FileErr> {
         let message = match self.pending.take() {
             Some(m) => m,
             None => Message::read_from(self).await?,
@@ -381,6 +422,7 @@ impl MessageSource {
 }
 
 impl ByteSource for MessageSource {
+    type Error = FileErr;
     /// Too complex to unroll by hand. Let's just box it.
     type Future<'a> = BoxFuture<'a, Result<Bytes, FileErr>>;
 
@@ -417,22 +459,71 @@ impl MessageSink {
     /// Create a fresh sink. Overwrite if file already exists.
     pub async fn new(file_id: FileId, beacon_interval: u32, limit: u64) -> Result<Self, FileErr> {
         let file = AsyncFile::new_ow(file_id).await?;
-        Self::new_with(file, beacon_interval, limit).await
+        Self::new_with(file, beacon_interval, limit, None).await
+    }
+
+    /// Like [`MessageSink::new`], but encrypt message payloads with the current key of the
+    /// provider. If there is no current key, the file will not be encrypted.
+    pub async fn new_with_keys(
+        file_id: FileId,
+        beacon_interval: u32,
+        limit: u64,
+        keys: &dyn KeyProvider,
+    ) -> Result<Self, FileErr> {
+        let cipher = Cipher::current(keys)?;
+        let file = AsyncFile::new_ow(file_id).await?;
+        Self::new_with(file, beacon_interval, limit, cipher).await
     }
 
     /// Create a sink. Append if file already exists, and follow its beacon interval.
+    /// Fails if the existing file is encrypted.
     pub async fn append(
         file_id: FileId,
         beacon_interval: u32,
         limit: u64,
+    ) -> Result<Self, FileErr> {
+        Self::append_with(file_id, beacon_interval, limit, None).await
+    }
+
+    /// Like [`MessageSink::append`], but encrypt message payloads. A new file is encrypted with
+    /// the current key of the provider, while an existing file continues with its own key.
+    /// Fails if the existing file is not encrypted but there is a current key.
+    pub async fn append_with_keys(
+        file_id: FileId,
+        beacon_interval: u32,
+        limit: u64,
+        keys: &dyn KeyProvider,
+    ) -> Result<Self, FileErr> {
+        Self::append_with(file_id, beacon_interval, limit, Some(keys)).await
+    }
+
+    async fn append_with(
+        file_id: FileId,
+        beacon_interval: u32,
+        limit: u64,
+        keys: Option<&dyn KeyProvider>,
     ) -> Result<Self, FileErr> {
         let file = AsyncFile::new_rw(file_id.clone()).await?;
         if file.size() == 0 {
-            Self::new_with(file, beacon_interval, limit).await
+            let cipher = match keys {
+                Some(keys) => Cipher::current(keys)?,
+                None => None,
+            };
+            Self::new_with(file, beacon_interval, limit, cipher).await
         } else {
             let source =
                 DynFileSource::FileReader(FileReader::new_with(file, 0, Default::default())?);
             let mut source = MessageSource::new_with(source, StreamMode::Replay).await?;
+            let cipher = match (&source.header.key_id, keys) {
+                (Some(key_id), Some(keys)) => Some(Cipher::for_file(keys, key_id)?),
+                (Some(key_id), None) => {
+                    return Err(CryptoErr::KeyNotFound(key_id.clone()).into());
+                }
+                (None, Some(keys)) if keys.current_key().is_some() => {
+                    return Err(CryptoErr::Unencrypted.into());
+                }
+                (None, _) => None,
+            };
             let mut offset = 0;
             match source.rewind(SeqPos::End).await {
                 Ok(mut nth) => {
@@ -440,7 +531,7 @@ impl MessageSink {
                     // we must read the last message, and truncate the EOS
                     let mut read = false;
                     loop {
-                        match source.next().await {
+                        match source.next_raw().await {
                             Ok(m) => {
                                 if is_end_of_stream(&m.message) {
                                     if read {
@@ -505,12 +596,10 @@ impl MessageSink {
 
                 Ok(Self {
                     sink: FileSinkState::Alive(sink),
-                    offset,
-                    beacon_interval,
-                    beacon: Default::default(),
-                    beacon_count: 0,
+                    mux: BeaconMux::new(offset, beacon_interval),
                     message_count: 0,
                     started_from: offset,
+                    cipher,
                 })
             } else {
                 unreachable!()
@@ -518,9 +607,15 @@ impl MessageSink {
         }
     }
 
-    async fn new_with(file: AsyncFile, beacon_interval: u32, limit: u64) -> Result<Self, FileErr> {
+    async fn new_with(
+        file: AsyncFile,
+        beacon_interval: u32,
+        limit: u64,
+        cipher: Option<Cipher>,
+    ) -> Result<Self, FileErr> {
         assert!(Header::size() <= beacon_interval as usize);
-        let header = Self::new_header(&file, beacon_interval);
+        let mut header = Self::new_header(&file, beacon_interval);
+        header.key_id = cipher.as_ref().map(|c| c.key_id().to_owned());
         let mut sink = FileSink::new(file, limit)?;
         let mut offset = header.write_to(&mut sink)?;
         if offset == beacon_interval as usize {
@@ -535,12 +630,10 @@ impl MessageSink {
 
         Ok(Self {
             sink: FileSinkState::Alive(sink),
-            offset: offset as u64,
-            beacon_interval,
-            beacon: Default::default(),
-            beacon_count: 0,
+            mux: BeaconMux::new(offset as u64, beacon_interval),
             message_count: 0,
             started_from: offset as u64,
+            cipher,
         })
     }
 
@@ -553,61 +646,18 @@ impl MessageSink {
             file_name,
             created_at: Timestamp::now_utc(),
             beacon_interval,
+            key_id: None,
         }
     }
 
     /// This method does not block. To make sure messages have been written, call [`MessageSink::flush`].
     pub fn write(&mut self, message: OwnedMessage) -> Result<Checksum, FileErr> {
-        let key = (message.stream_key(), message.shard_id());
-        let (seq_no, ts) = (message.sequence(), message.timestamp());
-        let message = Message {
-            message,
-            checksum: 0,
+        let sink = match &mut self.sink {
+            FileSinkState::Alive(sink) => sink,
+            FileSinkState::Dead => panic!("FileSinkState::Dead"),
         };
-        let mut buffer = ByteBuffer::new();
-        let (_, checksum) = message.write_to(&mut buffer)?;
-        let entry = self.beacon.entry(key).or_insert(BeaconState {
-            seq_no,
-            ts,
-            running_checksum: RunningChecksum::new(),
-        });
-        entry.seq_no = std::cmp::max(seq_no, entry.seq_no);
-        entry.ts = std::cmp::max(ts, entry.ts);
-        entry.running_checksum.update(checksum);
-
-        while !buffer.is_empty() {
-            let chunk = self.beacon_interval as usize
-                - (self.offset % self.beacon_interval as u64) as usize;
-            let chunk: ByteBuffer = buffer.consume(std::cmp::min(chunk, buffer.size()));
-            self.offset += chunk.write_to(self.sink())? as u64;
-
-            if self.offset > 0 && self.offset % self.beacon_interval as u64 == 0 {
-                let num_markers = Beacon::num_markers(self.beacon_interval as usize);
-                let mut items = Vec::new();
-                // We may not have enough space to fit in all beacon for every stream.
-                // In which case, we'll round-robin among them.
-                for ((key, sid), beacon) in self
-                    .beacon
-                    .iter()
-                    .skip(self.beacon_count as usize % self.beacon.len())
-                    .chain(self.beacon.iter())
-                    .take(std::cmp::min(self.beacon.len(), num_markers))
-                {
-                    items.push(Marker {
-                        header: MessageHeader::new(key.to_owned(), *sid, beacon.seq_no, beacon.ts),
-                        running_checksum: beacon.running_checksum.crc(),
-                    });
-                }
-                let beacon_count = items.len();
-                let beacon = Beacon {
-                    remaining_messages_bytes: buffer.size() as u32,
-                    items,
-                };
-                self.offset += beacon.write_to(self.sink())? as u64;
-                self.beacon_count += beacon_count as u32;
-            }
-        }
-
+        let message = encrypt_message(self.cipher.as_ref(), message)?;
+        let checksum = self.mux.write(sink, message)?;
         self.message_count += 1;
 
         Ok(checksum)
@@ -615,7 +665,7 @@ impl MessageSink {
 
     #[inline]
     pub fn offset(&self) -> u64 {
-        self.offset
+        self.mux.offset
     }
 
     /// Where this sink was started
@@ -660,10 +710,45 @@ impl MessageSink {
     }
 
     pub(crate) fn update_stream_state(&mut self, key: (StreamKey, ShardId), state: BeaconState) {
-        self.beacon.insert(key, state);
+        self.mux.update_stream_state(key, state);
+    }
+}
+
+/// Encrypt the payload, unless it is an internal message.
+pub(crate) fn encrypt_message(
+    cipher: Option<&Cipher>,
+    message: OwnedMessage,
+) -> Result<OwnedMessage, FileErr> {
+    match cipher {
+        Some(cipher) if message.stream_key().name() != SEA_STREAMER_INTERNAL => {
+            let payload = cipher.encrypt(message.header(), message.message().as_bytes())?;
+            let (header, _) = message.take();
+            Ok(OwnedMessage::new(header, payload))
+        }
+        _ => Ok(message),
     }
 }
 
+/// Decrypt the payload if the file is encrypted, unless it is an internal message.
+pub(crate) fn decrypt_message(
+    header: &Header,
+    cipher: Option<&Cipher>,
+    message: Message,
+) -> Result<Message, FileErr> {
+    let key_id = match &header.key_id {
+        Some(key_id) if message.message.stream_key().name() != SEA_STREAMER_INTERNAL => key_id,
+        _ => return Ok(message),
+    };
+    let cipher = cipher.ok_or_else(|| CryptoErr::KeyNotFound(key_id.clone()))?;
+    let Message { message, checksum } = message;
+    let payload = cipher.decrypt(message.header(), message.message().as_bytes())?;
+    let (header, _) = message.take();
+    Ok(Message {
+        message: OwnedMessage::new(header, payload),
+        checksum,
+    })
+}
+
 /// This can be written to a file to properly end the stream
 pub fn end_of_stream() -> OwnedMessage {
     let header = MessageHeader::new(
@@ -160,9 +160,21 @@ impl Writer {
     async fn new(file_id: FileId, options: &FileConnectOptions) -> Result<Self, FileErr> {
         let end_with_eos = options.end_with_eos();
         let file_size_limit = options.file_size_limit();
-        let mut sink =
-            MessageSink::append(file_id.clone(), options.beacon_interval(), file_size_limit)
-                .await?;
+        let mut sink = match options.key_provider() {
+            Some(keys) => {
+                MessageSink::append_with_keys(
+                    file_id.clone(),
+                    options.beacon_interval(),
+                    file_size_limit,
+                    keys.as_ref(),
+                )
+                .await?
+            }
+            None => {
+                MessageSink::append(file_id.clone(), options.beacon_interval(), file_size_limit)
+                    .await?
+            }
+        };
         let (sender, receiver) = unbounded::<Request>();
         let mut streams: HashMap<(StreamKey, ShardId), StreamState> = Default::default();
         #[cfg(feature = "runtime-async-std")]
@@ -206,7 +218,7 @@ impl Writer {
                             // 2. go forward from there and read all messages up to started_from, recording the latest messages
                             source.rewind(SeqPos::At(n as u64)).await?;
                             while source.offset() < sink.started_from() {
-                                match source.next().await {
+                                match source.next_raw().await {
                                     Ok(msg) => {
                                         let m = &msg.message;
                                         let entry = streams
@@ -2,15 +2,10 @@ use flume::{bounded, unbounded, Receiver, Sender, TryRecvError};
 
 use crate::{
     watcher::{new_watcher, FileEvent, Watcher},
-    AsyncFile, Bytes, FileErr,
+    AsyncFile, ByteSink, Bytes, FileErr,
 };
 use sea_streamer_runtime::{spawn_task, TaskHandle};
 
-pub trait ByteSink {
-    /// This should never block.
-    fn write(&mut self, bytes: Bytes) -> Result<(), FileErr>;
-}
-
 const CHUNK_SIZE: usize = 1024 * 1024; // 1 MiB
 
 /// Buffered file writer.
@@ -250,6 +245,8 @@ impl FileSink {
 }
 
 impl ByteSink for FileSink {
+    type Error = FileErr;
+
     /// This method never blocks
     fn write(&mut self, bytes: Bytes) -> Result<(), FileErr> {
         if self.sender.send(Request::Bytes(bytes)).is_err() {
@@ -8,19 +8,10 @@ use sea_streamer_types::{
 
 use crate::{
     watcher::{new_watcher, FileEvent, Watcher},
-    AsyncFile, ByteBuffer, Bytes, FileErr, FileId, ReadFrom,
+    AsyncFile, ByteBuffer, ByteSource, Bytes, FileErr, FileId, ReadFrom,
 };
 use sea_streamer_runtime::{spawn_task, timeout, TaskHandle};
 
-pub trait ByteSource {
-    type Future<'a>: Future<Output = Result<Bytes, FileErr>>
-    where
-        Self: 'a;
-
-    #[allow(clippy::needless_lifetimes)]
-    fn request_bytes<'a>(&'a mut self, size: usize) -> Self::Future<'a>;
-}
-
 /// `FileSource` treats files as a live stream of bytes.
 /// It will read til the end, and will resume reading when the file grows.
 /// It relies on `notify::RecommendedWatcher`, which is the OS's native notify mechanism.
@@ -258,6 +249,7 @@ impl FileSource {
 }
 
 impl ByteSource for FileSource {
+    type Error = FileErr;
     type Future<'a> = FileSourceFuture<'a>;
 
     /// Stream N bytes from file. If there is not enough bytes, it will wait until there are,
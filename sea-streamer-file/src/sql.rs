@@ -0,0 +1,379 @@
+//! Query `.ss` files with SQL, by means of a DataFusion [`TableProvider`].
+//!
+//! ```ignore
+//! let ctx = SessionContext::new();
+//! let table = FileTable::new(FileId::new("capture.ss"), Default::default()).await?;
+//! ctx.register_table(TableReference::bare("capture.ss"), Arc::new(table))?;
+//! ctx.sql(r#"SELECT stream_key, count(*) FROM "capture.ss" GROUP BY 1"#).await?.show().await?;
+//! ```
+//!
+//! The columns are the same as those produced by the [`crate::converter`]. With
+//! [`PayloadFormat::Json`], the schema of the payload columns is inferred from the first
+//! messages of the file, see [`FileTableOptions::set_schema_sample`].
+//!
+//! Predicates on `timestamp` are used to seek the file with [`SeekTarget::Timestamp`] before
+//! scanning, which only reads from the closest beacon onwards. This assumes that messages are
+//! written in the order of their timestamps, which is the case for files written by a producer.
+use std::{
+    any::Any,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use ::datafusion::{
+    arrow::{datatypes::SchemaRef, record_batch::RecordBatch},
+    catalog::Session,
+    datasource::TableProvider,
+    error::{DataFusionError, Result as DataFusionResult},
+    execution::{SendableRecordBatchStream, TaskContext},
+    logical_expr::{Between, BinaryExpr, Expr, Operator, TableProviderFilterPushDown, TableType},
+    physical_plan::{
+        stream::RecordBatchStreamAdapter,
+        streaming::{PartitionStream, StreamingTableExec},
+        ExecutionPlan,
+    },
+    scalar::ScalarValue,
+};
+use sea_streamer_types::{
+    export::{async_trait, futures::stream},
+    Buffer, Message as MessageTrait, Timestamp, SEA_STREAMER_INTERNAL,
+};
+
+use crate::{
+    converter::{PayloadFormat, RecordBatchBuilder, DEFAULT_BATCH_SIZE},
+    is_end_of_stream, FileErr, FileId, MessageSource, SeekErr, SeekTarget, StreamMode,
+};
+
+pub const DEFAULT_SCHEMA_SAMPLE: usize = 1024;
+
+#[derive(Debug, Clone)]
+pub struct FileTableOptions {
+    payload_format: PayloadFormat,
+    batch_size: usize,
+    schema_sample: usize,
+}
+
+/// A DataFusion table backed by a `.ss` file.
+#[derive(Debug)]
+pub struct FileTable {
+    file_id: FileId,
+    schema: SchemaRef,
+    json_schema: Option<SchemaRef>,
+    options: FileTableOptions,
+    messages_read: Arc<AtomicUsize>,
+}
+
+#[derive(Debug)]
+struct FilePartition {
+    file_id: FileId,
+    schema: SchemaRef,
+    json_schema: Option<SchemaRef>,
+    options: FileTableOptions,
+    since: Option<Timestamp>,
+    messages_read: Arc<AtomicUsize>,
+}
+
+struct Scan {
+    source: MessageSource,
+    builder: RecordBatchBuilder,
+    batch_size: usize,
+    ended: bool,
+    messages_read: Arc<AtomicUsize>,
+}
+
+impl Default for FileTableOptions {
+    fn default() -> Self {
+        Self {
+            payload_format: PayloadFormat::Json,
+            batch_size: DEFAULT_BATCH_SIZE,
+            schema_sample: DEFAULT_SCHEMA_SAMPLE,
+        }
+    }
+}
+
+impl FileTableOptions {
+    pub fn payload_format(&self) -> PayloadFormat {
+        self.payload_format
+    }
+    /// Default is `Json`, such that JSON payloads can be queried as columns.
+    pub fn set_payload_format(&mut self, v: PayloadFormat) -> &mut Self {
+        self.payload_format = v;
+        self
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+    /// Number of rows per RecordBatch.
+    ///
+    /// Default is [`DEFAULT_BATCH_SIZE`].
+    pub fn set_batch_size(&mut self, v: usize) -> &mut Self {
+        self.batch_size = v.max(1);
+        self
+    }
+
+    pub fn schema_sample(&self) -> usize {
+        self.schema_sample
+    }
+    /// Number of messages to read from the beginning of the file to infer the schema of JSON
+    /// payloads. Fields not seen in the sample are not queryable.
+    ///
+    /// Default is [`DEFAULT_SCHEMA_SAMPLE`].
+    pub fn set_schema_sample(&mut self, v: usize) -> &mut Self {
+        self.schema_sample = v.max(1);
+        self
+    }
+}
+
+impl FileTable {
+    /// Open the file and determine the schema.
+    pub async fn new(file_id: FileId, options: FileTableOptions) -> DataFusionResult<Self> {
+        let mut source = MessageSource::new(file_id.clone(), StreamMode::Replay)
+            .await
+            .map_err(external)?;
+        let mut builder = RecordBatchBuilder::new(options.payload_format);
+        while builder.len() < options.schema_sample {
+            let message = match source.next().await {
+                Ok(message) => message.message,
+                Err(FileErr::NotEnoughBytes) => break,
+                Err(e) => return Err(external(e)),
+            };
+            if is_end_of_stream(&message) {
+                break;
+            }
+            if message.header().stream_key().name() != SEA_STREAMER_INTERNAL {
+                builder.append(message.header(), message.message().as_bytes());
+            }
+        }
+        let sample = builder.finish()?;
+
+        Ok(Self {
+            file_id,
+            schema: sample.schema(),
+            json_schema: builder.json_schema().cloned(),
+            options,
+            messages_read: Default::default(),
+        })
+    }
+
+    pub fn file_id(&self) -> &FileId {
+        &self.file_id
+    }
+
+    /// Number of messages read from the file by the scans of this table so far. Messages
+    /// skipped by seeking to a timestamp are not read, and thus not counted.
+    pub fn messages_read(&self) -> usize {
+        self.messages_read.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl TableProvider for FileTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    async fn scan(
+        &self,
+        _state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        let since = filters
+            .iter()
+            .filter_map(seek_millis)
+            .max()
+            .map(|ms| Timestamp::from_unix_timestamp_nanos(ms as i128 * 1_000_000))
+            .transpose()
+            .map_err(|e| DataFusionError::External(Box::new(e)))?;
+        let partition = FilePartition {
+            file_id: self.file_id.clone(),
+            schema: self.schema.clone(),
+            json_schema: self.json_schema.clone(),
+            options: self.options.clone(),
+            since,
+            messages_read: self.messages_read.clone(),
+        };
+
+        Ok(Arc::new(StreamingTableExec::try_new(
+            self.schema.clone(),
+            vec![Arc::new(partition)],
+            projection,
+            vec![],
+            false,
+            limit,
+        )?))
+    }
+
+    /// Timestamp predicates are only used to skip the beginning of the file, so they are
+    /// still evaluated by DataFusion afterwards.
+    fn supports_filters_pushdown(
+        &self,
+        filters: &[&Expr],
+    ) -> DataFusionResult<Vec<TableProviderFilterPushDown>> {
+        Ok(filters
+            .iter()
+            .map(|f| match seek_millis(f) {
+                Some(_) => TableProviderFilterPushDown::Inexact,
+                None => TableProviderFilterPushDown::Unsupported,
+            })
+            .collect())
+    }
+}
+
+/// Messages with a timestamp (in millis) at or before the returned value can be skipped.
+fn seek_millis(expr: &Expr) -> Option<i64> {
+    match expr {
+        Expr::BinaryExpr(BinaryExpr { left, op, right }) => match op {
+            Operator::And => match (seek_millis(left), seek_millis(right)) {
+                (Some(a), Some(b)) => Some(a.max(b)),
+                (a, b) => a.or(b),
+            },
+            Operator::Or => Some(seek_millis(left)?.min(seek_millis(right)?)),
+            _ => match (left.as_ref(), right.as_ref()) {
+                (Expr::Column(c), Expr::Literal(v)) if c.name == "timestamp" => bound(*op, v),
+                (Expr::Literal(v), Expr::Column(c)) if c.name == "timestamp" => {
+                    bound(op.swap()?, v)
+                }
+                _ => None,
+            },
+        },
+        Expr::Between(Between {
+            expr,
+            negated: false,
+            low,
+            ..
+        }) => match (expr.as_ref(), low.as_ref()) {
+            (Expr::Column(c), Expr::Literal(v)) if c.name == "timestamp" => {
+                bound(Operator::GtEq, v)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn bound(op: Operator, value: &ScalarValue) -> Option<i64> {
+    let millis = match value {
+        ScalarValue::TimestampSecond(Some(v), _) => v.checked_mul(1000)?,
+        ScalarValue::TimestampMillisecond(Some(v), _) => *v,
+        ScalarValue::TimestampMicrosecond(Some(v), _) => v.div_euclid(1000),
+        ScalarValue::TimestampNanosecond(Some(v), _) => v.div_euclid(1_000_000),
+        _ => return None,
+    };
+    match op {
+        Operator::Gt => Some(millis),
+        Operator::GtEq | Operator::Eq => millis.checked_sub(1),
+        _ => None,
+    }
+}
+
+impl PartitionStream for FilePartition {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, _ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let file_id = self.file_id.clone();
+        let json_schema = self.json_schema.clone();
+        let options = self.options.clone();
+        let since = self.since;
+        let messages_read = self.messages_read.clone();
+
+        let batches = stream::try_unfold(None, move |scan: Option<Scan>| {
+            let file_id = file_id.clone();
+            let json_schema = json_schema.clone();
+            let options = options.clone();
+            let messages_read = messages_read.clone();
+            async move {
+                let mut scan = match scan {
+                    Some(scan) => scan,
+                    None => match Scan::open(file_id, json_schema, &options, since, messages_read)
+                        .await?
+                    {
+                        Some(scan) => scan,
+                        None => return Ok(None),
+                    },
+                };
+                Ok(scan.next_batch().await?.map(|batch| (batch, Some(scan))))
+            }
+        });
+
+        Box::pin(RecordBatchStreamAdapter::new(self.schema.clone(), batches))
+    }
+}
+
+impl Scan {
+    /// Returns None if there is nothing after `since`.
+    async fn open(
+        file_id: FileId,
+        json_schema: Option<SchemaRef>,
+        options: &FileTableOptions,
+        since: Option<Timestamp>,
+        messages_read: Arc<AtomicUsize>,
+    ) -> DataFusionResult<Option<Self>> {
+        let mut source = MessageSource::new(file_id, StreamMode::Replay)
+            .await
+            .map_err(external)?;
+        if let Some(since) = since {
+            match source.seek_all(SeekTarget::Timestamp(since)).await {
+                Ok(()) => (),
+                Err(FileErr::SeekErr(SeekErr::OutOfBound)) => return Ok(None),
+                Err(e) => return Err(external(e)),
+            }
+        }
+        let mut builder = RecordBatchBuilder::new(options.payload_format);
+        if let Some(json_schema) = json_schema {
+            builder.set_json_schema(json_schema);
+        }
+        Ok(Some(Self {
+            source,
+            builder,
+            batch_size: options.batch_size,
+            ended: false,
+            messages_read,
+        }))
+    }
+
+    async fn next_batch(&mut self) -> DataFusionResult<Option<RecordBatch>> {
+        while !self.ended && self.builder.len() < self.batch_size {
+            let message = match self.source.next().await {
+                Ok(message) => message.message,
+                Err(FileErr::NotEnoughBytes) => {
+                    self.ended = true;
+                    break;
+                }
+                Err(e) => return Err(external(e)),
+            };
+            self.messages_read.fetch_add(1, Ordering::Relaxed);
+            if is_end_of_stream(&message) {
+                self.ended = true;
+                break;
+            }
+            if message.header().stream_key().name() != SEA_STREAMER_INTERNAL {
+                self.builder
+                    .append(message.header(), message.message().as_bytes());
+            }
+        }
+        if self.builder.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(self.builder.finish()?))
+        }
+    }
+}
+
+fn external(err: FileErr) -> DataFusionError {
+    DataFusionError::External(Box::new(err))
+}
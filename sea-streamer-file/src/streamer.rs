@@ -1,15 +1,15 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 use thiserror::Error;
 
 use crate::{
-    consumer::new_consumer, end_producer, format::Header, new_producer, AsyncFile, FileConsumer,
-    FileErr, FileId, FileProducer, FileResult, DEFAULT_BEACON_INTERVAL, DEFAULT_FILE_SIZE_LIMIT,
-    DEFAULT_PREFETCH_MESSAGE,
+    consumer::new_consumer, crypto::KeyProvider, end_producer, format::Header, new_producer,
+    AsyncFile, FileConsumer, FileErr, FileId, FileProducer, FileResult, DEFAULT_BEACON_INTERVAL,
+    DEFAULT_FILE_SIZE_LIMIT, DEFAULT_PREFETCH_MESSAGE,
 };
 use sea_streamer_types::{
     export::async_trait, ConnectOptions as ConnectOptionsTrait, ConsumerGroup, ConsumerMode,
-    ConsumerOptions as ConsumerOptionsTrait, ProducerOptions as ProducerOptionsTrait, StreamErr,
-    StreamKey, StreamUrlErr, Streamer as StreamerTrait, StreamerUri,
+    ConsumerOptions as ConsumerOptionsTrait, LoadBalance, ProducerOptions as ProducerOptionsTrait,
+    StreamErr, StreamKey, StreamUrlErr, Streamer as StreamerTrait, StreamerUri,
 };
 
 #[derive(Debug, Clone)]
@@ -25,6 +25,7 @@ pub struct FileConnectOptions {
     beacon_interval: u32,
     file_size_limit: u64,
     prefetch_message: usize,
+    key_provider: Option<Arc<dyn KeyProvider>>,
 }
 
 #[derive(Debug, Clone)]
@@ -40,6 +41,7 @@ enum CreateFileOption {
 pub struct FileConsumerOptions {
     mode: ConsumerMode,
     group: Option<ConsumerGroup>,
+    load_balance: LoadBalance,
     auto_stream_reset: AutoStreamReset,
     live_streaming: bool,
 }
@@ -70,6 +72,10 @@ pub enum ConfigErr {
     LatestButNotLive,
     #[error("Consumers in the same ConsumerGroup must use the same ConsumerMode")]
     SameGroupSameMode,
+    #[error("Consumers in the same ConsumerGroup must use the same KeyProvider")]
+    SameGroupSameKeyProvider,
+    #[error("Consumers in the same ConsumerGroup must use the same LoadBalance")]
+    SameGroupSameLoadBalance,
     #[error("Please choose a 'better aligned' beacon interval")]
     InvalidBeaconInterval,
 }
@@ -172,8 +178,10 @@ impl StreamerTrait for FileStreamer {
             self.file_id.clone(),
             stream_mode,
             options.group,
+            options.load_balance,
             streams.to_vec(),
             self.options.prefetch_message,
+            self.options.key_provider.clone(),
         )
         .await?;
         Ok(consumer)
@@ -201,6 +209,7 @@ impl Default for FileConnectOptions {
             beacon_interval: DEFAULT_BEACON_INTERVAL,
             file_size_limit: DEFAULT_FILE_SIZE_LIMIT,
             prefetch_message: DEFAULT_PREFETCH_MESSAGE,
+            key_provider: None,
         }
     }
 }
@@ -280,6 +289,20 @@ impl FileConnectOptions {
         self.prefetch_message = v;
         self
     }
+
+    pub fn key_provider(&self) -> Option<&Arc<dyn KeyProvider>> {
+        self.key_provider.as_ref()
+    }
+
+    /// Producers encrypt message payloads of new files with the current key, and continue
+    /// existing files with their own keys. Consumers decrypt encrypted files with the keys.
+    /// Requires the `encryption` feature, see [`crate::crypto`].
+    ///
+    /// Default is `None`, i.e. files are not encrypted.
+    pub fn set_key_provider(&mut self, v: Arc<dyn KeyProvider>) -> &mut Self {
+        self.key_provider = Some(v);
+        self
+    }
 }
 
 impl ConsumerOptionsTrait for FileConsumerOptions {
@@ -289,6 +312,7 @@ impl ConsumerOptionsTrait for FileConsumerOptions {
         Self {
             mode,
             group: None,
+            load_balance: Default::default(),
             auto_stream_reset: AutoStreamReset::Latest,
             live_streaming: true,
         }
@@ -303,7 +327,8 @@ impl ConsumerOptionsTrait for FileConsumerOptions {
     }
 
     /// If multiple consumers share the same group, only one in the group will receive a message.
-    /// This is load-balanced in a round-robin fashion.
+    /// This is load-balanced in a round-robin fashion, unless set otherwise with
+    /// [`FileConsumerOptions::set_load_balance`].
     fn set_consumer_group(&mut self, group: ConsumerGroup) -> FileResult<&mut Self> {
         self.group = Some(group);
         Ok(self)
@@ -332,6 +357,18 @@ impl FileConsumerOptions {
     pub fn live_streaming(&self) -> &bool {
         &self.live_streaming
     }
+
+    /// How messages are distributed among the consumers of the same group. The strategy of the
+    /// first consumer joining a group is used.
+    ///
+    /// If unset, defaults to `RoundRobin`.
+    pub fn set_load_balance(&mut self, v: LoadBalance) -> &mut Self {
+        self.load_balance = v;
+        self
+    }
+    pub fn load_balance(&self) -> &LoadBalance {
+        &self.load_balance
+    }
 }
 
 impl Default for FileConsumerOptions {
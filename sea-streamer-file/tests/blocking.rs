@@ -0,0 +1,119 @@
+mod util;
+use util::*;
+
+// cargo test --test blocking --features=test,runtime-tokio -- --nocapture
+#[cfg(feature = "test")]
+#[test]
+fn blocking_sample() -> anyhow::Result<()> {
+    use sea_streamer_file::{blocking::MessageReader, is_end_of_stream, FileErr};
+    use sea_streamer_types::Message;
+    use std::{fs::File, io::BufReader};
+
+    let file = BufReader::new(File::open("tests/sample-1.ss")?);
+    let mut reader = MessageReader::new(file)?;
+    let mut seq = Vec::new();
+    loop {
+        match reader.next() {
+            Ok(m) => {
+                if is_end_of_stream(&m.message) {
+                    break;
+                }
+                if m.message.stream_key().name() == "event" {
+                    seq.push(m.message.sequence());
+                }
+            }
+            Err(FileErr::NotEnoughBytes) => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    assert_eq!(seq, (1..=22).collect::<Vec<_>>());
+
+    Ok(())
+}
+
+#[cfg(feature = "test")]
+#[cfg_attr(feature = "runtime-tokio", tokio::test)]
+#[cfg_attr(feature = "runtime-async-std", async_std::test)]
+async fn blocking_loopback() -> anyhow::Result<()> {
+    use sea_streamer_file::{
+        blocking::{MessageReader, MessageWriter},
+        format::Header,
+        is_end_of_stream, FileErr, MessageSource, StreamMode,
+    };
+    use sea_streamer_types::{
+        Buffer, Message, MessageHeader, OwnedMessage, ShardId, StreamKey, Timestamp,
+    };
+    use std::{fs::File, io::BufWriter};
+
+    const TEST: &str = "blocking";
+
+    let now = Timestamp::now_utc();
+    let now = now.replace_millisecond(now.millisecond())?;
+    let file_id = temp_file(format!("{}-{}", TEST, millis_of(&now)).as_str())?;
+    println!("{file_id}");
+
+    let header = Header {
+        file_name: TEST.to_owned(),
+        created_at: now,
+        beacon_interval: 1024,
+        key_id: None,
+    };
+    let message = |i: u64| {
+        let key = StreamKey::new(if i % 2 == 0 { "hello" } else { "world" }).unwrap();
+        let header = MessageHeader::new(key, ShardId::new(0), i, now);
+        OwnedMessage::new(
+            header,
+            format!("message-{i}-{}", "x".repeat(i as usize)).into_bytes(),
+        )
+    };
+
+    // write in memory, then read back
+    let mut writer = MessageWriter::new(Vec::new(), header.clone())?;
+    for i in 0..100 {
+        writer.write(message(i))?;
+    }
+    let bytes = writer.end(true)?;
+    assert!(bytes.len() > 5 * 1024);
+
+    let mut reader = MessageReader::new(bytes.as_slice())?;
+    assert_eq!(reader.file_header(), &header);
+    for i in 0..100 {
+        let m = reader.next()?;
+        assert_eq!(m.message.header(), message(i).header());
+        assert_eq!(
+            m.message.message().as_bytes(),
+            message(i).message().as_bytes()
+        );
+    }
+    assert!(reader.beacon().0 > 0);
+    assert!(!reader.beacon().1.is_empty());
+    assert!(is_end_of_stream(&reader.next()?.message));
+    assert!(matches!(reader.next(), Err(FileErr::NotEnoughBytes)));
+
+    // the output must be identical to that read by MessageSource
+    std::fs::write(file_id.path(), &bytes)?;
+    let mut source = MessageSource::new(file_id.clone(), StreamMode::Replay).await?;
+    for i in 0..100 {
+        let m = source.next().await?;
+        assert_eq!(m.message.header(), message(i).header());
+    }
+
+    // write to a file
+    let file = BufWriter::new(File::create(file_id.path())?);
+    let mut writer = MessageWriter::new(file, header)?;
+    for i in 0..100 {
+        writer.write(message(i))?;
+    }
+    writer.end(false)?;
+    let mut source = MessageSource::new(file_id, StreamMode::Replay).await?;
+    for i in 0..100 {
+        let m = source.next().await?;
+        assert_eq!(
+            m.message.message().as_bytes(),
+            message(i).message().as_bytes()
+        );
+    }
+    assert!(matches!(source.next().await, Err(FileErr::NotEnoughBytes)));
+
+    Ok(())
+}
@@ -302,6 +302,84 @@ async fn group() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[cfg(feature = "test")]
+#[cfg_attr(feature = "runtime-tokio", tokio::test)]
+#[cfg_attr(feature = "runtime-async-std", async_std::test)]
+async fn sticky() -> anyhow::Result<()> {
+    use sea_streamer_file::{
+        AutoStreamReset, ConfigErr, FileConsumerOptions, FileErr, FileStreamer, MessageSink,
+        DEFAULT_FILE_SIZE_LIMIT,
+    };
+    use sea_streamer_types::{
+        Consumer, ConsumerGroup, ConsumerMode, ConsumerOptions, LoadBalance, Message,
+        MessageHeader, OwnedMessage, ShardId, StreamErr, StreamKey, Streamer, Timestamp,
+    };
+    use std::collections::BTreeMap;
+
+    const TEST: &str = "sticky";
+    INIT.call_once(env_logger::init);
+
+    let now = Timestamp::now_utc();
+    let file_id = temp_file(format!("{}-{}", TEST, millis_of(&now)).as_str())?;
+    println!("{file_id}");
+    let stream_key = StreamKey::new("nuts")?;
+
+    let streamer = FileStreamer::connect(file_id.to_streamer_uri()?, Default::default()).await?;
+    let mut sink = MessageSink::new(file_id.clone(), 1024, DEFAULT_FILE_SIZE_LIMIT).await?;
+
+    let mut options = FileConsumerOptions::new(ConsumerMode::LoadBalanced);
+    options.set_consumer_group(ConsumerGroup::new("friends"))?;
+    options.set_auto_stream_reset(AutoStreamReset::Earliest);
+    options.set_load_balance(LoadBalance::Sticky);
+
+    let chip = streamer
+        .create_consumer(std::slice::from_ref(&stream_key), options.clone())
+        .await?;
+    let dale = streamer
+        .create_consumer(std::slice::from_ref(&stream_key), options.clone())
+        .await?;
+    // members of a group must balance in the same way
+    let mut round_robin = options.clone();
+    round_robin.set_load_balance(LoadBalance::RoundRobin);
+    assert!(matches!(
+        streamer
+            .create_consumer(std::slice::from_ref(&stream_key), round_robin)
+            .await,
+        Err(StreamErr::Backend(FileErr::ConfigErr(
+            ConfigErr::SameGroupSameLoadBalance
+        )))
+    ));
+
+    for i in 0..80 {
+        let header = MessageHeader::new(
+            stream_key.clone(),
+            ShardId::new(i % 8),
+            i / 8,
+            Timestamp::now_utc(),
+        );
+        sink.write(OwnedMessage::new(header, Vec::new()))?;
+    }
+    sink.flush().await?;
+
+    // each shard goes to one of them only, in order
+    let mut owners = BTreeMap::new();
+    for (name, consumer) in [("chip", &chip), ("dale", &dale)] {
+        let mut sequences: BTreeMap<ShardId, u64> = BTreeMap::new();
+        for _ in 0..40 {
+            let mess = consumer.next().await?;
+            let shard_id = mess.shard_id();
+            assert_eq!(*owners.entry(shard_id).or_insert(name), name);
+            let seq = sequences.entry(shard_id).or_default();
+            assert_eq!(mess.sequence(), *seq);
+            *seq += 1;
+        }
+        assert_eq!(sequences.len(), 4);
+    }
+
+    println!(" ... ok");
+    Ok(())
+}
+
 #[cfg(feature = "test")]
 #[cfg_attr(feature = "runtime-tokio", tokio::test)]
 #[cfg_attr(feature = "runtime-async-std", async_std::test)]
@@ -0,0 +1,115 @@
+mod util;
+use util::*;
+
+static INIT: std::sync::Once = std::sync::Once::new();
+
+// cargo test --test converter --features=test,runtime-tokio,converter -- --nocapture
+#[cfg(all(feature = "test", feature = "converter"))]
+#[cfg_attr(feature = "runtime-tokio", tokio::test)]
+#[cfg_attr(feature = "runtime-async-std", async_std::test)]
+async fn converter() -> anyhow::Result<()> {
+    use arrow::{
+        array::{Array, Int64Array, StringArray, UInt64Array},
+        ipc::reader::FileReader,
+    };
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use sea_streamer_file::{
+        converter::{convert, ConverterOptions, OutputFormat, PayloadFormat},
+        MessageSink, DEFAULT_FILE_SIZE_LIMIT,
+    };
+    use sea_streamer_types::{MessageHeader, OwnedMessage, ShardId, StreamKey, Timestamp};
+    use std::{fs::File, path::PathBuf};
+
+    const TEST: &str = "converter";
+    INIT.call_once(env_logger::init);
+
+    let now = Timestamp::now_utc();
+    let file_id = temp_file(format!("{}-{}", TEST, millis_of(&now)).as_str())?;
+    println!("{file_id}");
+
+    let hello = StreamKey::new("hello")?;
+    let world = StreamKey::new("world")?;
+    let mut sink = MessageSink::new(file_id.clone(), 1024, DEFAULT_FILE_SIZE_LIMIT).await?;
+    for i in 0..50 {
+        let key = if i % 5 == 0 { &world } else { &hello };
+        let header = MessageHeader::new(key.clone(), ShardId::new(1), i, now);
+        let payload = format!(r#"{{"id":{i},"user":{{"name":"n-{i}"}}}}"#);
+        sink.write(OwnedMessage::new(header, payload.into_bytes()))?;
+    }
+    sink.flush().await?;
+
+    let output = PathBuf::from(format!("/tmp/{}-{}.parquet", TEST, millis_of(&now)));
+    let mut options = ConverterOptions::default();
+    options
+        .set_payload_format(PayloadFormat::Json)
+        .set_batch_size(16)
+        .set_chunk_size(Some(25))
+        .set_stream_keys(vec![hello.clone()]);
+    let outputs = convert(file_id.clone(), output, &options).await?;
+    assert_eq!(outputs.len(), 2);
+
+    let mut seq = Vec::new();
+    for path in outputs.iter() {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?.build()?;
+        for batch in reader {
+            let batch = batch?;
+            let stream_key = batch
+                .column_by_name("stream_key")
+                .unwrap()
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .unwrap();
+            assert!(stream_key.iter().all(|k| k == Some("hello")));
+            let sequence = batch
+                .column_by_name("sequence")
+                .unwrap()
+                .as_any()
+                .downcast_ref::<UInt64Array>()
+                .unwrap();
+            let id = batch
+                .column_by_name("payload.id")
+                .unwrap()
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .unwrap();
+            let name = batch
+                .column_by_name("payload.user.name")
+                .unwrap()
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .unwrap();
+            for i in 0..batch.num_rows() {
+                assert_eq!(sequence.value(i), id.value(i) as u64);
+                assert_eq!(name.value(i), format!("n-{}", id.value(i)));
+                seq.push(sequence.value(i));
+            }
+        }
+    }
+    assert_eq!(seq, (0..50).filter(|i| i % 5 != 0).collect::<Vec<_>>());
+
+    let output = PathBuf::from(format!("/tmp/{}-{}.arrow", TEST, millis_of(&now)));
+    options
+        .set_output_format(OutputFormat::ArrowIpc)
+        .set_payload_format(PayloadFormat::Utf8)
+        .set_chunk_size(None)
+        .set_stream_keys(Vec::new());
+    let outputs = convert(file_id, output, &options).await?;
+    assert_eq!(outputs.len(), 1);
+
+    let reader = FileReader::try_new(File::open(&outputs[0])?, None)?;
+    let mut rows = 0;
+    for batch in reader {
+        let batch = batch?;
+        let payload = batch
+            .column_by_name("payload")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(payload.null_count(), 0);
+        rows += batch.num_rows();
+    }
+    assert_eq!(rows, 50);
+
+    Ok(())
+}
@@ -0,0 +1,193 @@
+mod util;
+use util::*;
+
+static INIT: std::sync::Once = std::sync::Once::new();
+
+// cargo test --test encryption --features=test,runtime-tokio,encryption -- --nocapture
+// cargo test --test encryption --features=test,runtime-async-std,encryption -- --nocapture
+#[cfg(all(feature = "test", feature = "encryption"))]
+#[cfg_attr(feature = "runtime-tokio", tokio::test)]
+#[cfg_attr(feature = "runtime-async-std", async_std::test)]
+async fn encryption() -> anyhow::Result<()> {
+    use std::{sync::Arc, time::Duration};
+
+    use sea_streamer_file::{
+        blocking::MessageReader,
+        crypto::{CryptoErr, KeyRing},
+        query_streamer, AutoStreamReset, ConfigErr, FileConnectOptions, FileConsumerOptions,
+        FileErr, FileStreamer, MessageSource, StreamMode,
+    };
+    use sea_streamer_runtime::sleep;
+    use sea_streamer_types::{
+        Buffer, Consumer, ConsumerGroup, ConsumerMode, ConsumerOptions, Message, Producer,
+        StreamErr, StreamKey, Streamer, Timestamp,
+    };
+
+    INIT.call_once(env_logger::init);
+
+    const TEST: &str = "encryption";
+    let now = Timestamp::now_utc();
+    let file_id = temp_file(format!("{}-{}", TEST, millis_of(&now)).as_str())?;
+    println!("{file_id}");
+    let stream_key = StreamKey::new("secret")?;
+    let streams = [stream_key.clone()];
+
+    let mut keys: KeyRing = "
+        # comment
+        2023-10 000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f
+        2023-09 ffeeddccbbaa99887766554433221100ffeeddccbbaa99887766554433221100
+    "
+    .parse()?;
+    assert_eq!(keys.current_key_id(), Some("2023-10"));
+    assert_eq!(
+        "2023-10 0001".parse::<KeyRing>().unwrap_err(),
+        CryptoErr::InvalidKey
+    );
+
+    let mut options = FileConnectOptions::default();
+    options.set_beacon_interval(1024)?;
+    options.set_end_with_eos(true);
+    options.set_key_provider(Arc::new(keys.clone()));
+    let streamer = FileStreamer::connect(file_id.to_streamer_uri()?, options.clone()).await?;
+    let mut producer = streamer
+        .create_producer(stream_key.clone(), Default::default())
+        .await?;
+    for i in 0..100 {
+        producer.send(format!("plaintext-{i}"))?;
+    }
+    producer.flush().await?;
+    streamer.disconnect().await?;
+    std::mem::drop(producer);
+    sleep(Duration::from_millis(1)).await;
+
+    // payloads are not readable from the file, but stream keys are
+    let bytes = std::fs::read(file_id.path())?;
+    let contains = |needle: &[u8]| bytes.windows(needle.len()).any(|w| w == needle);
+    assert!(!contains(b"plaintext"));
+    assert!(contains(b"secret"));
+    assert!(contains(b"2023-10"));
+
+    let mut source = MessageSource::new(file_id.clone(), StreamMode::Replay).await?;
+    assert_eq!(source.file_header().key_id.as_deref(), Some("2023-10"));
+    assert!(matches!(
+        source.next().await,
+        Err(FileErr::CryptoErr(CryptoErr::KeyNotFound(_)))
+    ));
+    // seeking does not need the key
+    source
+        .seek_all(sea_streamer_file::SeekTarget::SeqNo(50))
+        .await?;
+    source.set_key_provider(&keys)?;
+    assert_eq!(
+        source.next().await?.message.message().as_str()?,
+        "plaintext-49"
+    );
+
+    // rotate the key: the existing file continues with its own key
+    keys.set_current_key("2023-09")?;
+    let mut options = options.clone();
+    options.set_key_provider(Arc::new(keys.clone()));
+    let streamer = FileStreamer::connect(file_id.to_streamer_uri()?, options.clone()).await?;
+    let mut producer = streamer
+        .create_producer(stream_key.clone(), Default::default())
+        .await?;
+    for i in 100..150 {
+        producer.send(format!("plaintext-{i}"))?;
+    }
+    producer.flush().await?;
+
+    let mut consumer_options = FileConsumerOptions::new(ConsumerMode::RealTime);
+    consumer_options.set_auto_stream_reset(AutoStreamReset::Earliest);
+    let consumer = streamer
+        .create_consumer(&streams, consumer_options.clone())
+        .await?;
+    for i in 0..150 {
+        let message = consumer.next().await?;
+        assert_eq!(message.sequence(), i + 1);
+        assert_eq!(message.message().as_str()?, format!("plaintext-{i}"));
+    }
+    streamer.disconnect().await?;
+    std::mem::drop(producer);
+    std::mem::drop(consumer);
+    sleep(Duration::from_millis(1)).await;
+
+    // a consumer without the key fails
+    let mut options = FileConnectOptions::default();
+    options.set_beacon_interval(1024)?;
+    let streamer = FileStreamer::connect(file_id.to_streamer_uri()?, options).await?;
+    let consumer = streamer.create_consumer(&streams, consumer_options).await?;
+    assert!(matches!(
+        consumer.next().await,
+        Err(StreamErr::Backend(FileErr::CryptoErr(
+            CryptoErr::KeyNotFound(_)
+        )))
+    ));
+    std::mem::drop(consumer);
+
+    // the blocking reader can decrypt as well
+    let mut reader = MessageReader::new(std::io::BufReader::new(std::fs::File::open(
+        file_id.path(),
+    )?))?;
+    reader.set_key_provider(&keys)?;
+    assert_eq!(reader.next()?.message.message().as_str()?, "plaintext-0");
+
+    // a key ring without the file's key
+    let mut others = KeyRing::new();
+    others.add_key("2023-11", [7; 32])?;
+    let mut source = MessageSource::new(file_id.clone(), StreamMode::Replay).await?;
+    assert!(matches!(
+        source.set_key_provider(&others),
+        Err(FileErr::CryptoErr(CryptoErr::KeyNotFound(_)))
+    ));
+
+    // a wrong key with the same id
+    let mut wrong = KeyRing::new();
+    wrong.add_key("2023-10", [7; 32])?;
+    source.set_key_provider(&wrong)?;
+    assert!(matches!(
+        source.next().await,
+        Err(FileErr::CryptoErr(CryptoErr::DecryptionFailed))
+    ));
+
+    // consumers with different key providers do not share a streamer
+    let file_id = temp_file(format!("{}-{}-live", TEST, millis_of(&now)).as_str())?;
+    let mut options = FileConnectOptions::default();
+    options.set_key_provider(Arc::new(keys.clone()));
+    let streamer = FileStreamer::connect(file_id.to_streamer_uri()?, options).await?;
+    let keyless =
+        FileStreamer::connect(file_id.to_streamer_uri()?, FileConnectOptions::default()).await?;
+    let mut producer = streamer
+        .create_producer(stream_key.clone(), Default::default())
+        .await?;
+
+    let consumer_options = FileConsumerOptions::new(ConsumerMode::RealTime);
+    let consumer = streamer
+        .create_consumer(&streams, consumer_options.clone())
+        .await?;
+    let keyless_consumer = keyless.create_consumer(&streams, consumer_options).await?;
+    assert_eq!(query_streamer(&file_id).await.unwrap().len(), 2);
+
+    producer.send("plaintext-live")?;
+    producer.flush().await?;
+    assert_eq!(consumer.next().await?.message().as_str()?, "plaintext-live");
+    assert!(matches!(
+        keyless_consumer.next().await,
+        Err(StreamErr::Backend(FileErr::CryptoErr(
+            CryptoErr::KeyNotFound(_)
+        )))
+    ));
+
+    let mut group_options = FileConsumerOptions::new(ConsumerMode::LoadBalanced);
+    group_options.set_consumer_group(ConsumerGroup::new("group"))?;
+    let _member = streamer
+        .create_consumer(&streams, group_options.clone())
+        .await?;
+    assert!(matches!(
+        keyless.create_consumer(&streams, group_options).await,
+        Err(StreamErr::Backend(FileErr::ConfigErr(
+            ConfigErr::SameGroupSameKeyProvider
+        )))
+    ));
+
+    Ok(())
+}
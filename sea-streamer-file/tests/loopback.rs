@@ -61,6 +61,7 @@ async fn loopback() -> anyhow::Result<()> {
         file_name: "hello".to_owned(),
         created_at: timestamp,
         beacon_interval: 12345,
+        key_id: None,
     };
     let size = HeaderV1::size();
     assert_eq!(size, header.clone().write_to(&mut sink)?);
@@ -68,6 +69,15 @@ async fn loopback() -> anyhow::Result<()> {
     let read = HeaderV1::read_from(&mut source).await?;
     assert_eq!(header, read);
 
+    let header = HeaderV1 {
+        key_id: Some("2023-10".to_owned()),
+        ..header
+    };
+    assert_eq!(size, header.clone().write_to(&mut sink)?);
+    sink.flush(3).await?;
+    let read = HeaderV1::read_from(&mut source).await?;
+    assert_eq!(header, read);
+
     let mess_header = format::MessageHeader(MessageHeader::new(
         StreamKey::new("stream_key")?,
         ShardId::new(1122334455667788),
@@ -209,6 +219,7 @@ async fn beacon() -> anyhow::Result<()> {
         file_name: path.to_string(),
         created_at: now,
         beacon_interval: 128,
+        key_id: None,
     };
     header.clone().write_to(&mut sink)?;
     sink.flush(1).await?;
@@ -594,6 +605,18 @@ async fn source() -> anyhow::Result<()> {
             .await;
         assert!(matches!(err, Err(FileErr::SeekErr(SeekErr::OutOfBound))));
         println!("Seek by Timestamp ... ok");
+
+        // Seek regardless of stream
+        let ts = Timestamp::from_unix_timestamp_nanos((start + 7) as i128 * 1_000_000)?;
+        source.seek_all(SeekTarget::Timestamp(ts)).await?;
+        for i in 8..=10 {
+            let m = source.next().await?;
+            assert_eq!(m.message.header().sequence(), &i);
+        }
+        let ts = Timestamp::from_unix_timestamp_nanos((start + 10) as i128 * 1_000_000)?;
+        let err = source.seek_all(SeekTarget::Timestamp(ts)).await;
+        assert!(matches!(err, Err(FileErr::SeekErr(SeekErr::OutOfBound))));
+        println!("Seek all by Timestamp ... ok");
     }
 
     fn now() -> Timestamp {
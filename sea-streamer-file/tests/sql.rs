@@ -0,0 +1,91 @@
+mod util;
+use util::*;
+
+static INIT: std::sync::Once = std::sync::Once::new();
+
+// cargo test --test sql --features=test,runtime-tokio,datafusion -- --nocapture
+#[cfg(all(feature = "test", feature = "datafusion", feature = "runtime-tokio"))]
+#[tokio::test]
+async fn sql() -> anyhow::Result<()> {
+    use datafusion::{
+        arrow::array::{Array, Int64Array, StringArray},
+        common::TableReference,
+        prelude::SessionContext,
+    };
+    use sea_streamer_file::{
+        sql::{FileTable, FileTableOptions},
+        MessageSink, DEFAULT_FILE_SIZE_LIMIT,
+    };
+    use sea_streamer_types::{MessageHeader, OwnedMessage, ShardId, StreamKey, Timestamp};
+    use std::sync::Arc;
+
+    const TEST: &str = "sql";
+    INIT.call_once(env_logger::init);
+
+    let now = Timestamp::now_utc();
+    let file_id = temp_file(format!("{}-{}", TEST, millis_of(&now)).as_str())?;
+    println!("{file_id}");
+
+    let start = now.unix_timestamp() * 1000;
+    let mut sink = MessageSink::new(file_id.clone(), 1024, DEFAULT_FILE_SIZE_LIMIT).await?;
+    for i in 0..100 {
+        let key = StreamKey::new(if i % 4 == 0 { "world" } else { "hello" })?;
+        let ts = Timestamp::from_unix_timestamp_nanos((start + i as i64) as i128 * 1_000_000)?;
+        let header = MessageHeader::new(key, ShardId::new(0), i, ts);
+        let payload = format!(r#"{{"id":{i},"user":{{"name":"n-{}"}}}}"#, i % 3);
+        sink.write(OwnedMessage::new(header, payload.into_bytes()))?;
+    }
+    sink.flush().await?;
+
+    let ctx = SessionContext::new();
+    let table = Arc::new(FileTable::new(file_id.clone(), FileTableOptions::default()).await?);
+    ctx.register_table(TableReference::bare("capture.ss"), table.clone())?;
+
+    let query = format!(
+        r#"SELECT stream_key, count(*) FROM "capture.ss"
+        WHERE timestamp >= to_timestamp_millis({}) GROUP BY 1 ORDER BY 1"#,
+        start + 50
+    );
+    // the timestamp predicate is handed to the table
+    let plan = ctx.sql(&query).await?.into_optimized_plan()?;
+    let plan = format!("{}", plan.display_indent());
+    assert!(plan.contains("partial_filters=["), "{plan}");
+    assert!(plan.contains("timestamp >="), "{plan}");
+
+    let batches = ctx.sql(&query).await?.collect().await?;
+    // the scan seeks to a beacon before the 50th message, instead of reading from the start
+    let read = table.messages_read();
+    assert!((50..100).contains(&read), "{read}");
+    assert_eq!(batches.len(), 1);
+    let keys = batches[0]
+        .column(0)
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .unwrap();
+    let counts = batches[0]
+        .column(1)
+        .as_any()
+        .downcast_ref::<Int64Array>()
+        .unwrap();
+    assert_eq!(keys.value(0), "hello");
+    assert_eq!(counts.value(0), 38);
+    assert_eq!(keys.value(1), "world");
+    assert_eq!(counts.value(1), 12);
+
+    let batches = ctx
+        .sql(r#"SELECT count(*) FROM "capture.ss" WHERE "payload.user.name" = 'n-0'"#)
+        .await?
+        .collect()
+        .await?;
+    let counts = batches[0]
+        .column(0)
+        .as_any()
+        .downcast_ref::<Int64Array>()
+        .unwrap();
+    assert_eq!(counts.value(0), 34);
+    assert_eq!(counts.null_count(), 0);
+    // without a timestamp predicate, all messages are read
+    assert_eq!(table.messages_read() - read, 100);
+
+    Ok(())
+}
@@ -0,0 +1,197 @@
+//! Restore the messages of a SeaStreamer file, e.g. one written by `redis-streams-dump`, into Redis Streams.
+use anyhow::{anyhow, bail, Result};
+use sea_streamer_file::{is_end_of_stream, FileErr, FileId, MessageSource, StreamMode};
+use sea_streamer_redis::{
+    RedisMessageId, RedisProducerOptions, RedisStreamer, SendFuture, Sharder, SharderConfig,
+};
+use sea_streamer_types::{
+    Message, Producer, StreamKey, Streamer, StreamerUri, Timestamp, SEA_STREAMER_INTERNAL,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+use structopt::StructOpt;
+use tokio::time::Instant;
+
+/// Number of messages in flight before we wait for their receipts
+const MAX_IN_FLIGHT: usize = 10_000;
+/// Longest a message is delayed for, about 30 years
+const MAX_DELAY: Duration = Duration::from_secs(1 << 30);
+
+#[derive(Debug, StructOpt)]
+struct Args {
+    #[structopt(long, help = "Input file, e.g. one written by `redis-streams-dump`")]
+    input: FileId,
+    #[structopt(long, help = "Streamer URI, i.e. try `redis://localhost`")]
+    output: StreamerUri,
+    #[structopt(
+        long,
+        parse(try_from_str = parse_mapping),
+        help = "Restore a stream under another key, e.g. `clock=clock-restored`. Can be repeated"
+    )]
+    map: Vec<(StreamKey, StreamKey)>,
+    #[structopt(
+        long,
+        help = "Add the messages with their original message ids. Fails if the target stream already has a greater id"
+    )]
+    keep_ids: bool,
+    #[structopt(
+        long,
+        help = "Restore the messages into the shards they were dumped from, i.e. `STREAM_KEY:SHARD_ID`"
+    )]
+    sharded: bool,
+    #[structopt(
+        long,
+        help = "Replay with the original intervals between messages, sped up by this factor, e.g. 1 for real time"
+    )]
+    pace: Option<f64>,
+    #[structopt(long, help = "Limit the throughput, in messages per second")]
+    rate: Option<f64>,
+}
+
+/// Routes each message to the shard it came from, which is passed as the key.
+#[derive(Debug, Clone)]
+struct OriginalShard;
+
+impl SharderConfig for OriginalShard {
+    fn init(&self) -> Box<dyn Sharder> {
+        Box::new(self.clone())
+    }
+}
+
+impl Sharder for OriginalShard {
+    fn shard(&mut self, _: &StreamKey, _: &[u8]) -> u64 {
+        unreachable!("Every message is sent with a key")
+    }
+
+    fn shard_by_key(&mut self, _: &StreamKey, key: &[u8], _: &[u8]) -> u64 {
+        u64::from_be_bytes(key.try_into().expect("The key is a shard id"))
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+
+    let Args {
+        input,
+        output,
+        map,
+        keep_ids,
+        sharded,
+        pace,
+        rate,
+    } = Args::from_args();
+
+    if !pace.map_or(true, is_positive) || !rate.map_or(true, is_positive) {
+        bail!("`--pace` and `--rate` must be positive numbers");
+    }
+    let map: HashMap<StreamKey, StreamKey> = map.into_iter().collect();
+
+    let mut source = MessageSource::new(input.clone(), StreamMode::Replay).await?;
+
+    let streamer = RedisStreamer::connect(output, Default::default()).await?;
+    let mut options = RedisProducerOptions::default();
+    if sharded {
+        options.set_sharder(OriginalShard);
+    }
+    let mut producer = streamer.create_generic_producer(options).await?;
+
+    let started = Instant::now();
+    let mut first: Option<Timestamp> = None;
+    let mut receipts: VecDeque<SendFuture> = VecDeque::new();
+    let mut count: u64 = 0;
+    loop {
+        let message = match source.next().await {
+            Ok(m) => m.message,
+            Err(FileErr::NotEnoughBytes | FileErr::StreamEnded) => break,
+            Err(e) => return Err(e.into()),
+        };
+        if is_end_of_stream(&message) {
+            break;
+        }
+        if message.stream_key().name() == SEA_STREAMER_INTERNAL {
+            continue;
+        }
+
+        let header = message.header();
+        let shard = header.shard_id().id();
+        if shard != 0 && !sharded {
+            bail!(
+                "`{}` was dumped from shard {shard}, restore with `--sharded`",
+                header.stream_key()
+            );
+        }
+        let stream_key = map.get(header.stream_key()).unwrap_or(header.stream_key());
+
+        // the later of the two deadlines
+        let mut deadline = None;
+        if let Some(pace) = pace {
+            let first = *first.get_or_insert(*header.timestamp());
+            let elapsed = (*header.timestamp() - first).max(time::Duration::ZERO);
+            deadline = Some(after(started, elapsed.as_seconds_f64() / pace)?);
+        }
+        if let Some(rate) = rate {
+            let at = after(started, count as f64 / rate)?;
+            deadline = Some(deadline.map_or(at, |d| d.max(at)));
+        }
+        if let Some(deadline) = deadline {
+            tokio::time::sleep_until(deadline).await;
+        }
+
+        let key = shard.to_be_bytes();
+        let key = if sharded { Some(key.as_slice()) } else { None };
+        let payload = message.message();
+        let receipt = if keep_ids {
+            producer.send_with_id_to(stream_key, key, message.message_id(), payload)?
+        } else if let Some(key) = key {
+            producer.send_with_key_to(stream_key, key, payload)?
+        } else {
+            producer.send_to(stream_key, payload)?
+        };
+        receipts.push_back(receipt);
+        count += 1;
+
+        // consecutive messages are pipelined, while we wait for the earliest receipts
+        while receipts.len() > MAX_IN_FLIGHT {
+            check(receipts.pop_front().unwrap(), count).await?;
+        }
+    }
+
+    producer.flush().await?;
+    for receipt in receipts {
+        check(receipt, count).await?;
+    }
+    log::info!("Restored {count} messages from {input}");
+
+    Ok(())
+}
+
+async fn check(receipt: SendFuture, count: u64) -> Result<()> {
+    receipt
+        .await
+        .map(|_| ())
+        .map_err(|err| anyhow!("Failed after sending {count} messages: {err}"))
+}
+
+fn is_positive(value: f64) -> bool {
+    value.is_finite() && value > 0.0
+}
+
+/// The instant `secs` after `started`. Fails if it is too far away, i.e. `--pace` or `--rate` is too slow.
+fn after(started: Instant, secs: f64) -> Result<Instant> {
+    if secs > MAX_DELAY.as_secs_f64() {
+        bail!("A message would be delayed for {secs} seconds, `--pace` or `--rate` is too slow");
+    }
+    started
+        .checked_add(Duration::from_secs_f64(secs))
+        .ok_or_else(|| anyhow!("A message would be delayed for {secs} seconds"))
+}
+
+fn parse_mapping(input: &str) -> Result<(StreamKey, StreamKey)> {
+    match input.split_once('=') {
+        Some((from, to)) => Ok((StreamKey::new(from)?, StreamKey::new(to)?)),
+        None => bail!("Expected `FROM=TO`, got `{input}`"),
+    }
+}
@@ -1,11 +1,17 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use sea_streamer_file::{FileId, MessageSink, DEFAULT_BEACON_INTERVAL, DEFAULT_FILE_SIZE_LIMIT};
 use sea_streamer_redis::{AutoStreamReset, RedisConsumerOptions, RedisStreamer};
 use sea_streamer_types::{
     Consumer, ConsumerMode, ConsumerOptions, Message, StreamUrl, Streamer, Timestamp,
     TIMESTAMP_FORMAT,
 };
-use std::time::Duration;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 use structopt::StructOpt;
 use time::PrimitiveDateTime;
 
@@ -16,12 +22,22 @@ struct Args {
         help = "Streamer URI with stream key, i.e. try `redis://localhost/hello`"
     )]
     stream: StreamUrl,
+    #[structopt(
+        long,
+        help = "Also dump the streams with keys matching this glob pattern, e.g. `clock-*`"
+    )]
+    pattern: Option<String>,
     #[structopt(long, help = "Output file. Overwrites if exist")]
     output: FileId,
     #[structopt(long, help = "Timestamp start of range")]
     since: Option<String>,
     #[structopt(long, help = "Timestamp end of range")]
     until: Option<String>,
+    #[structopt(
+        long,
+        help = "Keep waiting for new messages until `--until` or Ctrl-C, instead of exiting when there are none"
+    )]
+    follow: bool,
 }
 
 #[tokio::main]
@@ -30,21 +46,36 @@ async fn main() -> Result<()> {
 
     let Args {
         stream,
+        pattern,
         output,
         since,
         until,
+        follow,
     } = Args::from_args();
 
     let since = since.map(|s| parse_timestamp(&s).unwrap());
     let until = until.map(|s| parse_timestamp(&s).unwrap());
 
     let streamer = RedisStreamer::connect(stream.streamer(), Default::default()).await?;
+    let mut stream_keys = stream.stream_keys().to_vec();
+    if let Some(pattern) = pattern {
+        // streams created after this point are not dumped, even with `--follow`
+        let mut admin = streamer.admin().await?;
+        for stream_key in admin.streams(&pattern).await? {
+            if !stream_keys.contains(&stream_key) {
+                stream_keys.push(stream_key);
+            }
+        }
+        log::info!("Matched streams {stream_keys:?}");
+    }
+    if stream_keys.is_empty() {
+        bail!("No streams to dump");
+    }
+
     let mut options = RedisConsumerOptions::new(ConsumerMode::RealTime);
     options.set_auto_stream_reset(AutoStreamReset::Earliest);
 
-    let mut consumer = streamer
-        .create_consumer(stream.stream_keys(), options)
-        .await?;
+    let mut consumer = streamer.create_consumer(&stream_keys, options).await?;
     if let Some(since) = since {
         consumer.seek(since).await?;
     }
@@ -57,8 +88,38 @@ async fn main() -> Result<()> {
     .await?;
 
     let dur = Duration::from_secs(1);
+    // checked at least once every `dur`
+    let stopped = Arc::new(AtomicBool::new(false));
+    {
+        let stopped = stopped.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                stopped.store(true, Ordering::Release);
+            }
+        });
+    }
     let mut count = 0;
-    while let Ok(Ok(mess)) = tokio::time::timeout(dur, consumer.next()).await {
+    let mut written = 0;
+    while !stopped.load(Ordering::Acquire) {
+        let mess = match tokio::time::timeout(dur, consumer.next()).await {
+            Ok(Ok(mess)) => mess,
+            Ok(Err(err)) => {
+                log::error!("{err}");
+                break;
+            }
+            Err(_) if follow => {
+                if matches!(&until, Some(until) if &Timestamp::now_utc() > until) {
+                    break;
+                }
+                // make what we have so far readable while waiting
+                if written != count {
+                    sink.flush().await?;
+                    written = count;
+                }
+                continue;
+            }
+            Err(_) => break,
+        };
         if let Some(until) = &until {
             if &mess.timestamp() > until {
                 break;
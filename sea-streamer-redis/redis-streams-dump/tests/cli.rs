@@ -0,0 +1,230 @@
+// Runs the binaries. Requires a Redis server, set BROKERS_URL if it is not at localhost
+// cargo test --test cli --features=test -- --nocapture
+#[cfg(feature = "test")]
+#[tokio::test]
+async fn main() -> anyhow::Result<()> {
+    use sea_streamer_redis::{
+        AutoStreamReset, RedisConsumerOptions, RedisProducerOptions, RedisStreamer,
+        RoundRobinSharder,
+    };
+    use sea_streamer_types::{
+        Buffer, Consumer, ConsumerMode, ConsumerOptions, Message, Producer, StreamKey, Streamer,
+        Timestamp, TIMESTAMP_FORMAT,
+    };
+    use std::time::{Duration, Instant};
+    use tokio::{process::Command, time::sleep};
+
+    const TEST: &str = "cli";
+    const DUMP: &str = env!("CARGO_BIN_EXE_redis-streams-dump");
+    const RESTORE: &str = env!("CARGO_BIN_EXE_redis-streams-restore");
+    const GAP: Duration = Duration::from_millis(150);
+
+    let url = std::env::var("BROKERS_URL").unwrap_or_else(|_| "redis://localhost".to_owned());
+    let streamer = RedisStreamer::connect(url.parse()?, Default::default()).await?;
+
+    let millis = Timestamp::now_utc().unix_timestamp_nanos() / 1_000_000;
+    let prefix = format!("{TEST}-{millis}");
+    let key = |suffix: &str| StreamKey::new(format!("{prefix}-{suffix}"));
+    let file = |name: &str| {
+        std::env::temp_dir()
+            .join(format!("{prefix}-{name}.ss"))
+            .to_str()
+            .unwrap()
+            .to_owned()
+    };
+
+    // `a` is paced, `b` is only matched by the pattern, and `s` has 2 shards
+    let producer = streamer.create_generic_producer(Default::default()).await?;
+    for i in 0..4 {
+        producer.send_to(&key("a")?, format!("{i}"))?.await?;
+        sleep(GAP).await;
+    }
+    for i in 0..2 {
+        producer.send_to(&key("b")?, format!("{i}"))?.await?;
+    }
+    let mut options = RedisProducerOptions::default();
+    options.set_sharder(RoundRobinSharder::new(2));
+    let sharded = streamer.create_producer(key("s")?, options).await?;
+    for i in 0..4 {
+        let receipt = sharded.send(format!("{i}"))?.await?;
+        assert_eq!(receipt.shard_id().id(), i % 2);
+    }
+
+    let status = Command::new(DUMP)
+        .arg("--stream")
+        .arg(format!("{url}/{prefix}-a"))
+        .arg("--pattern")
+        .arg(format!("{prefix}-*"))
+        .arg("--output")
+        .arg(file("all"))
+        .status()
+        .await?;
+    assert!(status.success());
+
+    println!("Dump with --pattern ... ok");
+
+    // the shards cannot be restored into a plain stream
+    let status = Command::new(RESTORE)
+        .arg("--input")
+        .arg(file("all"))
+        .arg("--output")
+        .arg(&url)
+        .status()
+        .await?;
+    assert!(!status.success());
+
+    let started = Instant::now();
+    let mut restore = Command::new(RESTORE);
+    restore
+        .arg("--input")
+        .arg(file("all"))
+        .arg("--output")
+        .arg(&url)
+        .arg("--sharded")
+        .arg("--rate")
+        .arg("20");
+    for suffix in ["a", "b", "s"] {
+        restore
+            .arg("--map")
+            .arg(format!("{prefix}-{suffix}={prefix}-{suffix}-r"));
+    }
+    assert!(restore.status().await?.success());
+    // 10 messages at 20 per second
+    assert!(started.elapsed() >= Duration::from_millis(450));
+
+    assert_eq!(
+        read(&streamer, key("a-r")?, 4).await?,
+        [(0, 0), (0, 1), (0, 2), (0, 3)]
+    );
+    assert_eq!(read(&streamer, key("b-r")?, 2).await?, [(0, 0), (0, 1)]);
+    let mut restored = read(&streamer, key("s-r")?, 4).await?;
+    restored.sort_by_key(|(_, i)| *i);
+    assert_eq!(restored, [(0, 0), (1, 1), (0, 2), (1, 3)]);
+
+    println!("Restore with --sharded --rate --map ... ok");
+
+    let status = Command::new(DUMP)
+        .arg("--stream")
+        .arg(format!("{url}/{prefix}-a"))
+        .arg("--output")
+        .arg(file("a"))
+        .status()
+        .await?;
+    assert!(status.success());
+
+    let started = Instant::now();
+    let status = Command::new(RESTORE)
+        .arg("--input")
+        .arg(file("a"))
+        .arg("--output")
+        .arg(&url)
+        .arg("--pace")
+        .arg("2")
+        .arg("--map")
+        .arg(format!("{prefix}-a={prefix}-a-p"))
+        .status()
+        .await?;
+    assert!(status.success());
+    // the messages were at least 3 gaps apart, replayed at double speed
+    assert!(started.elapsed() >= GAP * 3 / 2);
+    assert_eq!(
+        read(&streamer, key("a-p")?, 4).await?,
+        [(0, 0), (0, 1), (0, 2), (0, 3)]
+    );
+
+    println!("Restore with --pace ... ok");
+
+    for (pace, rate) in [("0", "1"), ("1", "NaN"), ("inf", "1"), ("1", "0")] {
+        let status = Command::new(RESTORE)
+            .arg("--input")
+            .arg(file("a"))
+            .arg("--output")
+            .arg(&url)
+            .arg("--pace")
+            .arg(pace)
+            .arg("--rate")
+            .arg(rate)
+            .status()
+            .await?;
+        assert!(!status.success());
+    }
+    // so slow that the delay of the second message overflows
+    let status = Command::new(RESTORE)
+        .arg("--input")
+        .arg(file("a"))
+        .arg("--output")
+        .arg(&url)
+        .arg("--pace")
+        .arg("1e-300")
+        .arg("--map")
+        .arg(format!("{prefix}-a={prefix}-a-x"))
+        .status()
+        .await?;
+    assert!(!status.success());
+
+    println!("Reject invalid --pace and --rate ... ok");
+
+    producer.send_to(&key("f")?, "0")?.await?;
+    let until = (Timestamp::now_utc() + Duration::from_secs(4)).format(TIMESTAMP_FORMAT)?;
+    let mut dump = Command::new(DUMP)
+        .arg("--stream")
+        .arg(format!("{url}/{prefix}-f"))
+        .arg("--output")
+        .arg(file("f"))
+        .arg("--follow")
+        .arg("--until")
+        .arg(until)
+        .spawn()?;
+    // without `--follow`, it would have exited by now
+    sleep(Duration::from_secs(2)).await;
+    for i in 1..3 {
+        producer.send_to(&key("f")?, format!("{i}"))?.await?;
+    }
+    assert!(dump.wait().await?.success());
+
+    let status = Command::new(RESTORE)
+        .arg("--input")
+        .arg(file("f"))
+        .arg("--output")
+        .arg(&url)
+        .arg("--map")
+        .arg(format!("{prefix}-f={prefix}-f-r"))
+        .status()
+        .await?;
+    assert!(status.success());
+    assert_eq!(
+        read(&streamer, key("f-r")?, 3).await?,
+        [(0, 0), (0, 1), (0, 2)]
+    );
+
+    println!("Dump with --follow ... ok");
+
+    for name in ["all", "a", "f"] {
+        std::fs::remove_file(file(name))?;
+    }
+    producer.end().await?;
+    sharded.end().await?;
+
+    /// Read `n` messages from the start of a stream, as (shard, number)
+    async fn read(
+        streamer: &RedisStreamer,
+        stream_key: StreamKey,
+        n: usize,
+    ) -> anyhow::Result<Vec<(u64, u64)>> {
+        let mut options = RedisConsumerOptions::new(ConsumerMode::RealTime);
+        options.set_auto_stream_reset(AutoStreamReset::Earliest);
+        let consumer = streamer.create_consumer(&[stream_key], options).await?;
+        let mut messages = Vec::new();
+        for _ in 0..n {
+            let message = consumer.next().await?;
+            messages.push((
+                message.shard_id().id(),
+                message.message().as_str()?.parse()?,
+            ));
+        }
+        consumer.end().await?;
+        Ok(messages)
+    }
+
+    Ok(())
+}
@@ -0,0 +1,86 @@
+// Requires a Redis server, set BROKERS_URL if it is not at localhost
+// cargo test --test dump-restore --features=test -- --nocapture
+#[cfg(feature = "test")]
+#[tokio::test]
+async fn main() -> anyhow::Result<()> {
+    use sea_streamer_file::{
+        FileId, MessageSink, MessageSource, StreamMode, DEFAULT_BEACON_INTERVAL,
+        DEFAULT_FILE_SIZE_LIMIT,
+    };
+    use sea_streamer_redis::{
+        AutoStreamReset, MessageId, RedisConsumer, RedisConsumerOptions, RedisMessageId,
+        RedisStreamer,
+    };
+    use sea_streamer_types::{
+        Buffer, Consumer, ConsumerMode, ConsumerOptions, Message, Producer, StreamKey, Streamer,
+        Timestamp,
+    };
+
+    const TEST: &str = "dump-restore";
+    // the ids are kept through the file, which only has millisecond timestamps
+    const IDS: [MessageId; 4] = [(1_000, 0), (1_000, 70_000), (1_000, u64::MAX), (1_001, 1)];
+
+    let streamer = RedisStreamer::connect(
+        std::env::var("BROKERS_URL")
+            .unwrap_or_else(|_| "redis://localhost".to_owned())
+            .parse()?,
+        Default::default(),
+    )
+    .await?;
+
+    let millis = Timestamp::now_utc().unix_timestamp_nanos() / 1_000_000;
+    let stream = StreamKey::new(format!("{TEST}-{millis}"))?;
+    let restored = StreamKey::new(format!("{TEST}-{millis}-restored"))?;
+    let producer = streamer.create_generic_producer(Default::default()).await?;
+    for (i, id) in IDS.into_iter().enumerate() {
+        producer
+            .send_with_id_to(&stream, None, id, format!("{i}"))?
+            .await?;
+    }
+
+    let mut options = RedisConsumerOptions::new(ConsumerMode::RealTime);
+    options.set_auto_stream_reset(AutoStreamReset::Earliest);
+    let consumer = streamer
+        .create_consumer(std::slice::from_ref(&stream), options.clone())
+        .await?;
+
+    // what `redis-streams-dump` does
+    let path = std::env::temp_dir().join(format!("{TEST}-{millis}.ss"));
+    let file_id = FileId::new(path.to_str().unwrap());
+    let mut sink = MessageSink::new(
+        file_id.clone(),
+        DEFAULT_BEACON_INTERVAL,
+        DEFAULT_FILE_SIZE_LIMIT,
+    )
+    .await?;
+    for _ in IDS {
+        sink.write(consumer.next().await?.to_owned_message())?;
+    }
+    sink.flush().await?;
+
+    // what `redis-streams-restore --keep-ids` does
+    let mut source = MessageSource::new(file_id, StreamMode::Replay).await?;
+    for (i, id) in IDS.into_iter().enumerate() {
+        let message = source.next().await?.message;
+        assert_eq!(message.message_id(), id);
+        producer
+            .send_with_id_to(&restored, None, message.message_id(), message.message())?
+            .await?;
+        assert_eq!(message.message().as_str()?, format!("{i}"));
+    }
+    std::fs::remove_file(path)?;
+
+    let consumer: RedisConsumer = streamer
+        .create_consumer(std::slice::from_ref(&restored), options)
+        .await?;
+    for (i, id) in IDS.into_iter().enumerate() {
+        let message = consumer.next().await?;
+        assert_eq!(message.message_id(), id);
+        assert_eq!(message.message().as_str()?, format!("{i}"));
+    }
+
+    producer.end().await?;
+    consumer.end().await?;
+
+    Ok(())
+}
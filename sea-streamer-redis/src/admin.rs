@@ -0,0 +1,586 @@
+use redis::{
+    cmd as command,
+    streams::{
+        StreamInfoConsumersReply, StreamInfoGroupsReply, StreamPendingCountReply,
+        StreamPendingReply,
+    },
+    Value,
+};
+use std::{
+    collections::{BTreeSet, HashMap},
+    time::Duration,
+};
+
+use crate::{
+    abort_reshard, begin_reshard,
+    constants::{DRAIN_LEASE, SHARD_REFRESH},
+    discover_shards, map_err, message_id_from_str, read_shard_meta, renew_reshard, switch_shards,
+    MessageId, RedisCluster, RedisErr, RedisResult, ShardMeta, StreamShard,
+};
+use sea_streamer_runtime::sleep;
+use sea_streamer_types::{ConsumerGroup, ConsumerId, ShardId, StreamErr, StreamKey, Timestamp};
+
+/// Number of keys examined by each `SCAN`
+const SCAN_COUNT: usize = 1000;
+
+#[derive(Debug)]
+/// A handle to inspect and manage the consumer groups of streams, obtained by [`crate::RedisStreamer::admin`].
+///
+/// All methods work on a single shard of a stream, which can be listed by [`RedisAdmin::shards`].
+/// Shards are located with the same key assignment as producers and consumers, so it works with Redis Cluster.
+pub struct RedisAdmin {
+    cluster: RedisCluster,
+    /// The Redis key of each shard
+    keys: HashMap<StreamShard, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The summary form of `XPENDING`.
+pub struct PendingSummary {
+    /// Number of messages delivered but not yet acked in the group
+    pub count: usize,
+    /// The lowest id among the pending messages
+    pub first: Option<MessageId>,
+    /// The highest id among the pending messages
+    pub last: Option<MessageId>,
+    /// Consumers that have pending messages, with the number of messages
+    pub consumers: Vec<(String, usize)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// An entry in the extended form of `XPENDING`.
+pub struct PendingMessage {
+    pub id: MessageId,
+    /// The consumer this message was last delivered to
+    pub consumer: String,
+    /// Time since the last delivery
+    pub idle: Duration,
+    /// Number of times this message was delivered
+    pub deliveries: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Reply of `XINFO STREAM`.
+pub struct StreamInfo {
+    /// Number of entries in the stream
+    pub length: usize,
+    /// Number of consumer groups
+    pub groups: usize,
+    /// The id of the last entry ever added
+    pub last_generated_id: MessageId,
+    /// The id of the first entry, if the stream is not empty
+    pub first_id: Option<MessageId>,
+    /// The id of the last entry, if the stream is not empty
+    pub last_id: Option<MessageId>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// An entry of `XINFO GROUPS`.
+pub struct GroupInfo {
+    pub name: String,
+    /// Number of consumers in the group
+    pub consumers: usize,
+    /// Number of messages delivered but not yet acked
+    pub pending: usize,
+    /// The id of the last message delivered to the group
+    pub last_delivered_id: MessageId,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// An entry of `XINFO CONSUMERS`.
+pub struct ConsumerInfo {
+    pub name: String,
+    /// Number of messages delivered to this consumer but not yet acked
+    pub pending: usize,
+    /// Time since the consumer last interacted with the server
+    pub idle: Duration,
+}
+
+impl RedisAdmin {
+    pub(crate) fn new(cluster: RedisCluster) -> Self {
+        Self {
+            cluster,
+            keys: Default::default(),
+        }
+    }
+
+    /// List the shards of a stream.
+    pub async fn shards(&mut self, stream: &StreamKey) -> RedisResult<Vec<StreamShard>> {
+        let shards = discover_shards(&mut self.cluster, stream.clone()).await?;
+        let mut result = Vec::new();
+        for shard in shards {
+            result.push(shard.stream.clone());
+            self.keys.insert(shard.stream, shard.key);
+        }
+        Ok(result)
+    }
+
+    /// List the streams whose keys match the glob-style `pattern`, e.g. `orders-*`, by `SCAN` on every node.
+    /// Shards are matched by the key of their stream, so `orders-*` also finds `orders-eu` if it is sharded.
+    /// Keys that are not streams or not valid stream keys are ignored. Requires Redis 6.0 or above.
+    pub async fn streams(&mut self, pattern: &str) -> RedisResult<Vec<StreamKey>> {
+        let mut names = BTreeSet::new();
+        // in a cluster, each node only knows about its own keys
+        let mut connected = false;
+        for conn in self.cluster.conn.values_mut() {
+            let conn = match conn.try_get() {
+                Ok(conn) => conn,
+                Err(_) => continue,
+            };
+            connected = true;
+            for pattern in [pattern.to_owned(), format!("{pattern}:*")] {
+                let mut cursor: u64 = 0;
+                loop {
+                    let mut scan = command("SCAN");
+                    scan.arg(cursor)
+                        .arg("MATCH")
+                        .arg(&pattern)
+                        .arg("COUNT")
+                        .arg(SCAN_COUNT)
+                        .arg("TYPE")
+                        .arg("stream");
+                    let (next, keys): (u64, Vec<String>) =
+                        scan.query_async(conn).await.map_err(map_err)?;
+                    for key in keys {
+                        let name = match key.rsplit_once(':') {
+                            Some((name, shard)) if shard.parse::<u64>().is_ok() => name.to_owned(),
+                            _ => key,
+                        };
+                        names.insert(name);
+                    }
+                    if next == 0 {
+                        break;
+                    }
+                    cursor = next;
+                }
+            }
+        }
+        if !connected {
+            return Err(StreamErr::Connect("No open connections".to_owned()));
+        }
+        Ok(names
+            .into_iter()
+            .filter_map(|name| match StreamKey::new(&name) {
+                Ok(stream) => Some(stream),
+                Err(_) => {
+                    log::debug!("Ignoring `{name}`");
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// Summary of the messages pending in a consumer group, i.e. `XPENDING <key> <group>`.
+    pub async fn pending_summary(
+        &mut self,
+        shard: &StreamShard,
+        group: &ConsumerGroup,
+    ) -> RedisResult<PendingSummary> {
+        let reply: StreamPendingReply = self
+            .query(shard, |key| {
+                let mut cmd = command("XPENDING");
+                cmd.arg(key).arg(group.name());
+                cmd
+            })
+            .await?;
+        Ok(match reply {
+            StreamPendingReply::Empty => PendingSummary {
+                count: 0,
+                first: None,
+                last: None,
+                consumers: Vec::new(),
+            },
+            StreamPendingReply::Data(data) => PendingSummary {
+                count: data.count,
+                first: Some(message_id(&data.start_id)?),
+                last: Some(message_id(&data.end_id)?),
+                consumers: data
+                    .consumers
+                    .into_iter()
+                    .map(|c| (c.name, c.pending))
+                    .collect(),
+            },
+        })
+    }
+
+    /// Up to `count` messages pending in a consumer group after `after` (exclusive), oldest first,
+    /// i.e. `XPENDING <key> <group> [IDLE <min_idle>] <start> + <count> [<consumer>]`.
+    /// Optionally only those pending for `consumer` and idle for at least `min_idle`.
+    /// Requires Redis 6.2 or above.
+    pub async fn pending(
+        &mut self,
+        shard: &StreamShard,
+        group: &ConsumerGroup,
+        consumer: Option<&ConsumerId>,
+        min_idle: Option<Duration>,
+        after: Option<MessageId>,
+        count: usize,
+    ) -> RedisResult<Vec<PendingMessage>> {
+        let reply: StreamPendingCountReply = self
+            .query(shard, |key| {
+                let mut cmd = command("XPENDING");
+                cmd.arg(key).arg(group.name());
+                if let Some(min_idle) = min_idle {
+                    cmd.arg("IDLE").arg(millis(min_idle));
+                }
+                match after {
+                    Some((timestamp, seq)) => cmd.arg(format!("({timestamp}-{seq}")),
+                    None => cmd.arg("-"),
+                };
+                cmd.arg("+").arg(count);
+                if let Some(consumer) = consumer {
+                    cmd.arg(consumer.id());
+                }
+                cmd
+            })
+            .await?;
+        reply
+            .ids
+            .into_iter()
+            .map(|p| {
+                Ok(PendingMessage {
+                    id: message_id(&p.id)?,
+                    consumer: p.consumer,
+                    idle: Duration::from_millis(p.last_delivered_ms as u64),
+                    deliveries: p.times_delivered,
+                })
+            })
+            .collect()
+    }
+
+    /// `XINFO STREAM <key>`
+    pub async fn stream_info(&mut self, shard: &StreamShard) -> RedisResult<StreamInfo> {
+        let reply: HashMap<String, Value> = self
+            .query(shard, |key| {
+                let mut cmd = command("XINFO");
+                cmd.arg("STREAM").arg(key);
+                cmd
+            })
+            .await?;
+        let get = |field: &str| -> RedisResult<&Value> {
+            reply.get(field).ok_or_else(|| {
+                StreamErr::Backend(RedisErr::TypeError(format!(
+                    "XINFO STREAM has no field `{field}`"
+                )))
+            })
+        };
+        Ok(StreamInfo {
+            length: redis::from_redis_value(get("length")?).map_err(map_err)?,
+            groups: redis::from_redis_value(get("groups")?).map_err(map_err)?,
+            last_generated_id: message_id(
+                &redis::from_redis_value::<String>(get("last-generated-id")?).map_err(map_err)?,
+            )?,
+            first_id: entry_id(get("first-entry")?)?,
+            last_id: entry_id(get("last-entry")?)?,
+        })
+    }
+
+    /// `XINFO GROUPS <key>`
+    pub async fn groups(&mut self, shard: &StreamShard) -> RedisResult<Vec<GroupInfo>> {
+        let reply: StreamInfoGroupsReply = self
+            .query(shard, |key| {
+                let mut cmd = command("XINFO");
+                cmd.arg("GROUPS").arg(key);
+                cmd
+            })
+            .await?;
+        reply
+            .groups
+            .into_iter()
+            .map(|g| {
+                Ok(GroupInfo {
+                    last_delivered_id: message_id(&g.last_delivered_id)?,
+                    name: g.name,
+                    consumers: g.consumers,
+                    pending: g.pending,
+                })
+            })
+            .collect()
+    }
+
+    /// `XINFO CONSUMERS <key> <group>`
+    pub async fn consumers(
+        &mut self,
+        shard: &StreamShard,
+        group: &ConsumerGroup,
+    ) -> RedisResult<Vec<ConsumerInfo>> {
+        let reply: StreamInfoConsumersReply = self
+            .query(shard, |key| {
+                let mut cmd = command("XINFO");
+                cmd.arg("CONSUMERS").arg(key).arg(group.name());
+                cmd
+            })
+            .await?;
+        Ok(reply
+            .consumers
+            .into_iter()
+            .map(|c| ConsumerInfo {
+                name: c.name,
+                pending: c.pending,
+                idle: Duration::from_millis(c.idle as u64),
+            })
+            .collect())
+    }
+
+    /// `XGROUP DELCONSUMER <key> <group> <consumer>`. Returns the number of messages that were pending
+    /// for the consumer. Beware that these messages are no longer pending in the group, so they
+    /// will never be claimed by other consumers.
+    pub async fn delete_consumer(
+        &mut self,
+        shard: &StreamShard,
+        group: &ConsumerGroup,
+        consumer: &str,
+    ) -> RedisResult<usize> {
+        self.query(shard, |key| {
+            let mut cmd = command("XGROUP");
+            cmd.arg("DELCONSUMER")
+                .arg(key)
+                .arg(group.name())
+                .arg(consumer);
+            cmd
+        })
+        .await
+    }
+
+    /// `XGROUP SETID <key> <group> <id>`, i.e. set the last delivered id of the group.
+    /// The group will then be delivered the messages after `id`.
+    pub async fn set_group_id(
+        &mut self,
+        shard: &StreamShard,
+        group: &ConsumerGroup,
+        (timestamp, seq): MessageId,
+    ) -> RedisResult<()> {
+        let () = self
+            .query(shard, |key| {
+                let mut cmd = command("XGROUP");
+                cmd.arg("SETID")
+                    .arg(key)
+                    .arg(group.name())
+                    .arg(format!("{timestamp}-{seq}"));
+                cmd
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// `XGROUP DESTROY <key> <group>`. Returns whether the group existed.
+    pub async fn destroy_group(
+        &mut self,
+        shard: &StreamShard,
+        group: &ConsumerGroup,
+    ) -> RedisResult<bool> {
+        self.query(shard, |key| {
+            let mut cmd = command("XGROUP");
+            cmd.arg("DESTROY").arg(key).arg(group.name());
+            cmd
+        })
+        .await
+    }
+
+    /// Transfer the ownership of pending messages idle for at least `min_idle` to `consumer`,
+    /// i.e. `XCLAIM <key> <group> <consumer> <min_idle> <ids> JUSTID`.
+    /// The delivery counts are not incremented. Returns the ids actually claimed.
+    pub async fn claim(
+        &mut self,
+        shard: &StreamShard,
+        group: &ConsumerGroup,
+        consumer: &ConsumerId,
+        min_idle: Duration,
+        ids: &[MessageId],
+    ) -> RedisResult<Vec<MessageId>> {
+        let reply: Vec<String> = self
+            .query(shard, |key| {
+                let mut cmd = command("XCLAIM");
+                cmd.arg(key)
+                    .arg(group.name())
+                    .arg(consumer.id())
+                    .arg(millis(min_idle));
+                for (timestamp, seq) in ids {
+                    cmd.arg(format!("{timestamp}-{seq}"));
+                }
+                cmd.arg("JUSTID");
+                cmd
+            })
+            .await?;
+        reply.iter().map(|id| message_id(id)).collect()
+    }
+
+    /// Remove the consumers that have been idle for longer than `max_idle` and have nothing pending,
+    /// from the group on every shard of the stream. These are typically left behind by consumers
+    /// with random ids (see [`crate::consumer_id`]) that have since exited.
+    /// Consumers with pending messages are kept, so their messages can still be claimed.
+    /// Returns the consumers removed.
+    pub async fn remove_idle_consumers(
+        &mut self,
+        stream: &StreamKey,
+        group: &ConsumerGroup,
+        max_idle: Duration,
+    ) -> RedisResult<Vec<(StreamShard, ConsumerInfo)>> {
+        let mut removed = Vec::new();
+        for shard in self.shards(stream).await? {
+            for consumer in self.consumers(&shard, group).await? {
+                if consumer.pending == 0 && consumer.idle > max_idle {
+                    self.delete_consumer(&shard, group, &consumer.name).await?;
+                    removed.push((shard.clone(), consumer));
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    /// The sharding metadata of a stream, if it is sharded by [`crate::ConsistentHashSharder`].
+    pub async fn shard_meta(&mut self, stream: &StreamKey) -> RedisResult<Option<ShardMeta>> {
+        read_shard_meta(&mut self.cluster, stream).await
+    }
+
+    /// Change the number of shards of a stream sharded by [`crate::ConsistentHashSharder`], keeping the messages
+    /// of each key in order. If the stream has no sharding metadata yet, it is simply created with `num_shards`.
+    ///
+    /// Resharding drains the current shards before switching:
+    ///
+    /// 1. The stream is marked as draining. Producers notice within `SHARD_REFRESH`, and hold new messages,
+    ///    up to `MAX_HELD_MESSAGES`, after which sending fails with [`RedisErr::TryAgain`].
+    /// 2. We wait until every consumer group has consumed and acked all messages of the current shards.
+    /// 3. The stream is switched to `num_shards`. Producers release the held messages to the new shards,
+    ///    and consumers add the new shards as they discover them.
+    ///
+    /// If the shards are not drained within `timeout`, resharding is aborted and producers continue with the
+    /// current shards. The draining mark is a lease renewed while waiting: if this task is dropped or the process
+    /// dies, it expires after `DRAIN_LEASE` and producers continue with the current shards too. Consumers in `RealTime` mode are not waited for, as Redis does not track them.
+    pub async fn reshard(
+        &mut self,
+        stream: &StreamKey,
+        num_shards: u64,
+        timeout: Duration,
+    ) -> RedisResult<()> {
+        if num_shards == 0 {
+            return Err(StreamErr::Backend(RedisErr::InvalidClientConfig(
+                "There must be at least 1 shard".to_owned(),
+            )));
+        }
+        let draining =
+            match begin_reshard(&mut self.cluster, stream, num_shards, DRAIN_LEASE).await? {
+                Some(draining) => draining,
+                None => return Ok(()),
+            };
+        log::info!("Resharding `{stream}` from {draining} to {num_shards} shards");
+        let deadline = Timestamp::now_utc() + timeout;
+        // let every producer notice, so that nothing more is written to the current shards
+        sleep(SHARD_REFRESH * 2).await;
+        loop {
+            // the draining marker expires if we stop renewing it, e.g. if this process dies
+            match renew_reshard(&mut self.cluster, stream, num_shards, DRAIN_LEASE).await {
+                Ok(true) => (),
+                Ok(false) => {
+                    return Err(StreamErr::Backend(RedisErr::Unknown(format!(
+                        "Resharding `{stream}` was aborted"
+                    ))))
+                }
+                Err(err) => {
+                    abort_reshard(&mut self.cluster, stream, num_shards).await?;
+                    return Err(err);
+                }
+            }
+            match self.drained(stream, draining).await {
+                Ok(true) => break,
+                Ok(false) => (),
+                Err(err) => {
+                    abort_reshard(&mut self.cluster, stream, num_shards).await?;
+                    return Err(err);
+                }
+            }
+            if Timestamp::now_utc() > deadline {
+                abort_reshard(&mut self.cluster, stream, num_shards).await?;
+                return Err(StreamErr::Backend(RedisErr::TryAgain(format!(
+                    "`{stream}` was not drained in {timeout:?}"
+                ))));
+            }
+            sleep(Duration::from_secs(1)).await;
+        }
+        if switch_shards(&mut self.cluster, stream, num_shards).await? {
+            log::info!("Resharded `{stream}` to {num_shards} shards");
+            Ok(())
+        } else {
+            Err(StreamErr::Backend(RedisErr::Unknown(format!(
+                "Resharding `{stream}` was aborted"
+            ))))
+        }
+    }
+
+    /// Whether every consumer group has consumed and acked all messages of the first `num_shards` shards.
+    async fn drained(&mut self, stream: &StreamKey, num_shards: u64) -> RedisResult<bool> {
+        self.shards(stream).await?;
+        for shard in 0..num_shards {
+            let shard = (stream.clone(), ShardId::new(shard));
+            let key = match self.keys.get(&shard) {
+                Some(key) => key.to_owned(),
+                None => continue,
+            };
+            let exists: bool = self
+                .cluster
+                .query(&key, |key| {
+                    let mut cmd = command("EXISTS");
+                    cmd.arg(key);
+                    cmd
+                })
+                .await?;
+            if !exists {
+                continue;
+            }
+            let last_id = match self.stream_info(&shard).await?.last_id {
+                Some(last_id) => last_id,
+                None => continue,
+            };
+            for group in self.groups(&shard).await? {
+                if group.pending > 0 || group.last_delivered_id < last_id {
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    async fn query<T, F>(&mut self, shard: &StreamShard, cmd: F) -> RedisResult<T>
+    where
+        T: redis::FromRedisValue,
+        F: Fn(&str) -> redis::Cmd,
+    {
+        if !self.keys.contains_key(shard) {
+            self.shards(&shard.0).await?;
+        }
+        let key = match self.keys.get(shard) {
+            Some(key) => key.to_owned(),
+            None => {
+                return Err(StreamErr::Backend(RedisErr::Unknown(format!(
+                    "Unknown shard {}:{}",
+                    shard.0, shard.1
+                ))))
+            }
+        };
+        self.cluster.query(&key, cmd).await
+    }
+}
+
+fn message_id(id: &str) -> RedisResult<MessageId> {
+    message_id_from_str(id)
+}
+
+/// The id of an entry in `XINFO STREAM`, which is nil if the stream is empty
+fn entry_id(value: &Value) -> RedisResult<Option<MessageId>> {
+    match value {
+        Value::Nil => Ok(None),
+        Value::Bulk(values) => match values.first() {
+            Some(id) => Ok(Some(message_id(
+                &redis::from_redis_value::<String>(id).map_err(map_err)?,
+            )?)),
+            None => Ok(None),
+        },
+        _ => Err(StreamErr::Backend(RedisErr::TypeError(format!(
+            "Unexpected entry {value:?}"
+        )))),
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_millis().try_into().expect("Duration too long")
+}
@@ -1,11 +1,18 @@
 use std::{collections::HashMap, fmt::Debug, sync::Arc};
 
-use crate::{Connection, RedisConnectOptions, RedisResult};
+use crate::{
+    is_sentinel, map_err, Connection, ConnectionPool, RedisConnectOptions, RedisConnection,
+    RedisResult,
+};
+use redis::{from_redis_value, ErrorKind, FromRedisValue, Value};
 use sea_streamer_types::{export::url::Url, StreamErr, StreamUrlErr, StreamerUri};
 
 /// ID of a node in a Redis Cluster.
 pub type NodeId = Url;
 
+/// Number of hash slots in a Redis Cluster.
+pub const HASH_SLOTS: u16 = 16384;
+
 #[derive(Debug)]
 /// A set of connections maintained to a Redis Cluster with key cache.
 pub struct RedisCluster {
@@ -13,6 +20,9 @@ pub struct RedisCluster {
     pub(crate) options: Arc<RedisConnectOptions>,
     pub(crate) conn: HashMap<NodeId, Connection>,
     pub(crate) keys: HashMap<String, NodeId>,
+    pub(crate) slots: Vec<(u16, u16, NodeId)>,
+    /// Set if the connections are shared
+    pub(crate) pool: Option<ConnectionPool>,
 }
 
 impl RedisCluster {
@@ -29,9 +39,18 @@ impl RedisCluster {
             options,
             conn: Default::default(),
             keys: Default::default(),
+            slots: Default::default(),
+            pool: None,
         })
     }
 
+    /// Use the shared connections, if enabled by [`RedisConnectOptions::set_shared_connections`].
+    /// It must not be used for blocking commands.
+    pub(crate) fn shared(mut self) -> Self {
+        self.pool = self.options.pool.clone();
+        self
+    }
+
     pub fn protocol(&self) -> Option<&str> {
         self.cluster.protocol()
     }
@@ -40,7 +59,8 @@ impl RedisCluster {
     pub async fn reconnect_all(&mut self) -> RedisResult<()> {
         self.conn = Default::default();
         for node in self.cluster.nodes() {
-            let conn = Connection::create(node.clone(), self.options.clone()).await?;
+            let conn =
+                Connection::open(node.clone(), self.options.clone(), self.pool.clone()).await?;
             self.conn.insert(node.clone(), conn);
         }
         Ok(())
@@ -54,18 +74,75 @@ impl RedisCluster {
         Ok(())
     }
 
+    /// Query the slot assignment with `CLUSTER SLOTS`, and open connections to all master nodes.
+    /// Afterwards, keys will be sent to the node serving their hash slots in the first place.
+    pub async fn discover_slots(&mut self) -> RedisResult<()> {
+        let (queried, conn) = self.get_any()?;
+        let queried = queried.clone();
+        let reply: Vec<Vec<Value>> = redis::cmd("CLUSTER")
+            .arg("SLOTS")
+            .query_async(conn)
+            .await
+            .map_err(map_err)?;
+        let mut slots = Vec::new();
+        for range in reply {
+            // [start, end, [host, port, id, ..], replicas ..]
+            let (start, end, master) = match range.as_slice() {
+                [start, end, Value::Bulk(master), ..] if master.len() >= 2 => (start, end, master),
+                _ => continue,
+            };
+            let start: u16 = from_redis_value(start).map_err(map_err)?;
+            let end: u16 = from_redis_value(end).map_err(map_err)?;
+            let host: String = from_redis_value(&master[0]).map_err(map_err)?;
+            let port: u16 = from_redis_value(&master[1]).map_err(map_err)?;
+            // an empty host means the same host as the one we are asking
+            let host = if host.is_empty() || host == "?" {
+                queried.host_str().unwrap_or_default().to_owned()
+            } else {
+                host
+            };
+            let node: NodeId = format!("{}://{}:{}", queried.scheme(), host, port)
+                .parse()
+                .map_err(|e| StreamErr::StreamUrlErr(StreamUrlErr::UrlParseError(e)))?;
+            slots.push((start, end, node));
+        }
+        slots.sort_by_key(|(start, _, _)| *start);
+        for (_, _, node) in slots.iter() {
+            if !self.conn.contains_key(node) {
+                let conn = Connection::open_or_reconnect(
+                    node.clone(),
+                    self.options.clone(),
+                    self.pool.clone(),
+                )
+                .await;
+                self.conn.insert(node.clone(), conn);
+            }
+        }
+        self.slots = slots;
+        Ok(())
+    }
+
     #[inline]
-    /// Get the cached node for this key. There is no guarantee that the key assignment is right.
+    /// Get the cached node for this key, or the node serving its hash slot.
+    /// There is no guarantee that the key assignment is right.
     pub fn node_for(&self, key: &str) -> &NodeId {
-        Self::get_node_for(&self.keys, &self.cluster, key)
+        Self::get_node_for(&self.keys, &self.slots, &self.cluster, key)
     }
 
     fn get_node_for<'a>(
         keys: &'a HashMap<String, NodeId>,
+        slots: &'a [(u16, u16, NodeId)],
         cluster: &'a StreamerUri,
         key: &str,
     ) -> &'a NodeId {
         if let Some(node) = keys.get(key) {
+            return node;
+        }
+        let slot = key_slot(key);
+        if let Some((_, _, node)) = slots
+            .iter()
+            .find(|(start, end, _)| *start <= slot && slot <= *end)
+        {
             node
         } else {
             cluster.nodes().first().expect("Should not be empty")
@@ -82,7 +159,7 @@ impl RedisCluster {
     }
 
     /// Get any available connection to the cluster
-    pub fn get_any(&mut self) -> RedisResult<(&NodeId, &mut redis::aio::Connection)> {
+    pub fn get_any(&mut self) -> RedisResult<(&NodeId, &mut RedisConnection)> {
         for (node, inner) in self.conn.iter_mut() {
             if let Ok(conn) = inner.try_get() {
                 return Ok((node, conn));
@@ -93,8 +170,8 @@ impl RedisCluster {
 
     #[inline]
     /// Get a connection to the specific node, will wait and retry a few times until dead.
-    pub async fn get(&mut self, node: &NodeId) -> RedisResult<&mut redis::aio::Connection> {
-        Self::get_connection(&mut self.conn, &self.options, node).await
+    pub async fn get(&mut self, node: &NodeId) -> RedisResult<&mut RedisConnection> {
+        Self::get_connection(&mut self.conn, &self.options, &self.pool, node).await
     }
 
     #[inline]
@@ -103,30 +180,132 @@ impl RedisCluster {
     pub async fn get_connection_for(
         &mut self,
         key: &str,
-    ) -> RedisResult<(&NodeId, &mut redis::aio::Connection)> {
-        let node = Self::get_node_for(&self.keys, &self.cluster, key);
+    ) -> RedisResult<(&NodeId, &mut RedisConnection)> {
+        let node = Self::get_node_for(&self.keys, &self.slots, &self.cluster, key);
         Ok((
             node,
-            Self::get_connection(&mut self.conn, &self.options, node).await?,
+            Self::get_connection(&mut self.conn, &self.options, &self.pool, node).await?,
         ))
     }
 
+    /// Run a command on the node assigned with `key`. If the key has been moved,
+    /// the key assignment is updated and the command is retried once on the new node.
+    pub async fn query<T, F>(&mut self, key: &str, cmd: F) -> RedisResult<T>
+    where
+        T: FromRedisValue,
+        F: Fn(&str) -> redis::Cmd,
+    {
+        let mut redirected = false;
+        loop {
+            let (node, conn) = self.get_connection_for(key).await?;
+            let node = node.to_owned();
+            match cmd(key).query_async(conn).await {
+                Ok(value) => return Ok(value),
+                Err(err) => {
+                    if err.kind() == ErrorKind::Moved && !redirected {
+                        if let Some((to, _slot)) = err.redirect_node() {
+                            // `to` must be in form of `host:port` without protocol
+                            if let Ok(to) = format!("{}://{}", node.scheme(), to).parse() {
+                                self.moved(key, to);
+                                redirected = true;
+                                continue;
+                            }
+                        }
+                    } else if err.kind() == ErrorKind::IoError {
+                        self.reconnect(&node).ok();
+                    }
+                    return Err(map_err(err));
+                }
+            }
+        }
+    }
+
     async fn get_connection<'a>(
         conn: &'a mut HashMap<NodeId, Connection>,
         options: &Arc<RedisConnectOptions>,
+        pool: &Option<ConnectionPool>,
         node: &NodeId,
-    ) -> RedisResult<&'a mut redis::aio::Connection> {
+    ) -> RedisResult<&'a mut RedisConnection> {
         assert!(!node.scheme().is_empty(), "Must have protocol");
-        assert!(node.host_str().is_some(), "Must have host");
-        assert!(node.port().is_some(), "Must have port");
+        // the node standing for a master behind Sentinel is addressed by the master name
+        if !is_sentinel(node) {
+            assert!(node.host_str().is_some(), "Must have host");
+            assert!(node.port().is_some(), "Must have port");
+        }
         if let Some(state) = conn.get_mut(node) {
             state.get().await.ok();
         } else {
             conn.insert(
                 node.clone(),
-                Connection::create_or_reconnect(node.clone(), options.clone()).await?,
+                Connection::open_or_reconnect(node.clone(), options.clone(), pool.clone()).await,
             );
         }
         conn.get_mut(node).expect("Must exist").try_get()
     }
 }
+
+/// The hash slot of a key. Only the hash tag is hashed if there is one,
+/// so `{user}:1` and `{user}:2` are in the same slot.
+pub fn key_slot(key: &str) -> u16 {
+    let bytes = key.as_bytes();
+    let hashed = match bytes.iter().position(|b| *b == b'{') {
+        Some(open) => match bytes[open + 1..].iter().position(|b| *b == b'}') {
+            Some(0) | None => bytes,
+            Some(len) => &bytes[open + 1..open + 1 + len],
+        },
+        None => bytes,
+    };
+    crc16(hashed) % HASH_SLOTS
+}
+
+/// CRC16-CCITT (XMODEM), as specified by Redis Cluster
+fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for byte in bytes {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_key_slot() {
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+        assert_eq!(key_slot("foo"), 12182);
+        assert_eq!(key_slot("bar"), 5061);
+        assert_eq!(key_slot("hello"), 866);
+        assert_eq!(key_slot("{foo}:1"), key_slot("foo"));
+        assert_eq!(key_slot("a{foo}b"), key_slot("foo"));
+        // empty hash tag: the whole key is hashed
+        assert_ne!(key_slot("foo{}"), key_slot(""));
+        // only the first hash tag counts
+        assert_eq!(key_slot("foo{{bar}}zap"), key_slot("{bar"));
+    }
+
+    #[cfg(feature = "test")]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    async fn test_sentinel_node() {
+        use crate::SentinelConfig;
+
+        // nothing listens on port 1
+        let uri: StreamerUri = "redis+sentinel://localhost:1/mymaster".parse().unwrap();
+        let mut options = RedisConnectOptions::default();
+        let sentinel = SentinelConfig::from_uri(&uri, &options).unwrap().unwrap();
+        let node = sentinel.node_id().unwrap();
+        options.sentinel = Some(Arc::new(sentinel));
+        let mut cluster =
+            RedisCluster::new(StreamerUri::one(node.clone()), Arc::new(options)).unwrap();
+        assert!(cluster.get(&node).await.is_err());
+    }
+}
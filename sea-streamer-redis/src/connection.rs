@@ -1,46 +1,141 @@
-use std::{fmt::Debug, sync::Arc, time::Duration};
+use std::{
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use crate::{
-    map_err, NodeId, RedisConnectOptions, RedisErr, RedisResult, DEFAULT_TIMEOUT, REDIS_PORT,
+    connect_tls, is_sentinel, map_err, ConnectionPool, NodeId, RedisConnectOptions, RedisErr,
+    RedisResult, DEFAULT_TIMEOUT, REDIS_PORT,
+};
+use redis::{
+    aio::{ConnectionLike, MultiplexedConnection},
+    Cmd, ConnectionAddr, ConnectionInfo, Pipeline, RedisConnectionInfo, RedisFuture, Value,
 };
-use redis::{ConnectionAddr, ConnectionInfo, RedisConnectionInfo};
-use sea_streamer_runtime::{sleep, timeout};
-use sea_streamer_types::{ConnectOptions, StreamErr};
+use sea_streamer_runtime::{sleep, spawn_task, timeout};
+use sea_streamer_types::{ConnectOptions, StreamErr, Timestamp};
 
 #[derive(Debug)]
-/// A wrapped [`redis::aio::Connection`] that can auto-reconnect.
+/// A wrapped [`RedisConnection`] that can auto-reconnect.
 pub struct Connection {
     node: NodeId,
     options: Arc<RedisConnectOptions>,
     state: State,
+    /// Raised by the Sentinel watcher on failover
+    switched: Option<Arc<AtomicBool>>,
+    /// Where the multiplexed connection comes from, if it is shared
+    pool: Option<ConnectionPool>,
+    /// Of the shared connection, so that the pool replaces it only once
+    generation: u64,
+}
+
+/// A connection to a Redis node.
+pub enum RedisConnection {
+    /// Owned by one producer or consumer. Blocking commands, like `XREAD` with `BLOCK`, need a dedicated connection.
+    Dedicated(redis::aio::Connection),
+    /// A handle of a multiplexed connection, shared by the producers, consumers and admin handles of a streamer.
+    /// See [`RedisConnectOptions::set_shared_connections`].
+    Shared(MultiplexedConnection),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Exponential backoff between attempts to reconnect, see [`RedisConnectOptions::set_reconnect_backoff`].
+pub struct ReconnectBackoff {
+    /// The delay before the first attempt. Defaults to 1 second.
+    pub initial: Duration,
+    /// The delay is doubled after every failed attempt, up to this. Defaults to 64 seconds.
+    pub max: Duration,
+    /// The connection is considered dead after this many failed attempts. None to retry forever. Defaults to 7.
+    pub max_attempts: Option<u32>,
 }
 
 enum State {
-    Alive(redis::aio::Connection),
-    Reconnecting { delay: u32 },
+    Alive(RedisConnection),
+    Reconnecting { attempt: u32 },
     Dead,
 }
 
+impl Debug for RedisConnection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Dedicated(_) => f.debug_tuple("Dedicated").finish(),
+            Self::Shared(_) => f.debug_tuple("Shared").finish(),
+        }
+    }
+}
+
 impl Debug for State {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Alive(_) => f.debug_tuple("Alive").finish(),
-            Self::Reconnecting { delay } => f.debug_tuple("Reconnecting").field(delay).finish(),
+            Self::Alive(conn) => f.debug_tuple("Alive").field(conn).finish(),
+            Self::Reconnecting { attempt } => f.debug_tuple("Reconnecting").field(attempt).finish(),
             Self::Dead => f.debug_tuple("Dead").finish(),
         }
     }
 }
 
+impl ConnectionLike for RedisConnection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        match self {
+            Self::Dedicated(conn) => conn.req_packed_command(cmd),
+            Self::Shared(conn) => conn.req_packed_command(cmd),
+        }
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        match self {
+            Self::Dedicated(conn) => conn.req_packed_commands(cmd, offset, count),
+            Self::Shared(conn) => conn.req_packed_commands(cmd, offset, count),
+        }
+    }
+
+    fn get_db(&self) -> i64 {
+        match self {
+            Self::Dedicated(conn) => conn.get_db(),
+            Self::Shared(conn) => conn.get_db(),
+        }
+    }
+}
+
+impl Default for ReconnectBackoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(64),
+            max_attempts: Some(7),
+        }
+    }
+}
+
+impl ReconnectBackoff {
+    /// The delay before an attempt, counting from 0. Up to 25% of jitter is added,
+    /// so that the connections dropped at the same time are not reopened all at once.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .initial
+            .saturating_mul(1 << attempt.min(16))
+            .min(self.max);
+        let jitter = (Timestamp::now_utc().nanosecond() % 1000) as f64 / 4000.0;
+        delay.mul_f64(1.0 + jitter)
+    }
+
+    fn gives_up(&self, attempts: u32) -> bool {
+        matches!(self.max_attempts, Some(max) if attempts >= max)
+    }
+}
+
 impl Connection {
     /// Create a connection; return error if failed.
     pub async fn create(node: NodeId, options: Arc<RedisConnectOptions>) -> RedisResult<Self> {
-        let conn = create_connection(node.clone(), options.clone()).await?;
-        log::debug!("Opened connection to {node}");
-        Ok(Self {
-            node,
-            options,
-            state: State::Alive(conn),
-        })
+        Self::open(node, options, None).await
     }
 
     /// Create a connection; but retry later if failed.
@@ -48,44 +143,95 @@ impl Connection {
         node: NodeId,
         options: Arc<RedisConnectOptions>,
     ) -> RedisResult<Self> {
-        if let Ok(conn) = create_connection(node.clone(), options.clone()).await {
-            Ok(Self {
-                node,
-                options,
-                state: State::Alive(conn),
-            })
+        Ok(Self::open_or_reconnect(node, options, None).await)
+    }
+
+    /// Like `create`, but take the shared connection from the pool if there is one.
+    pub(crate) async fn open(
+        node: NodeId,
+        options: Arc<RedisConnectOptions>,
+        pool: Option<ConnectionPool>,
+    ) -> RedisResult<Self> {
+        let mut conn = Self::new(node, options, pool);
+        let inner = conn.connect().await?;
+        log::debug!("Opened connection to {}", conn.node);
+        conn.state = State::Alive(inner);
+        Ok(conn)
+    }
+
+    /// Like `create_or_reconnect`, but take the shared connection from the pool if there is one.
+    pub(crate) async fn open_or_reconnect(
+        node: NodeId,
+        options: Arc<RedisConnectOptions>,
+        pool: Option<ConnectionPool>,
+    ) -> Self {
+        let mut conn = Self::new(node, options, pool);
+        if let Ok(inner) = conn.connect().await {
+            conn.state = State::Alive(inner);
+        }
+        conn
+    }
+
+    fn new(node: NodeId, options: Arc<RedisConnectOptions>, pool: Option<ConnectionPool>) -> Self {
+        // the pool follows failovers for the shared connections
+        let switched = if pool.is_none() {
+            watch(&node, &options)
         } else {
-            Ok(Self {
-                node,
-                options,
-                state: State::Reconnecting { delay: 1 },
-            })
+            None
+        };
+        Self {
+            node,
+            options,
+            state: State::Reconnecting { attempt: 0 },
+            switched,
+            pool,
+            generation: 0,
+        }
+    }
+
+    async fn connect(&mut self) -> RedisResult<RedisConnection> {
+        match &self.pool {
+            Some(pool) => {
+                let (conn, generation) = pool.get(&self.node).await?;
+                self.generation = generation;
+                Ok(RedisConnection::Shared(conn))
+            }
+            None => {
+                let mut conn = create_connection(self.node.clone(), self.options.clone()).await?;
+                health_check(&mut conn, &self.options).await?;
+                Ok(RedisConnection::Dedicated(conn))
+            }
         }
     }
 
     /// Drop the connection and reconnect *later*.
     pub fn reconnect(&mut self) {
-        self.state = State::Reconnecting { delay: 1 };
+        if let (Some(pool), State::Alive(_)) = (&self.pool, &self.state) {
+            // so that the other users of the shared connection get a new one too
+            pool.broken(&self.node, self.generation);
+        }
+        self.state = State::Reconnecting { attempt: 0 };
     }
 
-    /// Get a mutable connection, will wait and retry a few times until dead.
-    pub async fn get(&mut self) -> RedisResult<&mut redis::aio::Connection> {
-        match &mut self.state {
-            State::Alive(_) | State::Dead => (),
-            State::Reconnecting { delay } => {
-                assert!(*delay > 0);
-                sleep(Duration::from_secs(*delay as u64)).await;
-                match create_connection(self.node.clone(), self.options.clone()).await {
-                    Ok(conn) => {
-                        self.state = State::Alive(conn);
-                    }
-                    Err(_) => {
-                        if *delay > 60 {
-                            self.state = State::Dead;
-                        } else {
-                            self.state = State::Reconnecting { delay: *delay * 2 };
+    /// Get a mutable connection, will wait and retry with exponential backoff until dead.
+    pub async fn get(&mut self) -> RedisResult<&mut RedisConnection> {
+        self.check_switched();
+        if let State::Reconnecting { attempt } = self.state {
+            sleep(self.options.reconnect_backoff().delay(attempt)).await;
+            match self.connect().await {
+                Ok(conn) => {
+                    log::debug!("Reconnected to {}", self.node);
+                    self.state = State::Alive(conn);
+                }
+                Err(err) => {
+                    log::warn!("Failed to reconnect to {}: {err}", self.node);
+                    self.state = if self.options.reconnect_backoff().gives_up(attempt + 1) {
+                        State::Dead
+                    } else {
+                        State::Reconnecting {
+                            attempt: attempt + 1,
                         }
-                    }
+                    };
                 }
             }
         }
@@ -93,7 +239,8 @@ impl Connection {
     }
 
     /// Get a mutable connection, only if it is alive.
-    pub fn try_get(&mut self) -> RedisResult<&mut redis::aio::Connection> {
+    pub fn try_get(&mut self) -> RedisResult<&mut RedisConnection> {
+        self.check_switched();
         match &mut self.state {
             State::Alive(conn) => Ok(conn),
             State::Dead => Err(StreamErr::Connect(format!(
@@ -107,6 +254,16 @@ impl Connection {
         }
     }
 
+    /// If the master behind Sentinel has been replaced, reconnect to the new master.
+    fn check_switched(&mut self) {
+        if let Some(switched) = &self.switched {
+            if switched.swap(false, Ordering::AcqRel) && matches!(self.state, State::Alive(_)) {
+                log::info!("Master of {} has been switched, reconnecting", self.node);
+                self.reconnect();
+            }
+        }
+    }
+
     pub fn protocol(&self) -> &str {
         self.node.scheme()
     }
@@ -114,45 +271,193 @@ impl Connection {
     pub fn node_id(&self) -> &NodeId {
         &self.node
     }
+
+    /// Whether this is a shared connection.
+    pub fn is_shared(&self) -> bool {
+        self.pool.is_some()
+    }
 }
 
-async fn create_connection(
+/// Follow the failovers of this node, if it is a master behind Sentinel.
+pub(crate) fn watch(node: &NodeId, options: &Arc<RedisConnectOptions>) -> Option<Arc<AtomicBool>> {
+    match (is_sentinel(node), options.sentinel.as_ref()) {
+        (true, Some(sentinel)) => Some(sentinel.subscribe(options)),
+        _ => None,
+    }
+}
+
+/// A connection is healthy if it answers `PING` in time.
+pub(crate) async fn health_check<C: ConnectionLike>(
+    conn: &mut C,
+    options: &RedisConnectOptions,
+) -> RedisResult<()> {
+    let ping = redis::cmd("PING");
+    let pong = ping.query_async::<_, String>(conn);
+    match timeout(options.timeout().unwrap_or(DEFAULT_TIMEOUT), pong).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(err)) => Err(map_err(err)),
+        Err(_) => Err(StreamErr::Connect("Health check timeout".to_owned())),
+    }
+}
+
+pub(crate) async fn create_connection(
     url: NodeId,
     options: Arc<RedisConnectOptions>,
 ) -> RedisResult<redis::aio::Connection> {
+    let (addr, redis) = resolve(&url, &options).await?;
+    let mut conn = open_connection(addr, redis, &options).await?;
+    if is_sentinel(&url) {
+        check_master(&url, &mut conn).await?;
+    }
+    Ok(conn)
+}
+
+/// Like `create_connection`, but the connection is multiplexed.
+pub(crate) async fn create_multiplexed(
+    url: NodeId,
+    options: Arc<RedisConnectOptions>,
+) -> RedisResult<MultiplexedConnection> {
+    let (addr, redis) = resolve(&url, &options).await?;
+    let connect = async {
+        match addr {
+            ConnectionAddr::TcpTls {
+                host,
+                port,
+                insecure,
+            } => {
+                let stream = connect_tls(&host, port, insecure, options.tls()).await?;
+                let (conn, driver) = MultiplexedConnection::new(&redis, stream)
+                    .await
+                    .map_err(map_err)?;
+                // the connection is closed when the driver ends, i.e. when all handles are dropped
+                spawn_task(driver);
+                Ok(conn)
+            }
+            addr => {
+                let conn = ConnectionInfo { addr, redis };
+                let client = redis::Client::open(conn).map_err(map_err)?;
+                client
+                    .get_multiplexed_async_connection()
+                    .await
+                    .map_err(map_err)
+            }
+        }
+    };
+    let mut conn = match timeout(options.timeout().unwrap_or(DEFAULT_TIMEOUT), connect).await {
+        Ok(res) => res?,
+        Err(_) => return Err(StreamErr::Connect("Connection timeout".to_owned())),
+    };
+    if is_sentinel(&url) {
+        check_master(&url, &mut conn).await?;
+    }
+    Ok(conn)
+}
+
+/// The address of a node, which is the current master if the node stands for a master behind Sentinel.
+async fn resolve(
+    url: &NodeId,
+    options: &RedisConnectOptions,
+) -> RedisResult<(ConnectionAddr, RedisConnectionInfo)> {
+    let redis = RedisConnectionInfo {
+        db: options.db() as i64,
+        username: options.username().map(|s| s.to_owned()),
+        password: options.password().map(|s| s.to_owned()),
+    };
+    if is_sentinel(url) {
+        let sentinel = match options.sentinel.as_ref() {
+            Some(sentinel) => sentinel,
+            None => return Err(StreamErr::Connect("Sentinel not configured".to_owned())),
+        };
+        let addr = sentinel.resolve(options).await?;
+        log::debug!("Master of {url} is at {addr:?}");
+        return Ok((addr, redis));
+    }
+
     let host = if let Some(host) = url.host_str() {
         host.to_owned()
     } else {
         return Err(StreamErr::Connect("Host empty".to_owned()));
     };
     let port = url.port().unwrap_or(REDIS_PORT);
-    let conn = ConnectionInfo {
-        addr: match url.scheme() {
-            "redis" => ConnectionAddr::Tcp(host, port),
-            "rediss" => ConnectionAddr::TcpTls {
+    let addr = match url.scheme() {
+        "redis" => ConnectionAddr::Tcp(host, port),
+        "rediss" => ConnectionAddr::TcpTls {
+            host,
+            port,
+            insecure: options.disable_hostname_verification(),
+        },
+        "" => return Err(StreamErr::Connect("protocol not set".to_owned())),
+        protocol => return Err(StreamErr::Connect(format!("unknown protocol `{protocol}`"))),
+    };
+    Ok((addr, redis))
+}
+
+/// The Sentinels may be lagging behind a failover.
+async fn check_master<C: ConnectionLike>(url: &NodeId, conn: &mut C) -> RedisResult<()> {
+    let role: Vec<Value> = redis::cmd("ROLE")
+        .query_async(conn)
+        .await
+        .map_err(map_err)?;
+    match role.first() {
+        Some(Value::Data(role)) if role == b"master" => Ok(()),
+        _ => Err(StreamErr::Connect(format!("{url} is not a master"))),
+    }
+}
+
+pub(crate) async fn open_connection(
+    addr: ConnectionAddr,
+    redis: RedisConnectionInfo,
+    options: &RedisConnectOptions,
+) -> RedisResult<redis::aio::Connection> {
+    let connect = async {
+        match addr {
+            // we do the handshake ourselves, so that we control the TLS configuration
+            ConnectionAddr::TcpTls {
                 host,
                 port,
-                insecure: options.disable_hostname_verification(),
-            },
-            "" => return Err(StreamErr::Connect("protocol not set".to_owned())),
-            protocol => return Err(StreamErr::Connect(format!("unknown protocol `{protocol}`"))),
-        },
-        redis: RedisConnectionInfo {
-            db: options.db() as i64,
-            username: options.username().map(|s| s.to_owned()),
-            password: options.password().map(|s| s.to_owned()),
-        },
+                insecure,
+            } => {
+                let stream = connect_tls(&host, port, insecure, options.tls()).await?;
+                redis::aio::Connection::new(&redis, stream)
+                    .await
+                    .map_err(map_err)
+            }
+            addr => {
+                let conn = ConnectionInfo { addr, redis };
+                let client = redis::Client::open(conn).map_err(map_err)?;
+                client.get_async_connection().await.map_err(map_err)
+            }
+        }
     };
-    let client = redis::Client::open(conn).map_err(map_err)?;
     // I wish we could do `.await_timeout(d)` some day
-    match timeout(
-        options.timeout().unwrap_or(DEFAULT_TIMEOUT),
-        client.get_async_connection(),
-    )
-    .await
-    {
-        Ok(Ok(conn)) => Ok(conn),
-        Ok(Err(err)) => Err(map_err(err)),
+    match timeout(options.timeout().unwrap_or(DEFAULT_TIMEOUT), connect).await {
+        Ok(res) => res,
         Err(_) => Err(StreamErr::Connect("Connection timeout".to_owned())),
     }
 }
+
+#[cfg(test)]
+#[test]
+fn test_reconnect_backoff() {
+    let backoff = ReconnectBackoff::default();
+    let assert_around = |attempt: u32, secs: u64| {
+        let delay = backoff.delay(attempt);
+        let expected = Duration::from_secs(secs);
+        assert!(
+            expected <= delay && delay <= expected * 5 / 4,
+            "{delay:?} for attempt {attempt}"
+        );
+    };
+    assert_around(0, 1);
+    assert_around(1, 2);
+    assert_around(6, 64);
+    assert_around(100, 64);
+    // the same as it used to be: 1, 2, 4 .. 64 seconds
+    assert!(!backoff.gives_up(6));
+    assert!(backoff.gives_up(7));
+    let forever = ReconnectBackoff {
+        max_attempts: None,
+        ..backoff
+    };
+    assert!(!forever.gives_up(u32::MAX));
+}
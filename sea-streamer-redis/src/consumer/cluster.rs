@@ -1,10 +1,13 @@
 use flume::{bounded, Receiver, Sender, TryRecvError};
 use std::{collections::HashMap, sync::Arc, time::Duration};
 
-use super::{Node, ShardState, StreamShard};
-use crate::{Connection, MessageId, NodeId, RedisCluster, RedisConsumerOptions, RedisResult};
+use super::{format_stream_shard, rewind_latch, DeadLetterMsg, Node, ShardState, StreamShard};
+use crate::{
+    Connection, MessageId, NodeId, RedisCluster, RedisConnectOptions, RedisConsumerOptions,
+    RedisErr, RedisResult,
+};
 use sea_streamer_runtime::{sleep, spawn_task};
-use sea_streamer_types::{SharedMessage, Timestamp};
+use sea_streamer_types::{SharedMessage, StreamErr, Timestamp};
 
 const ONE_SEC: Duration = Duration::from_secs(1);
 
@@ -12,13 +15,18 @@ pub struct Cluster {
     shards: Vec<ShardState>,
     consumer_options: Arc<RedisConsumerOptions>,
     messages: Sender<RedisResult<SharedMessage>>,
+    dead_letters: Option<Sender<DeadLetterMsg>>,
     nodes: HashMap<NodeId, Sender<CtrlMsg>>,
     keys: HashMap<StreamShard, NodeId>,
+    /// Number of nodes yet to finish rewinding
+    rewinding: usize,
 }
 
 #[allow(clippy::large_enum_variant)]
 pub enum StatusMsg {
     Ready,
+    /// A node managed by a cluster has finished rewinding, and is paused until `Resume`
+    Rewound,
     Moved {
         shard: ShardState,
         from: NodeId,
@@ -35,6 +43,7 @@ pub enum CtrlMsg {
     AddShard(Box<ShardState>),
     Ack(StreamShard, MessageId, Timestamp),
     Rewind(Vec<StreamShard>, MessageId),
+    Resume,
     Commit(Sender<RedisResult<()>>),
     Kill(Sender<()>),
 }
@@ -53,43 +62,45 @@ impl Cluster {
         consumer_options: Arc<RedisConsumerOptions>,
         shards: Vec<ShardState>,
         messages: Sender<RedisResult<SharedMessage>>,
+        dead_letters: Option<Sender<DeadLetterMsg>>,
     ) -> RedisResult<Self> {
         Ok(Cluster {
             consumer_options,
             shards,
             messages,
+            dead_letters,
             nodes: Default::default(),
             keys: Default::default(),
+            rewinding: 0,
         })
     }
 
     pub async fn run(
         mut self,
-        cluster: RedisCluster,
+        mut cluster: RedisCluster,
         response: Receiver<CtrlMsg>,
         status: Sender<StatusMsg>,
     ) {
-        let RedisCluster {
-            cluster: cluster_uri,
-            options: connect_options,
-            conn: connections,
-            ..
-        } = cluster;
+        let cluster_uri = cluster.cluster.clone();
+        let connect_options = cluster.options.clone();
+        let mut connections = std::mem::take(&mut cluster.conn);
         let (sender, receiver) = bounded(128);
-        for (node_id, conn) in connections {
-            let node = self.add_node(node_id.clone(), sender.clone());
-            node.send_async(CtrlMsg::Init(Box::new((node_id, conn))))
-                .await
-                .unwrap();
+        // we assign each shard to the node serving its hash slot, they will be moved if it is wrong
+        let mut placement: HashMap<NodeId, Vec<ShardState>> = HashMap::new();
+        for shard in std::mem::take(&mut self.shards) {
+            let node_id = cluster.node_for(&shard.key).to_owned();
+            self.keys.insert(shard.key().to_owned(), node_id.clone());
+            placement.entry(node_id).or_default().push(shard);
         }
-        {
-            // we assign all shards to the first node, they will be moved later
-            let (node_id, node) = self.nodes.iter().next().unwrap();
-            for shard in std::mem::take(&mut self.shards) {
-                self.keys.insert(shard.key().to_owned(), node_id.to_owned());
-                node.send_async(CtrlMsg::AddShard(Box::new(shard)))
-                    .await
-                    .unwrap();
+        for (node_id, shards) in placement {
+            // if it fails, the status sender is dropped and the consumer fails to initialize
+            let conn = connections.remove(&node_id);
+            if let Err(err) = self
+                .init_node(node_id, conn, shards, &sender, &connect_options)
+                .await
+            {
+                log::error!("Cluster {cluster_uri} failed to initialize: {err}");
+                return;
             }
         }
         let mut ready_count = 0;
@@ -98,9 +109,17 @@ impl Cluster {
 
             match response.try_recv() {
                 Ok(res) => match res {
-                    CtrlMsg::Init(_) => panic!("Unexpected CtrlMsg {:?}", res),
+                    CtrlMsg::Init(_) | CtrlMsg::Resume => panic!("Unexpected CtrlMsg {:?}", res),
                     CtrlMsg::Read | CtrlMsg::Unread => {
-                        todo!("We should keep track of the reads on each node and load balance among them");
+                        // We don't know which node will have messages first, so every node is asked
+                        // to read. It means at most one message per node may be fetched in advance.
+                        let read = matches!(res, CtrlMsg::Read);
+                        for node in self.nodes.values() {
+                            let msg = if read { CtrlMsg::Read } else { CtrlMsg::Unread };
+                            if node.send_async(msg).await.is_err() {
+                                break;
+                            }
+                        }
                     }
                     CtrlMsg::Ack(key, b, c) => {
                         if let Some(at) = self.keys.get(&key) {
@@ -112,11 +131,54 @@ impl Cluster {
                             panic!("Unexpected shard `{:?}`", key);
                         }
                     }
-                    CtrlMsg::Rewind(_, _) => {
-                        todo!("For existing shards, we send a Rewind msg to the node. For new shards, we'd add them to the first node.");
+                    CtrlMsg::Rewind(shards, pos) => {
+                        // Existing shards are rewound on their nodes, new shards are added to the node
+                        // serving their hash slots. Every node is paused after rewinding, and resumed
+                        // after the latch is sent, so that no message is drained by the consumer.
+                        let mut plan: HashMap<NodeId, Vec<StreamShard>> = self
+                            .nodes
+                            .keys()
+                            .map(|node_id| (node_id.clone(), Vec::new()))
+                            .collect();
+                        for shard in shards {
+                            let node_id = match self.keys.get(&shard) {
+                                Some(node_id) => node_id.clone(),
+                                None => cluster.node_for(&format_stream_shard(&shard)).to_owned(),
+                            };
+                            plan.entry(node_id).or_default().push(shard);
+                        }
+                        self.keys = plan
+                            .iter()
+                            .flat_map(|(node_id, shards)| {
+                                shards.iter().map(|s| (s.clone(), node_id.clone()))
+                            })
+                            .collect();
+                        self.rewinding += plan.len();
+                        let mut result = Ok(());
+                        for (node_id, shards) in plan {
+                            let msg = CtrlMsg::Rewind(shards, pos);
+                            result = self
+                                .send_to_node(node_id, msg, &sender, &connect_options)
+                                .await;
+                            if result.is_err() {
+                                break;
+                            }
+                        }
+                        if self.fail(result).await {
+                            break;
+                        }
                     }
-                    CtrlMsg::AddShard(m) => {
-                        panic!("Unexpected CtrlMsg CtrlMsg::AddShard({:?})", m)
+                    CtrlMsg::AddShard(shard) => {
+                        // a shard discovered after resharding, placed like the initial shards
+                        let node_id = cluster.node_for(&shard.key).to_owned();
+                        self.keys.insert(shard.key().to_owned(), node_id.clone());
+                        let msg = CtrlMsg::AddShard(shard);
+                        let result = self
+                            .send_to_node(node_id, msg, &sender, &connect_options)
+                            .await;
+                        if self.fail(result).await {
+                            break;
+                        }
                     }
                     CtrlMsg::Commit(finally) => {
                         for node in self.nodes.values() {
@@ -165,37 +227,28 @@ impl Cluster {
                             log::debug!("Cluster {cluster_uri} ready");
                         }
                     }
+                    StatusMsg::Rewound => {
+                        self.rewinding -= 1;
+                        if self.rewinding == 0 {
+                            if self.messages.send_async(Ok(rewind_latch())).await.is_err() {
+                                break;
+                            }
+                            for node in self.nodes.values() {
+                                node.send_async(CtrlMsg::Resume).await.ok();
+                            }
+                        }
+                    }
                     StatusMsg::Moved { shard, from, to } => {
                         log::info!("Shard {shard:?} moving from {from} to {to}");
-                        let conn = if self.nodes.get(&to).is_none() {
-                            Some(
-                                Connection::create_or_reconnect(
-                                    to.clone(),
-                                    connect_options.clone(),
-                                )
-                                .await
-                                .unwrap(),
-                            )
-                        } else {
-                            None
-                        };
-                        self.add_node(to.clone(), sender.clone());
-                        let node = self.nodes.get(&to).unwrap();
+                        cluster.moved(&shard.key, to.clone());
                         if let Some(key) = self.keys.get_mut(shard.key()) {
                             *key = to.clone();
                         } else {
                             panic!("Unexpected shard `{}`", shard.key);
                         }
-                        if let Some(conn) = conn {
-                            node.send_async(CtrlMsg::Init(Box::new((to, conn))))
-                                .await
-                                .unwrap();
-                        }
-                        if node
-                            .send_async(CtrlMsg::AddShard(Box::new(shard)))
-                            .await
-                            .is_err()
-                        {
+                        let msg = CtrlMsg::AddShard(Box::new(shard));
+                        let result = self.send_to_node(to, msg, &sender, &connect_options).await;
+                        if self.fail(result).await {
                             break;
                         }
                     }
@@ -216,6 +269,72 @@ impl Cluster {
         log::debug!("Cluster {cluster_uri} exit");
     }
 
+    /// Send a message to a node, adding the node with a new connection if it does not exist yet.
+    ///
+    /// Errs if the connection cannot be created or the node task has died.
+    async fn send_to_node(
+        &mut self,
+        node_id: NodeId,
+        msg: CtrlMsg,
+        event_sender: &Sender<StatusMsg>,
+        connect_options: &Arc<RedisConnectOptions>,
+    ) -> RedisResult<()> {
+        if !self.nodes.contains_key(&node_id) {
+            self.init_node(
+                node_id.clone(),
+                None,
+                Vec::new(),
+                event_sender,
+                connect_options,
+            )
+            .await?;
+        }
+        let node = self.nodes.get(&node_id).unwrap();
+        node.send_async(msg)
+            .await
+            .map_err(|_| StreamErr::Backend(RedisErr::ConsumerDied))
+    }
+
+    /// Pass the error on to the consumer. Returns true if the cluster should exit.
+    async fn fail(&self, result: RedisResult<()>) -> bool {
+        match result {
+            Ok(()) => false,
+            Err(err) => {
+                log::error!("Cluster failed: {err}");
+                self.messages.send_async(Err(err)).await.ok();
+                true
+            }
+        }
+    }
+
+    /// Add a node with the given connection (or a new one) and shards
+    async fn init_node(
+        &mut self,
+        node_id: NodeId,
+        conn: Option<Connection>,
+        shards: Vec<ShardState>,
+        event_sender: &Sender<StatusMsg>,
+        connect_options: &Arc<RedisConnectOptions>,
+    ) -> RedisResult<()> {
+        let conn = match conn {
+            Some(conn) => conn,
+            None => {
+                Connection::create_or_reconnect(node_id.clone(), connect_options.clone()).await?
+            }
+        };
+        let node = self.add_node(node_id.clone(), event_sender.clone());
+        let died = |_| StreamErr::Backend(RedisErr::ConsumerDied);
+        node.send_async(CtrlMsg::Init(Box::new((node_id, conn))))
+            .await
+            .map_err(died)?;
+        for shard in shards {
+            node.send_async(CtrlMsg::AddShard(Box::new(shard)))
+                .await
+                .map_err(died)?;
+        }
+        Ok(())
+    }
+
     fn add_node(&mut self, node_id: NodeId, event_sender: Sender<StatusMsg>) -> &Sender<CtrlMsg> {
         if self.nodes.get(&node_id).is_none() {
             let (ctrl_sender, receiver) = bounded(128);
@@ -224,6 +343,7 @@ impl Cluster {
                 node_id.to_owned(),
                 self.consumer_options.clone(),
                 self.messages.clone(),
+                self.dead_letters.clone(),
             );
             spawn_task(node.run(receiver, event_sender));
         }
@@ -0,0 +1,240 @@
+use flume::{Receiver, Sender};
+use redis::{
+    cmd as command,
+    streams::{StreamId, StreamRangeReply},
+    FromRedisValue,
+};
+
+use super::{constants::DEAD_LETTER_SUFFIX, StreamShard};
+use crate::{
+    get_message_id, header_of_id, map_err, message_id_from_str, MessageId, RedisCluster, RedisErr,
+    RedisResult, MSG, ZERO,
+};
+use sea_streamer_types::{
+    Buffer, Message, MessageHeader, ShardId, SharedMessage, StreamErr, StreamKey,
+};
+
+const STREAM: &str = "stream";
+const SHARD: &str = "shard";
+const KEY: &str = "key";
+const ID: &str = "id";
+const GROUP: &str = "group";
+const CONSUMER: &str = "consumer";
+const DELIVERIES: &str = "deliveries";
+const IDLE: &str = "idle";
+
+/// An entry of `XPENDING`: (id, consumer, idle in ms, number of deliveries)
+pub(crate) type PendingEntry = (String, String, u64, usize);
+
+#[derive(Debug, Clone)]
+/// A message moved to a dead-letter stream, after it has been delivered
+/// [`max_deliveries`](crate::RedisConsumerOptions::set_max_deliveries) times without being acked.
+pub struct DeadLetter {
+    /// The entry in the dead-letter stream. The payload is the same as the original message.
+    pub message: SharedMessage,
+    /// The stream and shard the message was originally sent to
+    pub stream: StreamShard,
+    /// The id of the original message
+    pub id: MessageId,
+    /// The consumer group in which the message failed
+    pub group: String,
+    /// The last consumer the message was delivered to
+    pub consumer: String,
+    /// The number of times the message was delivered
+    pub deliveries: usize,
+    /// Milliseconds since the last delivery, when the message was moved
+    pub idle: u64,
+    /// The Redis key of the original shard
+    key: String,
+}
+
+impl DeadLetter {
+    /// The dead-letter stream this letter is in
+    pub fn dead_letter_stream(&self) -> &StreamKey {
+        self.message.header().stream_key()
+    }
+}
+
+/// Sent by a node to move the pending entries of a shard to the dead-letter stream.
+/// The dead-letter stream may live on another node, so this is done by a separate task.
+pub(crate) struct DeadLetterMsg {
+    pub stream: StreamShard,
+    pub key: String,
+    pub group: String,
+    pub entries: Vec<PendingEntry>,
+    pub reply: Sender<RedisResult<()>>,
+}
+
+pub(crate) struct DeadLetterWriter {
+    pub(crate) cluster: RedisCluster,
+    pub(crate) stream: Option<StreamKey>,
+}
+
+impl DeadLetterWriter {
+    /// Runs until all nodes of the consumer are dropped.
+    pub(crate) async fn run(mut self, requests: Receiver<DeadLetterMsg>) {
+        while let Ok(req) = requests.recv_async().await {
+            let result = self.write(&req).await;
+            req.reply.send_async(result).await.ok();
+        }
+        log::debug!("DeadLetterWriter exit");
+    }
+
+    async fn write(&mut self, req: &DeadLetterMsg) -> RedisResult<()> {
+        let dead_letter_key = match &self.stream {
+            Some(stream) => stream.name().to_owned(),
+            None => format!("{}{}", req.stream.0.name(), DEAD_LETTER_SUFFIX),
+        };
+        for (id, consumer, idle, deliveries) in req.entries.iter() {
+            let reply: StreamRangeReply = self
+                .query(&req.key, |key| {
+                    let mut cmd = command("XRANGE");
+                    cmd.arg(key).arg(id).arg(id);
+                    cmd
+                })
+                .await?;
+            // the entry may have been trimmed, in which case there is nothing to keep
+            if let Some(entry) = reply.ids.first() {
+                let payload = match entry.map.get(MSG) {
+                    Some(value) => Vec::<u8>::from_redis_value(value).map_err(map_err)?,
+                    None => Vec::new(),
+                };
+                let () = self
+                    .query(&dead_letter_key, |key| {
+                        let mut cmd = command("XADD");
+                        cmd.arg(key)
+                            .arg("*")
+                            .arg(MSG)
+                            .arg(payload.as_slice())
+                            .arg(STREAM)
+                            .arg(req.stream.0.name())
+                            .arg(SHARD)
+                            .arg(req.stream.1.id())
+                            .arg(KEY)
+                            .arg(&req.key)
+                            .arg(ID)
+                            .arg(id)
+                            .arg(GROUP)
+                            .arg(&req.group)
+                            .arg(CONSUMER)
+                            .arg(consumer)
+                            .arg(DELIVERIES)
+                            .arg(deliveries)
+                            .arg(IDLE)
+                            .arg(idle);
+                        cmd
+                    })
+                    .await?;
+            }
+            // if we fail here, the message will be dead-lettered again next time
+            let () = self
+                .query(&req.key, |key| {
+                    let mut cmd = command("XACK");
+                    cmd.arg(key).arg(&req.group).arg(id);
+                    cmd
+                })
+                .await?;
+            log::debug!(
+                "Moved {} {id} to {dead_letter_key} after {deliveries} deliveries",
+                req.key
+            );
+        }
+        Ok(())
+    }
+
+    async fn query<T, F>(&mut self, key: &str, cmd: F) -> RedisResult<T>
+    where
+        T: FromRedisValue,
+        F: Fn(&str) -> redis::Cmd,
+    {
+        self.cluster.query(key, cmd).await
+    }
+}
+
+/// Read up to `count` dead letters after `after` (exclusive) from a dead-letter stream, oldest first.
+pub(crate) async fn read_dead_letters(
+    cluster: &mut RedisCluster,
+    stream: &StreamKey,
+    after: Option<MessageId>,
+    count: usize,
+) -> RedisResult<Vec<DeadLetter>> {
+    let start = match after {
+        Some((timestamp, seq)) => format!("({timestamp}-{seq}"),
+        None => "-".to_owned(),
+    };
+    let reply: StreamRangeReply = cluster
+        .query(stream.name(), |key| {
+            let mut cmd = command("XRANGE");
+            cmd.arg(key).arg(&start).arg("+").arg("COUNT").arg(count);
+            cmd
+        })
+        .await?;
+    reply
+        .ids
+        .into_iter()
+        .map(|entry| parse_dead_letter(stream, entry))
+        .collect()
+}
+
+/// Send a dead letter back to the shard it came from, then remove it from the dead-letter stream.
+/// Returns the id of the message in the original stream.
+pub(crate) async fn replay_dead_letter(
+    cluster: &mut RedisCluster,
+    letter: &DeadLetter,
+) -> RedisResult<MessageId> {
+    let id: String = cluster
+        .query(&letter.key, |key| {
+            let mut cmd = command("XADD");
+            cmd.arg(key)
+                .arg("*")
+                .arg(MSG)
+                .arg(letter.message.message().as_bytes());
+            cmd
+        })
+        .await?;
+    let (timestamp, seq) = get_message_id(letter.message.header());
+    let () = cluster
+        .query(letter.message.stream_key().name(), |key| {
+            let mut cmd = command("XDEL");
+            cmd.arg(key).arg(format!("{timestamp}-{seq}"));
+            cmd
+        })
+        .await?;
+    message_id_from_str(&id)
+}
+
+fn parse_dead_letter(stream: &StreamKey, entry: StreamId) -> RedisResult<DeadLetter> {
+    let (timestamp, sequence) = header_of_id(&entry.id)?;
+    let payload: Vec<u8> = get(&entry, MSG)?;
+    let length = payload.len();
+    let original: String = get(&entry, ID)?;
+    let original = message_id_from_str(&original)?;
+    Ok(DeadLetter {
+        message: SharedMessage::new(
+            MessageHeader::new(stream.clone(), ZERO, sequence, timestamp),
+            payload,
+            0,
+            length,
+        ),
+        stream: (
+            StreamKey::new(get::<String>(&entry, STREAM)?)?,
+            ShardId::new(get(&entry, SHARD)?),
+        ),
+        id: original,
+        group: get(&entry, GROUP)?,
+        consumer: get(&entry, CONSUMER)?,
+        deliveries: get(&entry, DELIVERIES)?,
+        idle: get(&entry, IDLE)?,
+        key: get(&entry, KEY)?,
+    })
+}
+
+fn get<T: FromRedisValue>(entry: &StreamId, field: &str) -> RedisResult<T> {
+    match entry.map.get(field) {
+        Some(value) => T::from_redis_value(value).map_err(map_err),
+        None => Err(StreamErr::Backend(RedisErr::TypeError(format!(
+            "Dead letter {} has no field `{field}`",
+            entry.id
+        )))),
+    }
+}
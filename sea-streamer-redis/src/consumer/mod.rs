@@ -1,22 +1,30 @@
 mod cluster;
+mod dead_letter;
 mod future;
 mod node;
 mod options;
+mod ownership;
 mod shard;
+mod transport;
 
 use cluster::*;
+pub use dead_letter::*;
 use future::StreamFuture;
 pub use future::{NextFuture, StreamFuture as RedisMessageStream};
 use node::*;
 pub use options::*;
+use ownership::*;
+pub(crate) use shard::discover_shards;
+pub use shard::StreamShard;
 use shard::*;
+use transport::create_transport_consumer;
 
 use flume::{bounded, unbounded, Receiver, Sender};
-use std::{fmt::Debug, future::Future, sync::Arc, time::Duration};
+use std::{collections::HashSet, fmt::Debug, future::Future, sync::Arc, time::Duration};
 
 use crate::{
     from_seq_no, get_message_id, host_id, MessageId, RedisCluster, RedisErr, RedisResult,
-    DEFAULT_TIMEOUT, MAX_MSG_ID,
+    RedisTransport, TransactionAck, DEFAULT_TIMEOUT, MAX_MSG_ID, ZERO,
 };
 use sea_streamer_runtime::{spawn_task, timeout};
 use sea_streamer_types::{
@@ -33,6 +41,11 @@ pub struct RedisConsumer {
     streams: Vec<StreamShard>,
     receiver: Receiver<RedisResult<SharedMessage>>,
     handle: Sender<CtrlMsg>,
+    /// Shards created by resharding
+    discovered: Receiver<StreamShard>,
+    /// Streams that were not sharded on create
+    unsharded: HashSet<StreamKey>,
+    transport: RedisTransport,
 }
 
 #[derive(Debug, Clone)]
@@ -51,6 +64,8 @@ pub struct RedisConsumerOptions {
     batch_size: usize,
     shard_ownership: ShardOwnership,
     mkstream: bool,
+    max_deliveries: Option<usize>,
+    dead_letter_stream: Option<StreamKey>,
 }
 
 #[derive(Debug)]
@@ -58,6 +73,7 @@ struct ConsumerConfig {
     group_id: Option<ConsumerGroup>,
     consumer_id: Option<ConsumerId>,
     auto_ack: bool,
+    manual_ack: bool,
     pre_fetch: bool,
 }
 
@@ -71,10 +87,24 @@ pub mod constants {
     pub const DEFAULT_AUTO_CLAIM_IDLE: Duration = Duration::from_secs(60);
     pub const DEFAULT_BATCH_SIZE: usize = 100;
     pub const DEFAULT_LOAD_BALANCED_BATCH_SIZE: usize = 10;
+    pub const DEAD_LETTER_SUFFIX: &str = ".dead-letter";
+    pub const SHARD_META_SUFFIX: &str = ".shards";
     #[cfg(feature = "test")]
     pub const HEARTBEAT: Duration = Duration::from_secs(1);
     #[cfg(not(feature = "test"))]
     pub const HEARTBEAT: Duration = Duration::from_secs(10);
+    /// How often producers and consumers check the sharding metadata of streams
+    #[cfg(feature = "test")]
+    pub const SHARD_REFRESH: Duration = Duration::from_secs(1);
+    #[cfg(not(feature = "test"))]
+    pub const SHARD_REFRESH: Duration = Duration::from_secs(10);
+    /// How long the draining marker of a stream being resharded stays valid, unless renewed by `RedisAdmin::reshard`
+    #[cfg(feature = "test")]
+    pub const DRAIN_LEASE: Duration = Duration::from_secs(5);
+    #[cfg(not(feature = "test"))]
+    pub const DRAIN_LEASE: Duration = Duration::from_secs(60);
+    /// How many messages a producer holds for streams being resharded, before failing new ones
+    pub const MAX_HELD_MESSAGES: usize = 65536;
 }
 
 #[async_trait]
@@ -86,21 +116,25 @@ impl Consumer for RedisConsumer {
 
     #[inline]
     async fn seek(&mut self, ts: Timestamp) -> RedisResult<()> {
-        self.seek_to(((ts.unix_timestamp_nanos() / 1_000_000) as u64, u16::MAX))
+        self.seek_to(((ts.unix_timestamp_nanos() / 1_000_000) as u64, u64::MAX))
             .await
     }
 
     #[inline]
+    /// `SeqPos::At` must be a packed message id, otherwise `RedisErr::SequenceOverflow` is returned.
+    /// Use [`RedisConsumer::seek_to`] for the ids that overflow it.
     async fn rewind(&mut self, pos: SeqPos) -> RedisResult<()> {
         self.seek_to(match pos {
             SeqPos::Beginning => (0, 0),
             SeqPos::End => MAX_MSG_ID,
-            SeqPos::At(no) => from_seq_no(no),
+            SeqPos::At(no) => from_seq_no(no)?,
         })
         .await
     }
 
     fn assign(&mut self, (stream, shard): StreamShard) -> RedisResult<()> {
+        self.streams_only("Assign")?;
+        self.add_discovered();
         if !self.streams.iter().any(|(s, _)| s == &stream) {
             return Err(StreamErr::StreamKeyNotFound);
         }
@@ -115,6 +149,8 @@ impl Consumer for RedisConsumer {
     }
 
     fn unassign(&mut self, s: StreamShard) -> RedisResult<()> {
+        self.streams_only("Unassign")?;
+        self.add_discovered();
         if let Some((i, _)) = self.streams.iter().enumerate().find(|(_, t)| &s == *t) {
             self.streams.remove(i);
             if self.streams.is_empty() {
@@ -153,12 +189,38 @@ impl RedisConsumer {
 
     /// Return the stream-shards this consumer has been assigned.
     /// On create, it will self-assign all shards.
+    /// Shards created by [`crate::RedisAdmin::reshard`] are assigned as they are discovered,
+    /// and listed here after the next `seek`, `rewind`, `assign` or `unassign`.
     pub fn stream_shards(&self) -> &[StreamShard] {
         &self.streams
     }
 
+    /// The transport selected by the protocol of the streamer URI.
+    pub fn transport(&self) -> RedisTransport {
+        self.transport
+    }
+
+    fn streams_only(&self, what: &str) -> RedisResult<()> {
+        if self.transport == RedisTransport::Streams {
+            Ok(())
+        } else {
+            Err(self.transport.unsupported(what))
+        }
+    }
+
+    fn add_discovered(&mut self) {
+        for shard in self.discovered.drain() {
+            if !self.streams.contains(&shard) {
+                self.streams.push(shard);
+            }
+        }
+    }
+
     /// Like `Consumer::seek`, but with `MessageId`.
+    /// Unlike `rewind` with `SeqPos::At`, this accepts any id, including those that overflow a `SeqNo`.
     pub async fn seek_to(&mut self, id: MessageId) -> RedisResult<()> {
+        self.streams_only("Seek")?;
+        self.add_discovered();
         if self
             .handle
             .try_send(CtrlMsg::Rewind(self.streams.clone(), id))
@@ -178,6 +240,7 @@ impl RedisConsumer {
     #[inline]
     /// Mark a message as read. The ACK will be queued for commit.
     pub fn ack(&self, msg: &SharedMessage) -> RedisResult<()> {
+        self.streams_only("Ack")?;
         if self.config.auto_ack {
             return Err(StreamErr::Backend(RedisErr::InvalidClientConfig(
                 "Please do not set AutoCommit to Delayed.".to_owned(),
@@ -186,10 +249,13 @@ impl RedisConsumer {
         self.auto_ack(msg.header())
     }
 
+    /// Ack a message by its identifier. Returns `RedisErr::SequenceOverflow` for messages whose ids overflow
+    /// a `SeqNo` (see [`crate::RedisMessageId`]), which have to be acked with `ack` instead.
     pub fn ack_with(
         &self,
         (stream_key, shard_id, sequence): &(StreamKey, ShardId, SeqNo),
     ) -> RedisResult<()> {
+        self.streams_only("Ack")?;
         if self.config.auto_ack {
             return Err(StreamErr::Backend(RedisErr::InvalidClientConfig(
                 "Please do not set AutoCommit to Delayed.".to_owned(),
@@ -200,7 +266,7 @@ impl RedisConsumer {
             .handle
             .try_send(CtrlMsg::Ack(
                 (stream_key.clone(), *shard_id),
-                from_seq_no(*sequence),
+                from_seq_no(*sequence)?,
                 Timestamp::now_utc(),
             ))
             .is_ok()
@@ -228,8 +294,35 @@ impl RedisConsumer {
         }
     }
 
+    /// The input of a transaction, see [`crate::RedisTransaction::ack`].
+    pub(crate) fn transaction_ack(&self, header: &MessageHeader) -> RedisResult<TransactionAck> {
+        self.streams_only("Transaction")?;
+        let group = match self.config.group_id.as_ref() {
+            Some(group) => group.name().to_owned(),
+            None => return Err(StreamErr::ConsumerGroupNotSet),
+        };
+        if !self.config.manual_ack {
+            return Err(StreamErr::Backend(RedisErr::InvalidClientConfig(
+                "Transactions require manual acks. Please set AutoCommit to Rolling or Disabled."
+                    .to_owned(),
+            )));
+        }
+        let shard = (header.stream_key().clone(), *header.shard_id());
+        let key = if self.unsharded.contains(&shard.0) && shard.1 == ZERO {
+            shard.0.name().to_owned()
+        } else {
+            format_stream_shard(&shard)
+        };
+        Ok(TransactionAck {
+            key,
+            group,
+            id: get_message_id(header),
+        })
+    }
+
     /// Commit all pending acks and (optionally) wait for the result.
     pub fn commit(&mut self) -> RedisResult<impl Future<Output = RedisResult<()>>> {
+        self.streams_only("Commit")?;
         if self.config.pre_fetch {
             return Err(StreamErr::Backend(RedisErr::InvalidClientConfig(
                 "Manual commit is not allowed. Please use another AutoCommit option.".to_owned(),
@@ -250,6 +343,7 @@ impl RedisConsumer {
 
     /// Push a Commit request to the command queue, will be executed on the next cycle
     pub fn commit_asap(&mut self) -> RedisResult<()> {
+        self.streams_only("Commit")?;
         let (sender, _) = bounded(1);
         // unbounded, so never blocks
         if self.handle.try_send(CtrlMsg::Commit(sender)).is_ok() {
@@ -277,6 +371,9 @@ pub(crate) async fn create_consumer(
     mut options: RedisConsumerOptions,
     streams: Vec<StreamKey>,
 ) -> RedisResult<RedisConsumer> {
+    if conn.options.transport != RedisTransport::Streams {
+        return create_transport_consumer(conn, options, streams).await;
+    }
     let mode = *options.mode()?;
     if mode != ConsumerMode::RealTime {
         if options.consumer_group().is_err() {
@@ -291,17 +388,49 @@ pub(crate) async fn create_consumer(
         }
     }
 
-    if options.shard_ownership() == &ShardOwnership::Owned {
-        todo!("Hopefully this will come out in the next release.");
+    if options.shard_ownership() == &ShardOwnership::Owned && mode != ConsumerMode::LoadBalanced {
+        return Err(StreamErr::Unsupported(
+            "ShardOwnership::Owned requires ConsumerMode::LoadBalanced".to_owned(),
+        ));
     }
 
     let options = Arc::new(options);
     conn.reconnect_all().await?;
+    if conn.options.enable_cluster() {
+        conn.discover_slots().await?;
+    }
+    let dead_letters = if mode != ConsumerMode::RealTime && options.max_deliveries().is_some() {
+        let mut cluster = RedisCluster::new(conn.cluster.clone(), conn.options.clone())?.shared();
+        cluster.reconnect_all().await?;
+        if cluster.options.enable_cluster() {
+            cluster.discover_slots().await?;
+        }
+        let (sender, receiver) = unbounded();
+        let writer = DeadLetterWriter {
+            cluster,
+            stream: options.dead_letter_stream().cloned(),
+        };
+        spawn_task(writer.run(receiver));
+        Some(sender)
+    } else {
+        None
+    };
     let mut shards = Vec::new();
-    for stream in streams {
-        shards.extend(discover_shards(&mut conn, stream).await?);
+    for stream in streams.iter() {
+        shards.extend(discover_shards(&mut conn, stream.clone()).await?);
     }
-    let stream_shards = shards.iter().map(|s| s.stream.clone()).collect();
+    let stream_shards: Vec<StreamShard> = shards.iter().map(|s| s.stream.clone()).collect();
+    let unsharded: HashSet<StreamKey> = shards
+        .iter()
+        .filter(|s| s.key == s.stream.0.name())
+        .map(|s| s.stream.0.clone())
+        .collect();
+    let watcher = ShardWatcher {
+        cluster: RedisCluster::new(conn.cluster.clone(), conn.options.clone())?.shared(),
+        streams,
+        known: stream_shards.iter().cloned().collect(),
+        unsharded: unsharded.clone(),
+    };
 
     let dur = conn.options.timeout().unwrap_or(DEFAULT_TIMEOUT);
     let enable_cluster = conn.options.enable_cluster();
@@ -320,7 +449,7 @@ pub(crate) async fn create_consumer(
     let (status, ready) = bounded(1);
 
     if enable_cluster {
-        let cluster = Cluster::new(options.clone(), shards, sender)?;
+        let cluster = Cluster::new(options.clone(), shards, sender, dead_letters)?;
         spawn_task(cluster.run(conn, response, status));
     } else {
         if conn.cluster.nodes().len() != 1 {
@@ -329,17 +458,31 @@ pub(crate) async fn create_consumer(
                     .to_owned(),
             ));
         }
-        let node = Node::new(conn, options.clone(), shards, handle.clone(), sender)?;
+        let node = Node::new(
+            conn,
+            options.clone(),
+            shards,
+            handle.clone(),
+            sender,
+            dead_letters,
+        )?;
         spawn_task(node.run(response, status));
     }
 
     match timeout(dur, ready.recv_async()).await {
-        Ok(Ok(StatusMsg::Ready)) => Ok(RedisConsumer {
-            config,
-            streams: stream_shards,
-            receiver,
-            handle,
-        }),
+        Ok(Ok(StatusMsg::Ready)) => {
+            let (discovered, discovered_receiver) = unbounded();
+            spawn_task(watcher.run(handle.clone(), discovered));
+            Ok(RedisConsumer {
+                config,
+                streams: stream_shards,
+                receiver,
+                handle,
+                discovered: discovered_receiver,
+                unsharded,
+                transport: RedisTransport::Streams,
+            })
+        }
         _ => Err(StreamErr::Connect(format!(
             "Failed to initialize {}",
             if enable_cluster { "cluster" } else { "node" }
@@ -1,19 +1,19 @@
-use flume::{Receiver, RecvError, Sender, TryRecvError};
+use flume::{bounded, Receiver, RecvError, Sender, TryRecvError};
 use redis::{
     aio::ConnectionLike,
     cmd as command,
     streams::{StreamInfoConsumersReply, StreamReadOptions},
     AsyncCommands, ErrorKind, RedisWrite, ToRedisArgs, Value,
 };
-use std::{fmt::Display, sync::Arc, time::Duration};
+use std::{collections::BTreeMap, fmt::Display, sync::Arc, time::Duration};
 
 use super::{
-    constants::HEARTBEAT, format_stream_shard, AutoCommit, AutoStreamReset, CtrlMsg, ShardState,
-    StatusMsg, StreamShard,
+    constants::HEARTBEAT, format_stream_shard, AutoCommit, AutoStreamReset, CtrlMsg, DeadLetterMsg,
+    Ownership, PendingEntry, ShardOwnership, ShardState, StatusMsg, StreamShard,
 };
 use crate::{
-    map_err, AutoClaimReply, MessageId, NodeId, RedisCluster, RedisConsumerOptions, RedisErr,
-    RedisResult, StreamReadReply, MAX_MSG_ID, ZERO,
+    key_slot, map_err, AutoClaimReply, MessageId, NodeId, RedisCluster, RedisConnection,
+    RedisConsumerOptions, RedisErr, RedisResult, StreamReadReply, MAX_MSG_ID, ZERO,
 };
 use sea_streamer_runtime::sleep;
 use sea_streamer_types::{
@@ -25,17 +25,29 @@ const DOLLAR: &str = "$";
 const DIRECT: &str = ">";
 const ZERO_ZERO: &str = "0-0";
 const ONE_SEC: Duration = Duration::from_secs(1);
+/// How long to wait after reading nothing from shards in different slots without blocking
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Number of pending entries to check for dead letters in one request
+const DEAD_LETTER_BATCH: usize = 100;
 
 pub struct Node {
     id: NodeId,
     options: Arc<RedisConsumerOptions>,
     shards: Vec<ShardState>,
     messages: Sender<RedisResult<SharedMessage>>,
+    dead_letters: Option<Sender<DeadLetterMsg>>,
     opts: StreamReadOptions,
     group: GroupState,
+    ownership: Option<Ownership>,
     // in reverse order
     buffer: Vec<SharedMessage>,
     rewinding: bool,
+    /// Whether this node is managed by a cluster
+    cluster: bool,
+    /// A node managed by a cluster waits for `Resume` after rewinding
+    paused: bool,
+    /// The slot group to read first
+    rotation: usize,
 }
 
 struct GroupState {
@@ -94,9 +106,11 @@ impl Node {
         shards: Vec<ShardState>,
         handle: Sender<CtrlMsg>,
         messages: Sender<RedisResult<SharedMessage>>,
+        dead_letters: Option<Sender<DeadLetterMsg>>,
     ) -> RedisResult<Self> {
         let (node_id, conn) = cluster.conn.into_iter().next().unwrap();
-        let node = Node::add(node_id.clone(), options, messages);
+        let mut node = Node::add(node_id.clone(), options, messages, dead_letters);
+        node.cluster = false;
         // unbounded, so never blocks
         handle
             .try_send(CtrlMsg::Init(Box::new((node_id, conn))))
@@ -112,18 +126,24 @@ impl Node {
         id: NodeId,
         options: Arc<RedisConsumerOptions>,
         messages: Sender<RedisResult<SharedMessage>>,
+        dead_letters: Option<Sender<DeadLetterMsg>>,
     ) -> Self {
-        let opts = Self::read_options(options.mode, &options);
+        let opts = Self::read_options(options.mode, &options, true);
         let group_id = options
             .consumer_group()
             .map(|s| s.name().to_owned())
             .unwrap_or_default();
+        let ownership = match options.shard_ownership() {
+            ShardOwnership::Shared => None,
+            ShardOwnership::Owned => Some(Ownership::new(&options)),
+        };
 
         Self {
             id,
             options,
             shards: Vec::new(),
             messages,
+            dead_letters,
             opts,
             group: GroupState {
                 group_id,
@@ -133,15 +153,24 @@ impl Node {
                 last_check: Timestamp::now_utc(),
                 claiming: None,
             },
+            ownership,
             buffer: Vec::new(),
             rewinding: false,
+            cluster: true,
+            paused: false,
+            rotation: 0,
         }
     }
 
-    fn read_options(mode: ConsumerMode, options: &RedisConsumerOptions) -> StreamReadOptions {
-        let mut opts = StreamReadOptions::default()
-            .count(*options.batch_size())
-            .block(HEARTBEAT.as_secs() as usize * 1000);
+    fn read_options(
+        mode: ConsumerMode,
+        options: &RedisConsumerOptions,
+        block: bool,
+    ) -> StreamReadOptions {
+        let mut opts = StreamReadOptions::default().count(*options.batch_size());
+        if block {
+            opts = opts.block(HEARTBEAT.as_secs() as usize * 1000);
+        }
 
         if matches!(mode, ConsumerMode::Resumable | ConsumerMode::LoadBalanced) {
             opts = opts.group(
@@ -180,7 +209,7 @@ impl Node {
 
         'outer: loop {
             loop {
-                let ctrl = if self.pre_fetch() || !ready || read > 0 {
+                let ctrl = if (self.pre_fetch() || !ready || read > 0) && !self.paused {
                     match receiver.try_recv() {
                         Ok(ctrl) => ctrl,
                         Err(TryRecvError::Disconnected) => {
@@ -200,6 +229,9 @@ impl Node {
                 };
                 match ctrl {
                     CtrlMsg::Init(_) => panic!("Unexpected CtrlMsg"),
+                    CtrlMsg::Resume => {
+                        self.paused = false;
+                    }
                     CtrlMsg::Read => {
                         read += 1;
                     }
@@ -218,22 +250,13 @@ impl Node {
                         self.rewind_stream(shards, pos);
                         read = 0;
                         self.buffer.truncate(0);
-                        if self
-                            .messages
-                            .send_async(Ok(SharedMessage::new(
-                                MessageHeader::new(
-                                    StreamKey::new(SEA_STREAMER_INTERNAL).unwrap(),
-                                    ZERO,
-                                    0,
-                                    Timestamp::now_utc(),
-                                ),
-                                vec![],
-                                0,
-                                0,
-                            )))
-                            .await
-                            .is_err()
-                        {
+                        if self.cluster {
+                            // the cluster will send the latch after all nodes have rewound
+                            self.paused = true;
+                            if sender.send_async(StatusMsg::Rewound).await.is_err() {
+                                break 'outer;
+                            }
+                        } else if self.messages.send_async(Ok(rewind_latch())).await.is_err() {
                             break 'outer;
                         }
                     }
@@ -251,16 +274,22 @@ impl Node {
                         }
                     }
                     CtrlMsg::Kill(notify) => {
-                        if self.has_pending_ack() {
-                            if let Ok(inner) = conn.try_get() {
+                        if let Ok(inner) = conn.try_get() {
+                            if self.has_pending_ack() {
                                 self.commit_ack(inner).await.ok();
                             }
+                            if let Some(ownership) = &self.ownership {
+                                ownership.leave(inner, &mut self.shards).await.ok();
+                            }
                         }
                         notify.try_send(()).ok();
                         break 'outer;
                     }
                 }
             }
+            if self.paused {
+                continue;
+            }
             if self.shards.is_empty() {
                 sleep(ONE_SEC).await;
                 continue;
@@ -292,7 +321,8 @@ impl Node {
                             }
                         }
                     }
-                    Err(StreamErr::Backend(RedisErr::IoError(_))) => {
+                    Err(StreamErr::Backend(RedisErr::IoError(_) | RedisErr::ReadOnly(_))) => {
+                        // the master may have been demoted in a failover
                         conn.reconnect();
                         continue;
                     }
@@ -322,10 +352,28 @@ impl Node {
                     Ok(_) => {
                         ack_failure = 0;
                     }
-                    Err(StreamErr::Backend(RedisErr::IoError(_))) => {
+                    Err(StreamErr::Backend(RedisErr::IoError(_) | RedisErr::ReadOnly(_))) => {
+                        // the master may have been demoted in a failover
                         conn.reconnect();
                         continue;
                     }
+                    Err(StreamErr::Backend(RedisErr::Moved(_))) => {
+                        // the acks are moved along with the shards
+                        for event in self.move_shards(inner).await {
+                            if sender.send_async(event).await.is_err() {
+                                break 'outer;
+                            }
+                        }
+                    }
+                    Err(StreamErr::Backend(
+                        RedisErr::Ask(_)
+                        | RedisErr::TryAgain(_)
+                        | RedisErr::ClusterDown(_)
+                        | RedisErr::MasterDown(_),
+                    )) => {
+                        // the slot is being migrated or the cluster is recovering; retry later
+                        sleep(ONE_SEC).await;
+                    }
                     Err(err) => {
                         log::error!("{err}");
                         ack_failure += 1;
@@ -365,7 +413,7 @@ impl Node {
             && Timestamp::now_utc() - *self.options.auto_commit_interval() > self.group.last_commit
     }
 
-    async fn commit_ack(&mut self, conn: &mut redis::aio::Connection) -> RedisResult<()> {
+    async fn commit_ack(&mut self, conn: &mut RedisConnection) -> RedisResult<()> {
         for shard in self.shards.iter_mut() {
             if !shard.pending_ack.is_empty() {
                 match self.options.auto_commit() {
@@ -438,7 +486,7 @@ impl Node {
         }
     }
 
-    async fn read_next(&mut self, conn: &mut redis::aio::Connection) -> RedisResult<ReadResult> {
+    async fn read_next(&mut self, conn: &mut RedisConnection) -> RedisResult<ReadResult> {
         let mode = self.running_mode();
         if matches!(mode, ConsumerMode::Resumable | ConsumerMode::LoadBalanced)
             && self.group.first_read
@@ -447,15 +495,22 @@ impl Node {
             self.group.pending_state = true;
 
             for shard in self.shards.iter() {
-                let id = match self.options.auto_stream_reset() {
-                    AutoStreamReset::Earliest => "0",
-                    AutoStreamReset::Latest => DOLLAR,
+                // shards discovered after resharding are read from the beginning
+                let id = match shard.id {
+                    Some(MAX_MSG_ID) => DOLLAR.to_owned(),
+                    Some((a, b)) => format!("{a}-{b}"),
+                    None => match self.options.auto_stream_reset() {
+                        AutoStreamReset::Earliest => "0".to_owned(),
+                        AutoStreamReset::Latest => DOLLAR.to_owned(),
+                    },
                 };
-                let result: Result<Value, _> = if self.options.mkstream {
-                    conn.xgroup_create_mkstream(&shard.key, &self.group.group_id, id)
+                // shards of a sharded stream may not have been written to yet
+                let mkstream = self.options.mkstream || shard.key != shard.stream.0.name();
+                let result: Result<Value, _> = if mkstream {
+                    conn.xgroup_create_mkstream(&shard.key, &self.group.group_id, &id)
                         .await
                 } else {
-                    conn.xgroup_create(&shard.key, &self.group.group_id, id)
+                    conn.xgroup_create(&shard.key, &self.group.group_id, &id)
                         .await
                 };
 
@@ -464,15 +519,67 @@ impl Node {
                     Err(err) => {
                         if err.code() == Some("BUSYGROUP") {
                             // OK
+                        } else if err.kind() == ErrorKind::Moved {
+                            // the group will be created when the shard is added to the new node
+                            let events = self.move_shards(conn).await;
+                            return Ok(ReadResult::Events(events));
                         } else {
                             return self.send_error(map_err(err)).await;
                         }
                     }
                 }
             }
+
+            // messages that crashed us before should not be delivered again
+            if self.dead_letters.is_some() {
+                let consumer = self.options.consumer_id().unwrap().id().to_owned();
+                let keys: Vec<String> = self.shards.iter().map(|s| s.key.clone()).collect();
+                for key in keys.iter() {
+                    self.dead_letter(conn, key, &consumer, 0).await;
+                }
+            }
+        }
+
+        if let Some(ownership) = self.ownership.as_mut() {
+            if ownership.is_due() {
+                match ownership.heartbeat(conn, &mut self.shards).await {
+                    Ok(acquired) => {
+                        if acquired {
+                            self.group.pending_state = true;
+                            if self.dead_letters.is_some() {
+                                // the pending entries of the previous owners are now ours
+                                let consumer = self.options.consumer_id().unwrap().id().to_owned();
+                                let keys: Vec<String> = self
+                                    .shards
+                                    .iter()
+                                    .filter(|s| s.owned)
+                                    .map(|s| s.key.clone())
+                                    .collect();
+                                for key in keys.iter() {
+                                    self.dead_letter(conn, key, &consumer, 0).await;
+                                }
+                            }
+                        }
+                    }
+                    Err(StreamErr::Backend(RedisErr::IoError(err))) => {
+                        return Err(StreamErr::Backend(RedisErr::IoError(err)));
+                    }
+                    Err(StreamErr::Backend(RedisErr::Moved(_))) => {
+                        let events = self.move_shards(conn).await;
+                        return Ok(ReadResult::Events(events));
+                    }
+                    Err(err) => return self.send_error(err).await,
+                }
+            }
+            if !self.shards.iter().any(|s| s.owned) {
+                // wait for the next heartbeat
+                sleep(ONE_SEC).await;
+                return Ok(ReadResult::Msg(0));
+            }
         }
 
-        if mode == ConsumerMode::LoadBalanced {
+        // with owned shards, the pending entries are handed over on acquire
+        if mode == ConsumerMode::LoadBalanced && self.ownership.is_none() {
             if self.group.claiming.is_some() {
                 match self.auto_claim(conn).await {
                     Ok(ReadResult::Msg(0)) => (),
@@ -488,16 +595,104 @@ impl Node {
             }
         }
 
+        let groups = self.read_groups();
+        if groups.is_empty() {
+            sleep(ONE_SEC).await;
+            return Ok(ReadResult::Msg(0));
+        }
+        // Only a single group can be read with blocking, otherwise the other groups will be starved
+        let block = groups.len() == 1;
+        for i in 0..groups.len() {
+            let group = &groups[(self.rotation + i) % groups.len()];
+            let cmd = self.read_command(mode, group, block);
+            log::trace!("XREAD ...");
+            assert!(self.buffer.is_empty());
+            match conn.req_packed_command(&cmd).await {
+                Ok(value) => match StreamReadReply::from_redis_value(value) {
+                    Ok(StreamReadReply(mut mess)) => {
+                        log::trace!("Read {} messages", mess.len());
+                        if mess.is_empty() {
+                            continue;
+                        }
+                        self.rotation = (self.rotation + i + 1) % groups.len();
+                        mess.reverse();
+                        self.buffer = mess;
+                        return Ok(ReadResult::Msg(self.buffer.len()));
+                    }
+                    Err(err) => return self.send_error(err).await,
+                },
+                Err(err) => {
+                    let kind = err.kind();
+                    return if kind == ErrorKind::Moved {
+                        // we don't know which key is moved, so we have to try all
+                        let events = self.move_shards(conn).await;
+                        Ok(ReadResult::Events(events))
+                    } else if kind == ErrorKind::IoError || kind == ErrorKind::ReadOnly {
+                        Err(map_err(err))
+                    } else if is_cluster_error(kind) {
+                        // cluster is temporarily unavailable
+                        Err(StreamErr::Backend(RedisErr::TryAgain(err.to_string())))
+                    } else {
+                        self.send_error(map_err(err)).await
+                    };
+                }
+            }
+        }
+        // If we receive an empty reply, it means if we were reading the pending list
+        // then the list is now empty
+        self.group.pending_state = false;
+        if !block {
+            sleep(POLL_INTERVAL).await;
+        }
+        Ok(ReadResult::Msg(0))
+    }
+
+    /// The indices of shards to read, grouped by the commands to read them with.
+    /// In a cluster, all keys of a command must be in the same hash slot.
+    fn read_groups(&self) -> Vec<Vec<usize>> {
+        let owned_only = self.ownership.is_some();
+        let reading = self
+            .shards
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| !owned_only || s.owned);
+        if self.cluster {
+            let mut groups: BTreeMap<u16, Vec<usize>> = BTreeMap::new();
+            for (i, shard) in reading {
+                groups.entry(key_slot(&shard.key)).or_default().push(i);
+            }
+            groups.into_values().collect()
+        } else {
+            let group: Vec<usize> = reading.map(|(i, _)| i).collect();
+            if group.is_empty() {
+                Vec::new()
+            } else {
+                vec![group]
+            }
+        }
+    }
+
+    fn read_command(&self, mode: ConsumerMode, group: &[usize], block: bool) -> redis::Cmd {
         let mut cmd = command(match mode {
             ConsumerMode::RealTime => "XREAD",
             ConsumerMode::Resumable | ConsumerMode::LoadBalanced => "XREADGROUP",
         });
-        cmd.arg(&self.opts).arg("STREAMS");
+        if block {
+            cmd.arg(&self.opts);
+        } else {
+            cmd.arg(Self::read_options(
+                self.running_mode(),
+                &self.options,
+                false,
+            ));
+        }
+        cmd.arg("STREAMS");
 
-        for shard in self.shards.iter() {
-            cmd.arg(&shard.key);
+        for i in group {
+            cmd.arg(&self.shards[*i].key);
         }
-        for shard in self.shards.iter() {
+        for i in group {
+            let shard = &self.shards[*i];
             match mode {
                 ConsumerMode::RealTime => {
                     if let Some((a, b)) = shard.id {
@@ -531,44 +726,12 @@ impl Node {
                 std::str::from_utf8(cmd.get_packed_command().as_slice()).unwrap()
             );
         }
-        log::trace!("XREAD ...");
-        assert!(self.buffer.is_empty());
-        match conn.req_packed_command(&cmd).await {
-            Ok(value) => match StreamReadReply::from_redis_value(value) {
-                Ok(StreamReadReply(mut mess)) => {
-                    log::trace!("Read {} messages", mess.len());
-                    if mess.is_empty() {
-                        // If we receive an empty reply, it means if we were reading the pending list
-                        // then the list is now empty
-                        self.group.pending_state = false;
-                    }
-                    mess.reverse();
-                    self.buffer = mess;
-                    Ok(ReadResult::Msg(self.buffer.len()))
-                }
-                Err(err) => self.send_error(err).await,
-            },
-            Err(err) => {
-                let kind = err.kind();
-                if kind == ErrorKind::Moved {
-                    // we don't know which key is moved, so we have to try all
-                    let events = self.move_shards(conn).await;
-                    Ok(ReadResult::Events(events))
-                } else if kind == ErrorKind::IoError {
-                    Err(StreamErr::Backend(RedisErr::IoError(err.to_string())))
-                } else if is_cluster_error(kind) {
-                    // cluster is temporarily unavailable
-                    Err(StreamErr::Backend(RedisErr::TryAgain(err.to_string())))
-                } else {
-                    self.send_error(map_err(err)).await
-                }
-            }
-        }
+        cmd
     }
 
     fn rewind_stream(&mut self, shards: Vec<StreamShard>, to: MessageId) {
         self.rewinding = true;
-        self.opts = Self::read_options(self.running_mode(), &self.options);
+        self.opts = Self::read_options(self.running_mode(), &self.options, true);
         let mut leftover = Vec::new();
         let mut deleted = Vec::new();
         for owned in self.shards.iter() {
@@ -590,6 +753,7 @@ impl Node {
                 key,
                 id: Some(to),
                 pending_ack: Default::default(),
+                owned: false,
             });
         }
         if !deleted.is_empty() {
@@ -601,7 +765,7 @@ impl Node {
         }
     }
 
-    async fn auto_claim(&mut self, conn: &mut redis::aio::Connection) -> RedisResult<ReadResult> {
+    async fn auto_claim(&mut self, conn: &mut RedisConnection) -> RedisResult<ReadResult> {
         self.group.last_check = Timestamp::now_utc();
         let change = self.group.claiming.is_none();
         if self.group.claiming.is_none() {
@@ -636,6 +800,18 @@ impl Node {
             return Ok(ReadResult::Msg(0));
         }
 
+        if change && self.dead_letters.is_some() {
+            let claiming = self.group.claiming.as_ref().unwrap();
+            let (key, consumer) = (claiming.key.clone(), claiming.consumer.clone());
+            let idle = self
+                .options
+                .auto_claim_idle()
+                .as_millis()
+                .try_into()
+                .unwrap();
+            self.dead_letter(conn, &key, &consumer, idle).await;
+        }
+
         let claiming = self.group.claiming.as_ref().unwrap();
 
         let mut cmd = command("XAUTOCLAIM");
@@ -684,8 +860,8 @@ impl Node {
             },
             Err(err) => {
                 let kind = err.kind();
-                if kind == ErrorKind::IoError {
-                    Err(StreamErr::Backend(RedisErr::IoError(err.to_string())))
+                if kind == ErrorKind::IoError || kind == ErrorKind::ReadOnly {
+                    Err(map_err(err))
                 } else if is_cluster_error(kind) {
                     Err(StreamErr::Backend(RedisErr::TryAgain(err.to_string())))
                 } else {
@@ -695,7 +871,82 @@ impl Node {
         }
     }
 
-    async fn move_shards(&mut self, conn: &mut redis::aio::Connection) -> Vec<StatusMsg> {
+    /// Move the messages pending for `consumer` in a shard, which have been delivered
+    /// `max_deliveries` times, to the dead-letter stream. Failures are only logged,
+    /// the messages will be checked again on the next occasion.
+    async fn dead_letter(
+        &self,
+        conn: &mut RedisConnection,
+        key: &str,
+        consumer: &str,
+        min_idle: u64,
+    ) {
+        if let Err(err) = self.try_dead_letter(conn, key, consumer, min_idle).await {
+            log::warn!("Failed to move dead letters of {key}: {err}");
+        }
+    }
+
+    async fn try_dead_letter(
+        &self,
+        conn: &mut RedisConnection,
+        key: &str,
+        consumer: &str,
+        min_idle: u64,
+    ) -> RedisResult<()> {
+        let (max_deliveries, dead_letters) =
+            match (self.options.max_deliveries(), self.dead_letters.as_ref()) {
+                (Some(max_deliveries), Some(dead_letters)) => (*max_deliveries, dead_letters),
+                _ => return Ok(()),
+            };
+        let mut start = "-".to_owned();
+        let mut entries = Vec::new();
+        loop {
+            let reply: Vec<PendingEntry> = command("XPENDING")
+                .arg(key)
+                .arg(&self.group.group_id)
+                .arg("IDLE")
+                .arg(min_idle)
+                .arg(&start)
+                .arg("+")
+                .arg(DEAD_LETTER_BATCH)
+                .arg(consumer)
+                .query_async(conn)
+                .await
+                .map_err(map_err)?;
+            let done = reply.len() < DEAD_LETTER_BATCH;
+            if let Some((id, ..)) = reply.last() {
+                start = format!("({id}");
+            }
+            entries.extend(
+                reply
+                    .into_iter()
+                    .filter(|(_, _, _, deliveries)| *deliveries >= max_deliveries),
+            );
+            if done {
+                break;
+            }
+        }
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let (reply, result) = bounded(1);
+        dead_letters
+            .send_async(DeadLetterMsg {
+                stream: self.get_shard_state(key).stream.clone(),
+                key: key.to_owned(),
+                group: self.group.group_id.clone(),
+                entries,
+                reply,
+            })
+            .await
+            .map_err(|_| StreamErr::Backend(RedisErr::ConsumerDied))?;
+        result
+            .recv_async()
+            .await
+            .map_err(|_| StreamErr::Backend(RedisErr::ConsumerDied))?
+    }
+
+    async fn move_shards(&mut self, conn: &mut RedisConnection) -> Vec<StatusMsg> {
         let mut events = Vec::new();
         let shards = std::mem::take(&mut self.shards);
         for shard in shards {
@@ -787,3 +1038,18 @@ fn is_cluster_error(kind: redis::ErrorKind) -> bool {
 fn unbox<T>(value: Box<T>) -> T {
     *value
 }
+
+/// Marks the end of a rewind in the message channel
+pub fn rewind_latch() -> SharedMessage {
+    SharedMessage::new(
+        MessageHeader::new(
+            StreamKey::new(SEA_STREAMER_INTERNAL).unwrap(),
+            ZERO,
+            0,
+            Timestamp::now_utc(),
+        ),
+        vec![],
+        0,
+        0,
+    )
+}
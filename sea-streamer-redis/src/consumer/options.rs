@@ -1,6 +1,8 @@
 use super::{constants::*, ConsumerConfig, RedisConsumerOptions};
 use crate::{RedisErr, RedisResult};
-use sea_streamer_types::{ConsumerGroup, ConsumerId, ConsumerMode, ConsumerOptions, StreamErr};
+use sea_streamer_types::{
+    ConsumerGroup, ConsumerId, ConsumerMode, ConsumerOptions, StreamErr, StreamKey,
+};
 use std::time::Duration;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -38,9 +40,7 @@ pub enum AutoCommit {
 pub enum ShardOwnership {
     /// Consumers in the same group share the same shard
     Shared,
-    /// Consumers claim ownership of a shard
-    ///
-    /// > This feature is still WIP
+    /// Consumers claim ownership of a shard. Only applies to `LoadBalanced` mode.
     Owned,
 }
 
@@ -56,6 +56,10 @@ impl From<&RedisConsumerOptions> for ConsumerConfig {
             group_id: options.consumer_group().ok().cloned(),
             consumer_id: options.consumer_id().cloned(),
             auto_ack: options.auto_commit() == &AutoCommit::Delayed,
+            manual_ack: matches!(
+                options.auto_commit(),
+                AutoCommit::Rolling | AutoCommit::Disabled
+            ),
             pre_fetch: options.pre_fetch(),
         }
     }
@@ -83,6 +87,8 @@ impl ConsumerOptions for RedisConsumerOptions {
             },
             shard_ownership: ShardOwnership::Shared,
             mkstream: false,
+            max_deliveries: None,
+            dead_letter_stream: None,
         }
     }
 
@@ -106,20 +112,21 @@ impl ConsumerOptions for RedisConsumerOptions {
     ///
     /// ### (Coarse) Owned shard
     ///
-    /// > This feature is still WIP
-    ///
     /// Multiple consumers within the same group do not share a shard.
     /// Each consumer will attempt to claim ownership of a shard, and other consumers will not step in.
     /// However, if a consumer has been idle for too long (defined by `auto_claim_idle`),
     /// another consumer will step in and claim ownership of the shard.
     ///
     /// As new consumers join the group, if the shard distribution is not in a 'fair state' (at most 1 shard
-    /// per consumer), ownership of a shard may be transferred among consumers.
+    /// difference between consumers), ownership of a shard may be transferred among consumers.
+    /// The new owner claims the entries pending for the previous owner, so they will be redelivered.
     ///
     /// This mimicks Kafka's consumer group behaviour.
     ///
-    /// This is implemented via a dedicated key (for each stream) in the same Redis db,
-    /// and managed co-operatively among consumers.
+    /// This is implemented via dedicated keys (for each shard) in the same Redis db: a lease of the owner,
+    /// and a list of the group members, which are renewed on every heartbeat and managed co-operatively among consumers.
+    /// A consumer only heartbeats while it is streaming, so like in Kafka, a consumer that stops calling `next`
+    /// for longer than `auto_claim_idle` will lose its shards. Requires Redis 6.2 or above.
     fn set_consumer_group(&mut self, group_id: ConsumerGroup) -> RedisResult<&mut Self> {
         self.group_id = Some(group_id);
         Ok(self)
@@ -208,6 +215,7 @@ impl RedisConsumerOptions {
 
     /// The idle time for a consumer to be considered dead and allow others to XCLAIM its messages.
     /// This option is only relevant when `mode` is `LoadBalanced`.
+    /// With [`ShardOwnership::Owned`], this is the lifetime of a shard lease.
     ///
     /// Defaults to [`DEFAULT_AUTO_CLAIM_IDLE`]. None means never.
     pub fn set_auto_claim_idle(&mut self, v: Duration) -> &mut Self {
@@ -237,7 +245,7 @@ impl RedisConsumerOptions {
         &self.batch_size
     }
 
-    /// Default is `Shared`.
+    /// Default is `Shared`. See [`RedisConsumerOptions::set_consumer_group`] on the two models.
     pub fn shard_ownership(&self) -> &ShardOwnership {
         &self.shard_ownership
     }
@@ -264,6 +272,42 @@ impl RedisConsumerOptions {
         &self.mkstream
     }
 
+    /// The maximum number of times a message is delivered within a consumer group.
+    /// A message delivered this many times without being acked is considered poisonous:
+    /// instead of being redelivered, it is moved to the dead-letter stream along with the
+    /// failure metadata, and then acked.
+    ///
+    /// The delivery count is checked on the pending list of this consumer when it starts,
+    /// and on the pending list of others before claiming their messages.
+    /// This option is only relevant when `mode` is `Resumable` or `LoadBalanced`. Requires Redis 6.2 or above.
+    ///
+    /// If unset, messages are redelivered indefinitely.
+    pub fn set_max_deliveries(&mut self, v: Option<usize>) -> RedisResult<&mut Self> {
+        if v == Some(0) {
+            return Err(StreamErr::Backend(RedisErr::InvalidClientConfig(
+                "Max deliveries cannot be 0".to_owned(),
+            )));
+        }
+        self.max_deliveries = v;
+        Ok(self)
+    }
+    pub fn max_deliveries(&self) -> Option<&usize> {
+        self.max_deliveries.as_ref()
+    }
+
+    /// The stream dead letters are moved to. See [`RedisConsumerOptions::set_max_deliveries`].
+    /// It is a regular stream, so it can be inspected with [`crate::RedisStreamer::read_dead_letters`],
+    /// or consumed like any other stream.
+    ///
+    /// If unset, defaults to the stream key with the suffix [`DEAD_LETTER_SUFFIX`], for each stream.
+    pub fn set_dead_letter_stream(&mut self, v: Option<StreamKey>) -> &mut Self {
+        self.dead_letter_stream = v;
+        self
+    }
+    pub fn dead_letter_stream(&self) -> Option<&StreamKey> {
+        self.dead_letter_stream.as_ref()
+    }
+
     /// Whether to pre-fetch the next page as the consumer is streaming, which results in less jitter.
     ///
     /// If false, it only fetches when [`Consumer::next`](sea_streamer_types::Consumer::next) is called, aka. on demand.
@@ -0,0 +1,214 @@
+use redis::{cmd as command, Value};
+
+use super::{constants::HEARTBEAT, ShardState};
+use crate::{map_err, RedisConnection, RedisConsumerOptions, RedisResult};
+use sea_streamer_types::{ConsumerOptions, Timestamp};
+
+const ZERO_ZERO: &str = "0-0";
+const HANDOVER_BATCH: usize = 100;
+
+/// Renew our membership of a shard, remove the expired members and return the rest.
+/// Expiry is based on the server's clock, so members do not have to be in sync.
+const JOIN: &str = r#"
+local now = redis.call('TIME')
+now = tonumber(now[1]) * 1000 + math.floor(tonumber(now[2]) / 1000)
+redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', now)
+redis.call('ZADD', KEYS[1], now + tonumber(ARGV[2]), ARGV[1])
+redis.call('PEXPIRE', KEYS[1], ARGV[2])
+return redis.call('ZRANGE', KEYS[1], 0, -1)
+"#;
+
+/// Acquire the lease if it is free, or extend it if we are already the owner.
+const ACQUIRE: &str = r#"
+local owner = redis.call('GET', KEYS[1])
+if not owner then
+    redis.call('SET', KEYS[1], ARGV[1], 'PX', ARGV[2])
+    return 1
+elseif owner == ARGV[1] then
+    redis.call('PEXPIRE', KEYS[1], ARGV[2])
+    return 1
+end
+return 0
+"#;
+
+/// Release the lease, but only if we are the owner.
+const RELEASE: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+"#;
+
+/// Manages the leases of the shards of a node under [`super::ShardOwnership::Owned`].
+///
+/// For each shard, there is a sorted set of the members of the group, and a lease key holding
+/// the id of the owner. Both are hash-tagged with the shard's key, so they live in the same
+/// slot as the stream in a Redis Cluster. On every heartbeat, a member renews its membership,
+/// and computes the assignee of the shard: `members[shard_id % members.len()]` in sorted order.
+/// Every member arrives at the same assignment, so a member releases the shards assigned to
+/// someone else, and acquires the shards assigned to itself once they are released (or expired).
+#[derive(Debug)]
+pub struct Ownership {
+    group_id: String,
+    consumer_id: String,
+    lease: u64,
+    last_heartbeat: Option<Timestamp>,
+}
+
+impl Ownership {
+    pub fn new(options: &RedisConsumerOptions) -> Self {
+        Self {
+            group_id: options
+                .consumer_group()
+                .expect("Checked by create_consumer")
+                .name()
+                .to_owned(),
+            consumer_id: options
+                .consumer_id()
+                .expect("Checked by create_consumer")
+                .id()
+                .to_owned(),
+            lease: options
+                .auto_claim_idle()
+                .as_millis()
+                .try_into()
+                .expect("Lease too long"),
+            last_heartbeat: None,
+        }
+    }
+
+    pub fn is_due(&self) -> bool {
+        match self.last_heartbeat {
+            Some(last) => Timestamp::now_utc() - HEARTBEAT > last,
+            None => true,
+        }
+    }
+
+    /// Renew memberships and leases. Returns true if any shard is newly acquired.
+    pub async fn heartbeat(
+        &mut self,
+        conn: &mut RedisConnection,
+        shards: &mut [ShardState],
+    ) -> RedisResult<bool> {
+        self.last_heartbeat = Some(Timestamp::now_utc());
+        let mut acquired = false;
+        for shard in shards.iter_mut() {
+            let mut members: Vec<String> = command("EVAL")
+                .arg(JOIN)
+                .arg(1)
+                .arg(self.members_key(shard))
+                .arg(&self.consumer_id)
+                .arg(self.lease)
+                .query_async(conn)
+                .await
+                .map_err(map_err)?;
+            members.sort();
+            if members.is_empty() {
+                // we have just joined, this should not happen
+                continue;
+            }
+            let assignee = &members[(shard.stream.1.id() % members.len() as u64) as usize];
+
+            if assignee == &self.consumer_id {
+                let owned: bool = command("EVAL")
+                    .arg(ACQUIRE)
+                    .arg(1)
+                    .arg(self.lease_key(shard))
+                    .arg(&self.consumer_id)
+                    .arg(self.lease)
+                    .query_async(conn)
+                    .await
+                    .map_err(map_err)?;
+                if owned && !shard.owned {
+                    log::debug!("Consumer {} acquired {}", self.consumer_id, shard.key);
+                    self.handover(conn, shard).await?;
+                    acquired = true;
+                } else if !owned && shard.owned {
+                    log::warn!(
+                        "Consumer {} lost the lease of {}",
+                        self.consumer_id,
+                        shard.key
+                    );
+                }
+                shard.owned = owned;
+            } else if shard.owned {
+                log::debug!("Consumer {} released {}", self.consumer_id, shard.key);
+                self.release(conn, shard).await?;
+            }
+        }
+        Ok(acquired)
+    }
+
+    /// Release all leases and leave the group, so that others can take over immediately.
+    pub async fn leave(
+        &self,
+        conn: &mut RedisConnection,
+        shards: &mut [ShardState],
+    ) -> RedisResult<()> {
+        for shard in shards.iter_mut() {
+            if shard.owned {
+                self.release(conn, shard).await?;
+            }
+            let () = command("ZREM")
+                .arg(self.members_key(shard))
+                .arg(&self.consumer_id)
+                .query_async(conn)
+                .await
+                .map_err(map_err)?;
+        }
+        Ok(())
+    }
+
+    async fn release(&self, conn: &mut RedisConnection, shard: &mut ShardState) -> RedisResult<()> {
+        shard.owned = false;
+        let () = command("EVAL")
+            .arg(RELEASE)
+            .arg(1)
+            .arg(self.lease_key(shard))
+            .arg(&self.consumer_id)
+            .query_async(conn)
+            .await
+            .map_err(map_err)?;
+        Ok(())
+    }
+
+    /// Claim all entries pending for the previous owners, then read our pending list from the start.
+    async fn handover(
+        &self,
+        conn: &mut RedisConnection,
+        shard: &mut ShardState,
+    ) -> RedisResult<()> {
+        let mut cursor = ZERO_ZERO.to_owned();
+        loop {
+            let reply: Vec<Value> = command("XAUTOCLAIM")
+                .arg(&shard.key)
+                .arg(&self.group_id)
+                .arg(&self.consumer_id)
+                .arg(0)
+                .arg(&cursor)
+                .arg("COUNT")
+                .arg(HANDOVER_BATCH)
+                .arg("JUSTID")
+                .query_async(conn)
+                .await
+                .map_err(map_err)?;
+            cursor = match reply.first() {
+                Some(value) => redis::from_redis_value(value).map_err(map_err)?,
+                None => break,
+            };
+            if cursor == ZERO_ZERO {
+                break;
+            }
+        }
+        shard.id = None;
+        Ok(())
+    }
+
+    fn members_key(&self, shard: &ShardState) -> String {
+        format!("{{{}}}#{}#members", shard.key, self.group_id)
+    }
+
+    fn lease_key(&self, shard: &ShardState) -> String {
+        format!("{{{}}}#{}#owner", shard.key, self.group_id)
+    }
+}
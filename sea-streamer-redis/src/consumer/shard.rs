@@ -1,7 +1,10 @@
-use super::PendingAck;
-use crate::{get_message_id, map_err, MessageId, RedisCluster, RedisResult, ZERO};
+use super::{constants::SHARD_REFRESH, CtrlMsg, PendingAck};
+use crate::{get_message_id, map_err, read_shard_meta, MessageId, RedisCluster, RedisResult, ZERO};
+use flume::Sender;
 use redis::AsyncCommands;
-use sea_streamer_types::{MessageHeader, ShardId, StreamKey, Timestamp};
+use sea_streamer_runtime::sleep;
+use sea_streamer_types::{MessageHeader, ShardId, StreamErr, StreamKey, Timestamp};
+use std::collections::{BTreeSet, HashSet};
 
 pub type StreamShard = (StreamKey, ShardId);
 
@@ -11,6 +14,8 @@ pub struct ShardState {
     pub key: String,
     pub id: Option<MessageId>,
     pub pending_ack: Vec<PendingAck>,
+    /// Whether we hold the lease, only relevant for `ShardOwnership::Owned`
+    pub owned: bool,
 }
 
 impl ShardState {
@@ -35,11 +40,26 @@ pub async fn discover_shards(
     cluster: &mut RedisCluster,
     stream: StreamKey,
 ) -> RedisResult<Vec<ShardState>> {
-    let (_node, conn) = cluster.get_any()?;
-    let shard_keys: Vec<String> = conn
-        .keys(format!("{}:*", stream.name()))
-        .await
-        .map_err(map_err)?;
+    let pattern = format!("{}:*", stream.name());
+    let mut shard_keys = BTreeSet::new();
+    // in a cluster, each node only knows about its own keys
+    let mut connected = false;
+    for conn in cluster.conn.values_mut() {
+        if let Ok(conn) = conn.try_get() {
+            let keys: Vec<String> = conn.keys(&pattern).await.map_err(map_err)?;
+            shard_keys.extend(keys);
+            connected = true;
+        }
+    }
+    if !connected {
+        return Err(StreamErr::Connect("No open connections".to_owned()));
+    }
+    // shards recorded in the sharding metadata may not have been written to yet
+    if let Some(meta) = read_shard_meta(cluster, &stream).await? {
+        for shard in 0..meta.shards {
+            shard_keys.insert(format_stream_shard(&(stream.clone(), ShardId::new(shard))));
+        }
+    }
 
     Ok(if shard_keys.is_empty() {
         vec![ShardState {
@@ -47,6 +67,7 @@ pub async fn discover_shards(
             key: stream.name().to_owned(),
             id: None,
             pending_ack: Default::default(),
+            owned: false,
         }]
     } else {
         let mut shards: Vec<_> = shard_keys
@@ -60,6 +81,7 @@ pub async fn discover_shards(
                             key,
                             id: None,
                             pending_ack: Default::default(),
+                            owned: false,
                         })
                     } else {
                         log::warn!("Ignoring `{key}`");
@@ -73,3 +95,65 @@ pub async fn discover_shards(
         shards
     })
 }
+
+/// Watches the sharding metadata of streams, and adds the shards created by resharding to the consumer.
+pub struct ShardWatcher {
+    pub cluster: RedisCluster,
+    pub streams: Vec<StreamKey>,
+    pub known: HashSet<StreamShard>,
+    /// Streams that were not sharded when the consumer was created
+    pub unsharded: HashSet<StreamKey>,
+}
+
+impl ShardWatcher {
+    pub async fn run(mut self, handle: Sender<CtrlMsg>, discovered: Sender<StreamShard>) {
+        let mut warned = HashSet::new();
+        loop {
+            sleep(SHARD_REFRESH).await;
+            // exit when the consumer is dropped or killed
+            if discovered.is_disconnected() || handle.is_disconnected() {
+                break;
+            }
+            for stream in self.streams.iter() {
+                let meta = match read_shard_meta(&mut self.cluster, stream).await {
+                    Ok(Some(meta)) => meta,
+                    Ok(None) => continue,
+                    Err(err) => {
+                        log::warn!("Failed to read the shard metadata of `{stream}`: {err}");
+                        continue;
+                    }
+                };
+                if self.unsharded.contains(stream) {
+                    if warned.insert(stream.clone()) {
+                        log::warn!("`{stream}` has been sharded after the consumer was created");
+                    }
+                    continue;
+                }
+                for shard in 0..meta.shards {
+                    let stream_shard = (stream.clone(), ShardId::new(shard));
+                    if self.known.contains(&stream_shard) {
+                        continue;
+                    }
+                    log::info!("Discovered shard {}", format_stream_shard(&stream_shard));
+                    let state = ShardState {
+                        stream: stream_shard.clone(),
+                        key: format_stream_shard(&stream_shard),
+                        // read from the beginning, as the shard is new
+                        id: Some((0, 0)),
+                        pending_ack: Default::default(),
+                        owned: false,
+                    };
+                    if handle
+                        .send_async(CtrlMsg::AddShard(Box::new(state)))
+                        .await
+                        .is_err()
+                    {
+                        return;
+                    }
+                    discovered.send_async(stream_shard.clone()).await.ok();
+                    self.known.insert(stream_shard);
+                }
+            }
+        }
+    }
+}
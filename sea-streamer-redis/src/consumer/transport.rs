@@ -0,0 +1,282 @@
+use flume::{unbounded, Receiver, Sender, TryRecvError};
+use redis::{aio::PubSub, cmd as command};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use super::{ConsumerConfig, CtrlMsg, RedisConsumer, RedisConsumerOptions};
+use crate::{
+    create_connection, key_slot, map_err, NodeId, RedisCluster, RedisConnectOptions, RedisErr,
+    RedisResult, RedisTransport, ZERO,
+};
+use sea_streamer_runtime::{sleep, spawn_task, timeout};
+use sea_streamer_types::{
+    export::futures::StreamExt, ConsumerMode, ConsumerOptions, MessageHeader, SeqNo, SharedMessage,
+    StreamErr, StreamKey, Timestamp,
+};
+
+const ONE_SEC: Duration = Duration::from_secs(1);
+/// How long a `BRPOP` blocks, in seconds
+const BLOCK: &str = "1";
+/// When lists in different slots are polled in turn. Fractions are only accepted since Redis 6.
+const BLOCK_SLOT: &str = "0.1";
+
+/// Subscribes to the channels of the streams, on any node of the cluster.
+struct Subscriber {
+    nodes: Vec<NodeId>,
+    options: Arc<RedisConnectOptions>,
+    streams: Vec<StreamKey>,
+}
+
+pub(crate) async fn create_transport_consumer(
+    mut conn: RedisCluster,
+    options: RedisConsumerOptions,
+    streams: Vec<StreamKey>,
+) -> RedisResult<RedisConsumer> {
+    let transport = conn.options.transport;
+    match (transport, *options.mode()?) {
+        (RedisTransport::PubSub, ConsumerMode::RealTime) => (),
+        (RedisTransport::PubSub, _) => {
+            return Err(StreamErr::Unsupported(format!(
+                "{transport} only supports ConsumerMode::RealTime"
+            )))
+        }
+        (RedisTransport::List, ConsumerMode::Resumable) => {
+            return Err(StreamErr::Unsupported(format!(
+                "{transport} does not support ConsumerMode::Resumable"
+            )))
+        }
+        _ => (),
+    }
+
+    conn.reconnect_all().await?;
+    if conn.options.enable_cluster() {
+        conn.discover_slots().await?;
+    } else if conn.cluster.nodes().len() != 1 {
+        return Err(StreamErr::Connect(
+            "There are multiple nodes in streamer URI, please enable the cluster option".to_owned(),
+        ));
+    }
+    // Pub/Sub messages cannot be held back, and lists are only popped on demand
+    let (sender, receiver) = unbounded();
+    let (handle, response) = unbounded();
+    match transport {
+        RedisTransport::PubSub => {
+            let subscriber = Subscriber {
+                nodes: conn.cluster.nodes().to_vec(),
+                options: conn.options.clone(),
+                streams: streams.clone(),
+            };
+            // subscribe before returning, so that no message published afterwards is missed
+            let pubsub = subscriber.subscribe().await?;
+            spawn_task(subscriber.run(pubsub, sender, response));
+        }
+        RedisTransport::List => {
+            spawn_task(pop(conn, streams.clone(), sender, response));
+        }
+        RedisTransport::Streams => unreachable!(),
+    }
+
+    let (_, discovered) = unbounded();
+    Ok(RedisConsumer {
+        config: ConsumerConfig {
+            group_id: None,
+            consumer_id: None,
+            auto_ack: false,
+            manual_ack: false,
+            // Pub/Sub messages are pushed to us anyway, but a list is only popped on demand
+            pre_fetch: transport == RedisTransport::PubSub,
+        },
+        streams: streams.iter().map(|s| (s.clone(), ZERO)).collect(),
+        receiver,
+        handle,
+        discovered,
+        unsharded: streams.into_iter().collect(),
+        transport,
+    })
+}
+
+impl Subscriber {
+    async fn subscribe(&self) -> RedisResult<PubSub> {
+        let mut error = None;
+        // a message published on any node is broadcast to the whole cluster
+        for node in self.nodes.iter() {
+            match self.subscribe_on(node).await {
+                Ok(pubsub) => return Ok(pubsub),
+                Err(err) => {
+                    log::warn!("{node}: {err}");
+                    error = Some(err);
+                }
+            }
+        }
+        Err(error.expect("There must be at least one node"))
+    }
+
+    async fn subscribe_on(&self, node: &NodeId) -> RedisResult<PubSub> {
+        let conn = create_connection(node.clone(), self.options.clone()).await?;
+        let mut pubsub = conn.into_pubsub();
+        for stream in self.streams.iter() {
+            pubsub.subscribe(stream.name()).await.map_err(map_err)?;
+        }
+        Ok(pubsub)
+    }
+
+    async fn run(
+        self,
+        mut pubsub: PubSub,
+        messages: Sender<RedisResult<SharedMessage>>,
+        ctrl: Receiver<CtrlMsg>,
+    ) {
+        // there is no message id, so we number the messages ourselves
+        let mut sequence: SeqNo = 0;
+        'outer: loop {
+            let mut stream = pubsub.on_message();
+            loop {
+                match timeout(ONE_SEC, stream.next()).await {
+                    Ok(Some(message)) => {
+                        let stream_key = match StreamKey::new(message.get_channel_name()) {
+                            Ok(stream_key) => stream_key,
+                            Err(_) => continue,
+                        };
+                        let bytes = message.get_payload_bytes().to_vec();
+                        let size = bytes.len();
+                        sequence += 1;
+                        let header =
+                            MessageHeader::new(stream_key, ZERO, sequence, Timestamp::now_utc());
+                        let message = SharedMessage::new(header, bytes, 0, size);
+                        if messages.send_async(Ok(message)).await.is_err() {
+                            break 'outer;
+                        }
+                    }
+                    // disconnected
+                    Ok(None) => break,
+                    Err(_) => (),
+                }
+                if killed(&ctrl) {
+                    break 'outer;
+                }
+            }
+            drop(stream);
+            // messages published in the meantime are lost
+            pubsub = loop {
+                log::warn!("Subscription lost, resubscribing");
+                sleep(ONE_SEC).await;
+                if killed(&ctrl) {
+                    break 'outer;
+                }
+                if let Ok(pubsub) = self.subscribe().await {
+                    break pubsub;
+                }
+            };
+        }
+        log::debug!("Subscriber exit");
+    }
+}
+
+/// Pop the lists of the streams whenever `next` is called.
+async fn pop(
+    mut cluster: RedisCluster,
+    streams: Vec<StreamKey>,
+    messages: Sender<RedisResult<SharedMessage>>,
+    ctrl: Receiver<CtrlMsg>,
+) {
+    // keys in the same slot can be popped in one `BRPOP`
+    let mut slots: BTreeMap<u16, Vec<String>> = BTreeMap::new();
+    for stream in streams.iter() {
+        let slot = if cluster.options.enable_cluster() {
+            key_slot(stream.name())
+        } else {
+            0
+        };
+        slots
+            .entry(slot)
+            .or_default()
+            .push(stream.name().to_owned());
+    }
+    let groups: Vec<Vec<String>> = slots.into_values().collect();
+    let block = if groups.len() == 1 { BLOCK } else { BLOCK_SLOT };
+    // the number of pending `next` calls
+    let mut read: usize = 0;
+    let mut sequence: SeqNo = 0;
+    for keys in groups.iter().cycle() {
+        loop {
+            let msg = if read == 0 {
+                match ctrl.recv_async().await {
+                    Ok(msg) => msg,
+                    Err(_) => return,
+                }
+            } else {
+                match ctrl.try_recv() {
+                    Ok(msg) => msg,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return,
+                }
+            };
+            match msg {
+                CtrlMsg::Read => read += 1,
+                CtrlMsg::Unread => read = read.saturating_sub(1),
+                CtrlMsg::Kill(notify) => {
+                    notify.try_send(()).ok();
+                    return;
+                }
+                _ => (),
+            }
+        }
+
+        let result: RedisResult<Option<(String, Vec<u8>)>> = cluster
+            .query(&keys[0], |_| {
+                let mut cmd = command("BRPOP");
+                for key in keys.iter() {
+                    cmd.arg(key);
+                }
+                cmd.arg(block);
+                cmd
+            })
+            .await;
+        match result {
+            Ok(Some((key, bytes))) => {
+                let stream_key = StreamKey::new(key).expect("It must be one of the streams");
+                let size = bytes.len();
+                sequence += 1;
+                let header = MessageHeader::new(stream_key, ZERO, sequence, Timestamp::now_utc());
+                read = read.saturating_sub(1);
+                if messages
+                    .send_async(Ok(SharedMessage::new(header, bytes, 0, size)))
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+            }
+            // timed out
+            Ok(None) => (),
+            Err(StreamErr::Backend(
+                RedisErr::IoError(_)
+                | RedisErr::TryAgain(_)
+                | RedisErr::ClusterDown(_)
+                | RedisErr::ReadOnly(_),
+            )) => {
+                // the master may have been demoted in a failover
+                let node = cluster.node_for(&keys[0]).clone();
+                cluster.reconnect(&node).ok();
+                sleep(ONE_SEC).await;
+            }
+            Err(err) => {
+                messages.send_async(Err(err)).await.ok();
+                return;
+            }
+        }
+    }
+}
+
+fn killed(ctrl: &Receiver<CtrlMsg>) -> bool {
+    loop {
+        match ctrl.try_recv() {
+            Ok(CtrlMsg::Kill(notify)) => {
+                notify.try_send(()).ok();
+                return true;
+            }
+            Ok(_) => (),
+            Err(TryRecvError::Empty) => return false,
+            Err(TryRecvError::Disconnected) => return true,
+        }
+    }
+}
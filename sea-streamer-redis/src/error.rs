@@ -7,6 +7,8 @@ use thiserror::Error;
 pub enum RedisErr {
     #[error("Failed to parse message ID: {0}")]
     MessageId(String),
+    #[error("Message ID does not fit into a sequence number: {0}")]
+    SequenceOverflow(String),
     #[error("Failed to parse StreamReadReply: {0:?}")]
     StreamReadReply(String),
     #[error("The Producer task died")]
@@ -25,6 +25,13 @@
 //! + LoadBalanced mode with failover behaviour
 //! + Seek/rewind to point in time
 //! + Basic stream sharding: split a stream into multiple sub-streams
+//! + Consistent-hash sharding by message key, with resharding that keeps the messages of each key in order
+//! + Shard ownership: each shard is consumed by at most one member of a group, rebalanced as members join and leave
+//! + Stream trimming by length or by age, on `XADD` or by a background task that respects consumer groups
+//! + Dead-letter streams for messages that exceed the maximum number of deliveries
+//! + Inspection and management of pending entries and consumer groups with `RedisAdmin`
+//! + Transactions that send output and ack input atomically, for effectively-once processing within a single Redis
+//! + Pub/Sub and list transports with reduced semantics, for broadcasting and work queues
 //!
 //! It's best to look through the [tests](https://github.com/SeaQL/sea-streamer/tree/main/sea-streamer-redis/tests)
 //! for an illustration of the different streaming behaviour.
@@ -50,14 +57,33 @@
 //!
 //! If you are reading from a consumer group, you also have to consider when to ACK and how many ACKs to batch in one request. SeaStreamer can commit in the background on a regular interval, or you can commit asynchronously without blocking your process loop.
 //!
-//! In the future, we'd like to support Redis Cluster, because sharding without clustering is not very useful.
-//! Right now it's pretty much a work-in-progress.
-//! It's quite a difficult task, because clients have to take responsibility when working with a cluster.
+//! Redis Cluster is supported with `RedisConnectOptions::set_enable_cluster`, because sharding without clustering is not very useful.
 //! In Redis, shards and nodes is a M-N mapping - shards can be moved among nodes *at any time*.
-//! It makes testing much more difficult.
-//! Let us know if you'd like to help!
+//! Clients have to take responsibility when working with a cluster: SeaStreamer places each shard on the node serving its hash slot,
+//! follows `MOVED` redirects as slots are migrated while streaming, and rewinds across nodes.
+//! As the keys of a command have to be in the same slot, shards in different slots are polled in turn instead of in one blocking `XREAD`.
+//! Use hash tags in stream keys, e.g. `{my-stream}`, to keep all shards of a stream in the same slot.
 //!
-//! There is also a [small utility](https://github.com/SeaQL/sea-streamer/tree/main/sea-streamer-redis/redis-streams-dump) to dump Redis Streams messages into a SeaStreamer file.
+//! Redis Sentinel is supported with `redis+sentinel://` (or `rediss+sentinel://`) URIs listing the Sentinels, with the master name as the path,
+//! e.g. `redis+sentinel://sentinel-1:26379,sentinel-2:26379/mymaster`. The master is discovered through the Sentinels,
+//! and producers and consumers reconnect to the new master on `+switch-master`. Batches in flight are retried on the new master.
+//! The Sentinels are authenticated with the user info of the URI, e.g. `redis+sentinel://:password@sentinel-1/mymaster`,
+//! or `RedisConnectOptions::set_sentinel_password`; the master with `RedisConnectOptions::set_password` as usual.
+//!
+//! TLS is enabled with `rediss://` URIs and one of the features `runtime-tokio-native-tls`, `runtime-tokio-rustls`, `runtime-async-std-native-tls` or `runtime-async-std-rustls`.
+//! Custom CA certificates, client certificates for mutual TLS and the TLS implementation can be configured with `RedisConnectOptions::set_tls`.
+//!
+//! By default, each producer and consumer has its own connections. With `RedisConnectOptions::set_shared_connections`,
+//! producers, trimmers, admin handles and dead-letter writers share one multiplexed connection per node instead,
+//! while the blocking `XREAD`s of consumers keep their dedicated connections. Shared connections are pinged every
+//! `RedisConnectOptions::set_health_check_interval`, and broken ones are replaced. Reconnects back off exponentially with jitter,
+//! as configured by `RedisConnectOptions::set_reconnect_backoff`.
+//!
+//! Besides Redis Streams, Redis Pub/Sub and Redis lists can carry the messages, with `redis+pubsub://` and `redis+list://` URIs (or `rediss+pubsub://` and `rediss+list://`).
+//! They implement the same `Producer` and `Consumer` traits with reduced semantics, see `RedisTransport`: there is no seek, ack or commit.
+//! With Pub/Sub, every consumer receives the messages published while it is subscribed. With lists, each message is received by only one consumer, so the consumers share the work.
+//!
+//! There is also a [small utility](https://github.com/SeaQL/sea-streamer/tree/main/sea-streamer-redis/redis-streams-dump) to dump Redis Streams messages into a SeaStreamer file, and to restore them.
 //!
 //! This crate is built on top of [`redis`](https://docs.rs/redis).
 
@@ -73,6 +99,9 @@ pub const REDIS_PORT: u16 = 6379;
 /// The default timeout, if needed but unspecified
 pub const DEFAULT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
 
+/// The default interval of health checks on shared connections
+pub const DEFAULT_HEALTH_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
 /// The field of the message payload
 pub const MSG: &str = "msg";
 
@@ -83,20 +112,36 @@ pub const ZERO: ShardId = ShardId::new(0);
 #[cfg(all(feature = "runtime-async-std", feature = "runtime-tokio"))]
 compile_error!("'runtime-async-std' and 'runtime-tokio' cannot be enabled at the same time");
 
+mod admin;
 mod cluster;
 mod connection;
 mod consumer;
 mod error;
 mod host;
 mod message;
+mod pool;
 mod producer;
+mod sentinel;
+mod sharding;
 mod streamer;
+mod tls;
+mod transaction;
+mod transport;
+mod trim;
 
+pub use admin::*;
 pub use cluster::*;
 pub use connection::*;
 pub use consumer::*;
 pub use error::*;
 pub use host::*;
 pub use message::*;
+pub(crate) use pool::ConnectionPool;
 pub use producer::*;
+pub use sentinel::*;
+pub use sharding::*;
 pub use streamer::*;
+pub use tls::*;
+pub use transaction::*;
+pub use transport::*;
+pub use trim::*;
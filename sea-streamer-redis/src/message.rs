@@ -1,14 +1,18 @@
 use crate::{RedisErr, RedisResult, MSG, ZERO};
 use redis::Value;
 use sea_streamer_types::{
-    MessageHeader, SeqNo, ShardId, SharedMessage, StreamErr, StreamKey, Timestamp,
+    MessageHeader, OwnedMessage, SeqNo, ShardId, SharedMessage, StreamErr, StreamKey, Timestamp,
 };
 
-/// ID of a message in the form of (timestamp, sequence).
-pub type MessageId = (u64, u16);
+/// ID of a message in the form of (timestamp, sequence), the same as a Redis message id `<timestamp>-<sequence>`.
+pub type MessageId = (u64, u64);
 
 /// To indicate `$`, aka latest.
-pub const MAX_MSG_ID: MessageId = (u64::MAX, u16::MAX);
+pub const MAX_MSG_ID: MessageId = (u64::MAX, u64::MAX);
+
+/// The high 48 bits of the sequence number of an overflowed id that would otherwise read as a packed id.
+/// No timestamp can be packed with them.
+const COMPLEMENT: u64 = 0xFFFFFFFFFFFF;
 
 pub type RedisMessage = SharedMessage;
 
@@ -25,51 +29,106 @@ pub(crate) struct AutoClaimReply(pub(crate) Vec<RedisMessage>);
 ///
 /// This limits the number of messages per millisecond to 65536,
 /// and the maximum timestamp to 10889-08-02T05:31:50.
+/// Returns `RedisErr::SequenceOverflow` for the ids beyond, which can still be read, see [`RedisMessageId`].
 pub fn parse_message_id(id: &str) -> RedisResult<(Timestamp, SeqNo)> {
+    let id = message_id_from_str(id)?;
+    Ok((to_timestamp(id.0)?, pack_message_id(id)?))
+}
+
+/// Parse a Redis message id in the form of `<timestamp>-<sequence>`, without loss.
+pub fn message_id_from_str(id: &str) -> RedisResult<MessageId> {
     if let Some((timestamp, seq_no)) = id.split_once('-') {
-        if let Ok(timestamp) = timestamp.parse::<u64>() {
-            if let Ok(seq_no) = seq_no.parse::<u64>() {
-                if seq_no > 0xFFFF {
-                    return Err(StreamErr::Backend(RedisErr::MessageId(format!(
-                        "Sequence number out of range: {seq_no}"
-                    ))));
-                }
-                if timestamp > 0xFFFFFFFFFFFF {
-                    return Err(StreamErr::Backend(RedisErr::MessageId(format!(
-                        "Timestamp out of range: {timestamp}"
-                    ))));
-                }
-                return Ok((
-                    Timestamp::from_unix_timestamp_nanos(timestamp as i128 * 1_000_000).unwrap(),
-                    timestamp << 16 | seq_no,
-                ));
-            }
+        if let (Ok(timestamp), Ok(seq_no)) = (timestamp.parse(), seq_no.parse()) {
+            return Ok((timestamp, seq_no));
         }
     }
     Err(StreamErr::Backend(RedisErr::MessageId(id.to_owned())))
 }
 
+/// Pack a message id into a `SeqNo`, as in [`parse_message_id`].
+pub fn pack_message_id((timestamp, seq_no): MessageId) -> RedisResult<SeqNo> {
+    if seq_no > 0xFFFF {
+        return Err(StreamErr::Backend(RedisErr::SequenceOverflow(format!(
+            "Sequence number out of range: {timestamp}-{seq_no}"
+        ))));
+    }
+    if timestamp >= COMPLEMENT {
+        return Err(StreamErr::Backend(RedisErr::SequenceOverflow(format!(
+            "Timestamp out of range: {timestamp}-{seq_no}"
+        ))));
+    }
+    Ok(timestamp << 16 | seq_no)
+}
+
+fn to_timestamp(millis: u64) -> RedisResult<Timestamp> {
+    Timestamp::from_unix_timestamp_nanos(millis as i128 * 1_000_000).map_err(|_| {
+        StreamErr::Backend(RedisErr::MessageId(format!(
+            "Timestamp out of range: {millis}"
+        )))
+    })
+}
+
+/// The timestamp and sequence number of a message with this id, from which [`get_message_id`] recovers the full id.
+///
+/// The timestamp is the millisecond of the id, and the sequence number is the id packed as in [`parse_message_id`].
+/// If the id cannot be packed, the sequence number is the bitwise complement of the sub-sequence number instead,
+/// which does not unpack to a valid timestamp unless the sub-sequence number is beyond 2^60. Should it collide with
+/// the packed ids of its millisecond, only its low 16 bits are kept, under the high bits `COMPLEMENT`.
+/// As only whole milliseconds are used, the id survives the SeaStreamer file format.
+pub(crate) fn header_of_id(id: &str) -> RedisResult<(Timestamp, SeqNo)> {
+    let id = message_id_from_str(id)?;
+    Ok((to_timestamp(id.0)?, seq_no_of(id)))
+}
+
+fn seq_no_of((timestamp, seq_no): MessageId) -> SeqNo {
+    if let Ok(packed) = pack_message_id((timestamp, seq_no)) {
+        return packed;
+    }
+    log::debug!("Message id {timestamp}-{seq_no} overflows SeqNo");
+    let complement = !seq_no;
+    if timestamp < COMPLEMENT && complement >> 16 == timestamp {
+        COMPLEMENT << 16 | (complement & 0xFFFF)
+    } else {
+        complement
+    }
+}
+
 pub(crate) fn get_message_id(header: &MessageHeader) -> MessageId {
-    (
-        (header.timestamp().unix_timestamp_nanos() / 1_000_000)
-            .try_into()
-            .expect("RedisConsumer: timestamp out of range"),
-        (header.sequence() & 0xFFFF)
-            .try_into()
-            .expect("Never fails"),
-    )
+    let timestamp: u64 = (header.timestamp().unix_timestamp_nanos() / 1_000_000)
+        .try_into()
+        .expect("RedisConsumer: timestamp out of range");
+    let seq_no = *header.sequence();
+    if timestamp >= COMPLEMENT {
+        (timestamp, !seq_no)
+    } else if seq_no >> 16 == timestamp {
+        (timestamp, seq_no & 0xFFFF)
+    } else if seq_no >> 16 == COMPLEMENT {
+        (timestamp, !(timestamp << 16 | (seq_no & 0xFFFF)))
+    } else {
+        (timestamp, !seq_no)
+    }
 }
 
-pub(crate) fn from_seq_no(seq_no: SeqNo) -> MessageId {
-    (
-        (seq_no >> 16),
-        (seq_no & 0xFFFF).try_into().expect("Never fails"),
-    )
+/// Unpack a `SeqNo`, as in [`parse_message_id`]. Returns `RedisErr::SequenceOverflow` if it does not unpack
+/// to a valid timestamp, as it is then the sequence number of an id that overflows it, see [`header_of_id`].
+pub(crate) fn from_seq_no(seq_no: SeqNo) -> RedisResult<MessageId> {
+    let id = (seq_no >> 16, seq_no & 0xFFFF);
+    match to_timestamp(id.0) {
+        Ok(_) => Ok(id),
+        Err(_) => Err(StreamErr::Backend(RedisErr::SequenceOverflow(format!(
+            "Not a packed message id: {seq_no}"
+        )))),
+    }
 }
 
 /// A trait that adds some methods to [`RedisMessage`].
+///
+/// The sequence number of a message is its id packed into a `SeqNo`, see [`parse_message_id`]. Ids that overflow it,
+/// e.g. explicit ids or more than 65536 messages in a millisecond, are still read, and the full id can be recovered here.
+/// Their sequence number is not a packed id though, so it is neither monotonic nor unique within a stream, and cannot be used
+/// with `rewind` or `ack_with`; use `seek_to` or `ack` instead.
 pub trait RedisMessageId {
-    /// Get the Redis MessageId in form of (timestamp,seq) tuple from the message
+    /// Get the Redis MessageId in form of (timestamp,seq) tuple from the message, without loss
     fn message_id(&self) -> MessageId;
 }
 
@@ -79,6 +138,19 @@ impl RedisMessageId for RedisMessage {
     }
 }
 
+impl RedisMessageId for OwnedMessage {
+    fn message_id(&self) -> MessageId {
+        get_message_id(self.header())
+    }
+}
+
+/// For the receipts of sent messages
+impl RedisMessageId for MessageHeader {
+    fn message_id(&self) -> MessageId {
+        get_message_id(self)
+    }
+}
+
 // bulk(bulk(string-data('"my_stream_1"'), bulk(bulk(string-data('"1678280595282-0"'), bulk(string-data('"msg"'), string-data('"hi 0"'), field, value, ...)), ...)))
 // LOL such nesting. This is still undesirable, as there are 5 layers of nested Vec. But at least we don't have to copy the bytes again.
 impl StreamReadReply {
@@ -160,7 +232,7 @@ fn parse_messages(
             let value_0 = values.next().unwrap();
             let value_1 = values.next().unwrap();
             let id = string_from_redis_value(value_0)?;
-            let (timestamp, sequence) = parse_message_id(&id)?;
+            let (timestamp, sequence) = header_of_id(&id)?;
             if let Value::Bulk(values) = value_1 {
                 assert!(values.len() % 2 == 0);
                 let pairs = values.len() / 2;
@@ -211,3 +283,72 @@ pub(crate) fn bytes_from_redis_value(v: Value) -> RedisResult<Vec<u8>> {
         ))),
     }
 }
+
+#[cfg(test)]
+#[test]
+fn test_message_id() {
+    let header = |id: &str| {
+        let (timestamp, sequence) = header_of_id(id).unwrap();
+        MessageHeader::new(StreamKey::new("s").unwrap(), ZERO, sequence, timestamp)
+    };
+    let millis = |header: &MessageHeader| header.timestamp().unix_timestamp_nanos() / 1_000_000;
+
+    let packed = header("1678280595282-1");
+    assert_eq!(*packed.sequence(), 1678280595282 << 16 | 1);
+    assert_eq!(
+        packed.timestamp().unix_timestamp_nanos(),
+        1678280595282 * 1_000_000
+    );
+    assert_eq!(get_message_id(&packed), (1678280595282, 1));
+    assert_eq!(from_seq_no(*packed.sequence()).unwrap(), (1678280595282, 1));
+
+    // more than 65536 messages in a millisecond, or an explicit id
+    let overflow = header("1678280595282-65536");
+    assert_eq!(millis(&overflow), 1678280595282);
+    assert_eq!(get_message_id(&overflow), (1678280595282, 65536));
+    assert!(matches!(
+        from_seq_no(*overflow.sequence()),
+        Err(StreamErr::Backend(RedisErr::SequenceOverflow(_)))
+    ));
+    let overflow = header("1-18446744073709551615");
+    assert_eq!(millis(&overflow), 1);
+    assert_eq!(overflow.message_id(), (1, u64::MAX));
+    // the complement of the sub-sequence number reads as a packed id of the same millisecond
+    let id = format!("1678280595282-{}", !(1678280595282u64 << 16 | 7));
+    let overflow = header(&id);
+    assert_eq!(*overflow.sequence() >> 16, COMPLEMENT);
+    assert_eq!(
+        get_message_id(&overflow),
+        (1678280595282, !(1678280595282u64 << 16 | 7))
+    );
+    // no two ids of a millisecond share a sequence number
+    for seq in [
+        0,
+        1,
+        0xFFFF,
+        0x10000,
+        1 << 60,
+        !(1 << 16),
+        !0x10000,
+        u64::MAX,
+    ] {
+        for timestamp in [0, 1, 1678280595282] {
+            let (ts, seq_no) = header_of_id(&format!("{timestamp}-{seq}")).unwrap();
+            let header = MessageHeader::new(StreamKey::new("s").unwrap(), ZERO, seq_no, ts);
+            assert_eq!(get_message_id(&header), (timestamp, seq));
+        }
+    }
+
+    assert!(matches!(
+        parse_message_id("1678280595282-65536"),
+        Err(StreamErr::Backend(RedisErr::SequenceOverflow(_)))
+    ));
+    assert!(matches!(
+        parse_message_id("1678280595282"),
+        Err(StreamErr::Backend(RedisErr::MessageId(_)))
+    ));
+    assert_eq!(
+        message_id_from_str("1678280595282-65536").unwrap(),
+        (1678280595282, 65536)
+    );
+}
@@ -0,0 +1,197 @@
+use flume::{bounded, unbounded, Receiver, Sender};
+use redis::aio::MultiplexedConnection;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use crate::{
+    create_multiplexed, health_check, watch, NodeId, RedisConnectOptions, RedisErr, RedisResult,
+};
+use sea_streamer_runtime::{spawn_task, timeout};
+use sea_streamer_types::{StreamErr, Timestamp};
+
+#[derive(Debug, Clone)]
+/// Multiplexed connections shared by the producers, consumers and admin handles of a streamer,
+/// one per node. See [`RedisConnectOptions::set_shared_connections`].
+pub(crate) struct ConnectionPool {
+    sender: Sender<PoolMsg>,
+}
+
+type Reply = Sender<RedisResult<(MultiplexedConnection, u64)>>;
+
+enum PoolMsg {
+    /// Get the connection to a node, opening one if there is none
+    Get(NodeId, Reply),
+    /// The connection of this generation is broken
+    Broken(NodeId, u64),
+}
+
+#[derive(Default)]
+struct Shared {
+    conn: Option<MultiplexedConnection>,
+    /// Incremented whenever the connection is replaced
+    generation: u64,
+    /// The last failed attempt to connect
+    failed: Option<Timestamp>,
+    /// Raised by the Sentinel watcher on failover
+    switched: Option<Arc<AtomicBool>>,
+}
+
+impl ConnectionPool {
+    /// `options` must not contain this pool, otherwise the pool will never be dropped.
+    pub(crate) fn new(options: Arc<RedisConnectOptions>) -> Self {
+        assert!(options.pool.is_none());
+        let (sender, receiver) = unbounded();
+        spawn_task(run(options, receiver));
+        Self { sender }
+    }
+
+    /// Get the shared connection to a node and its generation.
+    pub(crate) async fn get(&self, node: &NodeId) -> RedisResult<(MultiplexedConnection, u64)> {
+        let (reply, result) = bounded(1);
+        // unbounded, so never blocks
+        if self.sender.send(PoolMsg::Get(node.clone(), reply)).is_err() {
+            return Err(StreamErr::Connect("Connection pool died".to_owned()));
+        }
+        match result.recv_async().await {
+            Ok(result) => result,
+            Err(_) => Err(StreamErr::Connect("Connection pool died".to_owned())),
+        }
+    }
+
+    /// Report that the shared connection of this generation is broken, so that it is replaced on the next `get`.
+    pub(crate) fn broken(&self, node: &NodeId, generation: u64) {
+        self.sender
+            .try_send(PoolMsg::Broken(node.clone(), generation))
+            .ok();
+    }
+}
+
+/// The pool task, which passes the messages on to a task per node. Runs until all handles of the pool are dropped.
+async fn run(options: Arc<RedisConnectOptions>, receiver: Receiver<PoolMsg>) {
+    let mut nodes: HashMap<NodeId, Sender<PoolMsg>> = HashMap::new();
+    // exit if all senders have been dropped, which in turn ends the node tasks
+    while let Ok(msg) = receiver.recv_async().await {
+        let node = match &msg {
+            PoolMsg::Get(node, _) | PoolMsg::Broken(node, _) => node,
+        };
+        let sender = nodes.entry(node.clone()).or_insert_with(|| {
+            let (sender, receiver) = unbounded();
+            spawn_task(run_node(node.clone(), options.clone(), receiver));
+            sender
+        });
+        // unbounded, so never blocks
+        sender.send(msg).ok();
+    }
+    log::debug!("Connection pool exit");
+}
+
+/// The task of a node, which owns its connection, so that connecting to or checking a slow node
+/// does not hold up the others.
+async fn run_node(node: NodeId, options: Arc<RedisConnectOptions>, receiver: Receiver<PoolMsg>) {
+    let interval = options.health_check_interval();
+    let mut shared = Shared {
+        switched: watch(&node, &options),
+        ..Default::default()
+    };
+    let mut checked = Timestamp::now_utc();
+    loop {
+        match timeout(interval, receiver.recv_async()).await {
+            Ok(Ok(PoolMsg::Get(_, reply))) => {
+                let result = get(&node, &mut shared, &options).await;
+                reply.try_send(result).ok();
+            }
+            Ok(Ok(PoolMsg::Broken(_, generation))) => {
+                // it may have been replaced already
+                if shared.generation == generation && shared.conn.take().is_some() {
+                    log::debug!("Shared connection to {node} is broken");
+                }
+            }
+            Ok(Err(_)) => break,
+            Err(_) => (),
+        }
+        if Timestamp::now_utc() - interval > checked {
+            if let Some(conn) = shared.conn.as_mut() {
+                if let Err(err) = health_check(conn, &options).await {
+                    log::warn!("Shared connection to {node} is unhealthy: {err}");
+                    shared.conn = None;
+                }
+            }
+            checked = Timestamp::now_utc();
+        }
+    }
+    log::debug!("Shared connection to {node} exit");
+}
+
+async fn get(
+    node: &NodeId,
+    shared: &mut Shared,
+    options: &Arc<RedisConnectOptions>,
+) -> RedisResult<(MultiplexedConnection, u64)> {
+    if let Some(switched) = &shared.switched {
+        if switched.swap(false, Ordering::AcqRel) && shared.conn.take().is_some() {
+            log::info!("Master of {node} has been switched, reconnecting");
+        }
+    }
+    if shared.conn.is_none() {
+        // the users are backing off on their own, but there can be many of them
+        if let Some(failed) = shared.failed {
+            if Timestamp::now_utc() - options.reconnect_backoff().initial < failed {
+                return Err(StreamErr::Backend(RedisErr::TryAgain(format!(
+                    "Reconnecting to {node}"
+                ))));
+            }
+        }
+        match create_multiplexed(node.clone(), options.clone()).await {
+            Ok(conn) => {
+                log::debug!("Opened shared connection to {node}");
+                shared.conn = Some(conn);
+                shared.generation += 1;
+                shared.failed = None;
+            }
+            Err(err) => {
+                shared.failed = Some(Timestamp::now_utc());
+                return Err(err);
+            }
+        }
+    }
+    Ok((shared.conn.clone().expect("Must exist"), shared.generation))
+}
+
+#[cfg(all(test, feature = "test"))]
+mod test {
+    use super::*;
+
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    async fn test_slow_node() {
+        use sea_streamer_types::ConnectOptions;
+        use std::time::{Duration, Instant};
+
+        // accepts connections, but never replies to `SELECT`
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let slow: NodeId = format!("redis://{}", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        // nothing listens on port 1
+        let down: NodeId = "redis://127.0.0.1:1".parse().unwrap();
+
+        let mut options = RedisConnectOptions::default();
+        options.set_db(1);
+        options.set_timeout(Duration::from_secs(5)).unwrap();
+        let pool = ConnectionPool::new(Arc::new(options));
+
+        let pending = pool.clone();
+        let slow_get = spawn_task(async move { pending.get(&slow).await.is_err() });
+        sea_streamer_runtime::sleep(Duration::from_millis(100)).await;
+        let start = Instant::now();
+        assert!(pool.get(&down).await.is_err());
+        assert!(start.elapsed() < Duration::from_secs(2));
+        drop(listener);
+        drop(slow_get);
+    }
+}
@@ -1,12 +1,20 @@
 use flume::{bounded, r#async::RecvFut, unbounded, Sender};
 use redis::{aio::ConnectionLike, cmd as command, ErrorKind, Pipeline};
-use std::{fmt::Debug, future::Future, sync::Arc, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    future::Future,
+    sync::Arc,
+    time::Duration,
+};
 
 use crate::{
-    map_err, parse_message_id, string_from_redis_value, RedisCluster, RedisErr, RedisResult, MSG,
-    ZERO,
+    constants::{MAX_HELD_MESSAGES, SHARD_REFRESH},
+    header_of_id, init_shard_meta, map_err, run_producer, string_from_redis_value, write_trim,
+    MessageId, RedisCluster, RedisErr, RedisResult, RedisTransaction, RedisTransport, StreamTrim,
+    TransactionRequest, Trimmer, MSG, ZERO,
 };
-use sea_streamer_runtime::{sleep, spawn_task};
+use sea_streamer_runtime::{sleep, spawn_task, timeout};
 use sea_streamer_types::{
     export::{async_trait, futures::FutureExt},
     Buffer, MessageHeader, Producer, ProducerOptions, ShardId, StreamErr, StreamKey, Timestamp,
@@ -14,34 +22,92 @@ use sea_streamer_types::{
 };
 
 const MAX_RETRY: usize = 100;
+/// How often to check whether a draining stream has been resharded
+const DRAIN_POLL: Duration = Duration::from_secs(1);
 
 #[derive(Debug, Clone)]
 /// The Redis Producer.
 pub struct RedisProducer {
     stream: Option<StreamKey>,
-    sender: Sender<SendRequest>,
+    sender: Sender<Request>,
 }
 
 #[derive(Default, Clone)]
-/// Options for Producers, including sharding.
+/// Options for Producers, including sharding and trimming.
 pub struct RedisProducerOptions {
     sharder: Option<Arc<dyn SharderConfig>>,
+    trim: Option<StreamTrim>,
+    trim_exact: bool,
+    trim_interval: Option<Duration>,
+    force_trim: bool,
 }
 
 impl Debug for RedisProducerOptions {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("RedisProducerOptions")
             .field("sharder", &self.sharder.as_ref())
+            .field("trim", &self.trim)
+            .field("trim_exact", &self.trim_exact)
+            .field("trim_interval", &self.trim_interval)
+            .field("force_trim", &self.force_trim)
             .finish()
     }
 }
 
 type Receipt = Sender<RedisResult<MessageHeader>>;
 
-struct SendRequest {
-    stream_key: StreamKey,
-    bytes: Vec<u8>,
-    receipt: Receipt,
+pub(crate) struct SendRequest {
+    pub(crate) stream_key: StreamKey,
+    pub(crate) key: Option<Vec<u8>>,
+    /// Explicit message id, instead of `*`
+    pub(crate) id: Option<MessageId>,
+    pub(crate) bytes: Vec<u8>,
+    pub(crate) receipt: Receipt,
+}
+
+pub(crate) enum Request {
+    Send(SendRequest),
+    Transaction(Box<TransactionRequest>),
+}
+
+impl SendRequest {
+    pub(crate) fn is_signal(&self) -> bool {
+        self.stream_key.name() == SEA_STREAMER_INTERNAL && self.bytes.is_empty()
+    }
+}
+
+impl Request {
+    /// Whether this request writes to the stream
+    fn touches(&self, stream_key: &StreamKey) -> bool {
+        match self {
+            Request::Send(request) => !request.is_signal() && &request.stream_key == stream_key,
+            Request::Transaction(txn) => txn.touches(stream_key),
+        }
+    }
+
+    /// Reply with an error instead of sending
+    fn fail(self, err: StreamErr<RedisErr>) {
+        match self {
+            Request::Send(request) => {
+                request.receipt.try_send(Err(err)).ok();
+            }
+            Request::Transaction(txn) => txn.reply(Err(err)),
+        }
+    }
+
+    fn streams(&self) -> Vec<StreamKey> {
+        match self {
+            Request::Send(request) if request.is_signal() => Vec::new(),
+            Request::Send(request) => vec![request.stream_key.clone()],
+            Request::Transaction(txn) => txn.streams().cloned().collect(),
+        }
+    }
+}
+
+/// What a producer knows about the sharding metadata of a stream
+struct ShardMetaState {
+    draining: bool,
+    checked: Timestamp,
 }
 
 /// A future that returns a Send Receipt. This future is cancel safe.
@@ -60,6 +126,15 @@ pub trait SharderConfig: Debug + Send + Sync {
     /// Each producer will create its own sharder.
     /// They should not have any shared state for the sake of concurrency.
     fn init(&self) -> Box<dyn Sharder>;
+
+    /// If Some, the number of shards of each stream is stored in Redis, and initialized to this value by
+    /// the first producer writing to the stream. Producers follow the changes made by [`crate::RedisAdmin::reshard`],
+    /// and let the sharder know with [`Sharder::set_num_shards`].
+    ///
+    /// Defaults to None.
+    fn initial_shards(&self) -> Option<u64> {
+        None
+    }
 }
 
 /// Trait that sharding strategies should implement. It should also impl `Debug` so its states can be inspected.
@@ -72,6 +147,17 @@ pub trait Sharder: Debug + Send {
     /// The Redis Cluster will assign this shard to a particular node as the cluster scales.
     /// Different shards may or may not end up in the same slot, and thus may or may not end up in the same node.
     fn shard(&mut self, stream_key: &StreamKey, bytes: &[u8]) -> u64;
+
+    /// Return the determined shard id for a message sent with a key, see [`RedisProducer::send_with_key`].
+    ///
+    /// Defaults to ignoring the key.
+    fn shard_by_key(&mut self, stream_key: &StreamKey, _key: &[u8], bytes: &[u8]) -> u64 {
+        self.shard(stream_key, bytes)
+    }
+
+    /// The number of shards of a stream as recorded in Redis.
+    /// Only called if [`SharderConfig::initial_shards`] is Some, before the first message to the stream and whenever it is resharded.
+    fn set_num_shards(&mut self, _stream_key: &StreamKey, _num_shards: u64) {}
 }
 
 #[derive(Debug, Clone)]
@@ -93,20 +179,7 @@ impl Producer for RedisProducer {
     type SendFuture = SendFuture;
 
     fn send_to<S: Buffer>(&self, stream: &StreamKey, payload: S) -> RedisResult<Self::SendFuture> {
-        // one shot channel
-        let (sender, receiver) = bounded(1);
-        // unbounded, so never blocks
-        self.sender
-            .send(SendRequest {
-                stream_key: stream.to_owned(),
-                bytes: payload.into_bytes(),
-                receipt: sender,
-            })
-            .map_err(|_| StreamErr::Backend(RedisErr::ProducerDied))?;
-
-        Ok(SendFuture {
-            fut: receiver.into_recv_async(),
-        })
+        self.request(stream, None, None, payload)
     }
 
     #[inline]
@@ -142,6 +215,68 @@ impl Producer for RedisProducer {
     }
 }
 
+impl RedisProducer {
+    /// Like `send`, but the shard is determined by `key` instead of the payload. See [`Sharder::shard_by_key`].
+    pub fn send_with_key<S: Buffer>(&self, key: &[u8], payload: S) -> RedisResult<SendFuture> {
+        self.send_with_key_to(self.anchored()?, key, payload)
+    }
+
+    /// Like `send_to`, but the shard is determined by `key` instead of the payload. See [`Sharder::shard_by_key`].
+    pub fn send_with_key_to<S: Buffer>(
+        &self,
+        stream: &StreamKey,
+        key: &[u8],
+        payload: S,
+    ) -> RedisResult<SendFuture> {
+        self.request(stream, Some(key.to_vec()), None, payload)
+    }
+
+    /// Like `send_to`, but the message is added with an explicit id instead of `*`, e.g. to restore messages with their original ids.
+    /// If `key` is Some, the shard is determined by it, as in `send_with_key_to`.
+    ///
+    /// Redis rejects an id that is not greater than the last id of the stream, and the receipt would be an error.
+    /// This is not supported by the Pub/Sub and List transports.
+    pub fn send_with_id_to<S: Buffer>(
+        &self,
+        stream: &StreamKey,
+        key: Option<&[u8]>,
+        id: MessageId,
+        payload: S,
+    ) -> RedisResult<SendFuture> {
+        self.request(stream, key.map(|k| k.to_vec()), Some(id), payload)
+    }
+
+    /// Start a transaction, to send messages and ack their input messages atomically. See [`RedisTransaction`].
+    pub fn transaction(&self) -> RedisTransaction {
+        RedisTransaction::new(self.stream.clone(), self.sender.clone())
+    }
+
+    fn request<S: Buffer>(
+        &self,
+        stream: &StreamKey,
+        key: Option<Vec<u8>>,
+        id: Option<MessageId>,
+        payload: S,
+    ) -> RedisResult<SendFuture> {
+        // one shot channel
+        let (sender, receiver) = bounded(1);
+        // unbounded, so never blocks
+        self.sender
+            .send(Request::Send(SendRequest {
+                stream_key: stream.to_owned(),
+                key,
+                id,
+                bytes: payload.into_bytes(),
+                receipt: sender,
+            }))
+            .map_err(|_| StreamErr::Backend(RedisErr::ProducerDied))?;
+
+        Ok(SendFuture {
+            fut: receiver.into_recv_async(),
+        })
+    }
+}
+
 impl ProducerOptions for RedisProducerOptions {}
 
 impl RedisProducerOptions {
@@ -164,6 +299,66 @@ impl RedisProducerOptions {
     pub fn sharder(&self) -> Option<&dyn SharderConfig> {
         self.sharder.as_deref()
     }
+
+    /// Trim the streams by length or by age, so that they do not grow indefinitely.
+    ///
+    /// Unless `trim_interval` is set, this is done on every `XADD`, regardless of consumer groups:
+    /// entries may be removed before they are consumed.
+    ///
+    /// `MaxAge` cannot be zero, or longer than the time since the Unix epoch.
+    ///
+    /// If unset, defaults to None, i.e. never trim.
+    pub fn set_trim(&mut self, v: Option<StreamTrim>) -> RedisResult<&mut Self> {
+        if let Some(StreamTrim::MaxAge(age)) = v {
+            let since_epoch = Timestamp::now_utc().unix_timestamp_nanos().max(0) as u128;
+            if age.is_zero() || age.as_nanos() > since_epoch {
+                return Err(StreamErr::Backend(RedisErr::InvalidClientConfig(format!(
+                    "Max age {age:?} must be positive and not longer than the time since the Unix epoch"
+                ))));
+            }
+        }
+        self.trim = v;
+        Ok(self)
+    }
+    pub fn trim(&self) -> Option<&StreamTrim> {
+        self.trim.as_ref()
+    }
+
+    /// Trim exactly (`=`) instead of approximately (`~`). Approximate trimming is much more efficient,
+    /// because Redis only removes whole macro nodes, but the stream may be a bit longer than specified.
+    ///
+    /// If unset, defaults to `false`.
+    pub fn set_trim_exact(&mut self, v: bool) -> &mut Self {
+        self.trim_exact = v;
+        self
+    }
+    pub fn trim_exact(&self) -> bool {
+        self.trim_exact
+    }
+
+    /// If set, trimming is done by a background task on this interval, instead of on every `XADD`.
+    /// The task respects consumer groups: it never removes entries that are pending (delivered but not acked)
+    /// in any group, or not yet delivered to any group. A stream is only trimmed after it has been written to by this producer.
+    ///
+    /// If unset, defaults to None.
+    pub fn set_trim_interval(&mut self, v: Option<Duration>) -> &mut Self {
+        self.trim_interval = v;
+        self
+    }
+    pub fn trim_interval(&self) -> Option<&Duration> {
+        self.trim_interval.as_ref()
+    }
+
+    /// Let the background task trim regardless of consumer groups.
+    ///
+    /// If unset, defaults to `false`.
+    pub fn set_force_trim(&mut self, v: bool) -> &mut Self {
+        self.force_trim = v;
+        self
+    }
+    pub fn force_trim(&self) -> bool {
+        self.force_trim
+    }
 }
 
 impl Future for SendFuture {
@@ -188,27 +383,200 @@ pub(crate) async fn create_producer(
     mut options: RedisProducerOptions,
 ) -> RedisResult<RedisProducer> {
     cluster.reconnect_all().await?; // init connections
+    if cluster.options.enable_cluster() {
+        cluster.discover_slots().await?;
+    }
     let (sender, receiver) = unbounded();
+    let transport = cluster.options.transport;
+    if transport != RedisTransport::Streams {
+        if options.sharder.is_some() {
+            return Err(transport.unsupported("Sharding"));
+        }
+        if options.trim.is_some() {
+            return Err(transport.unsupported("Trimming"));
+        }
+        spawn_task(run_producer(cluster, transport, receiver));
+        return Ok(RedisProducer {
+            stream: None,
+            sender,
+        });
+    }
+    let initial_shards = options.sharder.as_ref().and_then(|a| a.initial_shards());
     let mut sharder = options.sharder.take().map(|a| a.init());
+    let (inline_trim, trimmer) = match (options.trim, options.trim_interval) {
+        (Some(trim), Some(interval)) => {
+            let mut trimmer =
+                RedisCluster::new(cluster.cluster.clone(), cluster.options.clone())?.shared();
+            trimmer.reconnect_all().await?;
+            if trimmer.options.enable_cluster() {
+                trimmer.discover_slots().await?;
+            }
+            let (keys, receiver) = unbounded();
+            let trimmer = Trimmer {
+                cluster: trimmer,
+                trim,
+                exact: options.trim_exact,
+                interval,
+                force: options.force_trim,
+            };
+            spawn_task(trimmer.run(receiver));
+            (None, Some(keys))
+        }
+        (trim, _) => (trim, None),
+    };
+    let trim_exact = options.trim_exact;
+    let mut written = HashSet::new();
+    let mut shard_meta = HashMap::new();
+    // messages to streams being resharded, and the signals after them, in order
+    let mut held: Vec<Request> = Vec::new();
 
     // Redis commands are exclusive (`&mut self`), so we need a producer task
     spawn_task(async move {
-        // exit if all senders have been dropped
-        while let Ok(request) = receiver.recv_async().await {
-            let mut requests = vec![request];
+        loop {
+            let request = if held.is_empty() {
+                match receiver.recv_async().await {
+                    Ok(request) => Some(request),
+                    // exit if all senders have been dropped
+                    Err(_) => break,
+                }
+            } else {
+                match timeout(DRAIN_POLL, receiver.recv_async()).await {
+                    Ok(Ok(request)) => Some(request),
+                    Ok(Err(_)) => {
+                        // keep going until the held messages are sent
+                        sleep(DRAIN_POLL).await;
+                        None
+                    }
+                    Err(_) => None,
+                }
+            };
+            // the held messages of streams that have finished resharding go first
+            let mut requests = Vec::new();
+            if let (Some(initial_shards), Some(sharder)) = (initial_shards, sharder.as_mut()) {
+                let mut streams: Vec<StreamKey> = Vec::new();
+                for stream_key in held.iter().flat_map(|r| r.streams()) {
+                    if !streams.contains(&stream_key) {
+                        streams.push(stream_key);
+                    }
+                }
+                let mut draining = HashSet::new();
+                for stream_key in streams {
+                    if check_shard_meta(
+                        &mut cluster,
+                        sharder.as_mut(),
+                        &mut shard_meta,
+                        &stream_key,
+                        initial_shards,
+                    )
+                    .await
+                    {
+                        draining.insert(stream_key);
+                    }
+                }
+                let mut kept: Vec<Request> = Vec::new();
+                for request in held.drain(..) {
+                    let keep = match &request {
+                        Request::Send(r) if r.is_signal() => !kept.is_empty(),
+                        Request::Send(r) => {
+                            draining.contains(&r.stream_key)
+                                || kept.iter().any(|k| k.touches(&r.stream_key))
+                        }
+                        Request::Transaction(txn) => {
+                            !kept.is_empty() || txn.streams().any(|s| draining.contains(s))
+                        }
+                    };
+                    if keep {
+                        kept.push(request);
+                    } else {
+                        requests.push(request);
+                    }
+                }
+                held = kept;
+            }
+            // the released messages have already been checked
+            let mut released = requests.len();
+            requests.extend(request);
             requests.extend(receiver.drain());
             let mut remaining = requests.len();
             let mut requests = requests.into_iter();
             let mut batch: (Vec<(String, StreamKey, ShardId, Receipt)>, Pipeline) =
                 Default::default();
             let mut next_batch = batch.clone();
+            // a transaction waiting for the batch before it
+            let mut deferred: Option<Box<TransactionRequest>> = None;
             while remaining > 0 {
-                for SendRequest {
-                    stream_key,
-                    bytes,
-                    receipt,
-                } in requests.by_ref()
-                {
+                if let Some(txn) = deferred.take() {
+                    run_transaction(
+                        &mut cluster,
+                        sharder.as_mut(),
+                        inline_trim.as_ref().map(|trim| (trim, trim_exact)),
+                        trimmer.as_ref().map(|keys| (keys, &mut written)),
+                        *txn,
+                    )
+                    .await;
+                    remaining -= 1;
+                }
+                for request in requests.by_ref() {
+                    if released > 0 {
+                        released -= 1;
+                    } else if let (Some(initial_shards), Some(sharder)) =
+                        (initial_shards, sharder.as_mut())
+                    {
+                        // hold the messages to a draining stream until it is resharded, so that
+                        // the messages of a key are never split between the old and new shards
+                        let mut hold = match &request {
+                            Request::Send(r) if r.is_signal() => !held.is_empty(),
+                            Request::Send(r) => held.iter().any(|h| h.touches(&r.stream_key)),
+                            Request::Transaction(_) => !held.is_empty(),
+                        };
+                        for stream_key in request.streams() {
+                            hold |= check_shard_meta(
+                                &mut cluster,
+                                sharder.as_mut(),
+                                &mut shard_meta,
+                                &stream_key,
+                                initial_shards,
+                            )
+                            .await;
+                        }
+                        if hold {
+                            if held.len() < MAX_HELD_MESSAGES {
+                                held.push(request);
+                            } else {
+                                request.fail(StreamErr::Backend(RedisErr::TryAgain(format!(
+                                    "{MAX_HELD_MESSAGES} messages are held while resharding"
+                                ))));
+                            }
+                            remaining -= 1;
+                            continue;
+                        }
+                    }
+                    let SendRequest {
+                        stream_key,
+                        key,
+                        id,
+                        bytes,
+                        receipt,
+                    } = match request {
+                        Request::Send(request) => request,
+                        Request::Transaction(txn) => {
+                            if batch.0.is_empty() {
+                                run_transaction(
+                                    &mut cluster,
+                                    sharder.as_mut(),
+                                    inline_trim.as_ref().map(|trim| (trim, trim_exact)),
+                                    trimmer.as_ref().map(|keys| (keys, &mut written)),
+                                    *txn,
+                                )
+                                .await;
+                                remaining -= 1;
+                                continue;
+                            } else {
+                                deferred = Some(txn);
+                                break;
+                            }
+                        }
+                    };
                     if stream_key.name() == SEA_STREAMER_INTERNAL && bytes.is_empty() {
                         // A signalling message
                         next_batch.0.push((
@@ -221,15 +589,30 @@ pub(crate) async fn create_producer(
                     } else {
                         let redis_stream_key;
                         let (redis_key, shard) = if let Some(sharder) = sharder.as_mut() {
-                            let shard = sharder.shard(&stream_key, bytes.as_slice());
+                            let shard = match key.as_deref() {
+                                Some(key) => sharder.shard_by_key(&stream_key, key, &bytes),
+                                None => sharder.shard(&stream_key, bytes.as_slice()),
+                            };
                             redis_stream_key = format!("{name}:{shard}", name = stream_key.name());
                             (redis_stream_key.as_str(), ShardId::new(shard))
                         } else {
                             (stream_key.name(), ZERO)
                         };
+                        if let Some(keys) = trimmer.as_ref() {
+                            if !written.contains(redis_key) {
+                                written.insert(redis_key.to_owned());
+                                keys.send(redis_key.to_owned()).ok();
+                            }
+                        }
                         let mut cmd = command("XADD");
                         cmd.arg(redis_key);
-                        cmd.arg("*");
+                        if let Some(trim) = inline_trim.as_ref() {
+                            write_trim(&mut cmd, trim, trim_exact);
+                        }
+                        match id {
+                            Some((timestamp, seq)) => cmd.arg(format!("{timestamp}-{seq}")),
+                            None => cmd.arg("*"),
+                        };
                         let msg = [(MSG, bytes)];
                         cmd.arg(&msg);
                         let command = (redis_key.to_owned(), stream_key, shard, receipt);
@@ -277,7 +660,7 @@ pub(crate) async fn create_producer(
                                     .zip(batch.0.iter())
                                     .map(|(id, (_, stream_key, shard, _))| {
                                         match string_from_redis_value(id) {
-                                            Ok(id) => match parse_message_id(&id) {
+                                            Ok(id) => match header_of_id(&id) {
                                                 Ok((timestamp, sequence)) => {
                                                     Ok(MessageHeader::new(
                                                         stream_key.clone(),
@@ -319,7 +702,10 @@ pub(crate) async fn create_producer(
                                     // This is an exponential backoff, in seq of [1, 2, 4, 8, 16, 32, 64].
                                     sleep(Duration::from_secs(1 << std::cmp::min(6, asked))).await;
                                     asked += 1;
-                                } else if kind == ErrorKind::IoError {
+                                } else if kind == ErrorKind::IoError || kind == ErrorKind::ReadOnly
+                                {
+                                    // The connection is broken, or the master has been demoted in a failover.
+                                    // Reconnecting behind Sentinel will find the new master, then the batch is retried.
                                     let node = node.to_owned();
                                     cluster.reconnect(&node).ok();
                                 } else {
@@ -356,6 +742,71 @@ pub(crate) async fn create_producer(
     })
 }
 
+/// Execute a transaction and reply with the receipts.
+async fn run_transaction(
+    cluster: &mut RedisCluster,
+    sharder: Option<&mut Box<dyn Sharder>>,
+    trim: Option<(&StreamTrim, bool)>,
+    trimmer: Option<(&Sender<String>, &mut HashSet<String>)>,
+    txn: TransactionRequest,
+) {
+    let sharded = sharder.is_some();
+    let result = txn.execute(cluster, sharder, trim).await;
+    if let (Some((keys, written)), Ok(headers)) = (trimmer, result.as_ref()) {
+        for header in headers {
+            let redis_key = if sharded {
+                format!("{}:{}", header.stream_key().name(), header.shard_id().id())
+            } else {
+                header.stream_key().name().to_owned()
+            };
+            if !written.contains(&redis_key) {
+                keys.send(redis_key.clone()).ok();
+                written.insert(redis_key);
+            }
+        }
+    }
+    txn.reply(result);
+}
+
+/// Check the sharding metadata of a stream if it is due, and return whether the stream is draining.
+async fn check_shard_meta(
+    cluster: &mut RedisCluster,
+    sharder: &mut dyn Sharder,
+    known: &mut HashMap<StreamKey, ShardMetaState>,
+    stream_key: &StreamKey,
+    initial_shards: u64,
+) -> bool {
+    let now = Timestamp::now_utc();
+    if let Some(state) = known.get(stream_key) {
+        let interval = if state.draining {
+            DRAIN_POLL
+        } else {
+            SHARD_REFRESH
+        };
+        if now - interval < state.checked {
+            return state.draining;
+        }
+    }
+    let draining = match init_shard_meta(cluster, stream_key, initial_shards).await {
+        Ok(meta) => {
+            sharder.set_num_shards(stream_key, meta.shards);
+            meta.draining.is_some()
+        }
+        Err(err) => {
+            log::warn!("Failed to read the shard metadata of `{stream_key}`: {err}");
+            known.get(stream_key).map_or(false, |s| s.draining)
+        }
+    };
+    known.insert(
+        stream_key.clone(),
+        ShardMetaState {
+            draining,
+            checked: now,
+        },
+    );
+    draining
+}
+
 fn is_ask(kind: &ErrorKind) -> bool {
     matches!(
         kind,
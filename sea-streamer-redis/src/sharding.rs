@@ -0,0 +1,365 @@
+use redis::cmd as command;
+use std::{collections::HashMap, fmt::Debug, sync::Arc, time::Duration};
+
+use crate::{
+    constants::SHARD_META_SUFFIX, RedisCluster, RedisErr, RedisResult, Sharder, SharderConfig,
+};
+use sea_streamer_types::{fnv1a, StreamErr, StreamKey};
+
+// The `draining` marker comes with a `lease`, the time (in ms, of the Redis server) until which it is valid.
+// It is renewed by `RedisAdmin::reshard` while waiting, so if the admin dies, the marker expires and
+// producers release the messages they hold. An expired marker is removed by the next script that sees it.
+
+/// Initialize the metadata if it does not exist yet, and return it.
+const INIT: &str = r#"
+local t = redis.call('TIME')
+local now = t[1] * 1000 + math.floor(t[2] / 1000)
+redis.call('HSETNX', KEYS[1], 'shards', ARGV[1])
+redis.call('HSETNX', KEYS[1], 'epoch', 0)
+local lease = redis.call('HGET', KEYS[1], 'lease')
+if lease and tonumber(lease) < now then
+    redis.call('HDEL', KEYS[1], 'draining', 'lease')
+    redis.call('HINCRBY', KEYS[1], 'epoch', 1)
+end
+return redis.call('HMGET', KEYS[1], 'shards', 'draining', 'epoch')
+"#;
+
+/// Read the metadata, without the `draining` marker if it has expired.
+const READ: &str = r#"
+local t = redis.call('TIME')
+local now = t[1] * 1000 + math.floor(t[2] / 1000)
+local meta = redis.call('HMGET', KEYS[1], 'shards', 'draining', 'epoch', 'lease')
+if meta[4] and tonumber(meta[4]) < now then
+    meta[2] = false
+end
+return {meta[1], meta[2], meta[3]}
+"#;
+
+/// Start draining the current shards, and return their number. Return 0 if there is nothing to drain.
+const BEGIN: &str = r#"
+local t = redis.call('TIME')
+local now = t[1] * 1000 + math.floor(t[2] / 1000)
+local shards = redis.call('HGET', KEYS[1], 'shards')
+if not shards then
+    redis.call('HSET', KEYS[1], 'shards', ARGV[1], 'epoch', 1)
+    return 0
+end
+if tonumber(shards) == tonumber(ARGV[1]) then
+    return 0
+end
+local lease = redis.call('HGET', KEYS[1], 'lease')
+if redis.call('HEXISTS', KEYS[1], 'draining') == 1 and (not lease or tonumber(lease) >= now) then
+    return redis.error_reply('BUSY resharding is already in progress')
+end
+redis.call('HSET', KEYS[1], 'draining', ARGV[1], 'lease', now + tonumber(ARGV[2]))
+redis.call('HINCRBY', KEYS[1], 'epoch', 1)
+return tonumber(shards)
+"#;
+
+/// Extend the lease, if we are still the one draining.
+const RENEW: &str = r#"
+local t = redis.call('TIME')
+local now = t[1] * 1000 + math.floor(t[2] / 1000)
+local lease = redis.call('HGET', KEYS[1], 'lease')
+if redis.call('HGET', KEYS[1], 'draining') == ARGV[1] and lease and tonumber(lease) >= now then
+    redis.call('HSET', KEYS[1], 'lease', now + tonumber(ARGV[2]))
+    return 1
+end
+return 0
+"#;
+
+/// Switch to the new number of shards, if we are still the one draining.
+const SWITCH: &str = r#"
+local t = redis.call('TIME')
+local now = t[1] * 1000 + math.floor(t[2] / 1000)
+local lease = redis.call('HGET', KEYS[1], 'lease')
+if redis.call('HGET', KEYS[1], 'draining') == ARGV[1] and lease and tonumber(lease) >= now then
+    redis.call('HSET', KEYS[1], 'shards', ARGV[1])
+    redis.call('HDEL', KEYS[1], 'draining', 'lease')
+    redis.call('HINCRBY', KEYS[1], 'epoch', 1)
+    return 1
+end
+return 0
+"#;
+
+/// Stop draining to the given number of shards, keeping the current number of shards.
+const ABORT: &str = r#"
+if redis.call('HGET', KEYS[1], 'draining') == ARGV[1] then
+    redis.call('HDEL', KEYS[1], 'draining', 'lease')
+    redis.call('HINCRBY', KEYS[1], 'epoch', 1)
+end
+return 1
+"#;
+
+/// A function extracting the shard key from the payload of a message.
+pub type KeyExtractor = Arc<dyn Fn(&[u8]) -> &[u8] + Send + Sync>;
+
+#[derive(Clone)]
+/// Which part of a message decides its shard.
+pub enum ShardKey {
+    /// The whole payload.
+    Payload,
+    /// The payload up to the first occurrence of the delimiter, e.g. `user-1` in `user-1|{..}`.
+    /// If the delimiter is absent, the whole payload.
+    Prefix(u8),
+    /// A custom function, e.g. to pick a field of a structured payload.
+    Extractor(KeyExtractor),
+}
+
+impl Debug for ShardKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Payload => write!(f, "Payload"),
+            Self::Prefix(delimiter) => f.debug_tuple("Prefix").field(delimiter).finish(),
+            Self::Extractor(_) => write!(f, "Extractor"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Shard streams by the consistent hash of a key, so that messages of the same key always go to the same shard,
+/// and are thus consumed in order. The key is either given with [`crate::RedisProducer::send_with_key`],
+/// or extracted from the payload by [`ShardKey`].
+///
+/// The number of shards of each stream is stored in Redis (in the hash `STREAM_KEY.shards`), so that all producers
+/// and consumers agree. It is initialized to `num_shards` by the first producer, and can be changed afterwards with
+/// [`crate::RedisAdmin::reshard`]. Thanks to jump consistent hashing, only about `1 / num_shards` of the keys
+/// are moved to a different shard when a shard is added.
+pub struct ConsistentHashSharder {
+    num_shards: u64,
+    key: ShardKey,
+    /// The number of shards of each stream, as recorded in Redis
+    streams: HashMap<StreamKey, u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The sharding metadata of a stream, see [`ConsistentHashSharder`].
+pub struct ShardMeta {
+    /// The current number of shards
+    pub shards: u64,
+    /// The number of shards being switched to, if resharding is in progress
+    pub draining: Option<u64>,
+    /// Incremented on every change
+    pub epoch: u64,
+}
+
+impl ConsistentHashSharder {
+    /// Shard by the whole payload, unless a key is given on send.
+    ///
+    /// Returns [`RedisErr::InvalidClientConfig`] if `num_shards` is 0.
+    pub fn new(num_shards: u64) -> RedisResult<Self> {
+        Self::with_key(num_shards, ShardKey::Payload)
+    }
+
+    /// Shard by the part of the payload selected by `key`, unless a key is given on send.
+    pub fn with_key(num_shards: u64, key: ShardKey) -> RedisResult<Self> {
+        if num_shards == 0 {
+            return Err(StreamErr::Backend(RedisErr::InvalidClientConfig(
+                "There must be at least 1 shard".to_owned(),
+            )));
+        }
+        Ok(Self {
+            num_shards,
+            key,
+            streams: Default::default(),
+        })
+    }
+
+    /// Shard by the part of the payload returned by `extractor`.
+    pub fn with_extractor<F>(num_shards: u64, extractor: F) -> RedisResult<Self>
+    where
+        F: Fn(&[u8]) -> &[u8] + Send + Sync + 'static,
+    {
+        Self::with_key(num_shards, ShardKey::Extractor(Arc::new(extractor)))
+    }
+
+    fn num_shards_of(&self, stream_key: &StreamKey) -> u64 {
+        *self.streams.get(stream_key).unwrap_or(&self.num_shards)
+    }
+}
+
+impl SharderConfig for ConsistentHashSharder {
+    fn init(&self) -> Box<dyn Sharder> {
+        Box::new(self.clone())
+    }
+
+    fn initial_shards(&self) -> Option<u64> {
+        Some(self.num_shards)
+    }
+}
+
+impl Sharder for ConsistentHashSharder {
+    fn shard(&mut self, stream_key: &StreamKey, bytes: &[u8]) -> u64 {
+        let key = match &self.key {
+            ShardKey::Payload => bytes,
+            ShardKey::Prefix(delimiter) => match bytes.iter().position(|b| b == delimiter) {
+                Some(pos) => &bytes[..pos],
+                None => bytes,
+            },
+            ShardKey::Extractor(extractor) => extractor(bytes),
+        };
+        jump_consistent_hash(fnv1a(key), self.num_shards_of(stream_key))
+    }
+
+    fn shard_by_key(&mut self, stream_key: &StreamKey, key: &[u8], _: &[u8]) -> u64 {
+        jump_consistent_hash(fnv1a(key), self.num_shards_of(stream_key))
+    }
+
+    fn set_num_shards(&mut self, stream_key: &StreamKey, num_shards: u64) {
+        self.streams.insert(stream_key.clone(), num_shards);
+    }
+}
+
+/// "A Fast, Minimal Memory, Consistent Hash Algorithm" by Lamping and Veach.
+fn jump_consistent_hash(mut key: u64, num_buckets: u64) -> u64 {
+    let (mut b, mut j): (i64, i64) = (-1, 0);
+    while j < num_buckets as i64 {
+        b = j;
+        key = key.wrapping_mul(2862933555777941757).wrapping_add(1);
+        j = ((b + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+    }
+    b as u64
+}
+
+/// The Redis key of the sharding metadata of a stream.
+pub(crate) fn shard_meta_key(stream: &StreamKey) -> String {
+    format!("{}{}", stream.name(), SHARD_META_SUFFIX)
+}
+
+/// Read the sharding metadata of a stream, if there is any.
+pub(crate) async fn read_shard_meta(
+    cluster: &mut RedisCluster,
+    stream: &StreamKey,
+) -> RedisResult<Option<ShardMeta>> {
+    let (shards, draining, epoch): (Option<u64>, Option<u64>, Option<u64>) =
+        eval(cluster, stream, READ, &[]).await?;
+    Ok(shards.map(|shards| ShardMeta {
+        shards,
+        draining,
+        epoch: epoch.unwrap_or_default(),
+    }))
+}
+
+async fn eval<T: redis::FromRedisValue>(
+    cluster: &mut RedisCluster,
+    stream: &StreamKey,
+    script: &str,
+    args: &[u64],
+) -> RedisResult<T> {
+    cluster
+        .query(&shard_meta_key(stream), |key| {
+            let mut cmd = command("EVAL");
+            cmd.arg(script).arg(1).arg(key).arg(args);
+            cmd
+        })
+        .await
+}
+
+/// Read the sharding metadata of a stream, initializing it with `num_shards` if there is none.
+pub(crate) async fn init_shard_meta(
+    cluster: &mut RedisCluster,
+    stream: &StreamKey,
+    num_shards: u64,
+) -> RedisResult<ShardMeta> {
+    let (shards, draining, epoch): (u64, Option<u64>, u64) =
+        eval(cluster, stream, INIT, &[num_shards]).await?;
+    Ok(ShardMeta {
+        shards,
+        draining,
+        epoch,
+    })
+}
+
+/// Start resharding to `num_shards`, with the draining marker valid for `lease`.
+/// Return the number of shards to be drained, or None if it is already done.
+pub(crate) async fn begin_reshard(
+    cluster: &mut RedisCluster,
+    stream: &StreamKey,
+    num_shards: u64,
+    lease: Duration,
+) -> RedisResult<Option<u64>> {
+    let draining: u64 = eval(cluster, stream, BEGIN, &[num_shards, millis(lease)]).await?;
+    Ok(if draining == 0 { None } else { Some(draining) })
+}
+
+/// Extend the lease of resharding to `num_shards`. Return false if it has expired or been aborted in the meantime.
+pub(crate) async fn renew_reshard(
+    cluster: &mut RedisCluster,
+    stream: &StreamKey,
+    num_shards: u64,
+    lease: Duration,
+) -> RedisResult<bool> {
+    eval(cluster, stream, RENEW, &[num_shards, millis(lease)]).await
+}
+
+/// Finish resharding to `num_shards`. Return false if it has expired or been aborted in the meantime.
+pub(crate) async fn switch_shards(
+    cluster: &mut RedisCluster,
+    stream: &StreamKey,
+    num_shards: u64,
+) -> RedisResult<bool> {
+    eval(cluster, stream, SWITCH, &[num_shards]).await
+}
+
+/// Abort resharding to `num_shards`, keeping the current number of shards.
+pub(crate) async fn abort_reshard(
+    cluster: &mut RedisCluster,
+    stream: &StreamKey,
+    num_shards: u64,
+) -> RedisResult<()> {
+    let _: u64 = eval(cluster, stream, ABORT, &[num_shards]).await?;
+    Ok(())
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_millis().try_into().unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+#[test]
+fn test_consistent_hash() {
+    // reference values of the jump consistent hash
+    assert_eq!(jump_consistent_hash(1, 1), 0);
+    assert_eq!(jump_consistent_hash(42, 57), 43);
+    assert_eq!(jump_consistent_hash(0xDEAD10CC, 666), 361);
+    assert_eq!(jump_consistent_hash(256, 1024), 520);
+    // so the shards of keys are stable across releases
+    assert_eq!(fnv1a(b"user-1"), 0xf84ba2aa754a4bd4);
+    let shards: Vec<u64> = [2, 10, 100, 1000]
+        .into_iter()
+        .map(|n| jump_consistent_hash(fnv1a(b"user-1"), n))
+        .collect();
+    assert_eq!(shards, [1, 9, 47, 484]);
+    let before: Vec<u64> = (0..1000)
+        .map(|i| jump_consistent_hash(fnv1a(format!("user-{i}").as_bytes()), 10))
+        .collect();
+    assert!(before.iter().all(|s| *s < 10));
+    for s in 0..10 {
+        // roughly balanced
+        assert!(before.iter().filter(|b| **b == s).count() > 50);
+    }
+    let after: Vec<u64> = (0..1000)
+        .map(|i| jump_consistent_hash(fnv1a(format!("user-{i}").as_bytes()), 11))
+        .collect();
+    // keys either stay, or move to the new shard
+    let moved = before
+        .iter()
+        .zip(after.iter())
+        .filter(|(b, a)| {
+            assert!(b == a || **a == 10);
+            b != a
+        })
+        .count();
+    assert!(moved > 50 && moved < 150);
+
+    let stream = StreamKey::new("stream").unwrap();
+    assert!(ConsistentHashSharder::new(0).is_err());
+    let mut sharder = ConsistentHashSharder::with_key(4, ShardKey::Prefix(b'|'))
+        .unwrap()
+        .init();
+    let shard = sharder.shard(&stream, b"user-1|hello");
+    assert_eq!(sharder.shard(&stream, b"user-1|world"), shard);
+    assert_eq!(sharder.shard_by_key(&stream, b"user-1", b"anything"), shard);
+    sharder.set_num_shards(&stream, 1);
+    assert_eq!(sharder.shard(&stream, b"user-1|hello"), 0);
+}
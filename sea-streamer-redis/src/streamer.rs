@@ -1,8 +1,10 @@
 use std::{sync::Arc, time::Duration};
 
 use crate::{
-    create_consumer, create_producer, RedisCluster, RedisConsumer, RedisConsumerOptions, RedisErr,
-    RedisProducer, RedisProducerOptions, RedisResult, REDIS_PORT,
+    create_consumer, create_producer, read_dead_letters, replay_dead_letter, ConnectionPool,
+    DeadLetter, MessageId, ReconnectBackoff, RedisAdmin, RedisCluster, RedisConsumer,
+    RedisConsumerOptions, RedisErr, RedisProducer, RedisProducerOptions, RedisResult,
+    RedisTransport, SentinelConfig, TlsOptions, DEFAULT_HEALTH_CHECK_INTERVAL, REDIS_PORT,
 };
 use sea_streamer_types::{
     export::async_trait, ConnectOptions, StreamErr, StreamKey, StreamUrlErr, Streamer, StreamerUri,
@@ -21,9 +23,21 @@ pub struct RedisConnectOptions {
     db: u32,
     username: Option<String>,
     password: Option<String>,
+    sentinel_username: Option<String>,
+    sentinel_password: Option<String>,
     timeout: Option<Duration>,
     enable_cluster: bool,
     disable_hostname_verification: bool,
+    tls: TlsOptions,
+    shared_connections: bool,
+    reconnect_backoff: ReconnectBackoff,
+    health_check_interval: Option<Duration>,
+    /// Created on connect if connections are shared
+    pub(crate) pool: Option<ConnectionPool>,
+    /// Parsed from the streamer URI on connect
+    pub(crate) sentinel: Option<Arc<SentinelConfig>>,
+    /// Parsed from the streamer URI on connect
+    pub(crate) transport: RedisTransport,
 }
 
 #[async_trait]
@@ -35,23 +49,39 @@ impl Streamer for RedisStreamer {
     type ConsumerOptions = RedisConsumerOptions;
     type ProducerOptions = RedisProducerOptions;
 
-    async fn connect(uri: StreamerUri, options: Self::ConnectOptions) -> RedisResult<Self> {
+    async fn connect(uri: StreamerUri, mut options: Self::ConnectOptions) -> RedisResult<Self> {
         if uri.protocol().is_none() {
             return Err(StreamErr::StreamUrlErr(StreamUrlErr::ProtocolRequired));
         }
-        let uri = StreamerUri::many(uri.into_nodes().map(|u| {
-            // normalize uri
-            format!(
-                "{}://{}:{}",
-                u.scheme(),
-                u.host().expect("Should have host"),
-                u.port().unwrap_or(REDIS_PORT)
-            )
-            .parse()
-            .expect("Must not fail")
-        }));
+        let uri = if let Some(sentinel) = SentinelConfig::from_uri(&uri, &options)? {
+            if options.enable_cluster() {
+                return Err(StreamErr::Connect(
+                    "Redis Sentinel cannot be used with Redis Cluster".to_owned(),
+                ));
+            }
+            // a single node standing for the master
+            let uri = StreamerUri::one(sentinel.node_id()?);
+            options.sentinel = Some(Arc::new(sentinel));
+            uri
+        } else {
+            options.transport = RedisTransport::from_protocol(uri.protocol().unwrap()).0;
+            StreamerUri::many(uri.into_nodes().map(|u| {
+                // normalize uri, leaving the transport out
+                format!(
+                    "{}://{}:{}",
+                    RedisTransport::from_protocol(u.scheme()).1,
+                    u.host().expect("Should have host"),
+                    u.port().unwrap_or(REDIS_PORT)
+                )
+                .parse()
+                .expect("Must not fail")
+            }))
+        };
+        if options.shared_connections() {
+            options.pool = Some(ConnectionPool::new(Arc::new(options.clone())));
+        }
         let options = Arc::new(options);
-        let mut cluster = RedisCluster::new(uri.clone(), options.clone())?;
+        let mut cluster = RedisCluster::new(uri.clone(), options.clone())?.shared();
         cluster.reconnect_all().await?;
         Ok(RedisStreamer { uri, options })
     }
@@ -66,7 +96,7 @@ impl Streamer for RedisStreamer {
         &self,
         options: Self::ProducerOptions,
     ) -> RedisResult<Self::Producer> {
-        let cluster = RedisCluster::new(self.uri.clone(), self.options.clone())?;
+        let cluster = RedisCluster::new(self.uri.clone(), self.options.clone())?.shared();
         create_producer(cluster, options).await
     }
 
@@ -80,6 +110,47 @@ impl Streamer for RedisStreamer {
     }
 }
 
+impl RedisStreamer {
+    /// The transport selected by the protocol of the streamer URI, e.g. `redis+list://`.
+    pub fn transport(&self) -> RedisTransport {
+        self.options.transport
+    }
+
+    /// Create a handle to inspect and manage consumer groups.
+    pub async fn admin(&self) -> RedisResult<RedisAdmin> {
+        Ok(RedisAdmin::new(self.connect_cluster().await?))
+    }
+
+    /// Read up to `count` entries from a dead-letter stream, oldest first, starting after `after`.
+    /// See [`RedisConsumerOptions::set_max_deliveries`].
+    pub async fn read_dead_letters(
+        &self,
+        stream: &StreamKey,
+        after: Option<MessageId>,
+        count: usize,
+    ) -> RedisResult<Vec<DeadLetter>> {
+        let mut cluster = self.connect_cluster().await?;
+        read_dead_letters(&mut cluster, stream, after, count).await
+    }
+
+    /// Send a dead letter back to the stream and shard it came from, and remove it from the dead-letter stream.
+    /// The message is appended as a new entry, so it will be delivered to the consumer groups again.
+    /// Returns the new message id.
+    pub async fn replay_dead_letter(&self, letter: &DeadLetter) -> RedisResult<MessageId> {
+        let mut cluster = self.connect_cluster().await?;
+        replay_dead_letter(&mut cluster, letter).await
+    }
+
+    async fn connect_cluster(&self) -> RedisResult<RedisCluster> {
+        let mut cluster = RedisCluster::new(self.uri.clone(), self.options.clone())?.shared();
+        cluster.reconnect_all().await?;
+        if cluster.options.enable_cluster() {
+            cluster.discover_slots().await?;
+        }
+        Ok(cluster)
+    }
+}
+
 impl ConnectOptions for RedisConnectOptions {
     type Error = RedisErr;
 
@@ -120,10 +191,32 @@ impl RedisConnectOptions {
         self
     }
 
+    pub fn sentinel_username(&self) -> Option<&str> {
+        self.sentinel_username.as_deref()
+    }
+    /// Only relevant if connecting to `redis+sentinel://`. The username of the Sentinels, which is usually
+    /// not the same as the master's. The user info of the URI takes precedence, if any.
+    pub fn set_sentinel_username(&mut self, username: Option<String>) -> &mut Self {
+        self.sentinel_username = username;
+        self
+    }
+
+    pub fn sentinel_password(&self) -> Option<&str> {
+        self.sentinel_password.as_deref()
+    }
+    /// Only relevant if connecting to `redis+sentinel://`. The password of the Sentinels, which is usually
+    /// not the same as the master's. The user info of the URI takes precedence, if any.
+    pub fn set_sentinel_password(&mut self, password: Option<String>) -> &mut Self {
+        self.sentinel_password = password;
+        self
+    }
+
     pub fn enable_cluster(&self) -> bool {
         self.enable_cluster
     }
     /// Enable support for Redis Cluster.
+    /// The slot assignment is queried with `CLUSTER SLOTS` on connect, and connections are opened
+    /// to all master nodes. Any node of the cluster can be given in the streamer URI.
     pub fn set_enable_cluster(&mut self, bool: bool) -> &mut Self {
         self.enable_cluster = bool;
         self
@@ -140,4 +233,49 @@ impl RedisConnectOptions {
         self.disable_hostname_verification = bool;
         self
     }
+
+    pub fn tls(&self) -> &TlsOptions {
+        &self.tls
+    }
+    /// Only relevant if connecting to `rediss://` (or `rediss+sentinel://`).
+    /// Set the CA certificates, the client certificate for mutual TLS, and the TLS implementation.
+    pub fn set_tls(&mut self, tls: TlsOptions) -> &mut Self {
+        self.tls = tls;
+        self
+    }
+
+    pub fn shared_connections(&self) -> bool {
+        self.shared_connections
+    }
+    /// Share one multiplexed connection per node among the producers, admin handles and the background tasks
+    /// of consumers (shard discovery and dead letters) created from the same streamer, instead of opening
+    /// connections for each of them. Consumers still read with a blocking `XREAD` on a dedicated connection each.
+    ///
+    /// Shared connections are checked with `PING` every [`RedisConnectOptions::health_check_interval`],
+    /// and replaced if broken. Defaults to false.
+    pub fn set_shared_connections(&mut self, bool: bool) -> &mut Self {
+        self.shared_connections = bool;
+        self
+    }
+
+    pub fn reconnect_backoff(&self) -> &ReconnectBackoff {
+        &self.reconnect_backoff
+    }
+    /// How long to wait before reconnecting after a connection is broken. The delay grows exponentially on
+    /// every failed attempt. Defaults to 1 second, doubling up to 64 seconds, giving up after 7 attempts.
+    pub fn set_reconnect_backoff(&mut self, backoff: ReconnectBackoff) -> &mut Self {
+        self.reconnect_backoff = backoff;
+        self
+    }
+
+    /// Defaults to [`DEFAULT_HEALTH_CHECK_INTERVAL`].
+    pub fn health_check_interval(&self) -> Duration {
+        self.health_check_interval
+            .unwrap_or(DEFAULT_HEALTH_CHECK_INTERVAL)
+    }
+    /// Only relevant if connections are shared. How often to check the shared connections.
+    pub fn set_health_check_interval(&mut self, v: Duration) -> &mut Self {
+        self.health_check_interval = Some(v);
+        self
+    }
 }
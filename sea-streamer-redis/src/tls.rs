@@ -0,0 +1,339 @@
+use std::{fmt::Debug, pin::Pin};
+
+use crate::{RedisErr, RedisResult};
+use redis::aio::AsyncStream;
+use sea_streamer_types::StreamErr;
+
+/// A TLS stream, boxed in the same way as [`redis::aio::Connection`] boxes its streams.
+pub(crate) type TlsStream = Pin<Box<dyn AsyncStream + Send + Sync>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The TLS implementation used for `rediss://` connections.
+pub enum TlsBackend {
+    /// The platform's TLS library, i.e. OpenSSL, Secure Transport or SChannel.
+    /// Requires the feature `runtime-tokio-native-tls` or `runtime-async-std-native-tls`.
+    NativeTls,
+    /// Requires the feature `runtime-tokio-rustls` or `runtime-async-std-rustls`.
+    Rustls,
+}
+
+#[derive(Default, Clone)]
+/// TLS options for `rediss://` connections. See [`crate::RedisConnectOptions::set_tls`].
+///
+/// Certificates and keys are in PEM format. They apply to all nodes of a cluster, and to the Sentinels.
+pub struct TlsOptions {
+    backend: Option<TlsBackend>,
+    ca_certificates: Option<Vec<u8>>,
+    client_identity: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Debug for TlsOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TlsOptions")
+            .field("backend", &self.backend())
+            .field("ca_certificates", &self.ca_certificates.is_some())
+            .field("client_identity", &self.client_identity.is_some())
+            .finish()
+    }
+}
+
+impl TlsOptions {
+    pub fn backend(&self) -> TlsBackend {
+        match self.backend {
+            Some(backend) => backend,
+            None if cfg!(feature = "native-tls") => TlsBackend::NativeTls,
+            None if cfg!(feature = "rustls") => TlsBackend::Rustls,
+            None => TlsBackend::NativeTls,
+        }
+    }
+    /// If unset, defaults to native-tls if enabled, otherwise rustls.
+    pub fn set_backend(&mut self, backend: TlsBackend) -> &mut Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    pub fn ca_certificates(&self) -> Option<&[u8]> {
+        self.ca_certificates.as_deref()
+    }
+    /// A bundle of CA certificates to verify the server against, in place of the system's root certificates.
+    ///
+    /// If unset, the system's root certificates are used.
+    pub fn set_ca_certificates(&mut self, pem: Option<Vec<u8>>) -> &mut Self {
+        self.ca_certificates = pem;
+        self
+    }
+
+    /// Returns the certificate chain and the private key.
+    pub fn client_identity(&self) -> Option<(&[u8], &[u8])> {
+        self.client_identity
+            .as_ref()
+            .map(|(cert, key)| (cert.as_slice(), key.as_slice()))
+    }
+    /// The certificate chain and private key presented to the server, for mutual TLS
+    /// (i.e. `tls-auth-clients yes` on the server). The key has to be PKCS#8 with native-tls;
+    /// rustls also accepts PKCS#1 (RSA) and SEC1 (EC) keys.
+    ///
+    /// If unset, no client certificate is presented.
+    pub fn set_client_identity(&mut self, identity: Option<(Vec<u8>, Vec<u8>)>) -> &mut Self {
+        self.client_identity = identity;
+        self
+    }
+}
+
+/// Open a TCP connection and perform the TLS handshake.
+/// If `insecure`, the server certificate and hostname are not verified.
+#[cfg_attr(
+    not(any(feature = "native-tls", feature = "rustls")),
+    allow(unused_variables)
+)]
+pub(crate) async fn connect_tls(
+    host: &str,
+    port: u16,
+    insecure: bool,
+    options: &TlsOptions,
+) -> RedisResult<TlsStream> {
+    match options.backend() {
+        #[cfg(feature = "native-tls")]
+        TlsBackend::NativeTls => native::connect(host, port, insecure, options).await,
+        #[cfg(feature = "rustls")]
+        TlsBackend::Rustls => rustls::connect(host, port, insecure, options).await,
+        #[allow(unreachable_patterns)]
+        backend => Err(StreamErr::Backend(RedisErr::InvalidClientConfig(format!(
+            "{backend:?} is not enabled, please enable one of the TLS features of sea-streamer-redis"
+        )))),
+    }
+}
+
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+fn config_err<E: std::fmt::Display>(err: E) -> StreamErr<RedisErr> {
+    StreamErr::Backend(RedisErr::InvalidClientConfig(format!("TLS: {err}")))
+}
+
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+fn connect_err<E: std::fmt::Display>(err: E) -> StreamErr<RedisErr> {
+    StreamErr::Connect(format!("TLS: {err}"))
+}
+
+#[cfg(all(
+    feature = "runtime-tokio",
+    any(feature = "native-tls", feature = "rustls")
+))]
+async fn connect_tcp(host: &str, port: u16) -> RedisResult<tokio::net::TcpStream> {
+    tokio::net::TcpStream::connect((host, port))
+        .await
+        .map_err(|e| StreamErr::Connect(e.to_string()))
+}
+
+#[cfg(all(
+    feature = "runtime-async-std",
+    any(feature = "native-tls", feature = "rustls")
+))]
+async fn connect_tcp(host: &str, port: u16) -> RedisResult<async_std::net::TcpStream> {
+    async_std::net::TcpStream::connect((host, port))
+        .await
+        .map_err(|e| StreamErr::Connect(e.to_string()))
+}
+
+#[cfg(feature = "native-tls")]
+mod native {
+    use super::*;
+    use native_tls::{Certificate, Identity, TlsConnector};
+
+    pub(super) async fn connect(
+        host: &str,
+        port: u16,
+        insecure: bool,
+        options: &TlsOptions,
+    ) -> RedisResult<TlsStream> {
+        let mut builder = TlsConnector::builder();
+        if let Some(pem) = options.ca_certificates() {
+            builder.disable_built_in_roots(true);
+            for cert in Certificate::stack_from_pem(pem).map_err(config_err)? {
+                builder.add_root_certificate(cert);
+            }
+        }
+        if let Some((cert, key)) = options.client_identity() {
+            builder.identity(Identity::from_pkcs8(cert, key).map_err(config_err)?);
+        }
+        if insecure {
+            builder
+                .danger_accept_invalid_certs(true)
+                .danger_accept_invalid_hostnames(true);
+        }
+        let tcp = connect_tcp(host, port).await?;
+
+        #[cfg(feature = "runtime-tokio")]
+        {
+            let connector =
+                tokio_native_tls::TlsConnector::from(builder.build().map_err(config_err)?);
+            let stream = connector.connect(host, tcp).await.map_err(connect_err)?;
+            Ok(Box::pin(stream))
+        }
+        #[cfg(feature = "runtime-async-std")]
+        {
+            let connector = async_native_tls::TlsConnector::from(builder);
+            let stream = connector.connect(host, tcp).await.map_err(connect_err)?;
+            Ok(Box::pin(compat::Compat(stream)))
+        }
+    }
+}
+
+#[cfg(feature = "rustls")]
+mod rustls {
+    use super::*;
+    use ::rustls::{
+        client::{ServerCertVerified, ServerCertVerifier},
+        Certificate, ClientConfig, PrivateKey, RootCertStore, ServerName,
+    };
+    use rustls_pemfile::Item;
+    use std::{convert::TryFrom, sync::Arc, time::SystemTime};
+
+    pub(super) async fn connect(
+        host: &str,
+        port: u16,
+        insecure: bool,
+        options: &TlsOptions,
+    ) -> RedisResult<TlsStream> {
+        let config = Arc::new(config(insecure, options)?);
+        let domain = ServerName::try_from(host).map_err(config_err)?;
+        let tcp = connect_tcp(host, port).await?;
+
+        #[cfg(feature = "runtime-tokio")]
+        {
+            let connector = tokio_rustls::TlsConnector::from(config);
+            let stream = connector.connect(domain, tcp).await.map_err(connect_err)?;
+            Ok(Box::pin(stream))
+        }
+        #[cfg(feature = "runtime-async-std")]
+        {
+            let connector = futures_rustls::TlsConnector::from(config);
+            let stream = connector.connect(domain, tcp).await.map_err(connect_err)?;
+            Ok(Box::pin(compat::Compat(stream)))
+        }
+    }
+
+    fn config(insecure: bool, options: &TlsOptions) -> RedisResult<ClientConfig> {
+        let mut roots = RootCertStore::empty();
+        match options.ca_certificates() {
+            Some(pem) => {
+                for cert in rustls_pemfile::certs(&mut &pem[..]).map_err(config_err)? {
+                    roots.add(&Certificate(cert)).map_err(config_err)?;
+                }
+            }
+            None => {
+                let certs = rustls_native_certs::load_native_certs().map_err(config_err)?;
+                // a broken certificate in the system store should not prevent us from connecting
+                let (_, ignored) = roots
+                    .add_parsable_certificates(&certs.into_iter().map(|c| c.0).collect::<Vec<_>>());
+                if ignored > 0 {
+                    log::debug!("Ignored {ignored} root certificates of the system");
+                }
+            }
+        }
+
+        let builder = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots);
+        let mut config = match options.client_identity() {
+            Some((cert, key)) => {
+                let chain = rustls_pemfile::certs(&mut &cert[..])
+                    .map_err(config_err)?
+                    .into_iter()
+                    .map(Certificate)
+                    .collect();
+                builder
+                    .with_client_auth_cert(chain, private_key(key)?)
+                    .map_err(config_err)?
+            }
+            None => builder.with_no_client_auth(),
+        };
+        if insecure {
+            config
+                .dangerous()
+                .set_certificate_verifier(Arc::new(NoVerification));
+        }
+        Ok(config)
+    }
+
+    fn private_key(mut pem: &[u8]) -> RedisResult<PrivateKey> {
+        while let Some(item) = rustls_pemfile::read_one(&mut pem).map_err(config_err)? {
+            match item {
+                Item::PKCS8Key(key) | Item::RSAKey(key) | Item::ECKey(key) => {
+                    return Ok(PrivateKey(key))
+                }
+                _ => (),
+            }
+        }
+        Err(config_err("no private key found"))
+    }
+
+    /// Accepts any server certificate, see [`crate::RedisConnectOptions::set_disable_hostname_verification`].
+    struct NoVerification;
+
+    impl ServerCertVerifier for NoVerification {
+        fn verify_server_cert(
+            &self,
+            _: &Certificate,
+            _: &[Certificate],
+            _: &ServerName,
+            _: &mut dyn Iterator<Item = &[u8]>,
+            _: &[u8],
+            _: SystemTime,
+        ) -> Result<ServerCertVerified, ::rustls::Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+    }
+}
+
+#[cfg(all(
+    feature = "runtime-async-std",
+    any(feature = "native-tls", feature = "rustls")
+))]
+mod compat {
+    use sea_streamer_types::export::futures::{AsyncRead, AsyncWrite};
+    use std::{
+        io,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+    use tokio::io::ReadBuf;
+
+    /// Adapts a `futures::io` stream to the `tokio::io` traits, which [`redis::aio::Connection`] is based on.
+    pub(super) struct Compat<S>(pub(super) S);
+
+    impl<S: AsyncRead + Unpin> tokio::io::AsyncRead for Compat<S> {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            let unfilled = buf.initialize_unfilled();
+            match Pin::new(&mut self.get_mut().0).poll_read(cx, unfilled) {
+                Poll::Ready(Ok(n)) => {
+                    buf.advance(n);
+                    Poll::Ready(Ok(()))
+                }
+                Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+                Poll::Pending => Poll::Pending,
+            }
+        }
+    }
+
+    impl<S: AsyncWrite + Unpin> tokio::io::AsyncWrite for Compat<S> {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            Pin::new(&mut self.get_mut().0).poll_write(cx, buf)
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.get_mut().0).poll_flush(cx)
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.get_mut().0).poll_close(cx)
+        }
+    }
+}
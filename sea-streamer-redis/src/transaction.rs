@@ -0,0 +1,283 @@
+use flume::{bounded, Sender};
+use redis::{cmd as command, ErrorKind, Value};
+use std::time::Duration;
+
+use crate::{
+    header_of_id, key_slot, map_err, string_from_redis_value, write_trim, MessageId, RedisCluster,
+    RedisConsumer, RedisErr, RedisResult, Request, Sharder, StreamTrim, MSG, ZERO,
+};
+use sea_streamer_runtime::sleep;
+use sea_streamer_types::{
+    Buffer, MessageHeader, ShardId, SharedMessage, StreamErr, StreamKey, StreamUrlErr,
+};
+
+const MAX_RETRY: usize = 100;
+
+#[derive(Debug)]
+/// A group of messages to be sent, and messages to be acked, in one `MULTI` / `EXEC` transaction.
+/// Obtained by [`crate::RedisProducer::transaction`].
+///
+/// A stream processor that `XADD`s its output and then `XACK`s its input will produce duplicates,
+/// if it crashes in between. By sending the output and acking the input in the same transaction,
+/// either both or neither happen, so a pipeline within a single Redis is effectively-once:
+///
+/// ```ignore
+/// let input = consumer.next().await?;
+/// let mut txn = producer.transaction();
+/// txn.send(process(&input))?;
+/// txn.ack(&consumer, &input)?;
+/// txn.commit().await?;
+/// ```
+///
+/// The input consumer must be in a consumer group, and ack manually, i.e. use `AutoCommit::Rolling` or
+/// `AutoCommit::Disabled`. With `Delayed` it would ack by itself, and with `Immediate` messages are read
+/// with `NOACK`, leaving nothing to ack. Otherwise, `ack` fails with `RedisErr::InvalidClientConfig`.
+///
+/// In Redis Cluster, all keys of a transaction have to be in the same hash slot. Use the same hash tag
+/// in the input and output stream keys, e.g. `{orders}` and `{orders}-processed`, so that all their shards
+/// are in the same slot. Otherwise, `commit` fails with `RedisErr::CrossSlot`.
+pub struct RedisTransaction {
+    stream: Option<StreamKey>,
+    sender: Sender<Request>,
+    outputs: Vec<TransactionOutput>,
+    acks: Vec<TransactionAck>,
+}
+
+pub(crate) struct TransactionRequest {
+    outputs: Vec<TransactionOutput>,
+    acks: Vec<TransactionAck>,
+    receipt: Sender<RedisResult<Vec<MessageHeader>>>,
+}
+
+#[derive(Debug)]
+struct TransactionOutput {
+    stream_key: StreamKey,
+    key: Option<Vec<u8>>,
+    bytes: Vec<u8>,
+}
+
+#[derive(Debug)]
+/// The input message to be acked
+pub(crate) struct TransactionAck {
+    /// The Redis key of the shard
+    pub key: String,
+    pub group: String,
+    pub id: MessageId,
+}
+
+impl RedisTransaction {
+    pub(crate) fn new(stream: Option<StreamKey>, sender: Sender<Request>) -> Self {
+        Self {
+            stream,
+            sender,
+            outputs: Vec::new(),
+            acks: Vec::new(),
+        }
+    }
+
+    /// Send a message to the anchored stream on commit.
+    pub fn send<S: Buffer>(&mut self, payload: S) -> RedisResult<()> {
+        let stream = self.anchored()?.clone();
+        self.send_to(&stream, payload);
+        Ok(())
+    }
+
+    /// Send a message to a stream on commit.
+    pub fn send_to<S: Buffer>(&mut self, stream: &StreamKey, payload: S) {
+        self.outputs.push(TransactionOutput {
+            stream_key: stream.to_owned(),
+            key: None,
+            bytes: payload.into_bytes(),
+        });
+    }
+
+    /// Like `send`, but the shard is determined by `key`, see [`crate::RedisProducer::send_with_key`].
+    pub fn send_with_key<S: Buffer>(&mut self, key: &[u8], payload: S) -> RedisResult<()> {
+        let stream = self.anchored()?.clone();
+        self.send_with_key_to(&stream, key, payload);
+        Ok(())
+    }
+
+    /// Like `send_to`, but the shard is determined by `key`, see [`crate::RedisProducer::send_with_key`].
+    pub fn send_with_key_to<S: Buffer>(&mut self, stream: &StreamKey, key: &[u8], payload: S) {
+        self.outputs.push(TransactionOutput {
+            stream_key: stream.to_owned(),
+            key: Some(key.to_vec()),
+            bytes: payload.into_bytes(),
+        });
+    }
+
+    /// Ack a message received by `consumer` on commit.
+    pub fn ack(&mut self, consumer: &RedisConsumer, message: &SharedMessage) -> RedisResult<()> {
+        self.acks.push(consumer.transaction_ack(message.header())?);
+        Ok(())
+    }
+
+    /// Execute the transaction. Returns the receipts of the sent messages, in order.
+    pub async fn commit(self) -> RedisResult<Vec<MessageHeader>> {
+        if self.outputs.is_empty() && self.acks.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (receipt, result) = bounded(1);
+        self.sender
+            .send(Request::Transaction(Box::new(TransactionRequest {
+                outputs: self.outputs,
+                acks: self.acks,
+                receipt,
+            })))
+            .map_err(|_| StreamErr::Backend(RedisErr::ProducerDied))?;
+        match result.recv_async().await {
+            Ok(result) => result,
+            Err(_) => Err(StreamErr::Backend(RedisErr::ProducerDied)),
+        }
+    }
+
+    fn anchored(&self) -> RedisResult<&StreamKey> {
+        self.stream.as_ref().ok_or(StreamErr::NotAnchored)
+    }
+}
+
+impl TransactionRequest {
+    pub(crate) fn touches(&self, stream_key: &StreamKey) -> bool {
+        self.outputs.iter().any(|o| &o.stream_key == stream_key)
+    }
+
+    pub(crate) fn streams(&self) -> impl Iterator<Item = &StreamKey> {
+        self.outputs.iter().map(|o| &o.stream_key)
+    }
+
+    pub(crate) fn reply(self, result: RedisResult<Vec<MessageHeader>>) {
+        self.receipt.try_send(result).ok();
+    }
+
+    /// Run the transaction with `MULTI` / `EXEC`, returning the receipts of the sent messages.
+    pub(crate) async fn execute(
+        &self,
+        cluster: &mut RedisCluster,
+        mut sharder: Option<&mut Box<dyn Sharder>>,
+        trim: Option<(&StreamTrim, bool)>,
+    ) -> RedisResult<Vec<MessageHeader>> {
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        let mut keys = Vec::new();
+        let mut shards = Vec::new();
+        for output in self.outputs.iter() {
+            let (key, shard) = match sharder.as_mut() {
+                Some(sharder) => {
+                    let shard = match output.key.as_deref() {
+                        Some(key) => sharder.shard_by_key(&output.stream_key, key, &output.bytes),
+                        None => sharder.shard(&output.stream_key, &output.bytes),
+                    };
+                    (
+                        format!("{}:{shard}", output.stream_key.name()),
+                        ShardId::new(shard),
+                    )
+                }
+                None => (output.stream_key.name().to_owned(), ZERO),
+            };
+            let mut cmd = command("XADD");
+            cmd.arg(&key);
+            if let Some((trim, exact)) = trim {
+                write_trim(&mut cmd, trim, exact);
+            }
+            cmd.arg("*").arg(&[(MSG, output.bytes.as_slice())]);
+            pipe.add_command(cmd);
+            keys.push(key);
+            shards.push(shard);
+        }
+        for ack in self.acks.iter() {
+            let mut cmd = command("XACK");
+            cmd.arg(&ack.key)
+                .arg(&ack.group)
+                .arg(format!("{}-{}", ack.id.0, ack.id.1));
+            pipe.add_command(cmd).ignore();
+            keys.push(ack.key.clone());
+        }
+
+        if cluster.options.enable_cluster() {
+            let slot = key_slot(&keys[0]);
+            if let Some(key) = keys.iter().find(|k| key_slot(k) != slot) {
+                return Err(StreamErr::Backend(RedisErr::CrossSlot(format!(
+                    "`{key}` is not in the same slot as `{}`",
+                    keys[0]
+                ))));
+            }
+        }
+
+        let mut retried = 0;
+        let ids: Vec<Value> = loop {
+            let (node, conn) = match cluster.get_connection_for(&keys[0]).await {
+                Ok(conn) => conn,
+                Err(StreamErr::Backend(RedisErr::TryAgain(err))) => {
+                    // it will sleep inside `get_connection`
+                    retried += 1;
+                    if retried == MAX_RETRY {
+                        return Err(StreamErr::Backend(RedisErr::TryAgain(err)));
+                    }
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            let node = node.to_owned();
+            match pipe.query_async(conn).await {
+                Ok(ids) => break ids,
+                Err(err) => {
+                    retried += 1;
+                    if retried == MAX_RETRY {
+                        return Err(map_err(err));
+                    }
+                    // the transaction is aborted as a whole in these cases, so it is safe to retry
+                    match err.kind() {
+                        ErrorKind::Moved => match err.redirect_node() {
+                            Some((to, _slot)) => {
+                                // `to` must be in form of `host:port` without protocol
+                                let to = format!("{}://{}", cluster.protocol().unwrap(), to)
+                                    .parse()
+                                    .map_err(|e| {
+                                        StreamErr::StreamUrlErr(StreamUrlErr::UrlParseError(e))
+                                    })?;
+                                cluster.moved(&keys[0], to);
+                            }
+                            None => return Err(map_err(err)),
+                        },
+                        ErrorKind::Ask | ErrorKind::TryAgain | ErrorKind::ClusterDown => {
+                            // wait until the slot has finished moving
+                            sleep(Duration::from_secs(1)).await;
+                        }
+                        ErrorKind::ReadOnly => {
+                            // the master has been demoted in a failover
+                            cluster.reconnect(&node).ok();
+                        }
+                        _ => {
+                            // the transaction may or may not have been executed on IO errors
+                            if err.kind() == ErrorKind::IoError {
+                                cluster.reconnect(&node).ok();
+                            }
+                            return Err(map_err(err));
+                        }
+                    }
+                }
+            }
+        };
+
+        if ids.len() != self.outputs.len() {
+            return Err(StreamErr::Backend(RedisErr::ResponseError(format!(
+                "Expected {} message ids from EXEC, got {}",
+                self.outputs.len(),
+                ids.len()
+            ))));
+        }
+        ids.into_iter()
+            .zip(self.outputs.iter().zip(shards))
+            .map(|(id, (output, shard))| {
+                let (timestamp, sequence) = header_of_id(&string_from_redis_value(id)?)?;
+                Ok(MessageHeader::new(
+                    output.stream_key.clone(),
+                    shard,
+                    sequence,
+                    timestamp,
+                ))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,208 @@
+use flume::Receiver;
+use redis::{aio::ConnectionLike, cmd as command, ErrorKind, Pipeline};
+use std::{fmt::Display, time::Duration};
+
+use crate::{map_err, RedisCluster, RedisErr, Request, SendRequest, ZERO};
+use sea_streamer_runtime::sleep;
+use sea_streamer_types::{MessageHeader, SeqNo, StreamErr, Timestamp};
+
+const MAX_RETRY: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How messages are carried over Redis, selected by the protocol of the streamer URI.
+///
+/// Only `Streams` supports the full set of features. The other transports implement the same
+/// `Producer` and `Consumer` traits with reduced semantics: messages are not persisted in a log,
+/// so there is no seek, rewind, ack or commit, which all return `StreamErr::Unsupported`.
+/// Sharders, trimming and transactions are not supported either.
+pub enum RedisTransport {
+    /// Redis Streams, with `redis://` or `rediss://`.
+    Streams,
+    /// Pub/Sub channels, with `redis+pubsub://` or `rediss+pubsub://`.
+    ///
+    /// Messages are `PUBLISH`ed to the channel named after the stream key, and every subscribed consumer
+    /// receives all messages published while it is connected. Delivery is at-most-once: messages published while
+    /// a consumer is reconnecting are lost. Consumers must be in `ConsumerMode::RealTime`.
+    PubSub,
+    /// Lists as work queues, with `redis+list://` or `rediss+list://`.
+    ///
+    /// Messages are `LPUSH`ed to the list named after the stream key, and popped with `BRPOP`, so each message
+    /// is received by exactly one consumer, regardless of the consumer mode: consumers of the same list always
+    /// share the work. A consumer only pops a message when `next` is called, so messages are never buffered
+    /// by an idle consumer. A message is gone once popped, so it is lost if the consumer crashes before processing it.
+    /// `ConsumerMode::Resumable` is not supported.
+    List,
+}
+
+impl Default for RedisTransport {
+    fn default() -> Self {
+        Self::Streams
+    }
+}
+
+impl RedisTransport {
+    /// Split a protocol into the transport and the protocol of the underlying connection,
+    /// e.g. `rediss+list` into `List` and `rediss`.
+    pub(crate) fn from_protocol(protocol: &str) -> (Self, &str) {
+        match protocol {
+            "redis+pubsub" => (Self::PubSub, "redis"),
+            "rediss+pubsub" => (Self::PubSub, "rediss"),
+            "redis+list" => (Self::List, "redis"),
+            "rediss+list" => (Self::List, "rediss"),
+            protocol => (Self::Streams, protocol),
+        }
+    }
+
+    pub(crate) fn unsupported(&self, what: &str) -> StreamErr<RedisErr> {
+        StreamErr::Unsupported(format!("{what} is not supported by the {self} transport"))
+    }
+}
+
+impl Display for RedisTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Streams => write!(f, "Redis Streams"),
+            Self::PubSub => write!(f, "Redis Pub/Sub"),
+            Self::List => write!(f, "Redis List"),
+        }
+    }
+}
+
+/// The producer task of the Pub/Sub and List transports.
+/// Consecutive messages to the same stream are pipelined.
+pub(crate) async fn run_producer(
+    mut cluster: RedisCluster,
+    transport: RedisTransport,
+    receiver: Receiver<Request>,
+) {
+    assert_ne!(transport, RedisTransport::Streams);
+    // there is no message id, so we number the messages ourselves
+    let mut sequence: SeqNo = 0;
+    // exit if all senders have been dropped
+    while let Ok(request) = receiver.recv_async().await {
+        let mut requests = vec![request];
+        requests.extend(receiver.drain());
+        let mut requests = requests.into_iter().peekable();
+        while let Some(request) = requests.next() {
+            let request = match request {
+                Request::Send(request) => request,
+                Request::Transaction(txn) => {
+                    txn.reply(Err(transport.unsupported("Transaction")));
+                    continue;
+                }
+            };
+            if request.is_signal() {
+                // everything before has been sent
+                let header = MessageHeader::new(request.stream_key, ZERO, 0, Timestamp::now_utc());
+                request.receipt.send_async(Ok(header)).await.ok();
+                continue;
+            }
+            if request.id.is_some() {
+                let err = transport.unsupported("Explicit message id");
+                request.receipt.send_async(Err(err)).await.ok();
+                continue;
+            }
+            let mut batch = vec![request];
+            while let Some(Request::Send(next)) = requests.peek() {
+                if next.is_signal() || next.id.is_some() || next.stream_key != batch[0].stream_key {
+                    break;
+                }
+                if let Some(Request::Send(next)) = requests.next() {
+                    batch.push(next);
+                }
+            }
+
+            let key = batch[0].stream_key.name().to_owned();
+            let mut pipe = redis::pipe();
+            for request in batch.iter() {
+                let mut cmd = command(match transport {
+                    RedisTransport::PubSub => "PUBLISH",
+                    _ => "LPUSH",
+                });
+                cmd.arg(&key).arg(request.bytes.as_slice());
+                pipe.add_command(cmd);
+            }
+            let result = match push(&mut cluster, &key, &pipe, batch.len()).await {
+                Ok(result) => result,
+                Err(err) => {
+                    log::error!("{err:?}");
+                    return; // this will kill the producer
+                }
+            };
+            let timestamp = Timestamp::now_utc();
+            for SendRequest {
+                stream_key,
+                receipt,
+                ..
+            } in batch
+            {
+                let result = match result.as_ref() {
+                    Ok(()) => {
+                        sequence += 1;
+                        Ok(MessageHeader::new(stream_key, ZERO, sequence, timestamp))
+                    }
+                    Err(err) => Err(StreamErr::Backend(err.clone())),
+                };
+                receipt.send_async(result).await.ok();
+            }
+        }
+    }
+}
+
+/// Send a pipeline of `len` commands to the node serving `key`, following redirects.
+/// Returns Err if the cluster cannot be reached at all.
+async fn push(
+    cluster: &mut RedisCluster,
+    key: &str,
+    pipe: &Pipeline,
+    len: usize,
+) -> Result<Result<(), RedisErr>, StreamErr<RedisErr>> {
+    let mut retried = 0;
+    loop {
+        let (node, conn) = match cluster.get_connection_for(key).await {
+            Ok(conn) => conn,
+            Err(StreamErr::Backend(RedisErr::TryAgain(err))) => {
+                // it will sleep inside `get_connection`
+                retried += 1;
+                if retried < MAX_RETRY {
+                    continue;
+                }
+                return Err(StreamErr::Backend(RedisErr::TryAgain(err)));
+            }
+            Err(err) => return Err(err),
+        };
+        let node = node.to_owned();
+        let err = match conn.req_packed_commands(pipe, 0, len).await {
+            Ok(_) => return Ok(Ok(())),
+            Err(err) => err,
+        };
+        retried += 1;
+        let kind = err.kind();
+        if retried < MAX_RETRY {
+            if kind == ErrorKind::Moved {
+                if let Some((to, _slot)) = err.redirect_node() {
+                    // `to` must be in form of `host:port` without protocol
+                    if let Ok(to) = format!("{}://{}", node.scheme(), to).parse() {
+                        cluster.moved(key, to);
+                        continue;
+                    }
+                }
+            } else if matches!(
+                kind,
+                ErrorKind::Ask | ErrorKind::TryAgain | ErrorKind::ClusterDown
+            ) {
+                // wait until the slot has finished moving
+                sleep(Duration::from_secs(1)).await;
+                continue;
+            } else if kind == ErrorKind::IoError || kind == ErrorKind::ReadOnly {
+                // The connection is broken, or the master has been demoted in a failover.
+                cluster.reconnect(&node).ok();
+                continue;
+            }
+        }
+        return Ok(Err(match map_err(err) {
+            StreamErr::Backend(err) => err,
+            _ => unreachable!(),
+        }));
+    }
+}
@@ -0,0 +1,206 @@
+use flume::{Receiver, TryRecvError};
+use redis::{
+    cmd as command,
+    streams::{StreamInfoGroupsReply, StreamPendingReply, StreamRangeReply},
+    Cmd,
+};
+use std::{collections::BTreeSet, time::Duration};
+
+use crate::{message_id_from_str, MessageId, RedisCluster, RedisResult};
+use sea_streamer_runtime::sleep;
+use sea_streamer_types::Timestamp;
+
+/// Maximum number of entries to look up in one round of trimming by length
+const TRIM_BATCH: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How streams are trimmed. It applies to each shard of a stream.
+pub enum StreamTrim {
+    /// Keep at most this many entries, i.e. `MAXLEN`.
+    MaxLen(usize),
+    /// Remove entries older than this, i.e. `MINID`. Requires Redis 6.2 or above.
+    MaxAge(Duration),
+}
+
+/// A background task that trims the streams written by a producer on a regular interval.
+pub(crate) struct Trimmer {
+    pub(crate) cluster: RedisCluster,
+    pub(crate) trim: StreamTrim,
+    pub(crate) exact: bool,
+    pub(crate) interval: Duration,
+    pub(crate) force: bool,
+}
+
+impl Trimmer {
+    /// Runs until the producer is dropped. `keys` receives the Redis keys the producer writes to.
+    pub(crate) async fn run(mut self, keys: Receiver<String>) {
+        let mut known = BTreeSet::new();
+        loop {
+            sleep(self.interval).await;
+            loop {
+                match keys.try_recv() {
+                    Ok(key) => {
+                        known.insert(key);
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        log::debug!("Trimmer exit");
+                        return;
+                    }
+                }
+            }
+            for key in known.iter() {
+                if let Err(err) = self.trim_key(key).await {
+                    log::warn!("Failed to trim `{key}`: {err}");
+                }
+            }
+        }
+    }
+
+    /// Every command goes through [`RedisCluster::query`], which follows the key if it has been moved.
+    /// On other errors, we will try again in the next round.
+    async fn trim_key(&mut self, key: &str) -> RedisResult<()> {
+        let (trim, exact, force) = (self.trim, self.exact, self.force);
+        let cluster = &mut self.cluster;
+        let min_id = match trim {
+            StreamTrim::MaxLen(max_len) => {
+                if force {
+                    let () = cluster
+                        .query(key, |key| {
+                            let mut cmd = command("XTRIM");
+                            cmd.arg(key);
+                            write_trim(&mut cmd, &trim, exact);
+                            cmd
+                        })
+                        .await?;
+                    return Ok(());
+                }
+                let len: usize = cluster
+                    .query(key, |key| {
+                        let mut cmd = command("XLEN");
+                        cmd.arg(key);
+                        cmd
+                    })
+                    .await?;
+                if len <= max_len {
+                    return Ok(());
+                }
+                // MAXLEN would remove the oldest `len - max_len` entries
+                let count = std::cmp::min(len - max_len, TRIM_BATCH);
+                let reply: StreamRangeReply = cluster
+                    .query(key, |key| {
+                        let mut cmd = command("XRANGE");
+                        cmd.arg(key).arg("-").arg("+").arg("COUNT").arg(count);
+                        cmd
+                    })
+                    .await?;
+                match reply.ids.last() {
+                    Some(last) => next_id(message_id_from_str(&last.id)?),
+                    None => return Ok(()),
+                }
+            }
+            StreamTrim::MaxAge(age) => min_id_of_age(age),
+        };
+        let min_id = if force {
+            min_id
+        } else {
+            match group_floor(cluster, key).await? {
+                Some(floor) => std::cmp::min(min_id, floor),
+                None => min_id,
+            }
+        };
+        let () = cluster
+            .query(key, |key| {
+                let mut cmd = command("XTRIM");
+                cmd.arg(key)
+                    .arg("MINID")
+                    .arg(if exact { "=" } else { "~" })
+                    .arg(format_id(min_id));
+                cmd
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+/// The lowest ID that any consumer group still needs: the first entry pending (delivered but not acked)
+/// in a group, or the first entry not yet delivered to a group. None if there is no group.
+async fn group_floor(cluster: &mut RedisCluster, key: &str) -> RedisResult<Option<MessageId>> {
+    let groups: StreamInfoGroupsReply = cluster
+        .query(key, |key| {
+            let mut cmd = command("XINFO");
+            cmd.arg("GROUPS").arg(key);
+            cmd
+        })
+        .await?;
+    let mut floor = None;
+    for group in groups.groups {
+        let id = if group.pending > 0 {
+            let reply: StreamPendingReply = cluster
+                .query(key, |key| {
+                    let mut cmd = command("XPENDING");
+                    cmd.arg(key).arg(&group.name);
+                    cmd
+                })
+                .await?;
+            match reply {
+                StreamPendingReply::Data(data) => message_id_from_str(&data.start_id)?,
+                StreamPendingReply::Empty => {
+                    next_id(message_id_from_str(&group.last_delivered_id)?)
+                }
+            }
+        } else {
+            next_id(message_id_from_str(&group.last_delivered_id)?)
+        };
+        floor = Some(match floor {
+            Some(floor) => std::cmp::min(floor, id),
+            None => id,
+        });
+    }
+    Ok(floor)
+}
+
+/// Append `MAXLEN` or `MINID` to `XADD` or `XTRIM`, with the threshold as of now.
+pub(crate) fn write_trim(cmd: &mut Cmd, trim: &StreamTrim, exact: bool) {
+    let op = if exact { "=" } else { "~" };
+    match trim {
+        StreamTrim::MaxLen(max_len) => cmd.arg("MAXLEN").arg(op).arg(*max_len),
+        StreamTrim::MaxAge(age) => cmd.arg("MINID").arg(op).arg(format_id(min_id_of_age(*age))),
+    };
+}
+
+/// `0-0` if `age` reaches back before the Unix epoch.
+fn min_id_of_age(age: Duration) -> MessageId {
+    let cut_off = age
+        .try_into()
+        .ok()
+        .and_then(|age| Timestamp::now_utc().checked_sub(age));
+    let millis = match cut_off {
+        Some(cut_off) => (cut_off.unix_timestamp_nanos() / 1_000_000).max(0),
+        None => 0,
+    };
+    (millis.try_into().expect("Never fails"), 0)
+}
+
+fn next_id((timestamp, seq): MessageId) -> MessageId {
+    if seq == u64::MAX {
+        (timestamp + 1, 0)
+    } else {
+        (timestamp, seq + 1)
+    }
+}
+
+fn format_id((timestamp, seq): MessageId) -> String {
+    format!("{timestamp}-{seq}")
+}
+
+#[cfg(test)]
+#[test]
+fn test_min_id_of_age() {
+    let (millis, seq) = min_id_of_age(Duration::from_secs(60));
+    let now = (Timestamp::now_utc().unix_timestamp_nanos() / 1_000_000) as u64;
+    assert!(now - 61_000 <= millis && millis <= now - 60_000);
+    assert_eq!(seq, 0);
+    assert_eq!(min_id_of_age(Duration::from_secs(u64::MAX)), (0, 0));
+    assert_eq!(min_id_of_age(Duration::MAX), (0, 0));
+}
@@ -0,0 +1,147 @@
+mod util;
+use util::*;
+
+// cargo test --test admin --features=test,runtime-tokio -- --nocapture
+// cargo test --test admin --no-default-features --features=test,runtime-async-std -- --nocapture
+#[cfg(feature = "test")]
+#[cfg_attr(feature = "runtime-tokio", tokio::test)]
+#[cfg_attr(feature = "runtime-async-std", async_std::test)]
+async fn main() -> anyhow::Result<()> {
+    use sea_streamer_redis::{
+        AutoCommit, AutoStreamReset, RedisConnectOptions, RedisConsumerOptions,
+        RedisProducerOptions, RedisStreamer,
+    };
+    use sea_streamer_runtime::sleep;
+    use sea_streamer_types::{
+        ConsumerGroup, ConsumerId, ConsumerMode, ConsumerOptions, Producer, StreamKey, Streamer,
+        Timestamp,
+    };
+    use std::time::Duration;
+
+    const TEST: &str = "admin";
+    env_logger::init();
+
+    let streamer = RedisStreamer::connect(
+        std::env::var("BROKERS_URL")
+            .unwrap_or_else(|_| "redis://localhost".to_owned())
+            .parse()
+            .unwrap(),
+        RedisConnectOptions::default(),
+    )
+    .await?;
+
+    let now = Timestamp::now_utc();
+    let stream = StreamKey::new(format!(
+        "{}-{}",
+        TEST,
+        now.unix_timestamp_nanos() / 1_000_000
+    ))?;
+    let group = ConsumerGroup::new(format!("{}-group", stream.name()));
+
+    let mut producer = streamer
+        .create_producer(stream.clone(), RedisProducerOptions::default())
+        .await?;
+    for i in 0..10 {
+        producer.send(format!("{i}"))?;
+    }
+    producer.flush().await?;
+
+    let mut options = RedisConsumerOptions::new(ConsumerMode::LoadBalanced);
+    options.set_consumer_group(group.clone())?;
+    options.set_auto_stream_reset(AutoStreamReset::Earliest);
+    // without pre-fetch, only what we consume is delivered
+    options.set_auto_commit(AutoCommit::Disabled);
+    options.set_batch_size(5);
+
+    let mut alpha_options = options.clone();
+    alpha_options.set_consumer_id(ConsumerId::new("alpha"));
+    let mut alpha = streamer
+        .create_consumer(std::slice::from_ref(&stream), alpha_options)
+        .await?;
+    let seq = consume(&mut alpha, 5).await?;
+    assert_eq!(seq, [0, 1, 2, 3, 4]);
+
+    let mut admin = streamer.admin().await?;
+    let shards = admin.shards(&stream).await?;
+    assert_eq!(shards.len(), 1);
+    let shard = &shards[0];
+
+    let info = admin.stream_info(shard).await?;
+    assert_eq!(info.length, 10);
+    assert_eq!(info.groups, 1);
+    assert!(info.first_id.is_some());
+
+    let groups = admin.groups(shard).await?;
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].name, group.name());
+    assert_eq!(groups[0].pending, 5);
+
+    let summary = admin.pending_summary(shard, &group).await?;
+    assert_eq!(summary.count, 5);
+    assert_eq!(summary.consumers, [("alpha".to_owned(), 5)]);
+
+    let pending = admin.pending(shard, &group, None, None, None, 10).await?;
+    assert_eq!(pending.len(), 5);
+    assert!(pending
+        .iter()
+        .all(|p| p.consumer == "alpha" && p.deliveries == 1));
+    let after = admin
+        .pending(shard, &group, None, None, Some(pending[2].id), 10)
+        .await?;
+    assert_eq!(after, pending[3..]);
+
+    println!("Inspect pending ... ok");
+
+    let beta = ConsumerId::new("beta");
+    let ids: Vec<_> = pending[..2].iter().map(|p| p.id).collect();
+    let claimed = admin
+        .claim(shard, &group, &beta, Duration::from_secs(0), &ids)
+        .await?;
+    assert_eq!(claimed, ids);
+    let pending = admin
+        .pending(shard, &group, Some(&beta), None, None, 10)
+        .await?;
+    assert_eq!(pending.len(), 2);
+    assert_eq!(pending[0].deliveries, 1);
+
+    println!("Claim ... ok");
+
+    // a consumer that acks everything and exits
+    let mut gamma_options = options.clone();
+    gamma_options.set_consumer_id(ConsumerId::new("gamma"));
+    let mut gamma = streamer
+        .create_consumer(std::slice::from_ref(&stream), gamma_options)
+        .await?;
+    let seq = consume_and_ack(&mut gamma, 5).await?;
+    assert_eq!(seq, [5, 6, 7, 8, 9]);
+    gamma.commit()?.await?;
+    gamma.end().await?;
+    alpha.end().await?;
+
+    let mut consumers = admin.consumers(shard, &group).await?;
+    consumers.sort_by(|a, b| a.name.cmp(&b.name));
+    let names: Vec<_> = consumers.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, ["alpha", "beta", "gamma"]);
+
+    sleep(Duration::from_secs(1)).await;
+    let removed = admin
+        .remove_idle_consumers(&stream, &group, Duration::from_millis(500))
+        .await?;
+    assert_eq!(removed.len(), 1);
+    assert_eq!(removed[0].1.name, "gamma");
+
+    println!("Remove idle consumers ... ok");
+
+    assert_eq!(admin.delete_consumer(shard, &group, "beta").await?, 2);
+    admin.set_group_id(shard, &group, (0, 0)).await?;
+    let groups = admin.groups(shard).await?;
+    assert_eq!(groups[0].last_delivered_id, (0, 0));
+    assert!(admin.destroy_group(shard, &group).await?);
+    assert!(admin.groups(shard).await?.is_empty());
+
+    println!("Manage group ... ok");
+
+    producer.end().await?;
+
+    Ok(())
+}
@@ -0,0 +1,105 @@
+mod util;
+use util::*;
+
+// Requires a Redis Cluster, e.g. with `utils/create-cluster` in the Redis source tree:
+// ./create-cluster start && ./create-cluster create
+// CLUSTER_URL=redis://127.0.0.1:30001 cargo test --test cluster --features=test,runtime-tokio -- --nocapture
+// CLUSTER_URL=redis://127.0.0.1:30001 cargo test --test cluster --no-default-features --features=test,runtime-async-std -- --nocapture
+#[cfg(feature = "test")]
+#[cfg_attr(feature = "runtime-tokio", tokio::test)]
+#[cfg_attr(feature = "runtime-async-std", async_std::test)]
+async fn main() -> anyhow::Result<()> {
+    use sea_streamer_redis::{
+        AutoStreamReset, RedisConnectOptions, RedisConsumerOptions, RedisProducerOptions,
+        RedisStreamer, RoundRobinSharder,
+    };
+    use sea_streamer_types::{
+        Consumer, ConsumerMode, ConsumerOptions, Producer, SeqPos, StreamKey, Streamer, Timestamp,
+    };
+
+    const TEST: &str = "cluster";
+    const SHARDS: u32 = 6;
+    env_logger::init();
+
+    let url = match std::env::var("CLUSTER_URL") {
+        Ok(url) => url,
+        Err(_) => {
+            println!("CLUSTER_URL is not set, skipping");
+            return Ok(());
+        }
+    };
+
+    test(&url, ConsumerMode::RealTime).await?;
+    test(&url, ConsumerMode::Resumable).await?;
+    test(&url, ConsumerMode::LoadBalanced).await?;
+
+    async fn test(url: &str, mode: ConsumerMode) -> anyhow::Result<()> {
+        println!("ConsumerMode = {mode:?} ...");
+
+        let mut options = RedisConnectOptions::default();
+        options.set_enable_cluster(true);
+        let streamer = RedisStreamer::connect(url.parse().unwrap(), options).await?;
+
+        let now = Timestamp::now_utc();
+        let stream = StreamKey::new(format!(
+            "{}-{}",
+            TEST,
+            now.unix_timestamp_nanos() / 1_000_000
+        ))?;
+
+        // the shards are spread over the slots, and thus the nodes
+        let mut options = RedisProducerOptions::default();
+        options.set_sharder(RoundRobinSharder::new(SHARDS));
+        let mut producer = streamer.create_producer(stream.clone(), options).await?;
+
+        for i in 0..30 {
+            let message = format!("{i}");
+            let receipt = producer.send(message)?.await?;
+            assert_eq!(receipt.shard_id().id(), i % SHARDS as u64);
+        }
+
+        println!("Stream to nodes ... ok");
+
+        let mut options = RedisConsumerOptions::new(mode);
+        options.set_auto_stream_reset(AutoStreamReset::Earliest);
+
+        let mut consumer = streamer
+            .create_consumer(std::slice::from_ref(&stream), options)
+            .await?;
+
+        let mut seq = consume(&mut consumer, 30).await?;
+        seq.sort();
+        assert_eq!(seq, (0..30).collect::<Vec<_>>());
+
+        println!("Stream from nodes ... ok");
+
+        for i in 30..60 {
+            let message = format!("{i}");
+            producer.send(message)?;
+        }
+        producer.flush().await?;
+
+        let mut seq = consume(&mut consumer, 30).await?;
+        seq.sort();
+        assert_eq!(seq, (30..60).collect::<Vec<_>>());
+
+        println!("Stream more from nodes ... ok");
+
+        if mode == ConsumerMode::RealTime {
+            consumer.rewind(SeqPos::Beginning).await?;
+            let mut seq = consume(&mut consumer, 60).await?;
+            seq.sort();
+            assert_eq!(seq, (0..60).collect::<Vec<_>>());
+
+            println!("Rewind across nodes ... ok");
+        }
+
+        producer.end().await?;
+        consumer.end().await?;
+
+        println!("End test case.");
+        Ok(())
+    }
+
+    Ok(())
+}
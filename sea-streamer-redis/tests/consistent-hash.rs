@@ -0,0 +1,149 @@
+mod util;
+use util::*;
+
+// cargo test --test consistent-hash --features=test,runtime-tokio -- --nocapture
+// cargo test --test consistent-hash --no-default-features --features=test,runtime-async-std -- --nocapture
+#[cfg(feature = "test")]
+#[cfg_attr(feature = "runtime-tokio", tokio::test)]
+#[cfg_attr(feature = "runtime-async-std", async_std::test)]
+async fn main() -> anyhow::Result<()> {
+    use sea_streamer_redis::{
+        AutoCommit, AutoStreamReset, ConsistentHashSharder, RedisConnectOptions,
+        RedisConsumerOptions, RedisProducerOptions, RedisStreamer, ShardMeta,
+    };
+    use sea_streamer_runtime::sleep;
+    use sea_streamer_types::{
+        export::futures::future::join3, ConsumerMode, ConsumerOptions, Producer, StreamKey,
+        Streamer, Timestamp,
+    };
+    use std::{collections::HashMap, time::Duration};
+
+    const TEST: &str = "consistent-hash";
+    const KEYS: usize = 5;
+    env_logger::init();
+
+    let streamer = RedisStreamer::connect(
+        std::env::var("BROKERS_URL")
+            .unwrap_or_else(|_| "redis://localhost".to_owned())
+            .parse()
+            .unwrap(),
+        RedisConnectOptions::default(),
+    )
+    .await?;
+
+    let now = Timestamp::now_utc();
+    let stream = StreamKey::new(format!(
+        "{}-{}",
+        TEST,
+        now.unix_timestamp_nanos() / 1_000_000
+    ))?;
+
+    let key = |i: usize| format!("key-{}", i % KEYS);
+    // messages of the same key must be consumed in order
+    fn assert_ordered(seq: &[usize]) {
+        let mut last: HashMap<usize, usize> = HashMap::new();
+        for i in seq {
+            if let Some(prev) = last.insert(i % KEYS, *i) {
+                assert!(prev < *i, "{prev} should come before {i} in {seq:?}");
+            }
+        }
+    }
+
+    let mut options = RedisProducerOptions::default();
+    options.set_sharder(ConsistentHashSharder::new(2)?);
+    let mut producer = streamer.create_producer(stream.clone(), options).await?;
+
+    let mut shards = HashMap::new();
+    for i in 0..20 {
+        let receipt = producer
+            .send_with_key(key(i).as_bytes(), format!("{i}"))?
+            .await?;
+        // the same key always goes to the same shard
+        let shard = *shards.entry(key(i)).or_insert(*receipt.shard_id());
+        assert_eq!(receipt.shard_id(), &shard);
+        assert!(shard.id() < 2);
+    }
+
+    let mut admin = streamer.admin().await?;
+    assert_eq!(
+        admin.shard_meta(&stream).await?,
+        Some(ShardMeta {
+            shards: 2,
+            draining: None,
+            epoch: 0,
+        })
+    );
+
+    println!("Stream by key ... ok");
+
+    let mut options = RedisConsumerOptions::new(ConsumerMode::Resumable);
+    options.set_auto_stream_reset(AutoStreamReset::Earliest);
+    options.set_auto_commit(AutoCommit::Rolling);
+    options.set_auto_commit_interval(Duration::from_secs(0));
+    let mut consumer = streamer
+        .create_consumer(std::slice::from_ref(&stream), options)
+        .await?;
+
+    let mut seq = consume_and_ack(&mut consumer, 20).await?;
+    assert_ordered(&seq);
+    seq.sort();
+    assert_eq!(seq, (0..20).collect::<Vec<_>>());
+
+    println!("Consume by key ... ok");
+
+    for i in 20..30 {
+        producer.send_with_key(key(i).as_bytes(), format!("{i}"))?;
+    }
+    producer.flush().await?;
+
+    let (resharded, sent, consumed) = join3(
+        admin.reshard(&stream, 4, Duration::from_secs(60)),
+        async {
+            // these are held by the producer until resharding is done
+            sleep(Duration::from_secs(3)).await;
+            let mut receipts = Vec::new();
+            for i in 30..40 {
+                receipts.push(producer.send_with_key(key(i).as_bytes(), format!("{i}"))?);
+            }
+            anyhow::Ok(receipts)
+        },
+        consume_and_ack(&mut consumer, 10),
+    )
+    .await;
+    resharded?;
+    let consumed = consumed?;
+    assert_ordered(&consumed);
+    for receipt in sent? {
+        assert!(receipt.await?.shard_id().id() < 4);
+    }
+    assert_eq!(
+        admin.shard_meta(&stream).await?,
+        Some(ShardMeta {
+            shards: 4,
+            draining: None,
+            epoch: 2,
+        })
+    );
+
+    println!("Reshard ... ok");
+
+    for i in 40..50 {
+        producer.send_with_key(key(i).as_bytes(), format!("{i}"))?;
+    }
+    producer.flush().await?;
+
+    // the new shards are discovered while running
+    let mut rest = consume_and_ack(&mut consumer, 20).await?;
+    let mut seq = consumed;
+    seq.append(&mut rest);
+    assert_ordered(&seq);
+    seq.sort();
+    assert_eq!(seq, (20..50).collect::<Vec<_>>());
+
+    println!("Consume after reshard ... ok");
+
+    producer.end().await?;
+    consumer.end().await?;
+
+    Ok(())
+}
@@ -0,0 +1,102 @@
+mod util;
+use util::*;
+
+// cargo test --test dead-letter --features=test,runtime-tokio -- --nocapture
+// cargo test --test dead-letter --no-default-features --features=test,runtime-async-std -- --nocapture
+#[cfg(feature = "test")]
+#[cfg_attr(feature = "runtime-tokio", tokio::test)]
+#[cfg_attr(feature = "runtime-async-std", async_std::test)]
+async fn main() -> anyhow::Result<()> {
+    use sea_streamer_redis::{
+        AutoCommit, AutoStreamReset, RedisConnectOptions, RedisConsumerOptions,
+        RedisProducerOptions, RedisStreamer,
+    };
+    use sea_streamer_types::{
+        Buffer, ConsumerId, ConsumerMode, ConsumerOptions, Message, Producer, StreamKey, Streamer,
+        Timestamp,
+    };
+
+    const TEST: &str = "dead-letter";
+    env_logger::init();
+
+    let streamer = RedisStreamer::connect(
+        std::env::var("BROKERS_URL")
+            .unwrap_or_else(|_| "redis://localhost".to_owned())
+            .parse()
+            .unwrap(),
+        RedisConnectOptions::default(),
+    )
+    .await?;
+
+    let now = Timestamp::now_utc();
+    let stream = StreamKey::new(format!(
+        "{}-{}",
+        TEST,
+        now.unix_timestamp_nanos() / 1_000_000
+    ))?;
+    let dead_letter_stream = StreamKey::new(format!("{}-dlq", stream.name()))?;
+
+    let mut producer = streamer
+        .create_producer(stream.clone(), RedisProducerOptions::default())
+        .await?;
+    producer.send("0")?;
+    producer.flush().await?;
+
+    let mut options = RedisConsumerOptions::new(ConsumerMode::LoadBalanced);
+    options.set_consumer_id(ConsumerId::new("poisoned"));
+    options.set_auto_stream_reset(AutoStreamReset::Earliest);
+    options.set_auto_commit(AutoCommit::Rolling);
+    assert!(options.set_max_deliveries(Some(0)).is_err());
+    options.set_max_deliveries(Some(2))?;
+    options.set_dead_letter_stream(Some(dead_letter_stream.clone()));
+
+    // the consumer "crashes" twice without acking
+    for _ in 0..2 {
+        let mut consumer = streamer
+            .create_consumer(std::slice::from_ref(&stream), options.clone())
+            .await?;
+        let seq = consume(&mut consumer, 1).await?;
+        assert_eq!(seq, [0]);
+        consumer.end().await?;
+    }
+
+    println!("Redeliver ... ok");
+
+    producer.send("1")?;
+    producer.flush().await?;
+
+    // on restart, the message is moved to the dead-letter stream instead
+    let mut consumer = streamer
+        .create_consumer(std::slice::from_ref(&stream), options.clone())
+        .await?;
+    let seq = consume_and_ack(&mut consumer, 1).await?;
+    assert_eq!(seq, [1]);
+
+    let letters = streamer
+        .read_dead_letters(&dead_letter_stream, None, 10)
+        .await?;
+    assert_eq!(letters.len(), 1);
+    let letter = &letters[0];
+    assert_eq!(letter.message.message().as_str().unwrap(), "0");
+    assert_eq!(&letter.stream.0, &stream);
+    assert_eq!(letter.consumer, "poisoned");
+    assert_eq!(letter.deliveries, 2);
+    assert_eq!(letter.dead_letter_stream(), &dead_letter_stream);
+
+    println!("Dead letter ... ok");
+
+    streamer.replay_dead_letter(letter).await?;
+    let seq = consume_and_ack(&mut consumer, 1).await?;
+    assert_eq!(seq, [0]);
+    assert!(streamer
+        .read_dead_letters(&dead_letter_stream, None, 10)
+        .await?
+        .is_empty());
+
+    println!("Replay ... ok");
+
+    consumer.end().await?;
+    producer.end().await?;
+
+    Ok(())
+}
@@ -0,0 +1,106 @@
+mod util;
+use util::*;
+
+// cargo test --test explicit-id --features=test,runtime-tokio -- --nocapture
+// cargo test --test explicit-id --no-default-features --features=test,runtime-async-std -- --nocapture
+#[cfg(feature = "test")]
+#[cfg_attr(feature = "runtime-tokio", tokio::test)]
+#[cfg_attr(feature = "runtime-async-std", async_std::test)]
+async fn main() -> anyhow::Result<()> {
+    use sea_streamer_redis::{
+        AutoStreamReset, RedisConnectOptions, RedisConsumerOptions, RedisErr, RedisMessageId,
+        RedisProducerOptions, RedisStreamer, RoundRobinSharder,
+    };
+    use sea_streamer_types::{
+        Consumer, ConsumerMode, ConsumerOptions, Message, Producer, SeqPos, StreamErr, StreamKey,
+        Streamer, Timestamp,
+    };
+
+    const TEST: &str = "explicit-id";
+    env_logger::init();
+
+    let streamer = RedisStreamer::connect(
+        std::env::var("BROKERS_URL")
+            .unwrap_or_else(|_| "redis://localhost".to_owned())
+            .parse()
+            .unwrap(),
+        RedisConnectOptions::default(),
+    )
+    .await?;
+
+    let now = Timestamp::now_utc();
+    let millis = now.unix_timestamp_nanos() / 1_000_000;
+    let stream = StreamKey::new(format!("{TEST}-{millis}"))?;
+    let sharded = StreamKey::new(format!("{TEST}-{millis}-sharded"))?;
+
+    let producer = streamer
+        .create_producer(stream.clone(), RedisProducerOptions::default())
+        .await?;
+    for i in 0..10 {
+        let receipt = producer
+            .send_with_id_to(&stream, None, (1_000 + i / 2, i % 2), format!("{i}"))?
+            .await?;
+        assert_eq!(*receipt.sequence(), (1_000 + i / 2) << 16 | (i % 2));
+    }
+    // an id not greater than the last one is rejected
+    assert!(producer
+        .send_with_id_to(&stream, None, (1_000, 0), "10")?
+        .await
+        .is_err());
+    // ids beyond 65536 per millisecond do not fit into a packed sequence number, but are kept
+    let receipt = producer
+        .send_with_id_to(&stream, None, (2_000, 70_000), "10")?
+        .await?;
+    assert_eq!(receipt.message_id(), (2_000, 70_000));
+    let receipt = producer
+        .send_with_id_to(&stream, None, (2_000, u64::MAX), "11")?
+        .await?;
+    assert_eq!(receipt.message_id(), (2_000, u64::MAX));
+    println!("Send with id ... ok");
+
+    let mut options = RedisProducerOptions::default();
+    options.set_sharder(RoundRobinSharder::new(2));
+    let mut sharded_producer = streamer.create_producer(sharded.clone(), options).await?;
+    sharded_producer.send("0")?;
+    sharded_producer.flush().await?;
+
+    let mut admin = streamer.admin().await?;
+    let streams = admin.streams(&format!("{TEST}-{millis}*")).await?;
+    assert_eq!(streams, vec![stream.clone(), sharded.clone()]);
+    let streams = admin.streams(&format!("{TEST}-{millis}-*")).await?;
+    assert_eq!(streams, vec![sharded.clone()]);
+    println!("List streams ... ok");
+
+    let mut options = RedisConsumerOptions::new(ConsumerMode::RealTime);
+    options.set_auto_stream_reset(AutoStreamReset::Earliest);
+    let mut consumer = streamer
+        .create_consumer(std::slice::from_ref(&stream), options)
+        .await?;
+    let mess = consumer.next().await?;
+    assert_eq!(mess.message_id(), (1_000, 0));
+    let seq = consume(&mut consumer, 9).await?;
+    assert_eq!(seq, (1..10).collect::<Vec<_>>());
+    let mess = consumer.next().await?;
+    assert_eq!(mess.message_id(), (2_000, 70_000));
+    let overflowed = mess.sequence();
+    let mess = consumer.next().await?;
+    assert_eq!(mess.message_id(), (2_000, u64::MAX));
+    println!("Consume ... ok");
+
+    // the sequence number of an overflowed id is not a position
+    assert!(matches!(
+        consumer.rewind(SeqPos::At(overflowed)).await,
+        Err(StreamErr::Backend(RedisErr::SequenceOverflow(_)))
+    ));
+    // continue after the id
+    consumer.seek_to((1_004, 0)).await?;
+    let seq = consume(&mut consumer, 3).await?;
+    assert_eq!(seq, vec![9, 10, 11]);
+    println!("Seek ... ok");
+
+    producer.end().await?;
+    sharded_producer.end().await?;
+    consumer.end().await?;
+
+    Ok(())
+}
@@ -0,0 +1,110 @@
+mod util;
+use util::*;
+
+// cargo test --test ownership --features=test,runtime-tokio -- --nocapture
+// cargo test --test ownership --no-default-features --features=test,runtime-async-std -- --nocapture
+#[cfg(feature = "test")]
+#[cfg_attr(feature = "runtime-tokio", tokio::test)]
+#[cfg_attr(feature = "runtime-async-std", async_std::test)]
+async fn main() -> anyhow::Result<()> {
+    use sea_streamer_redis::{
+        AutoCommit, AutoStreamReset, RedisConnectOptions, RedisConsumerOptions,
+        RedisProducerOptions, RedisStreamer, RoundRobinSharder, ShardOwnership,
+    };
+    use sea_streamer_runtime::sleep;
+    use sea_streamer_types::{
+        export::futures::try_join, ConsumerMode, ConsumerOptions, Producer, StreamKey, Streamer,
+        Timestamp,
+    };
+    use std::{collections::BTreeSet, time::Duration};
+
+    const TEST: &str = "ownership";
+    const SHARDS: u32 = 4;
+    env_logger::init();
+
+    let streamer = RedisStreamer::connect(
+        std::env::var("BROKERS_URL")
+            .unwrap_or_else(|_| "redis://localhost".to_owned())
+            .parse()
+            .unwrap(),
+        RedisConnectOptions::default(),
+    )
+    .await?;
+
+    let now = Timestamp::now_utc();
+    let stream = StreamKey::new(format!(
+        "{}-{}",
+        TEST,
+        now.unix_timestamp_nanos() / 1_000_000
+    ))?;
+
+    let mut options = RedisProducerOptions::default();
+    options.set_sharder(RoundRobinSharder::new(SHARDS));
+    let mut producer = streamer.create_producer(stream.clone(), options).await?;
+
+    // the shards have to exist before consumers are created
+    for i in 0..4 {
+        producer.send(format!("{i}"))?;
+    }
+    producer.flush().await?;
+
+    let mut options = RedisConsumerOptions::new(ConsumerMode::LoadBalanced);
+    options.set_auto_stream_reset(AutoStreamReset::Earliest);
+    options.set_auto_commit(AutoCommit::Rolling);
+    options.set_shard_ownership(ShardOwnership::Owned);
+
+    let mut alpha = streamer
+        .create_consumer(std::slice::from_ref(&stream), options.clone())
+        .await?;
+
+    // alpha is alone, so it owns all shards
+    let mut seq = consume_and_ack(&mut alpha, 4).await?;
+    seq.sort();
+    assert_eq!(seq, [0, 1, 2, 3]);
+
+    println!("Own all shards ... ok");
+
+    let mut beta = streamer
+        .create_consumer(std::slice::from_ref(&stream), options.clone())
+        .await?;
+
+    // wait for alpha to release half of the shards and beta to acquire them
+    sleep(Duration::from_secs(5)).await;
+
+    for i in 4..44 {
+        producer.send(format!("{i}"))?;
+    }
+    producer.flush().await?;
+
+    let (a, b) = try_join!(
+        consume_and_ack(&mut alpha, 20),
+        consume_and_ack(&mut beta, 20)
+    )?;
+    let shards =
+        |seq: &[usize]| -> BTreeSet<usize> { seq.iter().map(|i| i % SHARDS as usize).collect() };
+    let (a, b) = (shards(&a), shards(&b));
+    assert_eq!(a.len(), 2);
+    assert_eq!(b.len(), 2);
+    assert!(a.is_disjoint(&b));
+
+    println!("Rebalance on join ... ok");
+
+    beta.end().await?;
+
+    for i in 44..52 {
+        producer.send(format!("{i}"))?;
+    }
+    producer.flush().await?;
+
+    // beta left, so alpha takes over its shards
+    let mut seq = consume_and_ack(&mut alpha, 8).await?;
+    seq.sort();
+    assert_eq!(seq, [44, 45, 46, 47, 48, 49, 50, 51]);
+
+    println!("Rebalance on leave ... ok");
+
+    producer.end().await?;
+    alpha.end().await?;
+
+    Ok(())
+}
@@ -0,0 +1,94 @@
+mod util;
+use util::*;
+
+// Requires a master, a replica and a Sentinel, e.g.
+// redis-server --port 6380 &
+// redis-server --port 6381 --replicaof 127.0.0.1 6380 &
+// printf "port 26379\nsentinel monitor mymaster 127.0.0.1 6380 1\nsentinel down-after-milliseconds mymaster 1000\nsentinel failover-timeout mymaster 5000\n" > sentinel.conf
+// redis-sentinel sentinel.conf &
+// SENTINEL_URL=redis+sentinel://127.0.0.1:26379/mymaster cargo test --test sentinel --features=test,runtime-tokio -- --nocapture
+// SENTINEL_URL=redis+sentinel://127.0.0.1:26379/mymaster cargo test --test sentinel --no-default-features --features=test,runtime-async-std -- --nocapture
+#[cfg(feature = "test")]
+#[cfg_attr(feature = "runtime-tokio", tokio::test)]
+#[cfg_attr(feature = "runtime-async-std", async_std::test)]
+async fn main() -> anyhow::Result<()> {
+    use sea_streamer_redis::{
+        AutoStreamReset, RedisConnectOptions, RedisConsumerOptions, RedisProducerOptions,
+        RedisStreamer,
+    };
+    use sea_streamer_runtime::sleep;
+    use sea_streamer_types::{
+        export::url::Url, ConsumerMode, ConsumerOptions, Producer, StreamKey, Streamer, Timestamp,
+    };
+    use std::time::Duration;
+
+    const TEST: &str = "sentinel";
+    env_logger::init();
+
+    let url = match std::env::var("SENTINEL_URL") {
+        Ok(url) => url,
+        Err(_) => {
+            println!("SENTINEL_URL is not set, skipping");
+            return Ok(());
+        }
+    };
+
+    let streamer = RedisStreamer::connect(url.parse()?, RedisConnectOptions::default()).await?;
+
+    let now = Timestamp::now_utc();
+    let stream = StreamKey::new(format!(
+        "{}-{}",
+        TEST,
+        now.unix_timestamp_nanos() / 1_000_000
+    ))?;
+
+    let mut producer = streamer
+        .create_producer(stream.clone(), RedisProducerOptions::default())
+        .await?;
+    for i in 0..10 {
+        producer.send(format!("{i}"))?;
+    }
+    producer.flush().await?;
+
+    let mut options = RedisConsumerOptions::new(ConsumerMode::RealTime);
+    options.set_auto_stream_reset(AutoStreamReset::Earliest);
+    let mut consumer = streamer
+        .create_consumer(std::slice::from_ref(&stream), options)
+        .await?;
+    let seq = consume(&mut consumer, 10).await?;
+    assert_eq!(seq, (0..10).collect::<Vec<_>>());
+
+    println!("Stream through Sentinel ... ok");
+
+    // let the replica catch up, then fail over
+    sleep(Duration::from_secs(1)).await;
+    let sentinel: Url = url.parse()?;
+    let client = redis::Client::open(format!(
+        "redis://{}:{}",
+        sentinel.host_str().unwrap(),
+        sentinel.port().unwrap_or(26379)
+    ))?;
+    let mut conn = client.get_async_connection().await?;
+    let () = redis::cmd("SENTINEL")
+        .arg("FAILOVER")
+        .arg(sentinel.path().trim_matches('/'))
+        .query_async(&mut conn)
+        .await?;
+
+    // messages sent during the failover are retried on the new master
+    for i in 10..20 {
+        producer.send(format!("{i}"))?;
+        sleep(Duration::from_millis(100)).await;
+    }
+    producer.flush().await?;
+
+    let seq = consume(&mut consumer, 10).await?;
+    assert_eq!(seq, (10..20).collect::<Vec<_>>());
+
+    println!("Failover ... ok");
+
+    producer.end().await?;
+    consumer.end().await?;
+
+    Ok(())
+}
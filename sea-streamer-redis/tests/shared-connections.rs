@@ -0,0 +1,89 @@
+mod util;
+use util::*;
+
+// cargo test --test shared-connections --features=test,runtime-tokio -- --nocapture
+// cargo test --test shared-connections --no-default-features --features=test,runtime-async-std -- --nocapture
+#[cfg(feature = "test")]
+#[cfg_attr(feature = "runtime-tokio", tokio::test)]
+#[cfg_attr(feature = "runtime-async-std", async_std::test)]
+async fn main() -> anyhow::Result<()> {
+    use sea_streamer_redis::{
+        AutoStreamReset, ReconnectBackoff, RedisConnectOptions, RedisConsumerOptions,
+        RedisProducerOptions, RedisStreamer,
+    };
+    use sea_streamer_types::{
+        ConsumerMode, ConsumerOptions, Producer, StreamKey, Streamer, Timestamp,
+    };
+    use std::time::Duration;
+
+    const TEST: &str = "shared-connections";
+    env_logger::init();
+
+    let mut options = RedisConnectOptions::default();
+    options.set_shared_connections(true);
+    options.set_health_check_interval(Duration::from_millis(100));
+    options.set_reconnect_backoff(ReconnectBackoff {
+        initial: Duration::from_millis(100),
+        max: Duration::from_secs(1),
+        max_attempts: Some(10),
+    });
+    let streamer = RedisStreamer::connect(
+        std::env::var("BROKERS_URL")
+            .unwrap_or_else(|_| "redis://localhost".to_owned())
+            .parse()?,
+        options,
+    )
+    .await?;
+    println!("Connect Streamer ... ok");
+
+    let now = Timestamp::now_utc();
+    let stream = StreamKey::new(format!(
+        "{}-{}",
+        TEST,
+        now.unix_timestamp_nanos() / 1_000_000
+    ))?;
+
+    // the producers share one connection
+    let mut producers = Vec::new();
+    for _ in 0..4 {
+        producers.push(
+            streamer
+                .create_producer(stream.clone(), RedisProducerOptions::default())
+                .await?,
+        );
+    }
+    for i in 0..100 {
+        producers[i % 4].send(format!("{i}"))?;
+    }
+    for producer in producers.iter_mut() {
+        producer.flush().await?;
+    }
+
+    // outlive a few health checks
+    sea_streamer_runtime::sleep(Duration::from_millis(500)).await;
+    for i in 100..200 {
+        producers[i % 4].send(format!("{i}"))?;
+    }
+    for producer in producers.iter_mut() {
+        producer.flush().await?;
+    }
+    println!("Produce ... ok");
+
+    // consumers still read with dedicated connections
+    let mut options = RedisConsumerOptions::new(ConsumerMode::RealTime);
+    options.set_auto_stream_reset(AutoStreamReset::Earliest);
+    let mut consumer = streamer
+        .create_consumer(std::slice::from_ref(&stream), options)
+        .await?;
+    let mut seq = consume(&mut consumer, 200).await?;
+    seq.sort();
+    assert_eq!(seq, (0..200).collect::<Vec<_>>());
+    println!("Consume ... ok");
+
+    for producer in producers {
+        producer.end().await?;
+    }
+    consumer.end().await?;
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+// cargo test --test streams --features=test,runtime-tokio -- --nocapture
+// cargo test --test streams --no-default-features --features=test,runtime-async-std -- --nocapture
+#[cfg(feature = "test")]
+#[cfg_attr(feature = "runtime-tokio", tokio::test)]
+#[cfg_attr(feature = "runtime-async-std", async_std::test)]
+async fn main() -> anyhow::Result<()> {
+    use sea_streamer_redis::{
+        RedisConnectOptions, RedisProducerOptions, RedisStreamer, RoundRobinSharder,
+    };
+    use sea_streamer_types::{Producer, StreamKey, Streamer, Timestamp};
+
+    const TEST: &str = "streams";
+    env_logger::init();
+
+    let url = std::env::var("BROKERS_URL").unwrap_or_else(|_| "redis://localhost".to_owned());
+    let (scheme, rest) = url.split_once("://").expect("Should have protocol");
+    let streamer = RedisStreamer::connect(url.parse()?, RedisConnectOptions::default()).await?;
+
+    let now = Timestamp::now_utc();
+    let prefix = format!("{}-{}", TEST, now.unix_timestamp_nanos() / 1_000_000);
+    let plain = StreamKey::new(format!("{prefix}-plain"))?;
+    let sharded = StreamKey::new(format!("{prefix}-sharded"))?;
+    let list = StreamKey::new(format!("{prefix}-list"))?;
+
+    let mut producer = streamer
+        .create_producer(plain.clone(), RedisProducerOptions::default())
+        .await?;
+    producer.send("0")?;
+    producer.flush().await?;
+
+    // creates `{prefix}-sharded:0` and `{prefix}-sharded:1`
+    let mut options = RedisProducerOptions::default();
+    options.set_sharder(RoundRobinSharder::new(2));
+    let mut sharded_producer = streamer.create_producer(sharded.clone(), options).await?;
+    sharded_producer.send("0")?;
+    sharded_producer.send("1")?;
+    sharded_producer.flush().await?;
+
+    // a list, which is not a stream
+    let list_streamer = RedisStreamer::connect(
+        format!("{scheme}+list://{rest}").parse()?,
+        RedisConnectOptions::default(),
+    )
+    .await?;
+    let mut list_producer = list_streamer
+        .create_producer(list.clone(), RedisProducerOptions::default())
+        .await?;
+    list_producer.send("0")?;
+    list_producer.flush().await?;
+
+    let mut admin = streamer.admin().await?;
+    assert_eq!(
+        admin.streams(&format!("{prefix}-*")).await?,
+        vec![plain.clone(), sharded.clone()]
+    );
+    println!("Match all ... ok");
+
+    // the shards are matched by the key of their stream
+    assert_eq!(
+        admin.streams(&format!("{prefix}-sharded")).await?,
+        vec![sharded.clone()]
+    );
+    assert_eq!(
+        admin.streams(&format!("{prefix}-sharded:*")).await?,
+        vec![sharded.clone()]
+    );
+    println!("Match shards ... ok");
+
+    assert!(admin.streams(&format!("{prefix}-list")).await?.is_empty());
+    assert!(admin.streams(&format!("{prefix}-none*")).await?.is_empty());
+    println!("Skip non-streams ... ok");
+
+    producer.end().await?;
+    sharded_producer.end().await?;
+    list_producer.end().await?;
+
+    Ok(())
+}
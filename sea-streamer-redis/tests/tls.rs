@@ -0,0 +1,102 @@
+mod util;
+use util::*;
+
+// Requires a TLS-enabled redis-server that authenticates clients, e.g. with the certificates generated by
+// `utils/gen-test-certs.sh` of the Redis repository:
+// redis-server --port 0 --tls-port 6380 --tls-cert-file tls/redis.crt --tls-key-file tls/redis.key --tls-ca-cert-file tls/ca.crt --tls-auth-clients yes &
+// TLS_URL=rediss://localhost:6380 TLS_DIR=tls cargo test --test tls --features=test,runtime-tokio-native-tls,runtime-tokio-rustls -- --nocapture
+// TLS_URL=rediss://localhost:6380 TLS_DIR=tls cargo test --test tls --no-default-features --features=test,runtime-async-std-native-tls,runtime-async-std-rustls -- --nocapture
+// For Redis Cluster, also set TLS_CLUSTER_URL to a node of a cluster started with `--tls-cluster yes` and the same certificates.
+#[cfg(feature = "test")]
+#[cfg_attr(feature = "runtime-tokio", tokio::test)]
+#[cfg_attr(feature = "runtime-async-std", async_std::test)]
+async fn main() -> anyhow::Result<()> {
+    use sea_streamer_redis::{
+        AutoStreamReset, RedisConnectOptions, RedisConsumerOptions, RedisProducerOptions,
+        RedisStreamer, TlsBackend, TlsOptions,
+    };
+    use sea_streamer_types::{
+        ConsumerMode, ConsumerOptions, Producer, StreamKey, Streamer, Timestamp,
+    };
+
+    const TEST: &str = "tls";
+    env_logger::init();
+
+    let url = match std::env::var("TLS_URL") {
+        Ok(url) => url,
+        Err(_) => {
+            println!("TLS_URL is not set, skipping");
+            return Ok(());
+        }
+    };
+    let dir = std::env::var("TLS_DIR").unwrap_or_else(|_| "tls".to_owned());
+    let read = |file: &str| std::fs::read(format!("{dir}/{file}"));
+    let ca = read("ca.crt")?;
+    let identity = (read("client.crt")?, read("client.key")?);
+
+    let mut backends = Vec::new();
+    if cfg!(feature = "native-tls") {
+        backends.push(TlsBackend::NativeTls);
+    }
+    if cfg!(feature = "rustls") {
+        backends.push(TlsBackend::Rustls);
+    }
+
+    for backend in backends {
+        println!("TlsBackend = {backend:?} ...");
+
+        let mut tls = TlsOptions::default();
+        tls.set_backend(backend);
+        tls.set_ca_certificates(Some(ca.clone()));
+        tls.set_client_identity(Some(identity.clone()));
+
+        let mut options = RedisConnectOptions::default();
+        options.set_tls(tls.clone());
+        test(&url, options).await?;
+
+        println!("Mutual TLS ... ok");
+
+        if let Ok(url) = std::env::var("TLS_CLUSTER_URL") {
+            let mut options = RedisConnectOptions::default();
+            options.set_enable_cluster(true);
+            options.set_tls(tls);
+            test(&url, options).await?;
+
+            println!("Mutual TLS with Redis Cluster ... ok");
+        }
+    }
+
+    async fn test(url: &str, options: RedisConnectOptions) -> anyhow::Result<()> {
+        let streamer = RedisStreamer::connect(url.parse()?, options).await?;
+
+        let now = Timestamp::now_utc();
+        let stream = StreamKey::new(format!(
+            "{}-{}",
+            TEST,
+            now.unix_timestamp_nanos() / 1_000_000
+        ))?;
+
+        let mut producer = streamer
+            .create_producer(stream.clone(), RedisProducerOptions::default())
+            .await?;
+        for i in 0..10 {
+            producer.send(format!("{i}"))?;
+        }
+        producer.flush().await?;
+
+        let mut options = RedisConsumerOptions::new(ConsumerMode::RealTime);
+        options.set_auto_stream_reset(AutoStreamReset::Earliest);
+        let mut consumer = streamer
+            .create_consumer(std::slice::from_ref(&stream), options)
+            .await?;
+        let seq = consume(&mut consumer, 10).await?;
+        assert_eq!(seq, (0..10).collect::<Vec<_>>());
+
+        producer.end().await?;
+        consumer.end().await?;
+
+        Ok(())
+    }
+
+    Ok(())
+}
@@ -0,0 +1,149 @@
+mod util;
+use util::*;
+
+// cargo test --test transaction --features=test,runtime-tokio -- --nocapture
+// cargo test --test transaction --no-default-features --features=test,runtime-async-std -- --nocapture
+// Set CLUSTER_URL to also test with Redis Cluster.
+#[cfg(feature = "test")]
+#[cfg_attr(feature = "runtime-tokio", tokio::test)]
+#[cfg_attr(feature = "runtime-async-std", async_std::test)]
+async fn main() -> anyhow::Result<()> {
+    use sea_streamer_redis::{
+        AutoCommit, AutoStreamReset, RedisConnectOptions, RedisConsumerOptions, RedisErr,
+        RedisProducerOptions, RedisStreamer,
+    };
+    use sea_streamer_types::{
+        Buffer, Consumer, ConsumerGroup, ConsumerMode, ConsumerOptions, Message, Producer,
+        StreamErr, StreamKey, Streamer, Timestamp,
+    };
+
+    const TEST: &str = "transaction";
+    env_logger::init();
+
+    test(
+        &std::env::var("BROKERS_URL").unwrap_or_else(|_| "redis://localhost".to_owned()),
+        RedisConnectOptions::default(),
+    )
+    .await?;
+
+    if let Ok(url) = std::env::var("CLUSTER_URL") {
+        let mut options = RedisConnectOptions::default();
+        options.set_enable_cluster(true);
+        test(&url, options).await?;
+    }
+
+    async fn test(url: &str, options: RedisConnectOptions) -> anyhow::Result<()> {
+        let cluster = options.enable_cluster();
+        println!("Cluster = {cluster} ...");
+
+        let streamer = RedisStreamer::connect(url.parse()?, options).await?;
+
+        let now = Timestamp::now_utc();
+        // the same hash tag keeps input and output in the same slot
+        let tag = format!("{}-{}", TEST, now.unix_timestamp_nanos() / 1_000_000);
+        let input = StreamKey::new(format!("{{{tag}}}-input"))?;
+        let output = StreamKey::new(format!("{{{tag}}}-output"))?;
+        let group = ConsumerGroup::new(format!("{tag}-group"));
+
+        let mut producer = streamer
+            .create_producer(input.clone(), RedisProducerOptions::default())
+            .await?;
+        for i in 0..10 {
+            producer.send(format!("{i}"))?;
+        }
+        producer.flush().await?;
+
+        let mut options = RedisConsumerOptions::new(ConsumerMode::Resumable);
+        options.set_consumer_group(group.clone())?;
+        options.set_auto_stream_reset(AutoStreamReset::Earliest);
+        // without pre-fetch, only what we consume is delivered
+        options.set_auto_commit(AutoCommit::Disabled);
+        options.set_batch_size(5);
+        let consumer = streamer
+            .create_consumer(std::slice::from_ref(&input), options.clone())
+            .await?;
+
+        let processor = streamer
+            .create_producer(output.clone(), RedisProducerOptions::default())
+            .await?;
+        for i in 0..5 {
+            let message = consumer.next().await?;
+            let number: usize = message.message().as_str()?.parse()?;
+            assert_eq!(number, i);
+            if i == 0 {
+                // these consumers ack by themselves, or read with NOACK
+                for auto_commit in [AutoCommit::Immediate, AutoCommit::Delayed] {
+                    let mut options = options.clone();
+                    options.set_consumer_group(ConsumerGroup::new(format!("{tag}-other")))?;
+                    options.set_auto_commit(auto_commit);
+                    let other = streamer
+                        .create_consumer(std::slice::from_ref(&input), options)
+                        .await?;
+                    let mut txn = processor.transaction();
+                    assert!(matches!(
+                        txn.ack(&other, &message),
+                        Err(StreamErr::Backend(RedisErr::InvalidClientConfig(_)))
+                    ));
+                    other.end().await?;
+                }
+            }
+            let mut txn = processor.transaction();
+            txn.send(format!("{}", number * 10))?;
+            txn.send(format!("{}", number * 10 + 1))?;
+            txn.ack(&consumer, &message)?;
+            let receipts = txn.commit().await?;
+            assert_eq!(receipts.len(), 2);
+            assert!(receipts[0].sequence() < receipts[1].sequence());
+            assert_eq!(receipts[0].stream_key(), &output);
+        }
+
+        let mut admin = streamer.admin().await?;
+        let shard = admin.shards(&input).await?.remove(0);
+        // the first 5 have been acked in the transactions
+        let pending = admin.pending_summary(&shard, &group).await?;
+        assert_eq!(pending.count, 0);
+
+        println!("Send and ack atomically ... ok");
+
+        // a new member of the group only gets what is not yet processed
+        consumer.end().await?;
+        let mut consumer = streamer
+            .create_consumer(std::slice::from_ref(&input), options)
+            .await?;
+        let seq = consume(&mut consumer, 5).await?;
+        assert_eq!(seq, [5, 6, 7, 8, 9]);
+
+        let mut options = RedisConsumerOptions::new(ConsumerMode::RealTime);
+        options.set_auto_stream_reset(AutoStreamReset::Earliest);
+        let mut reader = streamer
+            .create_consumer(std::slice::from_ref(&output), options)
+            .await?;
+        let seq = consume(&mut reader, 10).await?;
+        assert_eq!(seq, [0, 1, 10, 11, 20, 21, 30, 31, 40, 41]);
+
+        println!("Resume after transactions ... ok");
+
+        if cluster {
+            // keys without the same hash tag are likely in different slots
+            let elsewhere = StreamKey::new(format!("{tag}-elsewhere"))?;
+            let mut txn = processor.transaction();
+            txn.send_to(&elsewhere, "1");
+            txn.send("2")?;
+            match txn.commit().await {
+                Err(StreamErr::Backend(RedisErr::CrossSlot(_))) => (),
+                res => panic!("Unexpected {res:?}"),
+            }
+
+            println!("Cross slot ... ok");
+        }
+
+        producer.end().await?;
+        processor.end().await?;
+        consumer.end().await?;
+        reader.end().await?;
+
+        Ok(())
+    }
+
+    Ok(())
+}
@@ -0,0 +1,125 @@
+mod util;
+use util::*;
+
+// cargo test --test transport --features=test,runtime-tokio -- --nocapture
+// cargo test --test transport --no-default-features --features=test,runtime-async-std -- --nocapture
+#[cfg(feature = "test")]
+#[cfg_attr(feature = "runtime-tokio", tokio::test)]
+#[cfg_attr(feature = "runtime-async-std", async_std::test)]
+async fn main() -> anyhow::Result<()> {
+    use sea_streamer_redis::{
+        RedisConnectOptions, RedisConsumerOptions, RedisProducerOptions, RedisStreamer,
+        RedisTransport,
+    };
+    use sea_streamer_types::{
+        export::futures::future::join, Consumer, ConsumerMode, ConsumerOptions, Producer, SeqPos,
+        StreamErr, StreamKey, Streamer, Timestamp,
+    };
+
+    const TEST: &str = "transport";
+    env_logger::init();
+
+    let url = std::env::var("BROKERS_URL").unwrap_or_else(|_| "redis://localhost".to_owned());
+    let (scheme, rest) = url.split_once("://").expect("Should have protocol");
+
+    let now = Timestamp::now_utc();
+    let stream = StreamKey::new(format!(
+        "{}-{}",
+        TEST,
+        now.unix_timestamp_nanos() / 1_000_000
+    ))?;
+
+    let streamer = RedisStreamer::connect(
+        format!("{scheme}+pubsub://{rest}").parse()?,
+        RedisConnectOptions::default(),
+    )
+    .await?;
+    assert_eq!(streamer.transport(), RedisTransport::PubSub);
+
+    match streamer
+        .create_consumer(
+            std::slice::from_ref(&stream),
+            RedisConsumerOptions::new(ConsumerMode::Resumable),
+        )
+        .await
+    {
+        Err(StreamErr::Unsupported(_)) => (),
+        res => panic!("Unexpected {res:?}"),
+    }
+
+    let options = RedisConsumerOptions::new(ConsumerMode::RealTime);
+    let mut alpha = streamer
+        .create_consumer(std::slice::from_ref(&stream), options.clone())
+        .await?;
+    let mut beta = streamer
+        .create_consumer(std::slice::from_ref(&stream), options)
+        .await?;
+
+    let mut producer = streamer
+        .create_producer(stream.clone(), RedisProducerOptions::default())
+        .await?;
+    for i in 0..10 {
+        producer.send(format!("{i}"))?;
+    }
+    producer.flush().await?;
+
+    // every subscriber receives all messages
+    let seq = consume(&mut alpha, 10).await?;
+    assert_eq!(seq, (0..10).collect::<Vec<_>>());
+    let seq = consume(&mut beta, 10).await?;
+    assert_eq!(seq, (0..10).collect::<Vec<_>>());
+
+    match alpha.rewind(SeqPos::Beginning).await {
+        Err(StreamErr::Unsupported(_)) => (),
+        res => panic!("Unexpected {res:?}"),
+    }
+
+    println!("Pub/Sub ... ok");
+
+    producer.end().await?;
+    alpha.end().await?;
+    beta.end().await?;
+
+    let streamer = RedisStreamer::connect(
+        format!("{scheme}+list://{rest}").parse()?,
+        RedisConnectOptions::default(),
+    )
+    .await?;
+    assert_eq!(streamer.transport(), RedisTransport::List);
+
+    let mut producer = streamer
+        .create_producer(stream.clone(), RedisProducerOptions::default())
+        .await?;
+    // the messages wait in the list until they are consumed
+    for i in 0..20 {
+        producer.send(format!("{i}"))?;
+    }
+    producer.flush().await?;
+
+    let options = RedisConsumerOptions::new(ConsumerMode::LoadBalanced);
+    let mut alpha = streamer
+        .create_consumer(std::slice::from_ref(&stream), options.clone())
+        .await?;
+    let mut beta = streamer
+        .create_consumer(std::slice::from_ref(&stream), options)
+        .await?;
+
+    let (a, b) = join(consume(&mut alpha, 10), consume(&mut beta, 10)).await;
+    let (a, b) = (a?, b?);
+    // each consumer receives the messages in order
+    assert!(a.windows(2).all(|w| w[0] < w[1]));
+    assert!(b.windows(2).all(|w| w[0] < w[1]));
+    // each message is received by only one consumer
+    let mut seq = a;
+    seq.extend(b);
+    seq.sort();
+    assert_eq!(seq, (0..20).collect::<Vec<_>>());
+
+    println!("List ... ok");
+
+    producer.end().await?;
+    alpha.end().await?;
+    beta.end().await?;
+
+    Ok(())
+}
@@ -0,0 +1,118 @@
+mod util;
+use util::*;
+
+// cargo test --test trim --features=test,runtime-tokio -- --nocapture
+// cargo test --test trim --no-default-features --features=test,runtime-async-std -- --nocapture
+#[cfg(feature = "test")]
+#[cfg_attr(feature = "runtime-tokio", tokio::test)]
+#[cfg_attr(feature = "runtime-async-std", async_std::test)]
+async fn main() -> anyhow::Result<()> {
+    use sea_streamer_redis::{
+        AutoCommit, AutoStreamReset, RedisConnectOptions, RedisConsumerOptions,
+        RedisProducerOptions, RedisStreamer, StreamTrim,
+    };
+    use sea_streamer_runtime::sleep;
+    use sea_streamer_types::{
+        ConsumerMode, ConsumerOptions, Producer, StreamKey, Streamer, Timestamp,
+    };
+    use std::time::Duration;
+
+    const TEST: &str = "trim";
+    env_logger::init();
+
+    let streamer = RedisStreamer::connect(
+        std::env::var("BROKERS_URL")
+            .unwrap_or_else(|_| "redis://localhost".to_owned())
+            .parse()
+            .unwrap(),
+        RedisConnectOptions::default(),
+    )
+    .await?;
+
+    let now = Timestamp::now_utc();
+    let stream = StreamKey::new(format!(
+        "{}-{}",
+        TEST,
+        now.unix_timestamp_nanos() / 1_000_000
+    ))?;
+
+    let mut options = RedisProducerOptions::default();
+    for age in [Duration::ZERO, Duration::MAX] {
+        assert!(options.set_trim(Some(StreamTrim::MaxAge(age))).is_err());
+    }
+    options.set_trim(Some(StreamTrim::MaxLen(10)))?;
+    options.set_trim_exact(true);
+    let mut producer = streamer.create_producer(stream.clone(), options).await?;
+
+    for i in 0..30 {
+        producer.send(format!("{i}"))?;
+    }
+    producer.flush().await?;
+
+    let mut options = RedisConsumerOptions::new(ConsumerMode::RealTime);
+    options.set_auto_stream_reset(AutoStreamReset::Earliest);
+    let mut consumer = streamer
+        .create_consumer(std::slice::from_ref(&stream), options.clone())
+        .await?;
+    let seq = consume(&mut consumer, 10).await?;
+    assert_eq!(seq, (20..30).collect::<Vec<_>>());
+    consumer.end().await?;
+    producer.end().await?;
+
+    println!("Trim on XADD ... ok");
+
+    let stream = StreamKey::new(format!("{}-bg", stream.name()))?;
+
+    // create the group before anything is written
+    let mut group_options = RedisConsumerOptions::new(ConsumerMode::LoadBalanced);
+    group_options.set_auto_stream_reset(AutoStreamReset::Earliest);
+    group_options.set_auto_commit(AutoCommit::Rolling);
+    group_options.set_mkstream(true);
+    let mut group = streamer
+        .create_consumer(std::slice::from_ref(&stream), group_options)
+        .await?;
+
+    let mut producer_options = RedisProducerOptions::default();
+    producer_options.set_trim(Some(StreamTrim::MaxLen(5)))?;
+    producer_options.set_trim_exact(true);
+    producer_options.set_trim_interval(Some(Duration::from_secs(1)));
+    let mut producer = streamer
+        .create_producer(stream.clone(), producer_options)
+        .await?;
+
+    for i in 0..20 {
+        producer.send(format!("{i}"))?;
+    }
+    producer.flush().await?;
+    sleep(Duration::from_secs(3)).await;
+
+    // nothing has been acked, so nothing is trimmed
+    let mut consumer = streamer
+        .create_consumer(std::slice::from_ref(&stream), options.clone())
+        .await?;
+    let seq = consume(&mut consumer, 1).await?;
+    assert_eq!(seq, [0]);
+    consumer.end().await?;
+
+    println!("Respect consumer group ... ok");
+
+    let mut seq = consume_and_ack(&mut group, 20).await?;
+    seq.sort();
+    assert_eq!(seq, (0..20).collect::<Vec<_>>());
+    // wait for the acks to be committed and the stream to be trimmed
+    sleep(Duration::from_secs(3)).await;
+
+    let mut consumer = streamer
+        .create_consumer(std::slice::from_ref(&stream), options)
+        .await?;
+    let seq = consume(&mut consumer, 5).await?;
+    assert_eq!(seq, [15, 16, 17, 18, 19]);
+    consumer.end().await?;
+
+    println!("Trim in background ... ok");
+
+    group.end().await?;
+    producer.end().await?;
+
+    Ok(())
+}
@@ -41,6 +41,15 @@
 //! relay -- --input kafka://localhost:9092/clock --output redis://localhost:6379/clock
 //! ```
 //!
+//! Redis Pub/Sub channels and Redis lists (as work queues) are also supported, see `sea-streamer-redis`:
+//!
+//! ```shell
+//! # Redis Pub/Sub -> Redis Streams
+//! relay -- --input redis+pubsub://localhost:6379/clock --output redis://localhost:6379/clock
+//! # Redis Streams -> Redis list
+//! relay -- --input redis://localhost:6379/clock --output redis+list://localhost:6379/clock-queue
+//! ```
+//!
 //! Here is how to *replay* the stream from Kafka / Redis:
 //!
 //! ```shell
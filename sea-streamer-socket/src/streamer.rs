@@ -164,7 +164,8 @@ impl Streamer for SeaStreamer {
                         .map_err(map_err)?,
                 ),
                 #[cfg(feature = "backend-redis")]
-                "redis" | "rediss" => SeaStreamerInner::Redis(
+                "redis" | "rediss" | "redis+pubsub" | "rediss+pubsub" | "redis+list"
+                | "rediss+list" | "redis+sentinel" | "rediss+sentinel" => SeaStreamerInner::Redis(
                     RedisStreamer::connect(uri, options.into_redis_connect_options())
                         .await
                         .map_err(map_err)?,
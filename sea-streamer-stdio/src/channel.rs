@@ -0,0 +1,249 @@
+use flume::Sender;
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Read, Result as IoResult, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+#[cfg(unix)]
+use std::{
+    os::unix::{fs::FileTypeExt, net::UnixStream},
+    path::Path,
+};
+
+use sea_streamer_types::{SeqNo, StreamErr, StreamKey, StreamerUri};
+
+use crate::{consumer_group::Consumers, producer::Signal, StdioResult};
+
+lazy_static::lazy_static! {
+    static ref STDIO: Arc<Channel> = Arc::new(Channel::new(Endpoint::Stdio));
+}
+
+/// Where a `StdioStreamer` reads from and writes to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Endpoint {
+    /// The process's own stdin and stdout
+    Stdio,
+    /// A file, named pipe or Unix socket
+    Path { input: PathBuf, output: PathBuf },
+}
+
+/// The states of an endpoint. Each has its own consumer registry, stdin thread and stdout thread.
+#[derive(Debug)]
+pub(crate) struct Channel {
+    pub(crate) endpoint: Endpoint,
+    pub(crate) consumers: Mutex<Consumers>,
+    /// The state of the input thread
+    pub(crate) reader: Mutex<InputState>,
+    /// Set if the output thread is running
+    pub(crate) writer: Mutex<Option<Sender<Signal>>>,
+    pub(crate) producers: Mutex<Producers>,
+    #[cfg(unix)]
+    socket: Mutex<Option<UnixStream>>,
+}
+
+/// The state of the input thread of a `Channel`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum InputState {
+    /// Not running, the next consumer spawns it
+    Idle,
+    Running,
+    /// The input is a file which has been read to the end. It is not read again,
+    /// otherwise the messages would be delivered twice.
+    Exhausted,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Producers {
+    sequences: HashMap<StreamKey, SeqNo>,
+}
+
+pub(crate) enum Input {
+    Stdin,
+    Reader(Box<dyn BufRead + Send>),
+}
+
+pub(crate) enum Output {
+    Stdout,
+    Writer(Box<dyn Write + Send>),
+}
+
+impl Endpoint {
+    /// `stdio://` is the process's stdin and stdout, `stdio:///path` reads from and writes to
+    /// `path`, and `stdio:///input,/output` reads from `input` and writes to `output`.
+    pub(crate) fn from_uri(uri: &StreamerUri) -> StdioResult<Self> {
+        let paths: Vec<PathBuf> = uri
+            .nodes()
+            .iter()
+            .filter(|node| !node.path().is_empty() && node.path() != "/")
+            .map(|node| PathBuf::from(node.path()))
+            .collect();
+        match paths.len() {
+            0 => Ok(Self::Stdio),
+            1 => Ok(Self::Path {
+                input: paths[0].clone(),
+                output: paths[0].clone(),
+            }),
+            2 => Ok(Self::Path {
+                input: paths[0].clone(),
+                output: paths[1].clone(),
+            }),
+            _ => Err(StreamErr::Unsupported(
+                "stdio expects at most two paths: input and output".to_owned(),
+            )),
+        }
+    }
+}
+
+impl Channel {
+    fn new(endpoint: Endpoint) -> Self {
+        Self {
+            endpoint,
+            consumers: Default::default(),
+            reader: Mutex::new(InputState::Idle),
+            writer: Mutex::new(None),
+            producers: Default::default(),
+            #[cfg(unix)]
+            socket: Mutex::new(None),
+        }
+    }
+
+    /// The process's stdin and stdout are shared by all streamers, other endpoints are not.
+    pub(crate) fn open(endpoint: Endpoint) -> Arc<Self> {
+        match endpoint {
+            Endpoint::Stdio => STDIO.clone(),
+            endpoint => Arc::new(Self::new(endpoint)),
+        }
+    }
+
+    /// Whether the input can be read again after it ended, i.e. stdin or a Unix socket to reconnect to.
+    pub(crate) fn reopenable(&self) -> bool {
+        match &self.endpoint {
+            Endpoint::Stdio => true,
+            #[cfg(unix)]
+            Endpoint::Path { input, .. } => is_socket(input),
+            #[cfg(not(unix))]
+            Endpoint::Path { .. } => false,
+        }
+    }
+
+    /// This has the potential to block, e.g. until the other end of a named pipe is opened.
+    pub(crate) fn open_input(&self) -> IoResult<Input> {
+        match &self.endpoint {
+            Endpoint::Stdio => Ok(Input::Stdin),
+            Endpoint::Path { input, .. } => {
+                #[cfg(unix)]
+                if is_socket(input) {
+                    return Ok(Input::Reader(Box::new(BufReader::new(
+                        self.connect(input)?,
+                    ))));
+                }
+                Ok(Input::Reader(Box::new(BufReader::new(File::open(input)?))))
+            }
+        }
+    }
+
+    /// This has the potential to block, e.g. until the other end of a named pipe is opened.
+    pub(crate) fn open_output(&self) -> IoResult<Output> {
+        match &self.endpoint {
+            Endpoint::Stdio => Ok(Output::Stdout),
+            Endpoint::Path { output, .. } => {
+                #[cfg(unix)]
+                if is_socket(output) {
+                    return Ok(Output::Writer(Box::new(self.connect(output)?)));
+                }
+                Ok(Output::Writer(Box::new(
+                    OpenOptions::new().create(true).append(true).open(output)?,
+                )))
+            }
+        }
+    }
+
+    /// If input and output are the same Unix socket, they share the same connection.
+    #[cfg(unix)]
+    fn connect(&self, path: &Path) -> IoResult<UnixStream> {
+        if !matches!(&self.endpoint, Endpoint::Path { input, output } if input == output) {
+            return UnixStream::connect(path);
+        }
+        let mut socket = self.socket.lock().expect("Failed to lock socket");
+        if socket.is_none() {
+            socket.replace(UnixStream::connect(path)?);
+        }
+        socket.as_ref().unwrap().try_clone()
+    }
+}
+
+#[cfg(unix)]
+fn is_socket(path: &Path) -> bool {
+    std::fs::metadata(path)
+        .map(|m| m.file_type().is_socket())
+        .unwrap_or(false)
+}
+
+impl Producers {
+    // returns current Seq No
+    pub(crate) fn append(&mut self, stream: &StreamKey) -> SeqNo {
+        if let Some(val) = self.sequences.get_mut(stream) {
+            let seq = *val;
+            *val += 1;
+            seq
+        } else {
+            self.sequences.insert(stream.to_owned(), 1);
+            0
+        }
+    }
+}
+
+impl Output {
+    pub(crate) fn write_line(&mut self, line: &str) -> IoResult<()> {
+        match self {
+            // I believe println is atomic now, so we don't have to lock stdout
+            Self::Stdout => {
+                println!("{line}");
+                Ok(())
+            }
+            Self::Writer(writer) => {
+                writeln!(writer, "{line}")?;
+                writer.flush()
+            }
+        }
+    }
+
+    pub(crate) fn write_with<F>(&mut self, f: F) -> IoResult<()>
+    where
+        F: FnOnce(&mut dyn Write) -> IoResult<()>,
+    {
+        match self {
+            Self::Stdout => {
+                let stdout = std::io::stdout();
+                let mut stdout = stdout.lock();
+                f(&mut stdout)?;
+                stdout.flush()
+            }
+            Self::Writer(writer) => {
+                f(writer)?;
+                writer.flush()
+            }
+        }
+    }
+}
+
+impl Input {
+    pub(crate) fn read_line(&mut self, line: &mut String) -> IoResult<usize> {
+        match self {
+            Self::Stdin => std::io::stdin().read_line(line),
+            Self::Reader(reader) => reader.read_line(line),
+        }
+    }
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        match self {
+            Self::Stdin => std::io::stdin().read(buf),
+            Self::Reader(reader) => reader.read(buf),
+        }
+    }
+}
@@ -1,107 +1,211 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
 use flume::{
     r#async::{RecvFut, RecvStream},
     unbounded, Receiver, RecvError, Sender,
 };
-use std::sync::Mutex;
+use std::sync::Arc;
 
 use sea_streamer_types::{
     export::{
         async_trait,
-        futures::{future::MapErr, stream::Map as StreamMap, StreamExt, TryFutureExt},
+        futures::{future::Map, stream::Map as StreamMap, FutureExt, StreamExt},
     },
-    Consumer as ConsumerTrait, ConsumerGroup, SeqPos, ShardId, SharedMessage, StreamErr, StreamKey,
-    Timestamp,
+    Consumer as ConsumerTrait, ConsumerGroup, LoadBalance, SeqPos, ShardId, SharedMessage,
+    StreamErr, StreamKey, Timestamp,
 };
 
 use crate::{
-    consumer_group::{Cid, Consumers},
-    parse_meta,
-    util::PanicGuard,
-    PartialHeader, StdioErr, StdioResult,
+    channel::{Channel, Input, InputState},
+    consumer_group::Cid,
+    framing::{parse_json, read_frame},
+    parse_header, parse_meta,
+    util::{Pacer, PanicGuard},
+    AtEof, Attributes, PartialHeader, StdioConnectOptions, StdioErr, StdioFraming, StdioResult,
 };
 
-lazy_static::lazy_static! {
-    static ref CONSUMERS: Mutex<Consumers> = Mutex::new(Default::default());
-    static ref THREAD: Mutex<bool> = Mutex::new(false);
-}
-
 #[derive(Debug)]
 pub struct StdioConsumer {
+    channel: Arc<Channel>,
     id: Cid,
     streams: Vec<StreamKey>,
-    receiver: Receiver<SharedMessage>,
+    receiver: Receiver<Delivery>,
 }
 
 pub(crate) type ConsumerMember = StdioConsumer;
 
-pub type NextFuture<'a> = MapErr<RecvFut<'a, SharedMessage>, fn(RecvError) -> StreamErr<StdioErr>>;
+/// A message and its attributes, or None if the input has ended
+#[derive(Debug)]
+pub struct Delivery(Option<(SharedMessage, Attributes)>);
+
+pub type NextFuture<'a> =
+    Map<RecvFut<'a, Delivery>, fn(Result<Delivery, RecvError>) -> StdioResult<SharedMessage>>;
 
 pub type StdioMessageStream<'a> =
-    StreamMap<RecvStream<'a, SharedMessage>, fn(SharedMessage) -> StdioResult<SharedMessage>>;
+    StreamMap<RecvStream<'a, Delivery>, fn(Delivery) -> StdioResult<SharedMessage>>;
 
 pub type StdioMessage = SharedMessage;
 
 pub(crate) fn create_consumer(
+    channel: &Arc<Channel>,
     group: Option<ConsumerGroup>,
+    load_balance: LoadBalance,
     streams: Vec<StreamKey>,
-) -> StdioConsumer {
-    init();
-    let mut consumers = CONSUMERS.lock().expect("Failed to lock Consumers");
-    consumers.add(group, streams)
+    options: &StdioConnectOptions,
+) -> StdioResult<StdioConsumer> {
+    let consumer = {
+        let mut consumers = channel.consumers.lock().expect("Failed to lock Consumers");
+        consumers.add(channel.clone(), group, load_balance, streams)?
+    };
+    // subscribe before spawning the thread, in case the input is a regular file
+    init(channel, options);
+    Ok(consumer)
 }
 
-pub(crate) fn init() {
-    let mut thread = THREAD.lock().expect("Failed to lock stdin thread");
-    if !*thread {
-        let builder = std::thread::Builder::new().name("sea-streamer-stdio-stdin".into());
-        builder
-            .spawn(move || {
-                log::debug!("[{pid}] stdin thread spawned", pid = std::process::id());
-                let _guard = PanicGuard;
-                loop {
-                    let mut line = String::new();
-                    // this has the potential to block forever
-                    match std::io::stdin().read_line(&mut line) {
-                        Ok(0) => break, // this means stdin is closed
-                        Ok(_) => {}
-                        Err(e) => {
-                            panic!("{e:?}");
+pub(crate) fn init(channel: &Arc<Channel>, options: &StdioConnectOptions) {
+    let mut state = channel.reader.lock().expect("Failed to lock stdin thread");
+    match *state {
+        InputState::Idle => (),
+        InputState::Running => return,
+        InputState::Exhausted => {
+            // the messages have all been dispatched, the new consumers only get the end
+            if options.at_eof() == AtEof::End {
+                end(channel);
+            }
+            return;
+        }
+    }
+    let channel = channel.clone();
+    let framing = options.framing();
+    let mut pacer = options.pace().map(Pacer::new);
+    let at_eof = options.at_eof();
+    let builder = std::thread::Builder::new().name("sea-streamer-stdio-stdin".into());
+    builder
+        .spawn(move || {
+            log::debug!("[{pid}] stdin thread spawned", pid = std::process::id());
+            let _guard = PanicGuard;
+            // this has the potential to block, until the other end of a pipe is opened
+            let read = match channel.open_input() {
+                Ok(input) => {
+                    match framing {
+                        StdioFraming::Line | StdioFraming::Base64 | StdioFraming::Json => {
+                            read_lines(&channel, input, framing, pacer.as_mut())
                         }
+                        StdioFraming::Binary => read_frames(&channel, input, pacer.as_mut()),
                     }
-                    if line.ends_with('\n') {
-                        line.truncate(line.len() - 1);
-                    }
-                    let (meta, remaining) = parse_meta(&line)
-                        .unwrap_or_else(|_| panic!("Failed to parse line: {line}"));
-                    let offset = remaining.as_ptr() as usize - line.as_ptr() as usize;
-                    dispatch(meta, line.into_bytes(), offset);
+                    true
                 }
-                log::debug!("[{pid}] stdin thread exit", pid = std::process::id());
-                {
-                    let mut thread = THREAD.lock().expect("Failed to lock stdin thread");
-                    *thread = false;
+                Err(e) => {
+                    log::error!("Failed to open {:?}: {e}", channel.endpoint);
+                    false
                 }
-            })
-            .unwrap();
-        *thread = true;
+            };
+            log::debug!("[{pid}] stdin thread exit", pid = std::process::id());
+            // hold the lock, so that a consumer created in the meantime does not miss the end
+            let mut state = channel.reader.lock().expect("Failed to lock stdin thread");
+            if !read {
+                disconnect(&channel);
+            } else if at_eof == AtEof::End {
+                end(&channel);
+            }
+            *state = if read && !channel.reopenable() {
+                InputState::Exhausted
+            } else {
+                InputState::Idle
+            };
+        })
+        .unwrap();
+    *state = InputState::Running;
+}
+
+fn read_lines(
+    channel: &Channel,
+    mut input: Input,
+    framing: StdioFraming,
+    mut pacer: Option<&mut Pacer>,
+) {
+    loop {
+        let mut line = String::new();
+        // this has the potential to block forever
+        match input.read_line(&mut line) {
+            Ok(0) => break, // this means stdin is closed
+            Ok(_) => {}
+            Err(e) => {
+                panic!("{e:?}");
+            }
+        }
+        if line.ends_with('\n') {
+            line.truncate(line.len() - 1);
+        }
+        if framing == StdioFraming::Json {
+            if !line.trim().is_empty() {
+                let (meta, bytes) = parse_json(&line)
+                    .unwrap_or_else(|e| panic!("Failed to parse line: {line}; {e}"));
+                pace(&mut pacer, &meta);
+                dispatch(channel, meta, bytes, 0);
+            }
+            continue;
+        }
+        let (meta, remaining) =
+            parse_meta(&line).unwrap_or_else(|_| panic!("Failed to parse line: {line}"));
+        pace(&mut pacer, &meta);
+        if framing == StdioFraming::Base64 {
+            let bytes = STANDARD
+                .decode(remaining)
+                .unwrap_or_else(|_| panic!("Failed to decode base64 payload: {line}"));
+            dispatch(channel, meta, bytes, 0);
+        } else {
+            let offset = remaining.as_ptr() as usize - line.as_ptr() as usize;
+            dispatch(channel, meta, line.into_bytes(), offset);
+        }
+    }
+}
+
+fn read_frames(channel: &Channel, mut input: Input, mut pacer: Option<&mut Pacer>) {
+    // this has the potential to block forever
+    while let Some((header, payload)) = read_frame(&mut input).unwrap_or_else(|e| panic!("{e:?}")) {
+        let meta = if header.is_empty() {
+            PartialHeader::default()
+        } else {
+            parse_header(&header).unwrap_or_else(|_| panic!("Failed to parse header: {header}"))
+        };
+        pace(&mut pacer, &meta);
+        dispatch(channel, meta, payload, 0);
+    } // stdin is closed
+}
+
+fn pace(pacer: &mut Option<&mut Pacer>, meta: &PartialHeader) {
+    if let Some(pacer) = pacer {
+        pacer.wait(meta.timestamp);
     }
 }
 
-pub(crate) fn disconnect() {
-    let mut consumers = CONSUMERS.lock().expect("Failed to lock Consumers");
+/// Signal the end of input to all consumers, and then disconnect them
+fn end(channel: &Channel) {
+    let mut consumers = channel.consumers.lock().expect("Failed to lock Consumers");
+    consumers.end();
+    consumers.disconnect()
+}
+
+pub(crate) fn disconnect(channel: &Channel) {
+    let mut consumers = channel.consumers.lock().expect("Failed to lock Consumers");
     consumers.disconnect()
 }
 
-pub(crate) fn dispatch(meta: PartialHeader, bytes: Vec<u8>, offset: usize) {
-    let mut consumers = CONSUMERS.lock().expect("Failed to lock Consumers");
+pub(crate) fn dispatch(channel: &Channel, meta: PartialHeader, bytes: Vec<u8>, offset: usize) {
+    let mut consumers = channel.consumers.lock().expect("Failed to lock Consumers");
     consumers.dispatch(meta, bytes, offset)
 }
 
 impl StdioConsumer {
-    pub(crate) fn new(id: Cid, streams: Vec<StreamKey>) -> (Self, Sender<SharedMessage>) {
+    pub(crate) fn new(
+        channel: Arc<Channel>,
+        id: Cid,
+        streams: Vec<StreamKey>,
+    ) -> (Self, Sender<Delivery>) {
         let (sender, receiver) = unbounded();
         (
             Self {
+                channel,
                 id,
                 streams,
                 receiver,
@@ -111,9 +215,42 @@ impl StdioConsumer {
     }
 }
 
+impl StdioConsumer {
+    /// Like `next`, but also returns the attributes of the message, e.g. `key` and `trace` of
+    /// `[my_topic | key=user-7 | trace=abc] payload`.
+    pub async fn next_with_attributes(&self) -> StdioResult<(SharedMessage, Attributes)> {
+        match self.receiver.recv_async().await {
+            Ok(Delivery(Some(delivery))) => Ok(delivery),
+            Ok(Delivery(None)) => Err(StreamErr::Backend(StdioErr::StreamEnded)),
+            Err(e) => Err(StreamErr::Backend(StdioErr::RecvError(e))),
+        }
+    }
+}
+
+impl Delivery {
+    pub(crate) fn message(message: SharedMessage, attributes: Attributes) -> Self {
+        Self(Some((message, attributes)))
+    }
+
+    pub(crate) fn end() -> Self {
+        Self(None)
+    }
+
+    fn into_message(self) -> StdioResult<SharedMessage> {
+        match self.0 {
+            Some((message, _)) => Ok(message),
+            None => Err(StreamErr::Backend(StdioErr::StreamEnded)),
+        }
+    }
+}
+
 impl Drop for StdioConsumer {
     fn drop(&mut self) {
-        let mut consumers = CONSUMERS.lock().expect("Failed to lock Consumers");
+        let mut consumers = self
+            .channel
+            .consumers
+            .lock()
+            .expect("Failed to lock Consumers");
         consumers.remove(self.id)
     }
 }
@@ -149,13 +286,18 @@ impl ConsumerTrait for StdioConsumer {
         Err(StreamErr::StreamKeyNotFound)
     }
 
+    /// The attributes of the message are dropped, see [`StdioConsumer::next_with_attributes`].
+    ///
+    /// Returns [`StdioErr::StreamEnded`] at the end of input, if the streamer is connected with
+    /// [`AtEof::End`].
     fn next(&self) -> Self::NextFuture<'_> {
-        self.receiver
-            .recv_async()
-            .map_err(|e| StreamErr::Backend(StdioErr::RecvError(e)))
+        self.receiver.recv_async().map(|res| match res {
+            Ok(delivery) => delivery.into_message(),
+            Err(e) => Err(StreamErr::Backend(StdioErr::RecvError(e))),
+        })
     }
 
     fn stream<'a, 'b: 'a>(&'b mut self) -> Self::Stream<'a> {
-        self.receiver.stream().map(Result::Ok)
+        self.receiver.stream().map(Delivery::into_message)
     }
 }
@@ -1,11 +1,18 @@
 use flume::Sender;
-use std::collections::{BTreeMap, HashMap};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
 
 use sea_streamer_types::{
-    ConsumerGroup, Message, MessageHeader, SeqNo, ShardId, SharedMessage, StreamKey, Timestamp,
+    ConsumerGroup, GroupBalancer, LoadBalance, MessageHeader, SeqNo, ShardId, SharedMessage,
+    StreamErr, StreamKey, Timestamp,
 };
 
-use crate::{ConsumerMember, PartialHeader, BROADCAST};
+use crate::{
+    channel::Channel, consumer::Delivery, ConsumerMember, PartialHeader, StdioErr, StdioResult,
+    BROADCAST,
+};
 
 pub type Cid = u64;
 
@@ -14,34 +21,70 @@ pub struct Consumers {
     max_id: Cid,
     consumers: BTreeMap<Cid, ConsumerRelay>,
     sequences: HashMap<(StreamKey, ShardId), SeqNo>,
+    balancers: HashMap<ConsumerGroup, GroupBalancer<Cid>>,
 }
 
 /// We use flume because it works on any async runtime. But actually we only wanted a SPSC queue.
 #[derive(Debug)]
 struct ConsumerRelay {
     group: Option<ConsumerGroup>,
+    load_balance: LoadBalance,
     streams: Vec<StreamKey>,
-    sender: Sender<SharedMessage>,
+    sender: Sender<Delivery>,
 }
 
 impl Consumers {
-    pub fn add(&mut self, group: Option<ConsumerGroup>, streams: Vec<StreamKey>) -> ConsumerMember {
+    pub fn add(
+        &mut self,
+        channel: Arc<Channel>,
+        group: Option<ConsumerGroup>,
+        load_balance: LoadBalance,
+        streams: Vec<StreamKey>,
+    ) -> StdioResult<ConsumerMember> {
+        if let Some(group) = &group {
+            if let Some(member) = self
+                .consumers
+                .values()
+                .find(|c| c.group.as_ref() == Some(group))
+            {
+                if member.load_balance != load_balance {
+                    return Err(StreamErr::Backend(StdioErr::InvalidConfig(format!(
+                        "Consumers in the same ConsumerGroup must use the same LoadBalance: `{}` uses {:?}",
+                        group.name(),
+                        member.load_balance
+                    ))));
+                }
+            }
+        }
         let id = self.max_id;
         self.max_id += 1;
-        let (con, sender) = ConsumerMember::new(id, streams.clone());
+        let (con, sender) = ConsumerMember::new(channel, id, streams.clone());
         self.consumers.insert(
             id,
             ConsumerRelay {
                 group,
+                load_balance,
                 streams,
                 sender,
             },
         );
-        con
+        Ok(con)
     }
 
     pub fn remove(&mut self, id: Cid) {
-        self.consumers.remove(&id);
+        if let Some(ConsumerRelay {
+            group: Some(group), ..
+        }) = self.consumers.remove(&id)
+        {
+            // forget the assignments of a group when everyone has left
+            if !self
+                .consumers
+                .values()
+                .any(|c| c.group.as_ref() == Some(&group))
+            {
+                self.balancers.remove(&group);
+            }
+        }
     }
 
     pub fn dispatch(&mut self, meta: PartialHeader, bytes: Vec<u8>, offset: usize) {
@@ -62,6 +105,7 @@ impl Consumers {
             *entry = ret + 1;
             ret
         };
+        let attributes = meta.attributes;
         let length = bytes.len() - offset;
         let message = SharedMessage::new(
             MessageHeader::new(
@@ -92,22 +136,38 @@ impl Consumers {
                     }
                     None => {
                         // we don't care if it cannot be delivered
-                        consumer.sender.send(message.clone()).ok();
+                        consumer
+                            .sender
+                            .send(Delivery::message(message.clone(), attributes.clone()))
+                            .ok();
                     }
                 }
             }
         }
 
-        for ids in groups.values() {
-            // This round-robin is deterministic
-            let id = ids[message.sequence() as usize % ids.len()];
+        for (group, ids) in groups {
+            let consumers = &self.consumers;
+            let balancer = self.balancers.entry(group).or_insert_with(|| {
+                GroupBalancer::new(consumers.get(&ids[0]).unwrap().load_balance.clone())
+            });
+            let id = balancer.select(&ids, &message);
             let consumer = self.consumers.get(&id).unwrap();
             // ignore any error
-            consumer.sender.send(message.clone()).ok();
+            consumer
+                .sender
+                .send(Delivery::message(message.clone(), attributes.clone()))
+                .ok();
+        }
+    }
+
+    pub fn end(&mut self) {
+        for consumer in self.consumers.values() {
+            consumer.sender.send(Delivery::end()).ok();
         }
     }
 
     pub fn disconnect(&mut self) {
         self.consumers = Default::default();
+        self.balancers = Default::default();
     }
 }
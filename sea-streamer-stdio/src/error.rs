@@ -9,6 +9,10 @@ pub enum StdioErr {
     IoError(std::io::Error),
     #[error("StdioStreamer has been disconnected")]
     Disconnected,
+    #[error("Input has ended")]
+    StreamEnded,
+    #[error("Invalid config: {0}")]
+    InvalidConfig(String),
 }
 
 pub type StdioResult<T> = StreamResult<T, StdioErr>;
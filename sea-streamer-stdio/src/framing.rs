@@ -0,0 +1,365 @@
+use sea_streamer_types::{MessageHeader, SeqNo, ShardId, StreamKey};
+use serde::{
+    de::{MapAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use serde_json::value::RawValue;
+use std::io::{ErrorKind, Read, Result as IoResult, Write};
+
+use crate::{parse_timestamp, Attributes, ParseErr, PartialHeader};
+
+/// How messages are framed on stdin / stdout.
+///
+/// With [`StdioFraming::Line`], payloads have to be valid UTF-8 without newlines. The other
+/// framings allow arbitrary bytes, e.g. protobuf, to be piped between processes. Both ends of
+/// a pipe must use the same framing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StdioFraming {
+    /// One message per line, in the form of `[timestamp | stream_key | sequence] payload`.
+    Line,
+    /// Same as `Line`, except that payloads are base64 encoded (standard alphabet, with padding).
+    Base64,
+    /// Length-prefixed binary frames. The header is the same as in `Line`, without the brackets:
+    ///
+    /// ```ignore
+    /// +---------~--------+----~----+----------~----------+-----~-----+
+    /// | header len: u16  |  header | payload len: u32    |  payload  |
+    /// +---------~--------+----~----+----------~----------+-----~-----+
+    /// ```
+    ///
+    /// Integers are big endian. The header can be empty.
+    Binary,
+    /// One JSON object per line, in the same shape as the `ndjson` format of the file decoder:
+    ///
+    /// ```json
+    /// {"header":{"stream_key":"hello","shard_id":0,"sequence":1,"timestamp":"2023-06-05T13:55:53.001"},"payload":{"hello":"world"}}
+    /// ```
+    ///
+    /// All header fields are optional on input. Payloads that are valid JSON are embedded as is,
+    /// otherwise they are written as strings. JSON payloads are passed through verbatim,
+    /// so unknown fields (and key order) are preserved. Payloads have to be valid UTF-8.
+    ///
+    /// Unknown fields of the envelope become attributes, with their values as raw JSON:
+    /// `"source":"sensor-1"` becomes `source="sensor-1"`, and unknown header fields are prefixed
+    /// with `header.`. Attributes are written back the same way, so a message read and then sent
+    /// with its attributes keeps all its fields. Attribute values that are not valid JSON are written as strings.
+    Json,
+}
+
+impl Default for StdioFraming {
+    fn default() -> Self {
+        Self::Line
+    }
+}
+
+impl StdioFraming {
+    /// Whether payloads can be any bytes
+    pub fn is_binary_safe(&self) -> bool {
+        matches!(self, Self::Base64 | Self::Binary)
+    }
+}
+
+/// The fields of a JSON object in order, with their values unparsed.
+struct JsonObject<'a>(Vec<(String, &'a RawValue)>);
+
+#[derive(Default)]
+struct JsonHeader {
+    timestamp: Option<String>,
+    stream_key: Option<String>,
+    sequence: Option<SeqNo>,
+    shard_id: Option<u64>,
+}
+
+const HEADER_FIELDS: [&str; 4] = ["stream_key", "shard_id", "sequence", "timestamp"];
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum JsonPayload<'a> {
+    Json(&'a RawValue),
+    String(&'a str),
+}
+
+impl<'de> Deserialize<'de> for JsonObject<'de> {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        struct ObjectVisitor;
+
+        impl<'de> Visitor<'de> for ObjectVisitor {
+            type Value = JsonObject<'de>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "a JSON object")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut fields = Vec::new();
+                while let Some(field) = map.next_entry()? {
+                    fields.push(field);
+                }
+                Ok(JsonObject(fields))
+            }
+        }
+
+        d.deserialize_map(ObjectVisitor)
+    }
+}
+
+pub(crate) fn to_json(header: &MessageHeader, payload: &str, attributes: &Attributes) -> String {
+    let payload = match serde_json::from_str::<&RawValue>(payload) {
+        // JSON strings are quoted, so that they can be told apart from plain strings
+        Ok(json) if json.get() == payload && !payload.starts_with('"') => JsonPayload::Json(json),
+        _ => JsonPayload::String(payload),
+    };
+    let header = serde_json::to_string(header).expect("Failed to serialize JSON");
+    // the header without the closing brace, so that more fields can be appended
+    let mut json = format!(r#"{{"header":{}"#, &header[..header.len() - 1]);
+    for (name, value) in attributes.iter() {
+        if let Some(name) = name.strip_prefix("header.") {
+            if !HEADER_FIELDS.contains(&name) {
+                push_field(&mut json, name, value);
+            }
+        }
+    }
+    json.push_str(r#"},"payload":"#);
+    json.push_str(&serde_json::to_string(&payload).expect("Failed to serialize JSON"));
+    for (name, value) in attributes.iter() {
+        if !name.starts_with("header.") && name != "header" && name != "payload" {
+            push_field(&mut json, name, value);
+        }
+    }
+    json.push('}');
+    json
+}
+
+fn push_field(json: &mut String, name: &str, value: &str) {
+    json.push(',');
+    json.push_str(&serde_json::to_string(name).expect("Failed to serialize JSON"));
+    json.push(':');
+    match serde_json::from_str::<&RawValue>(value) {
+        Ok(raw) => json.push_str(raw.get()),
+        Err(_) => json.push_str(&serde_json::to_string(value).expect("Failed to serialize JSON")),
+    }
+}
+
+/// Returns the header and payload of a JSON line. Unknown fields are returned as attributes.
+pub(crate) fn parse_json(line: &str) -> Result<(PartialHeader, Vec<u8>), ParseErr> {
+    let JsonObject(fields) = serde_json::from_str(line).map_err(ParseErr::Json)?;
+    let mut header = JsonHeader::default();
+    // `null` is a payload too
+    let mut payload = None;
+    let mut attributes = Attributes::new();
+    for (name, value) in fields {
+        match name.as_str() {
+            "header" => {
+                let JsonObject(fields) =
+                    serde_json::from_str(value.get()).map_err(ParseErr::Json)?;
+                for (name, value) in fields {
+                    let value = value.get();
+                    match name.as_str() {
+                        "timestamp" => header.timestamp = from_json(value)?,
+                        "stream_key" => header.stream_key = from_json(value)?,
+                        "sequence" => header.sequence = from_json(value)?,
+                        "shard_id" => header.shard_id = from_json(value)?,
+                        _ => attributes.insert_raw(format!("header.{name}"), value),
+                    }
+                }
+            }
+            "payload" => payload = Some(value),
+            _ => attributes.insert_raw(name, value.get()),
+        }
+    }
+    let meta = PartialHeader {
+        timestamp: match header.timestamp {
+            Some(timestamp) => Some(
+                parse_timestamp(&timestamp)
+                    .map_err(|_| ParseErr::Unknown(timestamp))?
+                    .assume_utc(),
+            ),
+            None => None,
+        },
+        stream_key: match header.stream_key {
+            Some(stream_key) => {
+                Some(StreamKey::new(&stream_key).map_err(|_| ParseErr::Unknown(stream_key))?)
+            }
+            None => None,
+        },
+        sequence: header.sequence,
+        shard_id: header.shard_id.map(ShardId::new),
+        attributes,
+    };
+    let payload = match payload {
+        Some(json) if json.get().starts_with('"') => serde_json::from_str::<String>(json.get())
+            .map_err(ParseErr::Json)?
+            .into_bytes(),
+        Some(json) => json.get().as_bytes().to_vec(),
+        None => Vec::new(),
+    };
+    Ok((meta, payload))
+}
+
+fn from_json<'a, T: Deserialize<'a>>(value: &'a str) -> Result<T, ParseErr> {
+    serde_json::from_str(value).map_err(ParseErr::Json)
+}
+
+pub(crate) fn write_frame<W: Write + ?Sized>(
+    writer: &mut W,
+    header: &str,
+    payload: &[u8],
+) -> IoResult<()> {
+    let header_len = u16::try_from(header.len())
+        .map_err(|_| std::io::Error::new(ErrorKind::InvalidInput, "Header too long"))?;
+    let payload_len = u32::try_from(payload.len())
+        .map_err(|_| std::io::Error::new(ErrorKind::InvalidInput, "Payload too large"))?;
+    writer.write_all(&header_len.to_be_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    writer.write_all(&payload_len.to_be_bytes())?;
+    writer.write_all(payload)?;
+    Ok(())
+}
+
+/// Returns None if the reader is closed at a frame boundary.
+pub(crate) fn read_frame<R: Read>(reader: &mut R) -> IoResult<Option<(String, Vec<u8>)>> {
+    let mut header_len = [0u8; 2];
+    let mut read = 0;
+    while read < header_len.len() {
+        match reader.read(&mut header_len[read..]) {
+            Ok(0) if read == 0 => return Ok(None),
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    let mut header = vec![0u8; u16::from_be_bytes(header_len) as usize];
+    reader.read_exact(&mut header)?;
+    let header =
+        String::from_utf8(header).map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))?;
+    let mut payload_len = [0u8; 4];
+    reader.read_exact(&mut payload_len)?;
+    let mut payload = vec![0u8; u32::from_be_bytes(payload_len) as usize];
+    reader.read_exact(&mut payload)?;
+    Ok(Some((header, payload)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn test_frame_roundtrip() {
+        let mut bytes = Vec::new();
+        write_frame(&mut bytes, "2022-01-02T03:04:05 | hello | 1", b"\0\n\xff").unwrap();
+        write_frame(&mut bytes, "", b"").unwrap();
+        let mut reader = bytes.as_slice();
+        assert_eq!(
+            read_frame(&mut reader).unwrap(),
+            Some((
+                "2022-01-02T03:04:05 | hello | 1".to_owned(),
+                b"\0\n\xff".to_vec()
+            ))
+        );
+        assert_eq!(
+            read_frame(&mut reader).unwrap(),
+            Some((String::new(), Vec::new()))
+        );
+        assert_eq!(read_frame(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_json_roundtrip() {
+        let header = MessageHeader::new(
+            StreamKey::new("hello").unwrap(),
+            ShardId::new(2),
+            3,
+            datetime!(2022-01-02 03:04:05.678).assume_utc(),
+        );
+        for payload in [
+            r#"{"b":1,"a":[true,null],"unknown":{"x":1.50}}"#,
+            r#"a plain, raw message"#,
+            r#""a quoted string""#,
+            r#"null"#,
+            r#" 1"#,
+            r#""#,
+        ] {
+            let json = to_json(&header, payload, &Attributes::new());
+            let (meta, bytes) = parse_json(&json).unwrap();
+            assert_eq!(
+                meta,
+                PartialHeader {
+                    timestamp: Some(*header.timestamp()),
+                    stream_key: Some(header.stream_key().clone()),
+                    sequence: Some(3),
+                    shard_id: Some(ShardId::new(2)),
+                    attributes: Default::default(),
+                }
+            );
+            assert_eq!(std::str::from_utf8(&bytes).unwrap(), payload);
+        }
+        assert_eq!(
+            to_json(&header, r#"{"hello":"world"}"#, &Attributes::new()),
+            r#"{"header":{"stream_key":"hello","shard_id":2,"sequence":3,"timestamp":"2022-01-02T03:04:05.678"},"payload":{"hello":"world"}}"#
+        );
+    }
+
+    #[test]
+    fn test_json_unknown_fields() {
+        let line = r#"{"header":{"stream_key":"hello","shard_id":2,"sequence":3,"timestamp":"2022-01-02T03:04:05.678","trace":{"id":"abc","n":1.50}},"payload":{"a":1},"source":"sensor-1","ttl":60,"my key":null}"#;
+        let (meta, bytes) = parse_json(line).unwrap();
+        assert_eq!(
+            meta.attributes.get("header.trace"),
+            Some(r#"{"id":"abc","n":1.50}"#)
+        );
+        assert_eq!(meta.attributes.get("source"), Some(r#""sensor-1""#));
+        assert_eq!(meta.attributes.get("ttl"), Some("60"));
+        assert_eq!(meta.attributes.get("my key"), Some("null"));
+        let header = MessageHeader::new(
+            meta.stream_key.unwrap(),
+            meta.shard_id.unwrap(),
+            meta.sequence.unwrap(),
+            meta.timestamp.unwrap(),
+        );
+        let payload = std::str::from_utf8(&bytes).unwrap();
+        assert_eq!(to_json(&header, payload, &meta.attributes), line);
+
+        // attributes of other framings are written as strings, unless they are valid JSON
+        let attributes: Attributes = [("key", "user-7"), ("n", "1"), ("payload", "x")]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            to_json(&header, "hi", &attributes),
+            r#"{"header":{"stream_key":"hello","shard_id":2,"sequence":3,"timestamp":"2022-01-02T03:04:05.678"},"payload":"hi","key":"user-7","n":1}"#
+        );
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let (meta, bytes) = parse_json(r#"{"payload":"hi","extra":1}"#).unwrap();
+        assert_eq!(meta.timestamp, None);
+        assert_eq!(meta.stream_key, None);
+        assert_eq!(meta.attributes.get("extra"), Some("1"));
+        assert_eq!(bytes, b"hi");
+        let (meta, bytes) = parse_json(r#"{"header":{"stream_key":"hello"}}"#).unwrap();
+        assert_eq!(meta.stream_key, Some(StreamKey::new("hello").unwrap()));
+        assert!(bytes.is_empty());
+        assert!(matches!(
+            parse_json(r#"{"header":{"stream_key":"a b"}}"#),
+            Err(ParseErr::Unknown(_))
+        ));
+        assert!(matches!(parse_json("[1, 2]"), Err(ParseErr::Json(_))));
+    }
+
+    #[test]
+    fn test_frame_truncated() {
+        let mut bytes = Vec::new();
+        write_frame(&mut bytes, "hello", b"world").unwrap();
+        bytes.pop();
+        assert_eq!(
+            read_frame(&mut bytes.as_slice()).unwrap_err().kind(),
+            ErrorKind::UnexpectedEof
+        );
+        assert_eq!(
+            read_frame(&mut [0u8].as_slice()).unwrap_err().kind(),
+            ErrorKind::UnexpectedEof
+        );
+    }
+}
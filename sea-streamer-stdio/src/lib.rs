@@ -15,6 +15,20 @@
 //!
 //! You can also use `cat` to replay a file, but it runs from start to end as fast as possible then stops,
 //! which may or may not be the desired behavior.
+//! To replay a recording at the pace it was recorded, set `StdioConnectOptions::set_pace`, where messages are delivered
+//! according to the timestamps in their headers, times a speed factor. With `StdioConnectOptions::set_at_eof(AtEof::End)`,
+//! consumers get `StdioErr::StreamEnded` at the end of input, instead of waiting forever.
+//!
+//! Besides the process's own stdin and stdout (`stdio://`), a streamer can read from and write to a file, named pipe or Unix socket,
+//! e.g. `stdio:///path/to/fifo`, or `stdio:///path/to/input,/path/to/output` to read from and write to different paths.
+//! Each streamer has its own consumers and producers, so one process can join multiple pipelines:
+//!
+//! ```shell
+//! mkfifo stream_a stream_b
+//! processor_a > stream_a &
+//! processor_b < stream_b &
+//! processor_c # reads from stdio:///path/to/stream_a, writes to stdio:///path/to/stream_b
+//! ```
 //!
 //! You can write any valid UTF-8 string to stdin and each line will be considered a message. In addition, you can write some message meta in a simple format:
 //!
@@ -37,6 +51,17 @@
 //! [my_topic | 123 | 4] { "payload": "anything" }
 //! ```
 //!
+//! After the positional fields, there can be any number of named attributes, e.g. a key or a trace id:
+//!
+//! ```log
+//! [my_topic | 12 | key=user-7 | trace=abc] a string payload
+//! [key=a\|b\]c\\] the value of `key` is `a|b]c\`
+//! ```
+//!
+//! Names can contain alphanumerics, `.`, `_` and `-`. In values, `|`, `]` and `\` have to be escaped with a backslash.
+//! `MessageHeader` has no place for attributes, so they are delivered alongside: use `StdioConsumer::next_with_attributes`
+//! to read them, and `StdioProducer::send_with_attributes` to write them. Other methods ignore them.
+//!
 //! The following are all invalid:
 //!
 //! ```log
@@ -49,6 +74,24 @@
 //! You can create consumers that subscribe to only a subset of the topics.
 //!
 //! Consumers in the same `ConsumerGroup` will be load balanced (in a round-robin fashion), meaning you can spawn multiple async tasks to process messages in parallel.
+//! To keep related messages on the same worker, use `StdioConsumerOptions::set_load_balance` to hash by `(stream_key, shard_id)` or by a key of the message,
+//! or to assign them stickily, rebalancing when consumers join or leave.
+//!
+//! Payloads have to be valid UTF-8 without newlines. To pipe arbitrary bytes (e.g. protobuf) between processes, set `StdioConnectOptions::set_framing`
+//! to `StdioFraming::Base64`, where the payload of each line is base64 encoded, or `StdioFraming::Binary`, where messages are length-prefixed binary frames.
+//! Both ends of a pipe must use the same framing.
+//!
+//! To work with `jq` and other JSON tools, use `StdioFraming::Json`, where each line is a JSON object in the same shape as the `ndjson` format of the file decoder.
+//! JSON payloads are embedded (and passed through) as is, other payloads are written as strings:
+//!
+//! ```json
+//! {"header":{"stream_key":"hello","shard_id":0,"sequence":1,"timestamp":"2022-01-01T00:00:00.123"},"payload":{"anything":true}}
+//! {"header":{"stream_key":"hello"},"payload":"a string payload"}
+//! ```
+//!
+//! The header printed by the producer can be customized with `StdioProducerOptions`: pick the header fields, the timestamp format
+//! (`TimestampFormat::Rfc3339`, `TimestampFormat::UnixMillis` or `TimestampFormat::None`) and the separator, or `set_raw(true)` to print only the payload.
+//! This only applies to the `Line` and `Base64` framings. Only the default header can be parsed back by the stdio consumer.
 
 #![cfg_attr(docsrs, feature(doc_cfg))]
 #![deny(missing_debug_implementations)]
@@ -69,9 +112,11 @@ pub const TIMESTAMP_FORMAT: &[FormatItem<'static>] =
 pub const TIMESTAMP_FORMAT_SUBSEC: &[FormatItem<'static>] =
     format_description!("[year]-[month]-[day]T[hour]:[minute]:[second].[subsecond]");
 
+mod channel;
 mod consumer;
 mod consumer_group;
 mod error;
+mod framing;
 pub(crate) mod parser;
 mod producer;
 mod streamer;
@@ -79,6 +124,8 @@ mod util;
 
 pub use consumer::*;
 pub use error::*;
+pub use framing::*;
+pub use parser::Attributes;
 pub(crate) use parser::*;
 pub use producer::*;
 pub use streamer::*;
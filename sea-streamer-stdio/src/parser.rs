@@ -1,10 +1,5 @@
 use crate::{TIMESTAMP_FORMAT, TIMESTAMP_FORMAT_SUBSEC};
-use nom::{
-    bytes::complete::{is_not, take_while_m_n},
-    character::complete::char,
-    sequence::delimited,
-    IResult,
-};
+use nom::{bytes::complete::take_while_m_n, IResult};
 use sea_streamer_types::{
     is_valid_stream_key_char, SeqNo, ShardId, StreamKey, Timestamp, MAX_STREAM_KEY_LEN,
 };
@@ -18,6 +13,17 @@ pub struct PartialHeader {
     pub stream_key: Option<StreamKey>,
     pub sequence: Option<SeqNo>,
     pub shard_id: Option<ShardId>,
+    pub attributes: Attributes,
+}
+
+/// Named attributes of a message, e.g. `[my_topic | 12 | key=user-7 | trace=abc]`.
+///
+/// `MessageHeader` has no room for them, so they are delivered alongside the message,
+/// see [`crate::StdioConsumer::next_with_attributes`] and
+/// [`crate::StdioProducer::send_to_with_attributes`]. The order is preserved.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct Attributes {
+    pairs: Vec<(String, String)>,
 }
 
 #[derive(Error, Debug)]
@@ -26,24 +32,35 @@ pub enum ParseErr {
     Empty,
     #[error("Unknown part: {0}")]
     Unknown(String),
+    #[error("Invalid JSON: {0}")]
+    Json(serde_json::Error),
 }
 
 pub fn parse_meta(input: &str) -> Result<(PartialHeader, &str), ParseErr> {
     let (o, raw) = match parens(input) {
-        Ok(ok) => ok,
-        Err(_) => {
-            return Ok((
-                PartialHeader {
-                    timestamp: Some(Timestamp::now_utc()),
-                    ..Default::default()
-                },
-                input,
-            ))
-        }
+        Some(ok) => ok,
+        // the timestamp will be filled in on dispatch
+        None => return Ok((PartialHeader::default(), input)),
     };
-    let parts = raw.split('|').map(|s| s.trim());
+    Ok((parse_header(raw)?, o.trim()))
+}
+
+/// Parse the header without the brackets, i.e. `timestamp | stream_key | sequence | shard_id`,
+/// followed by any number of `name=value` attributes.
+///
+/// In attribute values, `|`, `]` and `\` have to be escaped with a backslash.
+pub fn parse_header(raw: &str) -> Result<PartialHeader, ParseErr> {
+    let parts = split_unescaped(raw, '|');
     let mut meta = PartialHeader::default();
     for part in parts {
+        let part = part.trim();
+        if let Some((name, value)) = part.split_once('=') {
+            if is_valid_attribute_name(name.trim_end()) {
+                meta.attributes
+                    .insert(name.trim_end(), unescape(value.trim_start()));
+                continue;
+            }
+        }
         let mut parsed = false;
         if meta.timestamp.is_none()
             && meta.stream_key.is_none()
@@ -90,13 +107,128 @@ pub fn parse_meta(input: &str) -> Result<(PartialHeader, &str), ParseErr> {
         && meta.stream_key.is_none()
         && meta.sequence.is_none()
         && meta.shard_id.is_none()
+        && meta.attributes.is_empty()
     {
         return Err(ParseErr::Empty);
     }
-    Ok((meta, o.trim()))
+    Ok(meta)
 }
 
-fn parse_timestamp(input: &str) -> Result<PrimitiveDateTime, time::error::Parse> {
+impl Attributes {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Names can contain alphanumerics, `.`, `_` and `-`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name is invalid.
+    pub fn insert<N: Into<String>, V: Into<String>>(&mut self, name: N, value: V) {
+        let name = name.into();
+        assert!(
+            is_valid_attribute_name(&name),
+            "Invalid attribute name: {name}"
+        );
+        self.insert_raw(name, value);
+    }
+
+    /// Like `insert`, but the name is not validated. Used for the fields of JSON envelopes,
+    /// which can be any string.
+    pub(crate) fn insert_raw<N: Into<String>, V: Into<String>>(&mut self, name: N, value: V) {
+        let name = name.into();
+        let value = value.into();
+        if let Some((_, v)) = self.pairs.iter_mut().find(|(n, _)| n == &name) {
+            *v = value;
+        } else {
+            self.pairs.push((name, value));
+        }
+    }
+
+    /// Returns the value of the attribute
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// Render as `name=value` parts, with values escaped
+    pub(crate) fn to_parts(&self) -> impl Iterator<Item = String> + '_ {
+        self.iter()
+            .map(|(name, value)| format!("{name}={}", escape(value)))
+    }
+}
+
+impl<N: Into<String>, V: Into<String>> FromIterator<(N, V)> for Attributes {
+    fn from_iter<I: IntoIterator<Item = (N, V)>>(iter: I) -> Self {
+        let mut attributes = Self::new();
+        for (name, value) in iter {
+            attributes.insert(name, value);
+        }
+        attributes
+    }
+}
+
+fn is_valid_attribute_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(is_valid_stream_key_char)
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | '|' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Split on `sep`, except where it is escaped by a backslash
+fn split_unescaped(input: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in input.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == sep {
+            parts.push(&input[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    parts.push(&input[start..]);
+    parts
+}
+
+pub(crate) fn parse_timestamp(input: &str) -> Result<PrimitiveDateTime, time::error::Parse> {
     PrimitiveDateTime::parse(input, &TIMESTAMP_FORMAT_SUBSEC)
         .or_else(|_| PrimitiveDateTime::parse(input, &TIMESTAMP_FORMAT))
 }
@@ -105,8 +237,25 @@ fn parse_stream_key(input: &str) -> IResult<&str, &str> {
     take_while_m_n(1, MAX_STREAM_KEY_LEN, is_valid_stream_key_char)(input)
 }
 
-fn parens(input: &str) -> IResult<&str, &str> {
-    delimited(char('['), is_not("]"), char(']'))(input)
+/// Returns the remaining and the content inside the brackets, which cannot be empty.
+/// A `]` escaped by a backslash does not close the brackets.
+fn parens(input: &str) -> Option<(&str, &str)> {
+    let inner = input.strip_prefix('[')?;
+    let mut escaped = false;
+    for (i, c) in inner.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == ']' {
+            return if i == 0 {
+                None
+            } else {
+                Some((&inner[i + 1..], &inner[..i]))
+            };
+        }
+    }
+    None
 }
 
 #[cfg(test)]
@@ -152,6 +301,7 @@ mod test {
                     stream_key: Some(StreamKey::new("my-fancy_topic.1").unwrap()),
                     sequence: None,
                     shard_id: None,
+                    attributes: Default::default(),
                 },
                 r#"["array", "of", "values"]"#
             )
@@ -169,6 +319,7 @@ mod test {
                     stream_key: Some(StreamKey::new("my-fancy_topic.1").unwrap()),
                     sequence: Some(123),
                     shard_id: None,
+                    attributes: Default::default(),
                 },
                 r#"a string payload"#
             )
@@ -188,6 +339,7 @@ mod test {
                     stream_key: Some(StreamKey::new("my-fancy_topic.1").unwrap()),
                     sequence: Some(123),
                     shard_id: Some(ShardId::new(4)),
+                    attributes: Default::default(),
                 },
                 r#"{ "payload": "anything" }"#
             )
@@ -204,6 +356,7 @@ mod test {
                     stream_key: Some(StreamKey::new("my-fancy_topic.1").unwrap()),
                     sequence: None,
                     shard_id: None,
+                    attributes: Default::default(),
                 },
                 r#"{ "payload": "anything" }"#
             )
@@ -220,6 +373,7 @@ mod test {
                     stream_key: Some(StreamKey::new("my-fancy_topic.1").unwrap()),
                     sequence: Some(123),
                     shard_id: None,
+                    attributes: Default::default(),
                 },
                 r#"["array", "of", "values"]"#
             )
@@ -236,12 +390,44 @@ mod test {
                     stream_key: Some(StreamKey::new("my-fancy_topic.1").unwrap()),
                     sequence: Some(123),
                     shard_id: Some(ShardId::new(4)),
+                    attributes: Default::default(),
                 },
                 r#"{ "payload": "anything" }"#
             )
         );
     }
 
+    #[test]
+    fn test_parse_meta_attributes() {
+        let (meta, payload) =
+            parse_meta(r#"[my_topic | 12 | key=user-7 | trace = abc] { "payload": "anything" }"#)
+                .unwrap();
+        assert_eq!(meta.stream_key, Some(StreamKey::new("my_topic").unwrap()));
+        assert_eq!(meta.sequence, Some(12));
+        assert_eq!(
+            meta.attributes,
+            [("key", "user-7"), ("trace", "abc")].into_iter().collect()
+        );
+        assert_eq!(payload, r#"{ "payload": "anything" }"#);
+
+        let (meta, payload) = parse_meta(r#"[key=a\|b\]c\\ | x=] payload"#).unwrap();
+        assert_eq!(meta.stream_key, None);
+        assert_eq!(meta.attributes.get("key"), Some(r#"a|b]c\"#));
+        assert_eq!(meta.attributes.get("x"), Some(""));
+        assert_eq!(payload, "payload");
+
+        let mut attributes = Attributes::new();
+        attributes.insert("key", r#"a|b]c\"#);
+        let header = attributes.to_parts().collect::<Vec<_>>().join(" | ");
+        assert_eq!(header, r#"key=a\|b\]c\\"#);
+        assert_eq!(parse_header(&header).unwrap().attributes, attributes);
+
+        assert!(matches!(
+            parse_meta(r#"[my_topic | a b=c] payload"#),
+            Err(ParseErr::Unknown(_))
+        ));
+    }
+
     #[test]
     fn test_parse_meta_error_1() {
         assert!(matches!(parse_meta(r#"[ ]"#), Err(ParseErr::Unknown(_))))
@@ -1,29 +1,33 @@
-use flume::{bounded, r#async::RecvFut, unbounded, Sender};
-use std::{collections::HashMap, fmt::Debug, future::Future, sync::Mutex};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use flume::{bounded, r#async::RecvFut, unbounded, Receiver, Sender};
+use std::{fmt::Debug, future::Future, io::ErrorKind, sync::Arc};
+use time::format_description::well_known::Rfc3339;
 
 use sea_streamer_types::{
     export::{async_trait, futures::FutureExt},
-    Buffer, Message, MessageHeader, Producer as ProducerTrait, Receipt, SeqNo, ShardId,
-    SharedMessage, StreamErr, StreamKey, StreamResult, Timestamp,
+    Buffer, Message, MessageHeader, Producer as ProducerTrait, Receipt, ShardId, SharedMessage,
+    StreamErr, StreamKey, StreamResult, Timestamp,
 };
 
-use crate::{PartialHeader, StdioErr, StdioResult, BROADCAST, TIMESTAMP_FORMAT};
-
-lazy_static::lazy_static! {
-    static ref PRODUCERS: Mutex<Producers> = Default::default();
-    static ref THREAD: Mutex<Option<Sender<Signal>>> = Mutex::new(None);
-}
-
-#[derive(Debug, Default)]
-struct Producers {
-    sequences: HashMap<StreamKey, SeqNo>,
-}
+use crate::{
+    channel::{Channel, Endpoint, Output},
+    framing::{to_json, write_frame},
+    Attributes, PartialHeader, StdioErr, StdioFraming, StdioProducerOptions, StdioResult,
+    TimestampFormat, TIMESTAMP_FORMAT,
+};
 
-enum Signal {
+pub(crate) enum Signal {
     SendRequest {
         message: SharedMessage,
         receipt: Sender<Receipt>,
         loopback: bool,
+        framing: StdioFraming,
+        options: Arc<StdioProducerOptions>,
+        attributes: Attributes,
+    },
+    /// Sends a receipt once everything before has been written
+    Flush {
+        receipt: Sender<()>,
     },
     Shutdown,
 }
@@ -33,6 +37,8 @@ pub struct StdioProducer {
     stream: Option<StreamKey>,
     request: Sender<Signal>,
     loopback: bool,
+    framing: StdioFraming,
+    options: Arc<StdioProducerOptions>,
 }
 
 pub struct SendFuture {
@@ -41,90 +47,191 @@ pub struct SendFuture {
 
 const ZERO: u64 = 0;
 
-pub(crate) fn init() {
-    let mut thread = THREAD.lock().expect("Failed to lock stdout thread");
+/// Returns the sender to the stdout thread, which is spawned if not running.
+pub(crate) fn init(channel: &Arc<Channel>) -> Sender<Signal> {
+    let mut thread = channel.writer.lock().expect("Failed to lock stdout thread");
     if thread.is_none() {
         let (sender, receiver) = unbounded();
+        let channel = channel.clone();
         let builder = std::thread::Builder::new().name("sea-streamer-stdio-stdout".into());
         builder
             .spawn(move || {
                 log::debug!("[{pid}] stdout thread spawned", pid = std::process::id());
-                // this thread locks the mutex forever
-                let mut producers = PRODUCERS
-                    .try_lock()
-                    .expect("Should have no other thread trying to access Producers");
-                while let Ok(signal) = receiver.recv() {
-                    match signal {
-                        Signal::SendRequest {
-                            mut message,
-                            receipt,
-                            loopback,
-                        } => {
-                            // we can time the difference from send() until now()
-                            message.touch(); // set timestamp to now
-
-                            // I believe println is atomic now, so we don't have to lock stdout
-                            // fn main() {
-                            //     std::thread::scope(|s| {
-                            //         for num in 0..100 {
-                            //             s.spawn(move || {
-                            //                 println!("Hello from thread number {}", num);
-                            //             });
-                            //         }
-                            //     });
-                            // }
-
-                            // don't print empty lines
-                            if message.message().size() != 0 {
-                                let stream_key = message.stream_key();
-                                let seq = producers.append(&stream_key);
-                                println!(
-                                    "[{timestamp} | {stream} | {seq}] {payload}",
-                                    timestamp = message
-                                        .timestamp()
-                                        .format(TIMESTAMP_FORMAT)
-                                        .expect("Timestamp format error"),
-                                    stream = stream_key,
-                                    seq = seq,
-                                    payload = message
-                                        .message()
-                                        .as_str()
-                                        .expect("Should have already checked is valid string"),
-                                );
-                                if loopback {
-                                    let payload = message.message();
-                                    super::consumer::dispatch(
-                                        PartialHeader {
-                                            timestamp: Some(message.timestamp()),
-                                            stream_key: Some(stream_key),
-                                            sequence: Some(seq),
-                                            shard_id: Some(message.shard_id()),
-                                        },
-                                        payload.into_bytes(),
-                                        0,
-                                    );
-                                }
-                            }
-                            let meta = message.take_header();
-                            // we don't care if the receipt can be delivered
-                            receipt.send(meta).ok();
-                        }
-                        Signal::Shutdown => break,
-                    }
+                // this has the potential to block, until the other end of a pipe is opened
+                match channel.open_output() {
+                    Ok(output) => write_messages(&channel, output, receiver),
+                    Err(e) => log::error!("Failed to open {:?}: {e}", channel.endpoint),
                 }
                 log::debug!("[{pid}] stdout thread exit", pid = std::process::id());
                 {
-                    let mut thread = THREAD.lock().expect("Failed to lock stdout thread");
+                    let mut thread = channel.writer.lock().expect("Failed to lock stdout thread");
                     thread.take(); // set to none
                 }
             })
             .unwrap();
         thread.replace(sender);
     }
+    thread.as_ref().expect("Should have initialized").to_owned()
+}
+
+fn write_messages(channel: &Channel, mut output: Output, receiver: Receiver<Signal>) {
+    // this thread locks the mutex forever
+    let mut producers = channel
+        .producers
+        .try_lock()
+        .expect("Should have no other thread trying to access Producers");
+    while let Ok(signal) = receiver.recv() {
+        match signal {
+            Signal::SendRequest {
+                mut message,
+                receipt,
+                loopback,
+                framing,
+                options,
+                attributes,
+            } => {
+                // we can time the difference from send() until now()
+                message.touch(); // set timestamp to now
+
+                // don't print empty lines, but empty payloads are frames like any other
+                if message.message().size() != 0 || framing.is_binary_safe() {
+                    let stream_key = message.stream_key();
+                    let seq = producers.append(&stream_key);
+                    let header = MessageHeader::new(
+                        stream_key.clone(),
+                        message.shard_id(),
+                        seq,
+                        message.timestamp(),
+                    );
+                    let payload = message.message();
+                    let res = match framing {
+                        StdioFraming::Line => output.write_line(&with_header(
+                            render_header(&options, &header, &attributes),
+                            payload
+                                .as_str()
+                                .expect("Should have already checked is valid string"),
+                        )),
+                        StdioFraming::Base64 => output.write_line(&with_header(
+                            render_header(&options, &header, &attributes),
+                            &STANDARD.encode(payload.as_bytes()),
+                        )),
+                        StdioFraming::Json => output.write_line(&to_json(
+                            &header,
+                            payload
+                                .as_str()
+                                .expect("Should have already checked is valid string"),
+                            &attributes,
+                        )),
+                        // the header has to be understood by the consumer on the other end
+                        StdioFraming::Binary => output.write_with(|writer| {
+                            write_frame(
+                                writer,
+                                &default_header(&header, &attributes),
+                                payload.as_bytes(),
+                            )
+                        }),
+                    };
+                    if let Err(e) = res {
+                        // e.g. the other end of the pipe has been closed
+                        log::error!("Failed to write to {:?}: {e}", channel.endpoint);
+                        break;
+                    }
+                    if loopback {
+                        super::consumer::dispatch(
+                            channel,
+                            PartialHeader {
+                                timestamp: Some(message.timestamp()),
+                                stream_key: Some(stream_key),
+                                sequence: Some(seq),
+                                shard_id: Some(message.shard_id()),
+                                attributes,
+                            },
+                            payload.into_bytes(),
+                            0,
+                        );
+                    }
+                }
+                let meta = message.take_header();
+                // we don't care if the receipt can be delivered
+                receipt.send(meta).ok();
+            }
+            Signal::Flush { receipt } => {
+                // we don't care if the receipt can be delivered
+                receipt.send(()).ok();
+            }
+            Signal::Shutdown => break,
+        }
+    }
+}
+
+/// The header in the default format, i.e. `timestamp | stream_key | sequence`, then the attributes
+fn default_header(header: &MessageHeader, attributes: &Attributes) -> String {
+    let mut parts = vec![
+        header
+            .timestamp()
+            .format(TIMESTAMP_FORMAT)
+            .expect("Timestamp format error"),
+        header.stream_key().to_string(),
+        header.sequence().to_string(),
+    ];
+    parts.extend(attributes.to_parts());
+    parts.join(" | ")
+}
+
+/// Returns None if no header should be printed.
+fn render_header(
+    options: &StdioProducerOptions,
+    header: &MessageHeader,
+    attributes: &Attributes,
+) -> Option<String> {
+    if options.raw() {
+        return None;
+    }
+    let mut parts = Vec::new();
+    match options.timestamp_format() {
+        TimestampFormat::Default => parts.push(
+            header
+                .timestamp()
+                .format(TIMESTAMP_FORMAT)
+                .expect("Timestamp format error"),
+        ),
+        TimestampFormat::Rfc3339 => parts.push(
+            header
+                .timestamp()
+                .format(&Rfc3339)
+                .expect("Timestamp format error"),
+        ),
+        TimestampFormat::UnixMillis => {
+            parts.push((header.timestamp().unix_timestamp_nanos() / 1_000_000).to_string())
+        }
+        TimestampFormat::None => (),
+    }
+    if options.stream_key() {
+        parts.push(header.stream_key().to_string());
+    }
+    if options.sequence() {
+        parts.push(header.sequence().to_string());
+    }
+    if options.shard_id() {
+        parts.push(header.shard_id().id().to_string());
+    }
+    parts.extend(attributes.to_parts());
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(options.separator()))
+    }
+}
+
+fn with_header(header: Option<String>, payload: &str) -> String {
+    match header {
+        Some(header) => format!("[{header}] {payload}"),
+        None => payload.to_owned(),
+    }
 }
 
-pub(crate) fn shutdown() {
-    let thread = THREAD.lock().expect("Failed to lock stdout thread");
+pub(crate) fn shutdown(channel: &Channel) {
+    let thread = channel.writer.lock().expect("Failed to lock stdout thread");
     if let Some(sender) = thread.as_ref() {
         sender
             .send(Signal::Shutdown)
@@ -132,25 +239,11 @@ pub(crate) fn shutdown() {
     }
 }
 
-pub(crate) fn shutdown_already() -> bool {
-    let thread = THREAD.lock().expect("Failed to lock stdout thread");
+pub(crate) fn shutdown_already(channel: &Channel) -> bool {
+    let thread = channel.writer.lock().expect("Failed to lock stdout thread");
     thread.is_none()
 }
 
-impl Producers {
-    // returns current Seq No
-    fn append(&mut self, stream: &StreamKey) -> SeqNo {
-        if let Some(val) = self.sequences.get_mut(stream) {
-            let seq = *val;
-            *val += 1;
-            seq
-        } else {
-            self.sequences.insert(stream.to_owned(), 1);
-            0
-        }
-    }
-}
-
 impl Future for SendFuture {
     type Output = StreamResult<MessageHeader, StdioErr>;
 
@@ -179,31 +272,9 @@ impl ProducerTrait for StdioProducer {
     type Error = StdioErr;
     type SendFuture = SendFuture;
 
+    /// With [`StdioFraming::Line`] or [`StdioFraming::Json`], the payload must be a valid UTF-8 string.
     fn send_to<S: Buffer>(&self, stream: &StreamKey, payload: S) -> StdioResult<Self::SendFuture> {
-        let payload = payload.as_str().map_err(StreamErr::Utf8Error)?.to_owned();
-        // basically using this as oneshot
-        let (sender, receiver) = bounded(1);
-        let size = payload.len();
-        self.request
-            .send(Signal::SendRequest {
-                message: SharedMessage::new(
-                    MessageHeader::new(
-                        stream.to_owned(),
-                        ShardId::new(ZERO),
-                        ZERO,
-                        Timestamp::now_utc(),
-                    ),
-                    payload.into_bytes(),
-                    0,
-                    size,
-                ),
-                receipt: sender,
-                loopback: self.loopback,
-            })
-            .map_err(|_| StreamErr::Backend(StdioErr::Disconnected))?;
-        Ok(SendFuture {
-            fut: receiver.into_recv_async(),
-        })
+        self.send_to_with_attributes(stream, payload, Attributes::new())
     }
 
     #[inline]
@@ -213,11 +284,16 @@ impl ProducerTrait for StdioProducer {
 
     #[inline]
     async fn flush(&mut self) -> StdioResult<()> {
-        // the trick here is to send an empty message (that will be dropped) to the stdout thread
-        // and wait for the receipt. By the time it returns a receipt, everything before should
-        // have already been sent
-        self.send_to(&StreamKey::new(BROADCAST)?, "")?.await?;
-        Ok(())
+        // send a flush request to the stdout thread and wait for the receipt.
+        // By the time it returns a receipt, everything before should have already been sent
+        let (sender, receiver) = bounded(1);
+        self.request
+            .send(Signal::Flush { receipt: sender })
+            .map_err(|_| StreamErr::Backend(StdioErr::Disconnected))?;
+        receiver
+            .recv_async()
+            .await
+            .map_err(|e| StreamErr::Backend(StdioErr::RecvError(e)))
     }
 
     fn anchor(&mut self, stream: StreamKey) -> StdioResult<()> {
@@ -239,21 +315,147 @@ impl ProducerTrait for StdioProducer {
 }
 
 impl StdioProducer {
+    /// Like `send_to`, but with attributes, which are rendered after the header fields,
+    /// e.g. `[2022-01-01T00:00:00 | my_topic | 12 | key=user-7 | trace=abc] payload`.
+    /// With [`StdioFraming::Json`], they are written as fields of the envelope.
+    pub fn send_to_with_attributes<S: Buffer>(
+        &self,
+        stream: &StreamKey,
+        payload: S,
+        attributes: Attributes,
+    ) -> StdioResult<SendFuture> {
+        if !self.framing.is_binary_safe() {
+            payload.as_str().map_err(StreamErr::Utf8Error)?;
+        } else if u32::try_from(payload.size()).is_err() {
+            return Err(StreamErr::Backend(StdioErr::IoError(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "Payload too large",
+            ))));
+        }
+        let payload = payload.into_bytes();
+        // basically using this as oneshot
+        let (sender, receiver) = bounded(1);
+        let size = payload.len();
+        self.request
+            .send(Signal::SendRequest {
+                message: SharedMessage::new(
+                    MessageHeader::new(
+                        stream.to_owned(),
+                        ShardId::new(ZERO),
+                        ZERO,
+                        Timestamp::now_utc(),
+                    ),
+                    payload,
+                    0,
+                    size,
+                ),
+                receipt: sender,
+                loopback: self.loopback,
+                framing: self.framing,
+                options: self.options.clone(),
+                attributes,
+            })
+            .map_err(|_| StreamErr::Backend(StdioErr::Disconnected))?;
+        Ok(SendFuture {
+            fut: receiver.into_recv_async(),
+        })
+    }
+
+    /// Like `send`, but with attributes. See [`StdioProducer::send_to_with_attributes`].
+    pub fn send_with_attributes<S: Buffer>(
+        &self,
+        payload: S,
+        attributes: Attributes,
+    ) -> StdioResult<SendFuture> {
+        self.send_to_with_attributes(self.anchored()?, payload, attributes)
+    }
+
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
-        Self::new_with(false)
+        Self::new_with(
+            &Channel::open(Endpoint::Stdio),
+            false,
+            Default::default(),
+            Default::default(),
+        )
     }
 
-    pub(crate) fn new_with(loopback: bool) -> Self {
-        init();
-        let request = {
-            let thread = THREAD.lock().expect("Failed to lock stdout thread");
-            thread.as_ref().expect("Should have initialized").to_owned()
-        };
+    pub(crate) fn new_with(
+        channel: &Arc<Channel>,
+        loopback: bool,
+        framing: StdioFraming,
+        options: StdioProducerOptions,
+    ) -> Self {
+        let request = init(channel);
         Self {
             stream: None,
             request,
             loopback,
+            framing,
+            options: Arc::new(options),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn test_render_header() {
+        let header = MessageHeader::new(
+            StreamKey::new("hello").unwrap(),
+            ShardId::new(2),
+            3,
+            datetime!(2022-01-02 03:04:05.678).assume_utc(),
+        );
+        let none = Attributes::new();
+        let mut options = StdioProducerOptions::default();
+        assert_eq!(
+            with_header(render_header(&options, &header, &none), "hi"),
+            "[2022-01-02T03:04:05 | hello | 3] hi"
+        );
+        assert_eq!(
+            render_header(&options, &header, &none),
+            Some(default_header(&header, &none))
+        );
+
+        options.set_timestamp_format(TimestampFormat::Rfc3339);
+        options.set_shard_id(true);
+        options.set_separator(",");
+        assert_eq!(
+            render_header(&options, &header, &none).unwrap(),
+            "2022-01-02T03:04:05.678Z,hello,3,2"
+        );
+
+        options.set_timestamp_format(TimestampFormat::UnixMillis);
+        options.set_stream_key(false);
+        options.set_shard_id(false);
+        assert_eq!(
+            render_header(&options, &header, &none).unwrap(),
+            "1641092645678,3"
+        );
+
+        options.set_timestamp_format(TimestampFormat::None);
+        options.set_sequence(false);
+        assert_eq!(
+            with_header(render_header(&options, &header, &none), "hi"),
+            "hi"
+        );
+
+        let attributes: Attributes = [("key", "user-7"), ("trace", "a|b")].into_iter().collect();
+        assert_eq!(
+            render_header(&options, &header, &attributes).unwrap(),
+            "key=user-7,trace=a\\|b"
+        );
+
+        let mut options = StdioProducerOptions::default();
+        assert_eq!(
+            default_header(&header, &attributes),
+            "2022-01-02T03:04:05 | hello | 3 | key=user-7 | trace=a\\|b"
+        );
+        options.set_raw(true);
+        assert_eq!(render_header(&options, &header, &none), None);
+    }
+}
@@ -1,32 +1,73 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 use crate::{
-    consumer, create_consumer, producer, StdioConsumer, StdioErr, StdioProducer, StdioResult,
+    channel::{Channel, Endpoint},
+    consumer, create_consumer, producer, StdioConsumer, StdioErr, StdioFraming, StdioProducer,
+    StdioResult,
 };
 use sea_streamer_types::{
     export::async_trait, ConnectOptions as ConnectOptionsTrait, ConsumerGroup, ConsumerMode,
-    ConsumerOptions as ConsumerOptionsTrait, ProducerOptions as ProducerOptionsTrait, StreamErr,
-    StreamKey, Streamer as StreamerTrait, StreamerUri,
+    ConsumerOptions as ConsumerOptionsTrait, LoadBalance, ProducerOptions as ProducerOptionsTrait,
+    StreamErr, StreamKey, Streamer as StreamerTrait, StreamerUri,
 };
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Clone)]
 pub struct StdioStreamer {
-    loopback: bool,
+    channel: Arc<Channel>,
+    options: StdioConnectOptions,
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct StdioConnectOptions {
     loopback: bool,
+    framing: StdioFraming,
+    pace: Option<f64>,
+    at_eof: AtEof,
+}
+
+/// What to do when stdin (or the input file) reaches the end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AtEof {
+    /// Consumers keep waiting, e.g. for loopback messages.
+    Wait,
+    /// Consumers get [`StdioErr::StreamEnded`] after all messages have been consumed.
+    End,
 }
 
 #[derive(Debug, Clone)]
 pub struct StdioConsumerOptions {
     mode: ConsumerMode,
     group: Option<ConsumerGroup>,
+    load_balance: LoadBalance,
 }
 
-#[derive(Debug, Default, Clone)]
-pub struct StdioProducerOptions {}
+/// Options on how the header of each message is printed. They only apply to the line based
+/// framings, i.e. [`StdioFraming::Line`] and [`StdioFraming::Base64`].
+///
+/// Note that the stdio consumer can only parse headers with the default separator and timestamp
+/// format.
+#[derive(Debug, Clone)]
+pub struct StdioProducerOptions {
+    raw: bool,
+    timestamp: TimestampFormat,
+    stream_key: bool,
+    sequence: bool,
+    shard_id: bool,
+    separator: String,
+}
+
+/// How timestamps are printed by the stdio producer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampFormat {
+    /// [`crate::TIMESTAMP_FORMAT`], e.g. `2022-01-01T00:00:00`
+    Default,
+    /// e.g. `2022-01-01T00:00:00.123Z`
+    Rfc3339,
+    /// Milliseconds since the Unix epoch, e.g. `1640995200123`
+    UnixMillis,
+    /// Do not print the timestamp
+    None,
+}
 
 #[async_trait]
 impl StreamerTrait for StdioStreamer {
@@ -37,21 +78,29 @@ impl StreamerTrait for StdioStreamer {
     type ConsumerOptions = StdioConsumerOptions;
     type ProducerOptions = StdioProducerOptions;
 
-    /// Nothing will happen until you create a producer/consumer
-    async fn connect(_: StreamerUri, options: Self::ConnectOptions) -> StdioResult<Self> {
-        let StdioConnectOptions { loopback } = options;
-        Ok(StdioStreamer { loopback })
+    /// Nothing will happen until you create a producer/consumer.
+    ///
+    /// `stdio://` (or [`StreamerUri::zero`]) is the process's own stdin and stdout, which are shared
+    /// among all streamers. `stdio:///path/to/fifo` reads from and writes to a file, named pipe
+    /// or Unix socket, and `stdio:///path/to/input,/path/to/output` reads from and writes to
+    /// different paths. Each of these streamers has its own consumers and producers, so a process
+    /// can join multiple pipelines. The paths are only opened when the first consumer or producer
+    /// is created.
+    async fn connect(uri: StreamerUri, options: Self::ConnectOptions) -> StdioResult<Self> {
+        let channel = Channel::open(Endpoint::from_uri(&uri)?);
+        Ok(StdioStreamer { channel, options })
     }
 
     /// Call this method if you want to exit gracefully. This waits asynchronously until all pending messages
     /// are sent.
     ///
-    /// The side effects is global: all existing consumers and producers will become unusable, until you connect again.
+    /// The side effects is global (for the same stdin / stdout): all existing consumers and producers will become
+    /// unusable, until you connect again.
     async fn disconnect(self) -> StdioResult<()> {
         // we can't reliably shutdown consumers
-        consumer::disconnect();
-        producer::shutdown();
-        while !producer::shutdown_already() {
+        consumer::disconnect(&self.channel);
+        producer::shutdown(&self.channel);
+        while !producer::shutdown_already(&self.channel) {
             sea_streamer_runtime::sleep(Duration::from_millis(1)).await;
         }
         Ok(())
@@ -59,9 +108,14 @@ impl StreamerTrait for StdioStreamer {
 
     async fn create_generic_producer(
         &self,
-        _: Self::ProducerOptions,
+        options: Self::ProducerOptions,
     ) -> StdioResult<Self::Producer> {
-        Ok(StdioProducer::new_with(self.loopback))
+        Ok(StdioProducer::new_with(
+            &self.channel,
+            self.options.loopback,
+            self.options.framing,
+            options,
+        ))
     }
 
     /// A background thread will be spawned to read stdin dedicatedly.
@@ -76,14 +130,26 @@ impl StreamerTrait for StdioStreamer {
                 if options.group.is_some() {
                     log::warn!("Consumer group is set and thus will be load-balanced.");
                 }
-                Ok(create_consumer(options.group, streams.to_vec()))
+                create_consumer(
+                    &self.channel,
+                    options.group,
+                    options.load_balance,
+                    streams.to_vec(),
+                    &self.options,
+                )
             }
             ConsumerMode::Resumable => Err(StreamErr::Unsupported(
                 "stdio does not support Resumable".to_owned(),
             )),
             ConsumerMode::LoadBalanced => {
                 if options.group.is_some() {
-                    Ok(create_consumer(options.group, streams.to_vec()))
+                    create_consumer(
+                        &self.channel,
+                        options.group,
+                        options.load_balance,
+                        streams.to_vec(),
+                        &self.options,
+                    )
                 } else {
                     Err(StreamErr::ConsumerGroupNotSet)
                 }
@@ -107,6 +173,56 @@ impl StdioConnectOptions {
     pub fn set_loopback(&mut self, b: bool) {
         self.loopback = b;
     }
+
+    pub fn framing(&self) -> StdioFraming {
+        self.framing
+    }
+
+    /// How messages are framed on stdin and stdout. Default is [`StdioFraming::Line`].
+    ///
+    /// The stdin thread is spawned by the first consumer, and keeps the framing it was spawned with.
+    pub fn set_framing(&mut self, framing: StdioFraming) {
+        self.framing = framing;
+    }
+
+    pub fn pace(&self) -> Option<f64> {
+        self.pace
+    }
+
+    /// If set, input messages are delivered at the pace of the timestamps in their headers,
+    /// times the speed factor, e.g. `Some(2.0)` replays a recording at double speed.
+    /// Messages without a timestamp are delivered immediately.
+    ///
+    /// Default is `None`, i.e. as fast as possible. Like framing, the stdin thread keeps
+    /// the pace it was spawned with.
+    ///
+    /// Returns [`StdioErr::InvalidConfig`] if the speed factor is not a positive finite number.
+    pub fn set_pace(&mut self, speed: Option<f64>) -> StdioResult<&mut Self> {
+        if let Some(speed) = speed {
+            if !(speed.is_finite() && speed > 0.0) {
+                return Err(StreamErr::Backend(StdioErr::InvalidConfig(format!(
+                    "Speed factor must be positive, got {speed}"
+                ))));
+            }
+        }
+        self.pace = speed;
+        Ok(self)
+    }
+
+    pub fn at_eof(&self) -> AtEof {
+        self.at_eof
+    }
+
+    /// What to do at the end of input. Default is [`AtEof::Wait`].
+    pub fn set_at_eof(&mut self, at_eof: AtEof) {
+        self.at_eof = at_eof;
+    }
+}
+
+impl Default for AtEof {
+    fn default() -> Self {
+        Self::Wait
+    }
 }
 
 impl ConnectOptionsTrait for StdioConnectOptions {
@@ -126,7 +242,11 @@ impl ConsumerOptionsTrait for StdioConsumerOptions {
     type Error = StdioErr;
 
     fn new(mode: ConsumerMode) -> Self {
-        Self { mode, group: None }
+        Self {
+            mode,
+            group: None,
+            load_balance: Default::default(),
+        }
     }
 
     fn mode(&self) -> StdioResult<&ConsumerMode> {
@@ -138,17 +258,101 @@ impl ConsumerOptionsTrait for StdioConsumerOptions {
     }
 
     /// If multiple consumers share the same group, only one in the group will receive a message.
-    /// This is load-balanced in a round-robin fashion.
+    /// This is load-balanced in a round-robin fashion, unless set otherwise with
+    /// [`StdioConsumerOptions::set_load_balance`].
     fn set_consumer_group(&mut self, group: ConsumerGroup) -> StdioResult<&mut Self> {
         self.group = Some(group);
         Ok(self)
     }
 }
 
+impl StdioConsumerOptions {
+    pub fn load_balance(&self) -> &LoadBalance {
+        &self.load_balance
+    }
+
+    /// How messages are distributed among the consumers of the same group. The strategy of the
+    /// first consumer joining a group is used. Default is `RoundRobin`.
+    ///
+    /// With `Sticky`, consumers of the same group should subscribe to the same streams,
+    /// otherwise the assignments will be rebalanced back and forth.
+    pub fn set_load_balance(&mut self, strategy: LoadBalance) {
+        self.load_balance = strategy;
+    }
+}
+
 impl Default for StdioConsumerOptions {
     fn default() -> Self {
         Self::new(ConsumerMode::RealTime)
     }
 }
 
+impl StdioProducerOptions {
+    pub fn raw(&self) -> bool {
+        self.raw
+    }
+
+    /// If set to true, only the payload will be printed, without the header. Default is false.
+    pub fn set_raw(&mut self, raw: bool) {
+        self.raw = raw;
+    }
+
+    pub fn timestamp_format(&self) -> TimestampFormat {
+        self.timestamp
+    }
+
+    pub fn set_timestamp_format(&mut self, format: TimestampFormat) {
+        self.timestamp = format;
+    }
+
+    pub fn stream_key(&self) -> bool {
+        self.stream_key
+    }
+
+    /// Whether to print the stream key. Default is true.
+    pub fn set_stream_key(&mut self, b: bool) {
+        self.stream_key = b;
+    }
+
+    pub fn sequence(&self) -> bool {
+        self.sequence
+    }
+
+    /// Whether to print the sequence number. Default is true.
+    pub fn set_sequence(&mut self, b: bool) {
+        self.sequence = b;
+    }
+
+    pub fn shard_id(&self) -> bool {
+        self.shard_id
+    }
+
+    /// Whether to print the shard id. Default is false.
+    pub fn set_shard_id(&mut self, b: bool) {
+        self.shard_id = b;
+    }
+
+    pub fn separator(&self) -> &str {
+        &self.separator
+    }
+
+    /// The separator between header fields. Default is ` | `.
+    pub fn set_separator<S: Into<String>>(&mut self, separator: S) {
+        self.separator = separator.into();
+    }
+}
+
+impl Default for StdioProducerOptions {
+    fn default() -> Self {
+        Self {
+            raw: false,
+            timestamp: TimestampFormat::Default,
+            stream_key: true,
+            sequence: true,
+            shard_id: false,
+            separator: " | ".to_owned(),
+        }
+    }
+}
+
 impl ProducerOptionsTrait for StdioProducerOptions {}
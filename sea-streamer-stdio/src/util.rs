@@ -1,3 +1,6 @@
+use sea_streamer_types::Timestamp;
+use std::time::{Duration, Instant};
+
 pub struct PanicGuard;
 
 impl Drop for PanicGuard {
@@ -7,3 +10,41 @@ impl Drop for PanicGuard {
         }
     }
 }
+
+/// Longest a message is delayed for, about 30 years
+const MAX_DELAY: Duration = Duration::from_secs(1 << 30);
+
+/// Delays messages according to their timestamps, relative to the first message.
+#[derive(Debug)]
+pub struct Pacer {
+    speed: f64,
+    origin: Option<(Timestamp, Instant)>,
+}
+
+impl Pacer {
+    pub fn new(speed: f64) -> Self {
+        Self {
+            speed,
+            origin: None,
+        }
+    }
+
+    /// Block until it is time to deliver a message with this timestamp
+    pub fn wait(&mut self, timestamp: Option<Timestamp>) {
+        let timestamp = match timestamp {
+            Some(timestamp) => timestamp,
+            None => return,
+        };
+        let (first, start) = *self.origin.get_or_insert((timestamp, Instant::now()));
+        let elapsed = timestamp - first;
+        if elapsed.is_positive() {
+            // the speed is positive and finite, but a very slow pace could still overflow
+            let secs = (elapsed.as_seconds_f64() / self.speed).min(MAX_DELAY.as_secs_f64());
+            let delay = Duration::from_secs_f64(secs);
+            let remaining = delay.saturating_sub(start.elapsed());
+            if !remaining.is_zero() {
+                std::thread::sleep(remaining);
+            }
+        }
+    }
+}
@@ -0,0 +1,81 @@
+// cargo test --test balance --features=test -- --nocapture
+#[cfg(feature = "test")]
+#[tokio::test]
+async fn main() -> anyhow::Result<()> {
+    use sea_streamer_stdio::{
+        StdioConnectOptions, StdioConsumer, StdioConsumerOptions, StdioErr, StdioStreamer,
+    };
+    use sea_streamer_types::{
+        Consumer, ConsumerGroup, ConsumerMode, ConsumerOptions, LoadBalance, Message, Producer,
+        StreamErr, StreamKey, Streamer, StreamerUri,
+    };
+    use std::time::Duration;
+
+    env_logger::init();
+
+    let streams = ["a", "b", "c", "d"]
+        .into_iter()
+        .map(StreamKey::new)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut options = StdioConnectOptions::default();
+    options.set_loopback(true);
+    let streamer = StdioStreamer::connect(StreamerUri::zero(), options).await?;
+    let producer = streamer
+        .create_producer(streams[0].clone(), Default::default())
+        .await?;
+
+    let mut consumer_opt = StdioConsumerOptions::new(ConsumerMode::LoadBalanced);
+    consumer_opt.set_consumer_group(ConsumerGroup::new("sticky".to_owned()))?;
+    consumer_opt.set_load_balance(LoadBalance::Sticky);
+    let first = streamer
+        .create_consumer(&streams, consumer_opt.clone())
+        .await?;
+    let second = streamer
+        .create_consumer(&streams, consumer_opt.clone())
+        .await?;
+    // members of a group must balance in the same way
+    let mut round_robin = consumer_opt.clone();
+    round_robin.set_load_balance(LoadBalance::RoundRobin);
+    assert!(matches!(
+        streamer.create_consumer(&streams, round_robin).await,
+        Err(StreamErr::Backend(StdioErr::InvalidConfig(_)))
+    ));
+
+    for i in 0..3 {
+        for stream in streams.iter() {
+            producer.send_to(stream, format!("{i}"))?;
+        }
+    }
+
+    // each stream goes to one consumer only, and in order
+    let first = consume(&first, 6).await;
+    let second = consume(&second, 6).await;
+    for (mine, theirs) in [(&first, &second), (&second, &first)] {
+        for (key, _) in mine.iter() {
+            assert!(theirs.iter().all(|(k, _)| k != key));
+            let seqs: Vec<_> = mine
+                .iter()
+                .filter(|(k, _)| k == key)
+                .map(|(_, s)| *s)
+                .collect();
+            assert_eq!(seqs, [0, 1, 2]);
+        }
+    }
+
+    streamer.disconnect().await?;
+
+    async fn consume(consumer: &StdioConsumer, num: usize) -> Vec<(StreamKey, u64)> {
+        let mut messages = Vec::new();
+        for _ in 0..num {
+            let mess = tokio::time::timeout(Duration::from_secs(1), consumer.next())
+                .await
+                .expect("Timeout")
+                .unwrap();
+            messages.push((mess.stream_key(), mess.sequence()));
+        }
+        messages
+    }
+
+    Ok(())
+}
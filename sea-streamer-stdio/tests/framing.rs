@@ -0,0 +1,142 @@
+// cargo test --test framing --features=test -- --nocapture
+#[cfg(feature = "test")]
+#[tokio::test]
+async fn main() -> anyhow::Result<()> {
+    use sea_streamer_stdio::{StdioConnectOptions, StdioFraming, StdioStreamer};
+    use sea_streamer_types::{
+        Buffer, Consumer, Message, Producer, StreamErr, StreamKey, Streamer, StreamerUri,
+    };
+
+    env_logger::init();
+
+    let stream = StreamKey::new("hello")?;
+    let payload: &[u8] = b"\x00\x01\n\xff binary";
+
+    let mut options = StdioConnectOptions::default();
+    options.set_loopback(true);
+    let streamer = StdioStreamer::connect(StreamerUri::zero(), options.clone()).await?;
+    let producer = streamer
+        .create_producer(stream.clone(), Default::default())
+        .await?;
+    // not valid UTF-8
+    assert!(matches!(
+        producer.send(payload),
+        Err(StreamErr::Utf8Error(_))
+    ));
+    streamer.disconnect().await?;
+
+    options.set_framing(StdioFraming::Base64);
+    let streamer = StdioStreamer::connect(StreamerUri::zero(), options).await?;
+    let producer = streamer
+        .create_producer(stream.clone(), Default::default())
+        .await?;
+    let consumer = streamer
+        .create_consumer(std::slice::from_ref(&stream), Default::default())
+        .await?;
+
+    for i in 0..5 {
+        producer.send(payload)?;
+        let mess = consumer.next().await?;
+        assert_eq!(mess.sequence(), i);
+        assert_eq!(mess.message().as_bytes(), payload);
+    }
+    // an empty payload is a message too
+    producer.send("")?;
+    let mess = consumer.next().await?;
+    assert_eq!(mess.sequence(), 5);
+    assert!(mess.message().as_bytes().is_empty());
+
+    streamer.disconnect().await?;
+
+    Ok(())
+}
+
+// cargo test --test framing --features=test -- --nocapture external
+#[cfg(feature = "test")]
+#[tokio::test]
+async fn external() -> anyhow::Result<()> {
+    use sea_streamer_stdio::{AtEof, StdioConnectOptions, StdioFraming, StdioStreamer};
+    use sea_streamer_types::{
+        Buffer, Consumer, Message, Producer, StreamKey, Streamer, StreamerUri,
+    };
+
+    let dir =
+        std::env::temp_dir().join(format!("sea-streamer-stdio-framing-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let stream = StreamKey::new("hello")?;
+
+    let read = |path: std::path::PathBuf, framing: StdioFraming| {
+        let stream = stream.clone();
+        async move {
+            let mut options = StdioConnectOptions::default();
+            options.set_framing(framing);
+            options.set_at_eof(AtEof::End);
+            let uri: StreamerUri = format!("stdio://{}", path.display()).parse()?;
+            let streamer = StdioStreamer::connect(uri, options).await?;
+            let consumer = streamer
+                .create_consumer(&[stream], Default::default())
+                .await?;
+            let mut messages = Vec::new();
+            while let Ok(mess) = consumer.next().await {
+                messages.push((
+                    mess.stream_key().name().to_owned(),
+                    mess.sequence(),
+                    mess.message().as_bytes().to_vec(),
+                ));
+            }
+            streamer.disconnect().await?;
+            anyhow::Ok(messages)
+        }
+    };
+    let expected = vec![
+        ("hello".to_owned(), 1, b"\x00\x01\n\xff".to_vec()),
+        ("hello".to_owned(), 2, Vec::new()),
+        ("hello".to_owned(), 3, b"three".to_vec()),
+    ];
+
+    // base64 lines, as produced by e.g. `base64` in a shell script
+    let base64 = dir.join("base64");
+    std::fs::write(
+        &base64,
+        "[2022-01-02T03:04:05 | hello | 1] AAEK/w==\n[2022-01-02T03:04:05 | hello | 2] \n[2022-01-02T03:04:05 | hello | 3] dGhyZWU=\n",
+    )?;
+    assert_eq!(read(base64, StdioFraming::Base64).await?, expected);
+
+    // length-prefixed binary frames, assembled by hand
+    let mut frames = Vec::new();
+    for (seq, payload) in [(1, &b"\x00\x01\n\xff"[..]), (2, b""), (3, b"three")] {
+        let header = format!("2022-01-02T03:04:05 | hello | {seq}");
+        frames.extend_from_slice(&(header.len() as u16).to_be_bytes());
+        frames.extend_from_slice(header.as_bytes());
+        frames.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        frames.extend_from_slice(payload);
+    }
+    let binary = dir.join("binary");
+    std::fs::write(&binary, &frames)?;
+    assert_eq!(read(binary, StdioFraming::Binary).await?, expected);
+
+    // the producer writes empty payloads as frames, which can be read back
+    let output = dir.join("output");
+    let mut options = StdioConnectOptions::default();
+    options.set_framing(StdioFraming::Binary);
+    let uri: StreamerUri = format!("stdio://{}", output.display()).parse()?;
+    let streamer = StdioStreamer::connect(uri, options).await?;
+    let mut producer = streamer
+        .create_producer(stream.clone(), Default::default())
+        .await?;
+    producer.send(&b"\x00\x01\n\xff"[..])?;
+    producer.send("")?;
+    producer.send("three")?;
+    producer.flush().await?;
+    streamer.disconnect().await?;
+    let messages = read(output, StdioFraming::Binary).await?;
+    let payloads: Vec<_> = messages.into_iter().map(|(_, _, p)| p).collect();
+    assert_eq!(
+        payloads,
+        expected.into_iter().map(|(_, _, p)| p).collect::<Vec<_>>()
+    );
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
@@ -0,0 +1,59 @@
+// cargo test --test pace --features=test -- --nocapture
+#[cfg(feature = "test")]
+#[tokio::test]
+async fn main() -> anyhow::Result<()> {
+    use sea_streamer_stdio::{AtEof, StdioConnectOptions, StdioErr, StdioStreamer};
+    use sea_streamer_types::{
+        Buffer, Consumer, Message, StreamErr, StreamKey, Streamer, StreamerUri,
+    };
+    use std::time::{Duration, Instant};
+
+    env_logger::init();
+
+    let dir = std::env::temp_dir().join(format!("sea-streamer-stdio-pace-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let recording = dir.join("recording.log");
+    std::fs::write(
+        &recording,
+        concat!(
+            "[2022-01-01T00:00:00.000 | hello] a\n",
+            "[2022-01-01T00:00:00.200 | hello] b\n",
+            "[hello] no timestamp\n",
+            "[2022-01-01T00:00:00.400 | hello] c\n",
+        ),
+    )?;
+
+    let hello = StreamKey::new("hello")?;
+    let mut options = StdioConnectOptions::default();
+    options.set_pace(Some(2.0))?;
+    for invalid in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+        assert!(matches!(
+            options.clone().set_pace(Some(invalid)),
+            Err(StreamErr::Backend(StdioErr::InvalidConfig(_)))
+        ));
+    }
+    options.set_at_eof(AtEof::End);
+    let uri: StreamerUri = format!("stdio://{}", recording.display()).parse()?;
+    let streamer = StdioStreamer::connect(uri, options).await?;
+
+    let start = Instant::now();
+    let consumer = streamer
+        .create_consumer(std::slice::from_ref(&hello), Default::default())
+        .await?;
+    for payload in ["a", "b", "no timestamp", "c"] {
+        let mess = consumer.next().await?;
+        assert_eq!(mess.message().as_str()?, payload);
+    }
+    // 400ms at double speed; there is no upper bound on a busy machine
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(200), "{elapsed:?}");
+
+    assert!(matches!(
+        consumer.next().await,
+        Err(StreamErr::Backend(StdioErr::StreamEnded))
+    ));
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
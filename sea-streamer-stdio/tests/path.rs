@@ -0,0 +1,131 @@
+// cargo test --test path --features=test -- --nocapture
+#[cfg(all(feature = "test", unix))]
+#[tokio::test]
+async fn main() -> anyhow::Result<()> {
+    use sea_streamer_stdio::{AtEof, StdioConnectOptions, StdioErr, StdioFraming, StdioStreamer};
+    use sea_streamer_types::{
+        Buffer, Consumer, Message, Producer, StreamErr, StreamKey, Streamer, StreamerUri,
+    };
+    use std::{
+        io::{BufRead, BufReader, Write},
+        os::unix::net::UnixListener,
+        time::Duration,
+    };
+
+    env_logger::init();
+
+    let dir = std::env::temp_dir().join(format!("sea-streamer-stdio-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let input = dir.join("input");
+    let output = dir.join("output");
+    let socket = dir.join("socket");
+    std::fs::write(
+        &input,
+        "[hello | 1] one\n[world | 1] not for us\n[hello | 2 | key=user-7] two\n",
+    )?;
+
+    let hello = StreamKey::new("hello")?;
+    let world = StreamKey::new("world")?;
+
+    // one streamer reads from a file, another writes to a file
+    let reader: StreamerUri = format!("stdio://{}", input.display()).parse()?;
+    let reader = StdioStreamer::connect(reader, Default::default()).await?;
+    let file_consumer = reader
+        .create_consumer(std::slice::from_ref(&hello), Default::default())
+        .await?;
+    let writer: StreamerUri = format!("stdio://{}", output.display()).parse()?;
+    let writer = StdioStreamer::connect(writer, Default::default()).await?;
+    let mut producer = writer
+        .create_producer(world.clone(), Default::default())
+        .await?;
+
+    for i in 1..=2 {
+        let (mess, attributes) = file_consumer.next_with_attributes().await?;
+        assert_eq!(mess.stream_key(), hello);
+        assert_eq!(mess.sequence(), i);
+        assert_eq!(
+            attributes.get("key"),
+            if i == 2 { Some("user-7") } else { None }
+        );
+        producer.send_with_attributes(mess.message().as_str()?, attributes)?;
+    }
+    producer.flush().await?;
+    writer.disconnect().await?;
+
+    let lines = std::fs::read_to_string(&output)?;
+    let lines: Vec<_> = lines.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].ends_with(" | world | 0] one"));
+    assert!(lines[1].ends_with(" | world | 1 | key=user-7] two"));
+
+    // the file is read only once, a consumer created after its end does not read it again
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let late_consumer = reader
+        .create_consumer(std::slice::from_ref(&hello), Default::default())
+        .await?;
+    for consumer in [&file_consumer, &late_consumer] {
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), consumer.next())
+                .await
+                .is_err()
+        );
+    }
+    let mut options = StdioConnectOptions::default();
+    options.set_at_eof(AtEof::End);
+    let uri: StreamerUri = format!("stdio://{}", input.display()).parse()?;
+    let ending = StdioStreamer::connect(uri, options).await?;
+    let consumer = ending
+        .create_consumer(std::slice::from_ref(&hello), Default::default())
+        .await?;
+    for _ in 1..=2 {
+        consumer.next().await?;
+    }
+    assert!(matches!(
+        consumer.next().await,
+        Err(StreamErr::Backend(StdioErr::StreamEnded))
+    ));
+    let late_consumer = ending
+        .create_consumer(std::slice::from_ref(&hello), Default::default())
+        .await?;
+    assert!(matches!(
+        late_consumer.next().await,
+        Err(StreamErr::Backend(StdioErr::StreamEnded))
+    ));
+
+    // a Unix socket can be read from and written to
+    let listener = UnixListener::bind(&socket)?;
+    let server = std::thread::spawn(move || -> std::io::Result<String> {
+        let (mut stream, _) = listener.accept()?;
+        stream.write_all(b"{\"header\":{\"stream_key\":\"hello\"},\"payload\":{\"a\":1}}\n")?;
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line)?;
+        Ok(line)
+    });
+    let mut options = StdioConnectOptions::default();
+    options.set_framing(StdioFraming::Json);
+    let uri: StreamerUri = format!("stdio://{}", socket.display()).parse()?;
+    let streamer = StdioStreamer::connect(uri, options).await?;
+    let consumer = streamer
+        .create_consumer(std::slice::from_ref(&hello), Default::default())
+        .await?;
+    let producer = streamer
+        .create_producer(world.clone(), Default::default())
+        .await?;
+    let mess = consumer.next().await?;
+    assert_eq!(mess.message().as_str()?, r#"{"a":1}"#);
+    producer.send(r#"{"b":2}"#)?;
+    let line = server.join().expect("Server panicked")?;
+    assert!(line.starts_with(r#"{"header":{"stream_key":"world","#));
+    assert!(line.ends_with("\"payload\":{\"b\":2}}\n"));
+
+    // the streamers are independent: the message from the socket did not go to the file consumer
+    assert!(
+        tokio::time::timeout(Duration::from_millis(10), file_consumer.next())
+            .await
+            .is_err()
+    );
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
@@ -10,6 +10,7 @@
 
 mod consumer;
 mod error;
+mod load_balance;
 mod message;
 mod options;
 mod producer;
@@ -18,6 +19,7 @@ mod streamer;
 
 pub use consumer::*;
 pub use error::*;
+pub use load_balance::*;
 pub use message::*;
 pub use options::*;
 pub use producer::*;
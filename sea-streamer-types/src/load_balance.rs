@@ -0,0 +1,307 @@
+use std::{collections::BTreeMap, fmt::Debug, sync::Arc};
+
+use crate::{Message, ShardId, SharedMessage, StreamKey};
+
+/// Extracts the key of a message, e.g. a field of the payload. Returns None if there is none.
+pub type MessageKeyFn = Arc<dyn Fn(&SharedMessage) -> Option<Vec<u8>> + Send + Sync>;
+
+#[derive(Clone)]
+/// How messages are distributed among the members of a consumer group, for backends that
+/// do the load balancing themselves (e.g. stdio and file).
+///
+/// Except for `RoundRobin`, messages of the same entity always go to the same member,
+/// so the order of messages per entity is preserved within a worker.
+pub enum LoadBalance {
+    /// Each message goes to the next member in turn. This is the default.
+    RoundRobin,
+    /// Messages are assigned by the hash of `(stream_key, shard_id)`.
+    HashStreamShard,
+    /// Messages are assigned by the hash of the key returned by the function.
+    /// Messages without a key are assigned round-robin.
+    ///
+    /// ```
+    /// use sea_streamer_types::{Buffer, LoadBalance, Message};
+    /// use std::sync::Arc;
+    ///
+    /// // hash by the text before the first `:` of the payload
+    /// let strategy = LoadBalance::HashKey(Arc::new(|message| {
+    ///     let payload = message.message();
+    ///     let key = payload.as_str().ok()?.split(':').next()?;
+    ///     Some(key.as_bytes().to_vec())
+    /// }));
+    /// ```
+    HashKey(MessageKeyFn),
+    /// Each `(stream_key, shard_id)` is assigned to the least loaded member when first seen,
+    /// and stays there. When members join or leave, the assignments are rebalanced, moving
+    /// as few entities as possible. Members are expected to subscribe to the same streams.
+    Sticky,
+}
+
+#[derive(Debug)]
+/// Keeps the state of a [`LoadBalance`] strategy for one consumer group.
+pub struct GroupBalancer<Id> {
+    strategy: LoadBalance,
+    members: Vec<Id>,
+    assignments: BTreeMap<(StreamKey, ShardId), Id>,
+}
+
+impl Default for LoadBalance {
+    fn default() -> Self {
+        Self::RoundRobin
+    }
+}
+
+/// `HashKey` strategies are equal only if they are clones of the same `Arc`.
+impl PartialEq for LoadBalance {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::RoundRobin, Self::RoundRobin)
+            | (Self::HashStreamShard, Self::HashStreamShard)
+            | (Self::Sticky, Self::Sticky) => true,
+            // only compare the data pointers, as vtables of the same type are not necessarily unique
+            (Self::HashKey(a), Self::HashKey(b)) => {
+                Arc::as_ptr(a) as *const () == Arc::as_ptr(b) as *const ()
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Eq for LoadBalance {}
+
+impl Debug for LoadBalance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RoundRobin => write!(f, "RoundRobin"),
+            Self::HashStreamShard => write!(f, "HashStreamShard"),
+            Self::HashKey(_) => write!(f, "HashKey(..)"),
+            Self::Sticky => write!(f, "Sticky"),
+        }
+    }
+}
+
+impl<Id: Copy + Ord + Debug> GroupBalancer<Id> {
+    pub fn new(strategy: LoadBalance) -> Self {
+        Self {
+            strategy,
+            members: Vec::new(),
+            assignments: Default::default(),
+        }
+    }
+
+    pub fn strategy(&self) -> &LoadBalance {
+        &self.strategy
+    }
+
+    /// Select the member to receive this message. Members can change between calls.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `members` is empty.
+    pub fn select(&mut self, members: &[Id], message: &SharedMessage) -> Id {
+        assert!(!members.is_empty(), "A group has at least one member");
+        // This round-robin is deterministic
+        let round_robin = || members[message.sequence() as usize % members.len()];
+        match &self.strategy {
+            LoadBalance::RoundRobin => round_robin(),
+            LoadBalance::HashStreamShard => {
+                let header = message.header();
+                let mut key = header.stream_key().name().as_bytes().to_vec();
+                key.extend_from_slice(&header.shard_id().id().to_be_bytes());
+                members[(fnv1a(&key) % members.len() as u64) as usize]
+            }
+            LoadBalance::HashKey(key_fn) => match key_fn(message) {
+                Some(key) => members[(fnv1a(&key) % members.len() as u64) as usize],
+                None => round_robin(),
+            },
+            LoadBalance::Sticky => {
+                if self.members != members {
+                    self.members = members.to_vec();
+                    self.rebalance();
+                }
+                let entity = (
+                    message.header().stream_key().to_owned(),
+                    *message.header().shard_id(),
+                );
+                if let Some(id) = self.assignments.get(&entity) {
+                    return *id;
+                }
+                let loads = self.loads();
+                let id = *loads
+                    .iter()
+                    .min_by_key(|(_, entities)| entities.len())
+                    .expect("Not empty")
+                    .0;
+                self.assignments.insert(entity, id);
+                id
+            }
+        }
+    }
+
+    /// Entities assigned to each member
+    fn loads(&self) -> BTreeMap<Id, Vec<(StreamKey, ShardId)>> {
+        let mut loads: BTreeMap<_, Vec<_>> =
+            self.members.iter().map(|m| (*m, Vec::new())).collect();
+        for (entity, id) in self.assignments.iter() {
+            loads
+                .get_mut(id)
+                .expect("Assigned to member")
+                .push(entity.clone());
+        }
+        loads
+    }
+
+    fn rebalance(&mut self) {
+        let members = &self.members;
+        // entities of members who left will be assigned again when seen
+        self.assignments.retain(|_, id| members.contains(id));
+        let mut loads = self.loads();
+        loop {
+            let (max, min) = match (
+                loads.iter().max_by_key(|(_, e)| e.len()),
+                loads.iter().min_by_key(|(_, e)| e.len()),
+            ) {
+                (Some((max, maxe)), Some((min, mine))) if maxe.len() > mine.len() + 1 => {
+                    (*max, *min)
+                }
+                _ => break,
+            };
+            let entity = loads.get_mut(&max).unwrap().pop().unwrap();
+            self.assignments.insert(entity.clone(), min);
+            loads.get_mut(&min).unwrap().push(entity);
+        }
+    }
+}
+
+/// FNV-1a, which is stable across platforms and releases, unlike the std hasher.
+/// The hash of `HashStreamShard` is over the stream key followed by the big-endian shard id.
+#[doc(hidden)]
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Buffer, MessageHeader, Timestamp};
+
+    fn message(stream_key: &str, shard_id: u64, sequence: u64) -> SharedMessage {
+        let payload = format!("{stream_key}:{sequence}").into_bytes();
+        let size = payload.len();
+        SharedMessage::new(
+            MessageHeader::new(
+                StreamKey::new(stream_key).unwrap(),
+                ShardId::new(shard_id),
+                sequence,
+                Timestamp::now_utc(),
+            ),
+            payload,
+            0,
+            size,
+        )
+    }
+
+    #[test]
+    fn test_hash_stream_shard() {
+        let mut balancer = GroupBalancer::new(LoadBalance::HashStreamShard);
+        let members = [1, 2, 3];
+        for shard in 0..10 {
+            let first = balancer.select(&members, &message("a", shard, 0));
+            for seq in 1..10 {
+                assert_eq!(balancer.select(&members, &message("a", shard, seq)), first);
+            }
+        }
+        let mut balancer = GroupBalancer::new(LoadBalance::RoundRobin);
+        assert_eq!(balancer.select(&members, &message("a", 0, 0)), 1);
+        assert_eq!(balancer.select(&members, &message("a", 0, 1)), 2);
+    }
+
+    #[test]
+    fn test_fnv1a() {
+        // reference values of FNV-1a 64-bit
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x85944171f73967e8);
+
+        // the assignments must not change across releases
+        let mut balancer = GroupBalancer::new(LoadBalance::HashStreamShard);
+        let assigned: Vec<_> = (0..10)
+            .map(|shard| balancer.select(&[1, 2, 3], &message("a", shard, 0)))
+            .collect();
+        assert_eq!(assigned, [3, 1, 2, 3, 2, 3, 1, 2, 2, 3]);
+        let mut balancer = GroupBalancer::new(LoadBalance::HashKey(Arc::new(|m| {
+            Some(m.header().stream_key().name().as_bytes().to_vec())
+        })));
+        let assigned: Vec<_> = ["a", "b", "c", "user-7"]
+            .into_iter()
+            .map(|key| balancer.select(&[1, 2, 3, 4], &message(key, 0, 0)))
+            .collect();
+        assert_eq!(assigned, [1, 2, 3, 3]);
+    }
+
+    #[test]
+    fn test_hash_key() {
+        let mut balancer = GroupBalancer::new(LoadBalance::HashKey(Arc::new(|m| {
+            let payload = m.message();
+            let key = payload.as_bytes().split(|b| *b == b':').next()?;
+            Some(key.to_vec())
+        })));
+        let members = [1, 2, 3, 4];
+        let first = balancer.select(&members, &message("b", 0, 0));
+        for shard in 0..10 {
+            assert_eq!(
+                balancer.select(&members, &message("b", shard, shard)),
+                first
+            );
+        }
+
+        // the same function, but not another one doing the same
+        let strategy = balancer.strategy().clone();
+        assert_eq!(&strategy, balancer.strategy());
+        assert_ne!(strategy, LoadBalance::HashKey(Arc::new(|_| None)));
+        assert_ne!(strategy, LoadBalance::RoundRobin);
+    }
+
+    #[test]
+    fn test_sticky() {
+        let mut balancer = GroupBalancer::new(LoadBalance::Sticky);
+        let keys = ["a", "b", "c", "d", "e", "f"];
+        let mut assigned = BTreeMap::new();
+        for key in keys {
+            assigned.insert(key, balancer.select(&[1, 2], &message(key, 0, 0)));
+        }
+        assert_eq!(assigned.values().filter(|i| **i == 1).count(), 3);
+        for key in keys {
+            assert_eq!(balancer.select(&[1, 2], &message(key, 0, 1)), assigned[key]);
+        }
+
+        // a member joins: two entities move to it, the rest stay
+        let mut moved = 0;
+        for key in keys {
+            let id = balancer.select(&[1, 2, 3], &message(key, 0, 2));
+            if id == 3 {
+                moved += 1;
+            } else {
+                assert_eq!(id, assigned[key]);
+            }
+            assigned.insert(key, id);
+        }
+        assert_eq!(moved, 2);
+
+        // a member leaves: only its entities move
+        for key in keys {
+            let id = balancer.select(&[1, 3], &message(key, 0, 3));
+            if assigned[key] != 2 {
+                assert_eq!(id, assigned[key]);
+            }
+            assigned.insert(key, id);
+        }
+        assert_eq!(assigned.values().filter(|i| **i == 1).count(), 3);
+        assert_eq!(assigned.values().filter(|i| **i == 3).count(), 3);
+    }
+}